@@ -1,9 +1,12 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Read;
-use log::{debug, error};
+use log::{debug, error, warn};
 use serde_derive::Deserialize;
 use orbclient::Color;
 
+use crate::core::{Anchor, KeyCombo};
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
 pub struct ConfigColor { data: u32 }
 
@@ -18,7 +21,298 @@ impl From<Color> for ConfigColor {
     }
 }
 
-#[derive(Deserialize, Clone)]
+/// Margins kept free around the work area when new windows are placed automatically, in pixels,
+/// as a table like `{ top = 48 }` where the sides left out are 0
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PlacementMargin {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+/// The Super shortcuts, which the `[shortcuts]` table binds to keys by the names in
+/// [ShortcutAction::NAMES]
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ShortcutAction {
+    QuitWindow,
+    CycleWindows,
+    VolumeDown,
+    VolumeUp,
+    VolumeToggle,
+    Maximize,
+    TileTop,
+    TileBottom,
+    TileLeft,
+    TileRight,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Copy,
+    Cut,
+    Paste,
+    LogState,
+    Peek,
+    PointerHighlight,
+    Redraw,
+    ToggleOnTop,
+    ScaleUp,
+    ScaleDown,
+    ScaleReset,
+}
+
+impl ShortcutAction {
+    /// Each action with its name in the `[shortcuts]` table
+    pub const NAMES: &'static [(&'static str, ShortcutAction)] = &[
+        ("quit_window", ShortcutAction::QuitWindow),
+        ("cycle_windows", ShortcutAction::CycleWindows),
+        ("volume_down", ShortcutAction::VolumeDown),
+        ("volume_up", ShortcutAction::VolumeUp),
+        ("volume_toggle", ShortcutAction::VolumeToggle),
+        ("maximize", ShortcutAction::Maximize),
+        ("tile_top", ShortcutAction::TileTop),
+        ("tile_bottom", ShortcutAction::TileBottom),
+        ("tile_left", ShortcutAction::TileLeft),
+        ("tile_right", ShortcutAction::TileRight),
+        ("move_up", ShortcutAction::MoveUp),
+        ("move_down", ShortcutAction::MoveDown),
+        ("move_left", ShortcutAction::MoveLeft),
+        ("move_right", ShortcutAction::MoveRight),
+        ("copy", ShortcutAction::Copy),
+        ("cut", ShortcutAction::Cut),
+        ("paste", ShortcutAction::Paste),
+        ("log_state", ShortcutAction::LogState),
+        ("peek", ShortcutAction::Peek),
+        ("pointer_highlight", ShortcutAction::PointerHighlight),
+        ("redraw", ShortcutAction::Redraw),
+        ("toggle_on_top", ShortcutAction::ToggleOnTop),
+        ("scale_up", ShortcutAction::ScaleUp),
+        ("scale_down", ShortcutAction::ScaleDown),
+        ("scale_reset", ShortcutAction::ScaleReset),
+    ];
+
+    fn from_config(config: &str) -> Option<ShortcutAction> {
+        ShortcutAction::NAMES.iter().find(|(name, _)| *name == config).map(|(_, action)| *action)
+    }
+
+    /// The keys the action is bound to when the `[shortcuts]` table doesn't bind it
+    fn default_keys(self) -> Vec<KeyCombo> {
+        let key = |scancode| KeyCombo { scancode, super_key: true, ..KeyCombo::default() };
+        let shifted = |scancode| KeyCombo { scancode, shift: true, super_key: true, ..KeyCombo::default() };
+        match self {
+            ShortcutAction::QuitWindow => vec![key(orbclient::K_Q)],
+            ShortcutAction::CycleWindows => vec![key(orbclient::K_TAB)],
+            ShortcutAction::VolumeDown => vec![key(orbclient::K_BRACE_OPEN)],
+            ShortcutAction::VolumeUp => vec![key(orbclient::K_BRACE_CLOSE)],
+            ShortcutAction::VolumeToggle => vec![key(orbclient::K_BACKSLASH)],
+            ShortcutAction::Maximize => vec![key(orbclient::K_M), key(orbclient::K_ENTER)],
+            ShortcutAction::TileTop => vec![shifted(orbclient::K_UP)],
+            ShortcutAction::TileBottom => vec![shifted(orbclient::K_DOWN)],
+            ShortcutAction::TileLeft => vec![shifted(orbclient::K_LEFT)],
+            ShortcutAction::TileRight => vec![shifted(orbclient::K_RIGHT)],
+            ShortcutAction::MoveUp => vec![key(orbclient::K_UP)],
+            ShortcutAction::MoveDown => vec![key(orbclient::K_DOWN)],
+            ShortcutAction::MoveLeft => vec![key(orbclient::K_LEFT)],
+            ShortcutAction::MoveRight => vec![key(orbclient::K_RIGHT)],
+            ShortcutAction::Copy => vec![key(orbclient::K_C)],
+            ShortcutAction::Cut => vec![key(orbclient::K_X)],
+            ShortcutAction::Paste => vec![key(orbclient::K_V)],
+            ShortcutAction::LogState => vec![key(orbclient::K_D)],
+            ShortcutAction::Peek => vec![key(orbclient::K_P)],
+            ShortcutAction::PointerHighlight => vec![key(orbclient::K_H)],
+            ShortcutAction::Redraw => vec![key(orbclient::K_R)],
+            ShortcutAction::ToggleOnTop => vec![key(orbclient::K_T)],
+            ShortcutAction::ScaleUp => vec![key(orbclient::K_EQUALS)],
+            ShortcutAction::ScaleDown => vec![key(orbclient::K_MINUS)],
+            ShortcutAction::ScaleReset => vec![key(orbclient::K_0)],
+        }
+    }
+
+    /// What the action does, as listed in the shortcuts overlay
+    pub fn description(self) -> &'static str {
+        match self {
+            ShortcutAction::QuitWindow => "Quit current window",
+            ShortcutAction::CycleWindows => "Cycle through active windows bringing to the front of the stack",
+            ShortcutAction::VolumeDown => "Volume down, by a larger step with Shift",
+            ShortcutAction::VolumeUp => "Volume up, by a larger step with Shift",
+            ShortcutAction::VolumeToggle => "Volume toggle (mute / unmute)",
+            ShortcutAction::Maximize => "Toggle window max (maximize or restore)",
+            ShortcutAction::TileTop => "Tile window to top",
+            ShortcutAction::TileBottom => "Tile window to bottom",
+            ShortcutAction::TileLeft => "Tile window to left",
+            ShortcutAction::TileRight => "Tile window to right",
+            ShortcutAction::MoveUp => "Move window up",
+            ShortcutAction::MoveDown => "Move window down",
+            ShortcutAction::MoveLeft => "Move window left",
+            ShortcutAction::MoveRight => "Move window right",
+            ShortcutAction::Copy => "Copy to copy buffer",
+            ShortcutAction::Cut => "Cut to copy buffer",
+            ShortcutAction::Paste => "Paste from the copy buffer",
+            ShortcutAction::LogState => "Log the window state for debugging",
+            ShortcutAction::Peek => "Peek at the window under the cursor while held",
+            ShortcutAction::PointerHighlight => "Toggle the ring highlighting the pointer",
+            ShortcutAction::Redraw => "Redraw the whole screen",
+            ShortcutAction::ToggleOnTop => "Toggle keeping the window on top",
+            ShortcutAction::ScaleUp => "Make the UI larger",
+            ShortcutAction::ScaleDown => "Make the UI smaller",
+            ShortcutAction::ScaleReset => "Reset the UI scale to that of the displays",
+        }
+    }
+
+    /// Every action bound to its default keys
+    pub fn defaults() -> BTreeMap<ShortcutAction, Vec<KeyCombo>> {
+        ShortcutAction::NAMES.iter().map(|(_, action)| (*action, action.default_keys())).collect()
+    }
+}
+
+/// What double-clicking a title bar does, set with `titlebar_doubleclick_action`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DoubleClickAction {
+    None,
+    Maximize,
+    Shade,
+}
+
+impl DoubleClickAction {
+    fn from_config(config: &str) -> Option<DoubleClickAction> {
+        match config {
+            "none" => Some(DoubleClickAction::None),
+            "maximize" => Some(DoubleClickAction::Maximize),
+            "shade" => Some(DoubleClickAction::Shade),
+            _ => None,
+        }
+    }
+}
+
+/// What a click on the maximize button does, set with `maximize_button_action`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MaximizeAction {
+    Maximize,
+    Fullscreen,
+}
+
+impl MaximizeAction {
+    fn from_config(config: &str) -> Option<MaximizeAction> {
+        match config {
+            "maximize" => Some(MaximizeAction::Maximize),
+            "fullscreen" => Some(MaximizeAction::Fullscreen),
+            _ => None,
+        }
+    }
+}
+
+/// How the progress of an animation speeds up and slows down over its duration, set with
+/// `animation_easing`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseInOut,
+    EaseOut,
+}
+
+impl Easing {
+    fn from_config(config: &str) -> Option<Easing> {
+        match config {
+            "linear" => Some(Easing::Linear),
+            "ease_in_out" => Some(Easing::EaseInOut),
+            "ease_out" => Some(Easing::EaseOut),
+            _ => None,
+        }
+    }
+
+    /// The eased progress at `t`, both going from 0.0 to 1.0. `t` is clamped to that range
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => if t < 0.5 {
+                4.0 * t * t * t
+            } else {
+                1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+            },
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+        }
+    }
+}
+
+/// The keys of an action in the `[shortcuts]` table: one `"<scancode>,<modifiers>"`, written like
+/// for `GRABKEY`, or a list of them
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum ShortcutKeys {
+    One(String),
+    Many(Vec<String>),
+}
+
+// The value of the option `name` read with `parse`, or `default` when it is missing or, with a
+// warning, when it isn't valid
+fn option<T>(value: Option<String>, name: &str, default: T, parse: impl Fn(&str) -> Option<T>) -> T {
+    match value {
+        Some(value) => parse(&value).unwrap_or_else(|| {
+            warn!("invalid {} '{}'", name, value);
+            default
+        }),
+        None => default,
+    }
+}
+
+// The shortcuts with the actions of the `[shortcuts]` table bound to its keys and the others to
+// their default keys, warning about the entries that are invalid. The keys must include `super`.
+// A key bound in the table is taken from the actions it is a default of, and a key bound to more
+// than one action in the table stays with the first of them in [ShortcutAction::NAMES]
+fn shortcuts(config: Option<BTreeMap<String, ShortcutKeys>>) -> BTreeMap<ShortcutAction, Vec<KeyCombo>> {
+    let mut configured: BTreeMap<ShortcutAction, Vec<KeyCombo>> = BTreeMap::new();
+    for (name, keys) in config.unwrap_or_default() {
+        let Some(action) = ShortcutAction::from_config(&name) else {
+            warn!("invalid shortcuts action '{}'", name);
+            continue;
+        };
+        let keys = match keys {
+            ShortcutKeys::One(key) => vec![key],
+            ShortcutKeys::Many(keys) => keys,
+        };
+        let combos: Vec<KeyCombo> = keys.iter().filter_map(|key| {
+            let combo = key.parse::<KeyCombo>().ok().filter(|combo| combo.super_key);
+            if combo.is_none() {
+                warn!("invalid shortcuts key '{}' for '{}', shortcuts are Super combinations", key, name);
+            }
+            combo
+        }).collect();
+        // only an empty list unbinds an action, not one where no key is valid
+        if combos.is_empty() && ! keys.is_empty() {
+            warn!("no valid shortcuts keys for '{}', keeping its default keys", name);
+            continue;
+        }
+        configured.insert(action, combos);
+    }
+
+    let mut bound: Vec<(KeyCombo, ShortcutAction)> = Vec::new();
+    for (action, combos) in configured.iter_mut() {
+        combos.retain(|combo| match bound.iter().find(|(other, _)| other == combo) {
+            Some((_, other)) => {
+                warn!("shortcuts key {:?} of {:?} ignored, it is already bound to {:?}", combo, action, other);
+                false
+            },
+            None => {
+                bound.push((*combo, *action));
+                true
+            },
+        });
+    }
+
+    let mut shortcuts = ShortcutAction::defaults();
+    for (action, keys) in shortcuts.iter_mut() {
+        match configured.remove(action) {
+            Some(combos) => *keys = combos,
+            None => keys.retain(|combo| ! bound.iter().any(|(other, _)| other == combo)),
+        }
+    }
+    shortcuts
+}
+
+#[derive(Clone)]
 pub struct Config {
     pub cursor: String,
     pub bottom_left_corner: String,
@@ -30,24 +324,178 @@ pub struct Config {
     pub window_max_unfocused: String,
     pub window_close: String,
     pub window_close_unfocused: String,
+    /// Image drawn centered behind the windows of every display, over the background color.
+    /// Empty for none
+    pub wallpaper: String,
+    /// Wallpapers of each display by index, used instead of [Config::wallpaper]. An empty or
+    /// missing entry falls back to it
+    pub display_wallpapers: Vec<String>,
 
-    #[serde(default = "background_color_default")]
     pub background_color: ConfigColor,
-    #[serde(default = "bar_color_default")]
     pub bar_color: ConfigColor,
-    #[serde(default = "bar_highlight_color_default")]
     pub bar_highlight_color: ConfigColor,
-    #[serde(default = "text_color_default")]
     pub text_color: ConfigColor,
-    #[serde(default = "text_highlight_color_default")]
     pub text_highlight_color: ConfigColor,
+    /// Color of the secondary text windows can show in their title bar
+    pub subtitle_color: ConfigColor,
+    /// Background color of notification toasts
+    pub toast_color: ConfigColor,
+    /// Text color of notification toasts
+    pub toast_text_color: ConfigColor,
+
+    /// Invert the direction of scroll events before forwarding them to windows
+    pub natural_scroll: bool,
+    /// Factor scroll events are multiplied by before they are forwarded, together with the
+    /// factor each window can set for itself
+    pub scroll_sensitivity: f32,
+    /// Refresh rate in Hz assumed for displays that don't report their own
+    pub refresh_rate: u32,
+    /// Start with sticky keys enabled, latching modifier presses until the next key press. Can
+    /// also be toggled at runtime by pressing Shift five times in a row
+    pub sticky_keys: bool,
+    /// Place new windows inside the work area of the display under the cursor. When disabled
+    /// they are centered on the whole screen, which may put them under a panel
+    pub confine_to_work_area: bool,
+    /// Minimum time in milliseconds between the resize events sent to a window while one of its
+    /// borders is dragged. The final size is always sent when the drag ends, 0 sends every resize
+    pub resize_debounce_ms: u32,
+    /// Radius in pixels of the blur behind windows with the blur flag, before display scaling.
+    /// Limited to [MAX_BLUR_RADIUS], 0 disables the blur
+    pub blur_radius: u32,
+    /// Percentage the volume shortcuts change the volume by, or four times as much with Shift
+    pub volume_step: u32,
+    /// Size in pixels of the square handles at the bottom corners of resizable windows, before
+    /// display scaling. Can be larger than the 8 pixel borders to make the corners easier to grab
+    pub corner_grab_size: u32,
+    /// Screen corner notification toasts are stacked in: "TL", "TR", "BL" or "BR"
+    pub toast_corner: Anchor,
+    /// Time in milliseconds a notification toast is shown for
+    pub toast_duration_ms: u32,
+    /// Most notification toasts shown at once, the others wait until one is dismissed
+    pub toast_max_visible: u32,
+    /// Typeface of the text orbital draws itself, such as window titles and pop-ups, loaded from
+    /// `/ui/fonts/<ui_font>/Fira/`. Falls back to regular "Sans" when it can't be loaded
+    pub ui_font: String,
+    /// Use the bold style of [Config::ui_font]
+    pub ui_font_bold: bool,
+    /// Use the italic style of [Config::ui_font]
+    pub ui_font_italic: bool,
+    /// Smooth the edges of that text. When disabled every pixel of a glyph is either fully drawn
+    /// or not at all, which can look sharper on low resolution displays. Glyphs are rasterized
+    /// without hinting in both cases, as orbfont has no way to enable it
+    pub font_antialias: bool,
+    /// Animate windows minimizing to their taskbar button and restoring from it with an outline
+    /// moving between the two
+    pub animate_windows: bool,
+    /// Order Super-Tab goes through the windows in: "stack" brings the next window in the
+    /// stacking order to the front, "mru" goes from the most recently focused to the least
+    pub switcher_order: String,
+    /// How much windows other than the focused one are darkened, from 0 for not at all to 255
+    /// for black. Transparent windows are only darkened where they are drawn
+    pub unfocused_dim: u8,
+    /// Draw a spinner over windows that haven't been drawn yet, until their first sync
+    pub loading_spinner: bool,
+    /// Seconds without input after which the screen is blanked, 0 to never blank it. Windows
+    /// can inhibit blanking while they are open, such as a video player
+    pub idle_blank_secs: u32,
+    /// Draw a ring around the pointer to make it easier to find. Can also be toggled at runtime
+    /// with Super-H
+    pub pointer_highlight: bool,
+    /// Draw a panel across the top of the first display with a window list, the title of the
+    /// focused window and a clock. Off by default, as it would overlap a launcher's panel
+    pub builtin_panel: bool,
+    /// What double-clicking a title bar does: "none" nothing, "maximize" toggles maximizing the
+    /// window and "shade" rolls it up to just its title bar and back down
+    pub titlebar_doubleclick_action: DoubleClickAction,
+    /// Largest number of bytes the clipboard holds, writes past it are cut short
+    pub max_clipboard_bytes: usize,
+    /// Move the drawn cursor smoothly toward the pointer over a few frames, for input devices
+    /// reporting at a low rate. This adds some latency to where the cursor is shown, clicks
+    /// always go where the pointer really is
+    pub smooth_cursor: bool,
+    /// Open a window where the last window with the same title was when it closed, tiled or
+    /// maximized again if it was
+    pub remember_geometry: bool,
+    /// Seconds without key events after which held modifiers are taken to be stuck, such as
+    /// when the release of Super was lost, and reset. 0 never resets them
+    pub modifier_timeout_secs: u32,
+    /// What the maximize button does: "maximize" fills the work area below the title bar,
+    /// "fullscreen" covers the whole display without decorations. Shift-clicking it does the other
+    pub maximize_button_action: MaximizeAction,
+    /// Snap windows dragged by their title or borders to a grid of `snap_grid_size` pixels.
+    /// Holding Control while dragging doesn't snap
+    pub snap_to_grid: bool,
+    /// Size of the grid cells for `snap_to_grid`
+    pub snap_grid_size: u32,
+    /// Snap the borders dragged when resizing a window to the edges of the other windows shown
+    /// within this many pixels, 0 to not snap. Holding Control while dragging doesn't snap
+    pub snap_distance: u32,
+    /// Log the percentiles of the time from input arriving to the next frame being composited,
+    /// for performance work
+    pub debug_input_latency: bool,
+    /// Copy a window that covers a whole display straight to it, without compositing what is
+    /// below it, while it is opaque and nothing is drawn over it but the cursor
+    pub fullscreen_scanout: bool,
+    /// Sync each display at most once per refresh interval of its own, from its refresh rate
+    /// or `refresh_rate`, holding back what changed on it until its next frame is due
+    pub frame_pacing: bool,
+    /// Easing curve of the window animations enabled by `animate_windows`: "linear" moves at a
+    /// constant speed, "ease_out" starts fast and slows down toward the end and "ease_in_out" also
+    /// starts slowly
+    pub animation_easing: Easing,
+    /// Actions fired by holding the cursor in a corner or at an edge of the displays, as
+    /// `"<corner>=<action>"` entries. The corners are "top_left", "top_right", "bottom_left",
+    /// "bottom_right", "top", "bottom", "left" and "right", the actions "show_switcher",
+    /// "show_desktop" and "pointer_highlight"
+    pub hot_corners: Vec<String>,
+    /// Kept free around the work area when centering new windows that don't ask for a position,
+    /// such as for a panel that hasn't reserved its space yet. Windows can still be moved there
+    pub placement_margin: PlacementMargin,
+    /// Redraw windows that aren't focused at most this many times a second, coalescing their
+    /// syncs in between into one. The focused window always redraws at full rate, 0 for no limit
+    pub bg_window_max_fps: u32,
+    /// Opacity of the window switcher, volume and shortcuts overlays, multiplying the alpha of
+    /// their background from `bar_color`: from 0.0 for see-through to 1.0 for drawn over the
+    /// windows as they are
+    pub osd_opacity: f32,
+    /// Show the size of a window while its borders are dragged to resize it, and for a moment after
+    pub resize_osd: bool,
+    /// How many times larger than the UI scale the cursors are drawn, for a cursor that is
+    /// easier to see. Limited to 1 to [MAX_UI_SCALE]
+    pub cursor_scale: u32,
+    /// Raise the window under the cursor above the other windows once it has been hovered for
+    /// this many milliseconds, without giving it focus unless `autoraise_focus` is set. 0 to
+    /// never raise windows on hover
+    pub autoraise_delay: u64,
+    /// Have `autoraise_delay` focus the hovered window as well, like clicking it would
+    pub autoraise_focus: bool,
+    /// Don't draw the title bar of a window until its first sync, so that it doesn't show around
+    /// an empty window while the client starts
+    pub defer_decorations: bool,
+    /// Let clients read back what is shown, from `orbital:contents`, `orbital:screenshot`,
+    /// `orbital:pixel` and `orbital:record`, such as for remote display or screen recording. Off
+    /// by default, as any client could then read the windows of all the others
+    pub screen_capture: bool,
+    /// The keys of each Super shortcut, from the `[shortcuts]` table mapping the names of
+    /// [ShortcutAction::NAMES] to a `"<scancode>,<modifiers>"` or a list of them, like
+    /// `quit_window = "16,super"`. The actions it leaves out keep their default keys
+    pub shortcuts: BTreeMap<ShortcutAction, Vec<KeyCombo>>,
 }
 
+/// Largest [Config::blur_radius] honoured, a larger radius is clamped to it
+pub const MAX_BLUR_RADIUS: u32 = 32;
+
+/// The largest UI scale, which the scale can be stepped up to at runtime, and the largest
+/// [Config::cursor_scale]
+pub const MAX_UI_SCALE: i32 = 4;
+
 fn background_color_default() -> ConfigColor { Color::rgb(0, 0, 0).into() }
 fn bar_color_default() -> ConfigColor { Color::rgba(0x1B, 0x1B, 0x1B, 224).into() }
 fn bar_highlight_color_default() -> ConfigColor { Color::rgba(0x36, 0x36, 0x36, 224).into() }
 fn text_color_default() -> ConfigColor { Color::rgb(0xE7, 0xE7, 0xE7).into() }
 fn text_highlight_color_default() -> ConfigColor { Color::rgb(0xE7, 0xE7, 0xE7).into() }
+fn subtitle_color_default() -> ConfigColor { Color::rgb(0x9E, 0x9E, 0x9E).into() }
+fn refresh_rate_default() -> u32 { 60 }
 
 /// Create a sane default Orbital [Config] in case none is supplied or it is unreadable
 impl Default for Config {
@@ -66,6 +514,8 @@ impl Default for Config {
             window_max_unfocused: String::default(),
             window_close: String::default(),
             window_close_unfocused: String::default(),
+            wallpaper: String::default(),
+            display_wallpapers: Vec::new(),
 
             // These are the default colors for Orbital that have been defined
             background_color: background_color_default(),
@@ -73,46 +523,363 @@ impl Default for Config {
             bar_highlight_color: bar_highlight_color_default(),
             text_color: text_color_default(),
             text_highlight_color: text_highlight_color_default(),
+            subtitle_color: subtitle_color_default(),
+            toast_color: bar_color_default(),
+            toast_text_color: text_color_default(),
+
+            natural_scroll: false,
+            scroll_sensitivity: 1.0,
+            refresh_rate: refresh_rate_default(),
+            sticky_keys: false,
+            confine_to_work_area: true,
+            resize_debounce_ms: 0,
+            blur_radius: 6,
+            volume_step: 5,
+            corner_grab_size: 8,
+            toast_corner: Anchor::TopRight,
+            toast_duration_ms: 4000,
+            toast_max_visible: 3,
+            ui_font: "Sans".to_string(),
+            ui_font_bold: false,
+            ui_font_italic: false,
+            font_antialias: true,
+            animate_windows: false,
+            switcher_order: "stack".to_string(),
+            unfocused_dim: 0,
+            loading_spinner: false,
+            idle_blank_secs: 0,
+            pointer_highlight: false,
+            builtin_panel: false,
+            titlebar_doubleclick_action: DoubleClickAction::None,
+            max_clipboard_bytes: 16 * 1024 * 1024,
+            smooth_cursor: false,
+            remember_geometry: false,
+            modifier_timeout_secs: 10,
+            maximize_button_action: MaximizeAction::Maximize,
+            snap_to_grid: false,
+            snap_grid_size: 16,
+            snap_distance: 0,
+            debug_input_latency: false,
+            fullscreen_scanout: true,
+            frame_pacing: false,
+            animation_easing: Easing::EaseOut,
+            hot_corners: Vec::new(),
+            placement_margin: PlacementMargin::default(),
+            bg_window_max_fps: 0,
+            osd_opacity: 1.0,
+            resize_osd: false,
+            cursor_scale: 1,
+            autoraise_delay: 0,
+            autoraise_focus: false,
+            defer_decorations: false,
+            screen_capture: false,
+            shortcuts: ShortcutAction::defaults(),
         }
     }
 }
 
-/// [Config] holds configuration information for Orbital, such as colors, cursors etc.
-impl Config {
-    // returns the default config if the string passed is not a valid config
-    fn config_from_string(config: &str) -> Config {
+/// The contents of a single config file. Every field is optional so that several files can be
+/// merged before the fields that are still missing are resolved against [Config::default]
+#[derive(Deserialize, Default)]
+struct PartialConfig {
+    cursor: Option<String>,
+    bottom_left_corner: Option<String>,
+    bottom_right_corner: Option<String>,
+    bottom_side: Option<String>,
+    left_side: Option<String>,
+    right_side: Option<String>,
+    window_max: Option<String>,
+    window_max_unfocused: Option<String>,
+    window_close: Option<String>,
+    window_close_unfocused: Option<String>,
+    wallpaper: Option<String>,
+    display_wallpapers: Option<Vec<String>>,
+
+    background_color: Option<ConfigColor>,
+    bar_color: Option<ConfigColor>,
+    bar_highlight_color: Option<ConfigColor>,
+    text_color: Option<ConfigColor>,
+    text_highlight_color: Option<ConfigColor>,
+    subtitle_color: Option<ConfigColor>,
+    toast_color: Option<ConfigColor>,
+    toast_text_color: Option<ConfigColor>,
+
+    natural_scroll: Option<bool>,
+    scroll_sensitivity: Option<f32>,
+    refresh_rate: Option<u32>,
+    sticky_keys: Option<bool>,
+    confine_to_work_area: Option<bool>,
+    resize_debounce_ms: Option<u32>,
+    blur_radius: Option<u32>,
+    volume_step: Option<u32>,
+    corner_grab_size: Option<u32>,
+    toast_corner: Option<String>,
+    toast_duration_ms: Option<u32>,
+    toast_max_visible: Option<u32>,
+    ui_font: Option<String>,
+    ui_font_bold: Option<bool>,
+    ui_font_italic: Option<bool>,
+    font_antialias: Option<bool>,
+    animate_windows: Option<bool>,
+    switcher_order: Option<String>,
+    unfocused_dim: Option<u8>,
+    loading_spinner: Option<bool>,
+    idle_blank_secs: Option<u32>,
+    pointer_highlight: Option<bool>,
+    builtin_panel: Option<bool>,
+    titlebar_doubleclick_action: Option<String>,
+    max_clipboard_bytes: Option<usize>,
+    smooth_cursor: Option<bool>,
+    remember_geometry: Option<bool>,
+    modifier_timeout_secs: Option<u32>,
+    maximize_button_action: Option<String>,
+    snap_to_grid: Option<bool>,
+    snap_grid_size: Option<u32>,
+    snap_distance: Option<u32>,
+    debug_input_latency: Option<bool>,
+    fullscreen_scanout: Option<bool>,
+    frame_pacing: Option<bool>,
+    animation_easing: Option<String>,
+    hot_corners: Option<Vec<String>>,
+    placement_margin: Option<PlacementMargin>,
+    bg_window_max_fps: Option<u32>,
+    osd_opacity: Option<f32>,
+    resize_osd: Option<bool>,
+    cursor_scale: Option<u32>,
+    autoraise_delay: Option<u64>,
+    autoraise_focus: Option<bool>,
+    defer_decorations: Option<bool>,
+    screen_capture: Option<bool>,
+    shortcuts: Option<BTreeMap<String, ShortcutKeys>>,
+}
+
+impl PartialConfig {
+    /// Merge `other` on top of this config, the fields set in `other` win
+    fn merge(self, other: PartialConfig) -> PartialConfig {
+        PartialConfig {
+            cursor: other.cursor.or(self.cursor),
+            bottom_left_corner: other.bottom_left_corner.or(self.bottom_left_corner),
+            bottom_right_corner: other.bottom_right_corner.or(self.bottom_right_corner),
+            bottom_side: other.bottom_side.or(self.bottom_side),
+            left_side: other.left_side.or(self.left_side),
+            right_side: other.right_side.or(self.right_side),
+            window_max: other.window_max.or(self.window_max),
+            window_max_unfocused: other.window_max_unfocused.or(self.window_max_unfocused),
+            window_close: other.window_close.or(self.window_close),
+            window_close_unfocused: other.window_close_unfocused.or(self.window_close_unfocused),
+            wallpaper: other.wallpaper.or(self.wallpaper),
+            display_wallpapers: other.display_wallpapers.or(self.display_wallpapers),
+
+            background_color: other.background_color.or(self.background_color),
+            bar_color: other.bar_color.or(self.bar_color),
+            bar_highlight_color: other.bar_highlight_color.or(self.bar_highlight_color),
+            text_color: other.text_color.or(self.text_color),
+            text_highlight_color: other.text_highlight_color.or(self.text_highlight_color),
+            subtitle_color: other.subtitle_color.or(self.subtitle_color),
+            toast_color: other.toast_color.or(self.toast_color),
+            toast_text_color: other.toast_text_color.or(self.toast_text_color),
+
+            natural_scroll: other.natural_scroll.or(self.natural_scroll),
+            scroll_sensitivity: other.scroll_sensitivity.or(self.scroll_sensitivity),
+            refresh_rate: other.refresh_rate.or(self.refresh_rate),
+            sticky_keys: other.sticky_keys.or(self.sticky_keys),
+            confine_to_work_area: other.confine_to_work_area.or(self.confine_to_work_area),
+            resize_debounce_ms: other.resize_debounce_ms.or(self.resize_debounce_ms),
+            blur_radius: other.blur_radius.or(self.blur_radius),
+            volume_step: other.volume_step.or(self.volume_step),
+            corner_grab_size: other.corner_grab_size.or(self.corner_grab_size),
+            toast_corner: other.toast_corner.or(self.toast_corner),
+            toast_duration_ms: other.toast_duration_ms.or(self.toast_duration_ms),
+            toast_max_visible: other.toast_max_visible.or(self.toast_max_visible),
+            ui_font: other.ui_font.or(self.ui_font),
+            ui_font_bold: other.ui_font_bold.or(self.ui_font_bold),
+            ui_font_italic: other.ui_font_italic.or(self.ui_font_italic),
+            font_antialias: other.font_antialias.or(self.font_antialias),
+            animate_windows: other.animate_windows.or(self.animate_windows),
+            switcher_order: other.switcher_order.or(self.switcher_order),
+            unfocused_dim: other.unfocused_dim.or(self.unfocused_dim),
+            loading_spinner: other.loading_spinner.or(self.loading_spinner),
+            idle_blank_secs: other.idle_blank_secs.or(self.idle_blank_secs),
+            pointer_highlight: other.pointer_highlight.or(self.pointer_highlight),
+            builtin_panel: other.builtin_panel.or(self.builtin_panel),
+            titlebar_doubleclick_action: other.titlebar_doubleclick_action.or(self.titlebar_doubleclick_action),
+            max_clipboard_bytes: other.max_clipboard_bytes.or(self.max_clipboard_bytes),
+            smooth_cursor: other.smooth_cursor.or(self.smooth_cursor),
+            remember_geometry: other.remember_geometry.or(self.remember_geometry),
+            modifier_timeout_secs: other.modifier_timeout_secs.or(self.modifier_timeout_secs),
+            maximize_button_action: other.maximize_button_action.or(self.maximize_button_action),
+            snap_to_grid: other.snap_to_grid.or(self.snap_to_grid),
+            snap_grid_size: other.snap_grid_size.or(self.snap_grid_size),
+            snap_distance: other.snap_distance.or(self.snap_distance),
+            debug_input_latency: other.debug_input_latency.or(self.debug_input_latency),
+            fullscreen_scanout: other.fullscreen_scanout.or(self.fullscreen_scanout),
+            frame_pacing: other.frame_pacing.or(self.frame_pacing),
+            animation_easing: other.animation_easing.or(self.animation_easing),
+            hot_corners: other.hot_corners.or(self.hot_corners),
+            placement_margin: other.placement_margin.or(self.placement_margin),
+            bg_window_max_fps: other.bg_window_max_fps.or(self.bg_window_max_fps),
+            osd_opacity: other.osd_opacity.or(self.osd_opacity),
+            resize_osd: other.resize_osd.or(self.resize_osd),
+            cursor_scale: other.cursor_scale.or(self.cursor_scale),
+            autoraise_delay: other.autoraise_delay.or(self.autoraise_delay),
+            autoraise_focus: other.autoraise_focus.or(self.autoraise_focus),
+            defer_decorations: other.defer_decorations.or(self.defer_decorations),
+            screen_capture: other.screen_capture.or(self.screen_capture),
+            shortcuts: other.shortcuts.or(self.shortcuts),
+        }
+    }
+
+    // returns an empty partial config if the string passed is not a valid config
+    fn from_string(config: &str) -> PartialConfig {
         match toml::from_str(config) {
             Ok(config) => config,
             Err(err) => {
                 error!("failed to parse config '{}'", err);
-                Config::default()
+                PartialConfig::default()
             }
         }
     }
+}
 
-    /// Read an Orbital configuration from a toml file at `path`
-    pub fn from_path(path: &str) -> Config {
-        let mut string = String::new();
+impl From<PartialConfig> for Config {
+    fn from(partial: PartialConfig) -> Self {
+        let default = Config::default();
+        Config {
+            cursor: partial.cursor.unwrap_or(default.cursor),
+            bottom_left_corner: partial.bottom_left_corner.unwrap_or(default.bottom_left_corner),
+            bottom_right_corner: partial.bottom_right_corner.unwrap_or(default.bottom_right_corner),
+            bottom_side: partial.bottom_side.unwrap_or(default.bottom_side),
+            left_side: partial.left_side.unwrap_or(default.left_side),
+            right_side: partial.right_side.unwrap_or(default.right_side),
+            window_max: partial.window_max.unwrap_or(default.window_max),
+            window_max_unfocused: partial.window_max_unfocused.unwrap_or(default.window_max_unfocused),
+            window_close: partial.window_close.unwrap_or(default.window_close),
+            window_close_unfocused: partial.window_close_unfocused.unwrap_or(default.window_close_unfocused),
+            wallpaper: partial.wallpaper.unwrap_or(default.wallpaper),
+            display_wallpapers: partial.display_wallpapers.unwrap_or(default.display_wallpapers),
 
-        match File::open(path) {
-            Ok(mut file) => match file.read_to_string(&mut string) {
-                Ok(_) => debug!("reading config from path: '{}'", path),
-                Err(err) => error!("failed to read config '{}': {}", path, err),
-            },
-            Err(err) => error!("failed to open config '{}': {}", path, err),
+            background_color: partial.background_color.unwrap_or(default.background_color),
+            bar_color: partial.bar_color.unwrap_or(default.bar_color),
+            bar_highlight_color: partial.bar_highlight_color.unwrap_or(default.bar_highlight_color),
+            text_color: partial.text_color.unwrap_or(default.text_color),
+            text_highlight_color: partial.text_highlight_color.unwrap_or(default.text_highlight_color),
+            subtitle_color: partial.subtitle_color.unwrap_or(default.subtitle_color),
+            toast_color: partial.toast_color.unwrap_or(default.toast_color),
+            toast_text_color: partial.toast_text_color.unwrap_or(default.toast_text_color),
+
+            natural_scroll: partial.natural_scroll.unwrap_or(default.natural_scroll),
+            scroll_sensitivity: partial.scroll_sensitivity.unwrap_or(default.scroll_sensitivity),
+            refresh_rate: partial.refresh_rate.unwrap_or(default.refresh_rate),
+            sticky_keys: partial.sticky_keys.unwrap_or(default.sticky_keys),
+            confine_to_work_area: partial.confine_to_work_area.unwrap_or(default.confine_to_work_area),
+            resize_debounce_ms: partial.resize_debounce_ms.unwrap_or(default.resize_debounce_ms),
+            blur_radius: partial.blur_radius.unwrap_or(default.blur_radius),
+            volume_step: partial.volume_step.unwrap_or(default.volume_step),
+            corner_grab_size: partial.corner_grab_size.unwrap_or(default.corner_grab_size),
+            toast_corner: option(partial.toast_corner, "toast_corner", default.toast_corner, |corner| corner.parse().ok()),
+            toast_duration_ms: partial.toast_duration_ms.unwrap_or(default.toast_duration_ms),
+            toast_max_visible: partial.toast_max_visible.unwrap_or(default.toast_max_visible),
+            ui_font: partial.ui_font.unwrap_or(default.ui_font),
+            ui_font_bold: partial.ui_font_bold.unwrap_or(default.ui_font_bold),
+            ui_font_italic: partial.ui_font_italic.unwrap_or(default.ui_font_italic),
+            font_antialias: partial.font_antialias.unwrap_or(default.font_antialias),
+            animate_windows: partial.animate_windows.unwrap_or(default.animate_windows),
+            switcher_order: partial.switcher_order.unwrap_or(default.switcher_order),
+            unfocused_dim: partial.unfocused_dim.unwrap_or(default.unfocused_dim),
+            loading_spinner: partial.loading_spinner.unwrap_or(default.loading_spinner),
+            idle_blank_secs: partial.idle_blank_secs.unwrap_or(default.idle_blank_secs),
+            pointer_highlight: partial.pointer_highlight.unwrap_or(default.pointer_highlight),
+            builtin_panel: partial.builtin_panel.unwrap_or(default.builtin_panel),
+            titlebar_doubleclick_action: option(partial.titlebar_doubleclick_action, "titlebar_doubleclick_action", default.titlebar_doubleclick_action, DoubleClickAction::from_config),
+            max_clipboard_bytes: partial.max_clipboard_bytes.unwrap_or(default.max_clipboard_bytes),
+            smooth_cursor: partial.smooth_cursor.unwrap_or(default.smooth_cursor),
+            remember_geometry: partial.remember_geometry.unwrap_or(default.remember_geometry),
+            modifier_timeout_secs: partial.modifier_timeout_secs.unwrap_or(default.modifier_timeout_secs),
+            maximize_button_action: option(partial.maximize_button_action, "maximize_button_action", default.maximize_button_action, MaximizeAction::from_config),
+            snap_to_grid: partial.snap_to_grid.unwrap_or(default.snap_to_grid),
+            snap_grid_size: partial.snap_grid_size.unwrap_or(default.snap_grid_size),
+            snap_distance: partial.snap_distance.unwrap_or(default.snap_distance),
+            debug_input_latency: partial.debug_input_latency.unwrap_or(default.debug_input_latency),
+            fullscreen_scanout: partial.fullscreen_scanout.unwrap_or(default.fullscreen_scanout),
+            frame_pacing: partial.frame_pacing.unwrap_or(default.frame_pacing),
+            animation_easing: option(partial.animation_easing, "animation_easing", default.animation_easing, Easing::from_config),
+            hot_corners: partial.hot_corners.unwrap_or(default.hot_corners),
+            placement_margin: partial.placement_margin.unwrap_or(default.placement_margin),
+            bg_window_max_fps: partial.bg_window_max_fps.unwrap_or(default.bg_window_max_fps),
+            osd_opacity: partial.osd_opacity.unwrap_or(default.osd_opacity),
+            resize_osd: partial.resize_osd.unwrap_or(default.resize_osd),
+            cursor_scale: partial.cursor_scale.unwrap_or(default.cursor_scale).clamp(1, MAX_UI_SCALE as u32),
+            autoraise_delay: partial.autoraise_delay.unwrap_or(default.autoraise_delay),
+            autoraise_focus: partial.autoraise_focus.unwrap_or(default.autoraise_focus),
+            defer_decorations: partial.defer_decorations.unwrap_or(default.defer_decorations),
+            screen_capture: partial.screen_capture.unwrap_or(default.screen_capture),
+            shortcuts: shortcuts(partial.shortcuts),
         }
+    }
+}
 
-        Self::config_from_string(&string)
+/// [Config] holds configuration information for Orbital, such as colors, cursors etc.
+impl Config {
+    /// Path of the wallpaper of the display at `index`, empty for none
+    pub fn wallpaper(&self, index: usize) -> &str {
+        match self.display_wallpapers.get(index) {
+            Some(path) if ! path.is_empty() => path,
+            _ => &self.wallpaper,
+        }
+    }
+
+    // returns the default config if the string passed is not a valid config
+    #[cfg(test)]
+    fn config_from_string(config: &str) -> Config {
+        PartialConfig::from_string(config).into()
+    }
+
+    /// Read an Orbital configuration from the toml files at `paths`, merging them in order so
+    /// that keys set in later files override the same keys from earlier ones. Files that are
+    /// missing or can't be read are skipped.
+    pub fn from_paths(paths: &[&str]) -> Config {
+        let mut partial = PartialConfig::default();
+
+        for path in paths {
+            let mut string = String::new();
+
+            match File::open(path) {
+                Ok(mut file) => match file.read_to_string(&mut string) {
+                    Ok(_) => debug!("reading config from path: '{}'", path),
+                    Err(err) => error!("failed to read config '{}': {}", path, err),
+                },
+                Err(err) => {
+                    debug!("skipping config '{}': {}", path, err);
+                    continue;
+                }
+            }
+
+            partial = partial.merge(PartialConfig::from_string(&string));
+        }
+
+        partial.into()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::config::{background_color_default, Config, text_highlight_color_default};
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::config::{background_color_default, Config, DoubleClickAction, Easing, MaximizeAction, MAX_UI_SCALE, PlacementMargin, ShortcutAction, text_highlight_color_default};
+    use crate::core::{Anchor, KeyCombo};
+
+    // write a config file to a unique path in the temp dir and return its path
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("orbital-test-{}-{}.toml", std::process::id(), name));
+        if let Err(err) = fs::write(&path, contents) {
+            panic!("could not write test config: {}", err);
+        }
+        path
+    }
 
     #[test]
     fn non_existent_config_file() {
-        let config = Config::from_path("no-such-file.toml");
+        let config = Config::from_paths(&["no-such-file.toml"]);
         assert_eq!(config.cursor, "");
         assert_eq!(config.text_highlight_color, text_highlight_color_default());
     }
@@ -142,4 +909,129 @@ window_close_unfocused = "/ui/window_close_unfocused.png""##;
         assert_eq!(config.background_color, background_color_default());
         assert_eq!(config.bottom_left_corner, "/ui/bottom_left_corner.png");
     }
+
+    #[test]
+    fn later_config_files_override_earlier_ones() {
+        let system = write_config("system", r##"cursor = "/ui/left_ptr.png"
+window_max = "/ui/window_max.png"
+natural_scroll = false"##);
+        let user = write_config("user", r##"natural_scroll = true
+window_max = "/home/user/window_max.png""##);
+
+        let config = Config::from_paths(&[system.to_str().unwrap_or(""), user.to_str().unwrap_or("")]);
+        assert_eq!(config.cursor, "/ui/left_ptr.png");
+        assert_eq!(config.window_max, "/home/user/window_max.png");
+        assert!(config.natural_scroll);
+        assert_eq!(config.background_color, background_color_default());
+
+        let _ = fs::remove_file(system);
+        let _ = fs::remove_file(user);
+    }
+
+    #[test]
+    fn missing_config_files_are_skipped() {
+        let system = write_config("only", r##"cursor = "/ui/left_ptr.png""##);
+
+        let config = Config::from_paths(&["no-such-file.toml", system.to_str().unwrap_or(""), "no-such-user-file.toml"]);
+        assert_eq!(config.cursor, "/ui/left_ptr.png");
+        assert_eq!(config.window_close, "");
+
+        let _ = fs::remove_file(system);
+    }
+
+    #[test]
+    fn display_wallpapers_fall_back_to_wallpaper() {
+        let config = Config::config_from_string(r##"wallpaper = "/ui/background.png"
+display_wallpapers = ["", "/ui/right.png"]"##);
+        assert_eq!(config.wallpaper(0), "/ui/background.png");
+        assert_eq!(config.wallpaper(1), "/ui/right.png");
+        assert_eq!(config.wallpaper(2), "/ui/background.png");
+
+        assert_eq!(Config::default().wallpaper(0), "");
+    }
+
+    #[test]
+    fn placement_margin_table_defaults_missing_sides() {
+        let config = Config::config_from_string("placement_margin = { top = 48, left = 8 }");
+        assert_eq!(config.placement_margin, PlacementMargin { top: 48, bottom: 0, left: 8, right: 0 });
+        assert_eq!(Config::config_from_string("").placement_margin, PlacementMargin::default());
+    }
+
+    #[test]
+    fn option_values_are_read_when_loading() {
+        let config = Config::config_from_string(r#"
+            toast_corner = "BL"
+            titlebar_doubleclick_action = "shade"
+            maximize_button_action = "fullscreen"
+            animation_easing = "ease_in_out"
+        "#);
+        assert_eq!(config.toast_corner, Anchor::BottomLeft);
+        assert_eq!(config.titlebar_doubleclick_action, DoubleClickAction::Shade);
+        assert_eq!(config.maximize_button_action, MaximizeAction::Fullscreen);
+        assert_eq!(config.animation_easing, Easing::EaseInOut);
+
+        // invalid values fall back to the defaults
+        let config = Config::config_from_string(r#"
+            toast_corner = "middle"
+            titlebar_doubleclick_action = "close"
+            maximize_button_action = "bogus"
+            animation_easing = "bounce"
+        "#);
+        assert_eq!(config.toast_corner, Anchor::TopRight);
+        assert_eq!(config.titlebar_doubleclick_action, DoubleClickAction::None);
+        assert_eq!(config.maximize_button_action, MaximizeAction::Maximize);
+        assert_eq!(config.animation_easing, Easing::EaseOut);
+    }
+
+    #[test]
+    fn cursor_scale_is_limited() {
+        assert_eq!(Config::config_from_string("cursor_scale = 4000000000").cursor_scale, MAX_UI_SCALE as u32);
+        assert_eq!(Config::config_from_string("cursor_scale = 0").cursor_scale, 1);
+        assert_eq!(Config::config_from_string("cursor_scale = 3").cursor_scale, 3);
+    }
+
+    #[test]
+    fn shortcuts_table_rebinds_actions() {
+        let config = Config::config_from_string(r#"
+            [shortcuts]
+            quit_window = "24,super"
+            maximize = ["50,super", "28,super+shift"]
+            peek = []
+            redraw = "19"
+            unknown_action = "30,super"
+        "#);
+        let super_key = |scancode| KeyCombo { scancode, super_key: true, ..KeyCombo::default() };
+
+        assert_eq!(config.shortcuts[&ShortcutAction::QuitWindow], vec![super_key(24)]);
+        assert_eq!(config.shortcuts[&ShortcutAction::Maximize], vec![
+            super_key(50),
+            KeyCombo { scancode: 28, shift: true, super_key: true, ..KeyCombo::default() },
+        ]);
+        // an empty list unbinds the action, while keys without super are ignored, and an action
+        // left without any keeps its defaults
+        assert!(config.shortcuts[&ShortcutAction::Peek].is_empty());
+        assert_eq!(config.shortcuts[&ShortcutAction::Redraw], vec![super_key(orbclient::K_R)]);
+        // the others keep their defaults
+        assert_eq!(config.shortcuts[&ShortcutAction::CycleWindows], vec![super_key(orbclient::K_TAB)]);
+        assert_eq!(config.shortcuts.len(), ShortcutAction::NAMES.len());
+
+        assert_eq!(Config::config_from_string("").shortcuts, ShortcutAction::defaults());
+    }
+
+    #[test]
+    fn shortcuts_keys_bound_once() {
+        let config = Config::config_from_string(r#"
+            [shortcuts]
+            cut = "19,super"
+            copy = ["45,super", "19,super"]
+        "#);
+        let super_key = |scancode| KeyCombo { scancode, super_key: true, ..KeyCombo::default() };
+
+        // the first action in the table order keeps a key bound twice
+        assert_eq!(config.shortcuts[&ShortcutAction::Copy], vec![super_key(45), super_key(19)]);
+        assert_eq!(config.shortcuts[&ShortcutAction::Cut], Vec::<KeyCombo>::new());
+        // and a key bound in the table is taken from the action it is a default of
+        assert!(config.shortcuts[&ShortcutAction::Redraw].is_empty());
+        assert_eq!(config.shortcuts[&ShortcutAction::Paste], vec![super_key(orbclient::K_V)]);
+    }
 }
@@ -35,17 +35,26 @@ fn display_fd_unmap(image: &mut ImageRef) {
     }
 }
 
+// The scale of decorations on a display `height` pixels high
+fn display_scale(height: i32) -> i32 {
+    (height / 1600) + 1
+}
+
 pub struct Display {
     pub x: i32,
     pub y: i32,
     pub scale: i32,
+    /// Refresh rate reported by the display scheme, `None` if it does not report one
+    pub refresh_hz: Option<u32>,
+    /// Height of the strip along the top reserved by a panel, which windows are kept out of
+    pub reserved_top: i32,
     pub file: File,
     pub image: ImageRef<'static>,
 }
 
 impl Display {
-    pub fn new(x: i32, y: i32, width: i32, height: i32, file: File) -> io::Result<Self> {
-        let scale = (height / 1600) + 1;
+    pub fn new(x: i32, y: i32, width: i32, height: i32, refresh_hz: Option<u32>, file: File) -> io::Result<Self> {
+        let scale = display_scale(height);
         let image =  display_fd_map(width, height, file.as_raw_fd() as usize)
                 .map_err(|err| {
                     error!("failed to map display: {}", err);
@@ -55,6 +64,8 @@ impl Display {
             x,
             y,
             scale,
+            refresh_hz,
+            reserved_top: 0,
             file,
             image,
         })
@@ -75,6 +86,7 @@ impl Display {
             Ok(ok) => {
                 display_fd_unmap(&mut self.image);
                 self.image = ok;
+                self.scale = display_scale(height);
             },
             Err(err) => {
                 error!("failed to resize display to {}x{}: {}", width, height, err);
@@ -94,6 +106,17 @@ impl Display {
     pub fn screen_rect(&self) -> Rect {
         Rect::new(self.x, self.y, self.image.width(), self.image.height())
     }
+
+    /// The part of the screen that automatically placed windows are confined to
+    pub fn work_area(&self) -> Rect {
+        work_area(&self.screen_rect(), self.reserved_top)
+    }
+}
+
+/// The part of `screen` below the `reserved_top` pixels reserved by a panel
+pub fn work_area(screen: &Rect, reserved_top: i32) -> Rect {
+    let reserved = reserved_top.clamp(0, screen.height());
+    Rect::new(screen.left(), screen.top() + reserved, screen.width(), screen.height() - reserved)
 }
 
 impl Drop for Display {
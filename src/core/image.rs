@@ -121,6 +121,38 @@ impl<'a> ImageRoi<'a> {
         }
     }
 
+    /// Blur the pixels of the region with a box blur of `radius` pixels, applied horizontally and
+    /// then vertically. Only pixels inside the region are sampled, so the cost only depends on
+    /// its size and not on the radius.
+    pub fn box_blur(&mut self, radius: i32) {
+        let (w, h) = (self.rect.width(), self.rect.height());
+        if radius <= 0 || w <= 0 || h <= 0 {
+            return;
+        }
+
+        let (w, h, radius) = (w as usize, h as usize, radius as usize);
+        let index = |x: usize, y: usize| {
+            (self.rect.top() as usize + y) * self.w as usize + self.rect.left() as usize + x
+        };
+
+        let mut pixels = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                pixels.push(self.data[index(x, y)]);
+            }
+        }
+
+        // rows, then columns
+        blur_lines(&mut pixels, h, w, |line, i| line * w + i, radius);
+        blur_lines(&mut pixels, w, h, |line, i| i * w + line, radius);
+
+        for y in 0..h {
+            for x in 0..w {
+                self.data[index(x, y)] = pixels[y * w + x];
+            }
+        }
+    }
+
     pub fn blit(&'a mut self, other: &ImageRoi) {
         for (self_row, other_row) in self.rows_mut().zip(other.rows()) {
             let len = cmp::min(self_row.len(), other_row.len());
@@ -131,6 +163,31 @@ impl<'a> ImageRoi<'a> {
     }
 }
 
+// Box blur `count` lines of `len` pixels each, where `index` gives the position in `pixels` of a
+// pixel in a line, using a running sum of every channel
+fn blur_lines<F: Fn(usize, usize) -> usize>(pixels: &mut [Color], count: usize, len: usize, index: F, radius: usize) {
+    let mut sums = vec![[0u32; 4]; len + 1];
+    for line in 0..count {
+        for i in 0..len {
+            let data = pixels[index(line, i)].data;
+            let previous = sums[i];
+            for (channel, sum) in sums[i + 1].iter_mut().enumerate() {
+                *sum = previous[channel] + ((data >> (channel * 8)) & 0xFF);
+            }
+        }
+
+        for i in 0..len {
+            let start = i.saturating_sub(radius);
+            let end = cmp::min(len, i + radius + 1);
+            let n = (end - start) as u32;
+            let data = sums[end].iter().zip(sums[start]).enumerate().fold(0, |data, (channel, (end, start))| {
+                data | ((end - start) / n) << (channel * 8)
+            });
+            pixels[index(line, i)].data = data;
+        }
+    }
+}
+
 pub struct ImageRef<'a> {
     w: i32,
     h: i32,
@@ -213,45 +270,65 @@ impl Image {
 
     pub fn from_path_scale<P: AsRef<Path>>(path: P, scale: i32) -> Option<Image> {
         match orbimage::Image::from_path(path) {
-            Ok(orb_image) => {
-                let width = orb_image.width();
-                let height = orb_image.height();
-                let data = orb_image.into_data();
-                match scale.cmp(&1) {
-                    Ordering::Equal => Some(Image::from_data(
-                        width as i32, height as i32, data
-                    )),
-                    Ordering::Greater => {
-                        let mut new_data = vec![
-                            Color::rgb(0, 0, 0);
-                            data.len() * (scale * scale) as usize
-                        ].into_boxed_slice();
-
-                        for y in 0..height as i32 {
-                            for x in 0..width as i32 {
-                                let i = y * width as i32 + x;
-                                let value = data[i as usize].data;
-                                for y_s in 0..scale {
-                                    for x_s in 0..scale {
-                                        let new_i = (y * scale + y_s) * width as i32 * scale + x * scale + x_s;
-                                        new_data[new_i as usize].data = value;
-                                    }
-                                }
+            Ok(orb_image) => Image::from_orbimage(orb_image).scale(scale),
+            Err(err) => {
+                error!("Image::from_path_scale: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Decode an image from the bytes of a PNG file, such as a resource embedded with
+    /// `include_bytes!`
+    pub fn from_bytes(data: &[u8]) -> Option<Image> {
+        match orbimage::parse_png(data) {
+            Ok(orb_image) => Some(Image::from_orbimage(orb_image)),
+            Err(err) => {
+                error!("Image::from_bytes: {}", err);
+                None
+            }
+        }
+    }
+
+    fn from_orbimage(orb_image: orbimage::Image) -> Image {
+        let width = orb_image.width();
+        let height = orb_image.height();
+        Image::from_data(width as i32, height as i32, orb_image.into_data())
+    }
+
+    /// Scale the image up by an integer factor, for HiDPI displays
+    pub fn scale(self, scale: i32) -> Option<Image> {
+        let (width, height) = (self.w, self.h);
+        let data = self.data;
+        match scale.cmp(&1) {
+            Ordering::Equal => Some(Image::from_data(
+                width, height, data
+            )),
+            Ordering::Greater => {
+                let mut new_data = vec![
+                    Color::rgb(0, 0, 0);
+                    data.len() * (scale * scale) as usize
+                ].into_boxed_slice();
+
+                for y in 0..height {
+                    for x in 0..width {
+                        let i = y * width + x;
+                        let value = data[i as usize].data;
+                        for y_s in 0..scale {
+                            for x_s in 0..scale {
+                                let new_i = (y * scale + y_s) * width * scale + x * scale + x_s;
+                                new_data[new_i as usize].data = value;
                             }
                         }
-
-                        Some(Image::from_data(
-                            width as i32 * scale, height as i32 * scale, new_data
-                        ))
-                    },
-                    Ordering::Less => {
-                        debug!("Image::from_path_scale: scale {} < 1", scale);
-                        None
                     }
                 }
+
+                Some(Image::from_data(
+                    width * scale, height * scale, new_data
+                ))
             },
-            Err(err) => {
-                error!("Image::from_path_scale: {}", err);
+            Ordering::Less => {
+                debug!("Image::scale: scale {} < 1", scale);
                 None
             }
         }
@@ -381,3 +458,51 @@ impl Renderer for ImageAligned {
         &self.mode
     }
 }
+
+#[cfg(test)]
+mod test {
+    use orbclient::{Color, Renderer};
+    use crate::core::image::Image;
+    use crate::core::rect::Rect;
+
+    const LEFT_PTR: &[u8] = include_bytes!("../../res/left_ptr.png");
+
+    #[test]
+    fn image_from_embedded_png() {
+        let image = Image::from_bytes(LEFT_PTR).unwrap_or(Image::new(0, 0));
+        assert_eq!((image.width(), image.height()), (12, 19));
+        // the opaque black tip of the cursor and the transparent area beside it
+        assert_eq!(image.data()[0].data, 0xFF00_0000);
+        assert_eq!(image.data()[1].data >> 24, 0);
+    }
+
+    #[test]
+    fn image_from_bytes_scaled() {
+        let image = Image::from_bytes(LEFT_PTR).and_then(|image| image.scale(2)).unwrap_or(Image::new(0, 0));
+        assert_eq!((image.width(), image.height()), (24, 38));
+        assert_eq!(image.data()[24 + 1].data, 0xFF00_0000);
+    }
+
+    #[test]
+    fn box_blur_spreads_within_region() {
+        let mut image = Image::from_color(5, 3, Color::rgb(0, 0, 0));
+        // a white pixel in the middle row, and one outside of the blurred region
+        image.data_mut()[5 + 2] = Color::rgb(255, 255, 255);
+        image.data_mut()[4] = Color::rgb(255, 255, 255);
+
+        image.roi(&Rect::new(0, 1, 4, 2)).box_blur(1);
+
+        // averaged over the 3x2 pixels around it that are inside the region
+        assert_eq!(image.data()[5 + 2].data, Color::rgba(42, 42, 42, 255).data);
+        assert_eq!(image.data()[5].data, Color::rgb(0, 0, 0).data);
+        assert_eq!(image.data()[2 * 5 + 3].data, Color::rgba(63, 63, 63, 255).data);
+        // pixels outside the region are untouched
+        assert_eq!(image.data()[4].data, Color::rgb(255, 255, 255).data);
+        assert_eq!(image.data()[2 * 5 + 4].data, Color::rgb(0, 0, 0).data);
+    }
+
+    #[test]
+    fn image_from_invalid_bytes() {
+        assert!(Image::from_bytes(b"not a png").is_none());
+    }
+}
@@ -1,8 +1,9 @@
 use std::{
     cell::RefCell,
+    cmp,
     collections::BTreeMap,
     env,
-    fs::File,
+    fs::{File, OpenOptions},
     io::{self, ErrorKind, Read, Write},
     iter,
     mem,
@@ -11,6 +12,7 @@ use std::{
     rc::Rc,
     slice,
     str,
+    time::{Duration, Instant},
 };
 
 use event::{user_data, EventQueue};
@@ -19,9 +21,10 @@ use libredox::flag;
 use log::{debug, error, info};
 use orbclient::{Color, Event};
 use syscall::{
-    data::Packet,
-    error::EINVAL,
-    flag::{O_CLOEXEC, O_CREAT, O_NONBLOCK, O_RDWR},
+    data::{Packet, TimeSpec},
+    error::{EBADF, EINVAL},
+    flag::{CLOCK_MONOTONIC, EVENT_READ, O_CLOEXEC, O_CREAT, O_NONBLOCK, O_RDWR},
+    number::SYS_FEVENT,
     flag::EventFlags,
     SchemeMut, PAGE_SIZE, KSMSG_MMAP_PREP, KSMSG_MMAP, KSMSG_MSYNC, KSMSG_MUNMAP, MapFlags, ESKMSG, SKMSG_PROVIDE_MMAP,
 };
@@ -40,6 +43,28 @@ const CLIPBOARD_FLAG: usize = 1 << 31;
 #[cfg(target_pointer_width = "64")]
 const CLIPBOARD_FLAG: usize = 1 << 63;
 
+#[cfg(target_pointer_width = "32")]
+const QUERY_FLAG: usize = 1 << 30;
+
+#[cfg(target_pointer_width = "64")]
+const QUERY_FLAG: usize = 1 << 62;
+
+#[cfg(target_pointer_width = "32")]
+const RECORD_FLAG: usize = 1 << 29;
+
+#[cfg(target_pointer_width = "64")]
+const RECORD_FLAG: usize = 1 << 61;
+
+/// Reserved path that opens a recording handle, see [Handler::handle_record_new]
+const RECORD: &str = "record";
+
+/// Reserved paths that open a read-only query handle instead of a window, such as
+/// `orbital:displays`. Anything after the first `/` is passed to [Handler::handle_query].
+const QUERIES: &[&str] = &[
+    "clipboard", "contents", "displays", "flags", "fonts", "frame", "heartbeat", "hittest", "modifiers",
+    "pixel", "scale", "screenshot", "windows",
+];
+
 #[derive(Debug, Fail)]
 pub enum Error {
     #[fail(display = "io error: {}", _0)]
@@ -82,6 +107,206 @@ pub struct Properties<'a> {
     pub title: &'a str
 }
 
+/// The corner of a window that stays in place when it is resized
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl str::FromStr for Anchor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "TL" => Ok(Anchor::TopLeft),
+            "TR" => Ok(Anchor::TopRight),
+            "BL" => Ok(Anchor::BottomLeft),
+            "BR" => Ok(Anchor::BottomRight),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How far a window's buffer is turned clockwise when it is drawn, set with `ROTATE,<degrees>`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    /// Whether the window is on its side, so its width and height on screen are swapped
+    pub fn sideways(self) -> bool {
+        matches!(self, Rotation::Cw90 | Rotation::Cw270)
+    }
+}
+
+impl str::FromStr for Rotation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Rotation::None),
+            "90" => Ok(Rotation::Cw90),
+            "180" => Ok(Rotation::Cw180),
+            "270" => Ok(Rotation::Cw270),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A key combination grabbed by a window with `GRABKEY,<scancode>,<modifiers>`, where the
+/// modifiers are a `+` separated list of `shift`, `ctrl`, `alt` and `super`, or empty for none
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct KeyCombo {
+    pub scancode: u8,
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub super_key: bool,
+}
+
+impl str::FromStr for KeyCombo {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scancode, modifiers) = s.split_once(',').unwrap_or((s, ""));
+        let mut combo = KeyCombo {
+            scancode: scancode.parse().map_err(|_| ())?,
+            ..KeyCombo::default()
+        };
+        for modifier in modifiers.split('+').filter(|modifier| ! modifier.is_empty()) {
+            match modifier {
+                "shift" => combo.shift = true,
+                "ctrl" => combo.ctrl = true,
+                "alt" => combo.alt = true,
+                "super" => combo.super_key = true,
+                _ => return Err(()),
+            }
+        }
+        Ok(combo)
+    }
+}
+
+/// Size constraints a window reports with
+/// `HINTS,<min_w>,<min_h>,<max_w>,<max_h>,<base_w>,<base_h>,<inc_w>,<inc_h>`. A maximum of 0 is
+/// unbounded, and an increment of 0 or 1 allows any size. With an increment the size is
+/// `base + n * increment`, such as the border around a terminal plus a whole number of cells.
+/// An `<aspect_w>,<aspect_h>` can follow, the width to height ratio kept when maximized
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SizeHints {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+    pub base: (i32, i32),
+    pub increment: (i32, i32),
+    /// Width to height ratio the window is maximized with, (0, 0) if it has none
+    pub aspect: (i32, i32),
+}
+
+impl SizeHints {
+    /// The size closest to `width` by `height` that satisfies the hints
+    pub fn constrain(&self, width: i32, height: i32) -> (i32, i32) {
+        (
+            constrain_axis(width, self.min.0, self.max.0, self.base.0, self.increment.0),
+            constrain_axis(height, self.min.1, self.max.1, self.base.1, self.increment.1),
+        )
+    }
+
+    /// The largest rect with the aspect ratio that fits in `area`, centered in it, or all of
+    /// `area` without an aspect ratio
+    pub fn fit_aspect(&self, area: &Rect) -> Rect {
+        let (aspect_w, aspect_h) = (i64::from(self.aspect.0), i64::from(self.aspect.1));
+        if aspect_w <= 0 || aspect_h <= 0 {
+            return *area;
+        }
+
+        let (mut width, mut height) = (i64::from(area.width()), i64::from(area.width()) * aspect_h / aspect_w);
+        if height > i64::from(area.height()) {
+            height = i64::from(area.height());
+            width = height * aspect_w / aspect_h;
+        }
+        let (width, height) = (width as i32, height as i32);
+        Rect::new(
+            area.left() + (area.width() - width) / 2,
+            area.top() + (area.height() - height) / 2,
+            width,
+            height,
+        )
+    }
+}
+
+fn constrain_axis(value: i32, min: i32, max: i32, base: i32, increment: i32) -> i32 {
+    let mut value = cmp::max(value, min);
+    if max > 0 {
+        value = cmp::min(value, max);
+    }
+    if increment > 1 && value > base {
+        value = base.saturating_add(value.saturating_sub(base) / increment * increment);
+        // snapping down went below the minimum, or to nothing, take the next step up if the
+        // maximum allows it
+        if value < cmp::max(min, 1) && (max <= 0 || value.saturating_add(increment) <= max) {
+            value = value.saturating_add(increment);
+        }
+    }
+    cmp::max(value, 1)
+}
+
+impl str::FromStr for SizeHints {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s.split(',').map(|part| part.parse::<i32>().ok().filter(|&value| value >= 0)).collect::<Vec<_>>();
+        let (hints, aspect) = match values[..] {
+            [ref hints @ .., Some(aspect_w), Some(aspect_h)] if hints.len() == 8 => (hints, (aspect_w, aspect_h)),
+            ref hints => (hints, (0, 0)),
+        };
+        match hints[..] {
+            [Some(min_w), Some(min_h), Some(max_w), Some(max_h), Some(base_w), Some(base_h), Some(inc_w), Some(inc_h)] => Ok(SizeHints {
+                min: (min_w, min_h),
+                max: (max_w, max_h),
+                base: (base_w, base_h),
+                increment: (inc_w, inc_h),
+                aspect,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A window's place in the stack relative to another window, given by its id
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StackRelation {
+    Above(usize),
+    Below(usize),
+}
+
+impl StackRelation {
+    /// The id of the window this relation is relative to
+    pub fn target(&self) -> usize {
+        match *self {
+            StackRelation::Above(id) | StackRelation::Below(id) => id,
+        }
+    }
+}
+
+/// What a client asks the window switcher to do with `SWITCHER,open|next|close`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SwitcherCommand {
+    /// Show the list of windows
+    Open,
+    /// Bring the next window in the list to the front, opening the switcher if needed
+    Next,
+    /// Hide the list, keeping the window at the front focused like releasing Super does
+    Close,
+}
+
 pub trait Handler {
     /// Called when the event loop is first ran
     fn handle_startup(&mut self, _orb: &mut Orbital) -> io::Result<()> { Ok(()) }
@@ -98,6 +323,8 @@ pub trait Handler {
     fn handle_scheme_after(&mut self, _orb: &mut Orbital) -> io::Result<()> { Ok(()) }
     /// Called after a batch of display events have been handled
     fn handle_display_after(&mut self, _orb: &mut Orbital) -> io::Result<()> { Ok(()) }
+    /// Called when a timeout set with [Orbital::set_timeout] has passed
+    fn handle_timer(&mut self, _orb: &mut Orbital) -> io::Result<()> { Ok(()) }
     /// Called after a batch of any events have been handled
     fn handle_after(&mut self, _orb: &mut Orbital) -> io::Result<()> { Ok(()) }
 
@@ -117,16 +344,95 @@ pub trait Handler {
     fn handle_window_mouse_cursor(&mut self, orb: &mut Orbital, id: usize, visible: bool) -> syscall::Result<()>;
     /// Called when the window asks to set mouse grabbing
     fn handle_window_mouse_grab(&mut self, orb: &mut Orbital, id: usize, grab: bool) -> syscall::Result<()>;
-    /// Called when the window asks to set mouse relative mode
-    fn handle_window_mouse_relative(&mut self, orb: &mut Orbital, id: usize, relative: bool) -> syscall::Result<()>;
+    /// Called when the window asks to set mouse relative mode, optionally keeping the cursor
+    /// visible while in it
+    fn handle_window_mouse_relative(&mut self, orb: &mut Orbital, id: usize, relative: bool, cursor_visible: bool) -> syscall::Result<()>;
     /// Called when the window asks to be repositioned
     fn handle_window_position(&mut self, orb: &mut Orbital, id: usize, x: Option<i32>, y: Option<i32>) -> syscall::Result<()>;
-    /// Called when the window asks to be resized
-    fn handle_window_resize(&mut self, orb: &mut Orbital, id: usize, w: Option<i32>, h: Option<i32>) -> syscall::Result<()>;
+    /// Called when the window asks to be repositioned relative to the top left of a display,
+    /// by its index in the displays query
+    fn handle_window_position_on_display(&mut self, orb: &mut Orbital, id: usize, display: usize, x: i32, y: i32) -> syscall::Result<()>;
+    /// Called when the window asks to be sized to a percentage of the work area of the display
+    /// under the cursor and centered on it
+    fn handle_window_place(&mut self, orb: &mut Orbital, id: usize, width_percent: i32, height_percent: i32) -> syscall::Result<()>;
+    /// Called when the window asks to be resized, keeping the `anchor` corner in place
+    fn handle_window_resize(&mut self, orb: &mut Orbital, id: usize, w: Option<i32>, h: Option<i32>, anchor: Anchor) -> syscall::Result<()>;
     /// Called when the window wants to set a flag
     fn handle_window_set_flag(&mut self, orb: &mut Orbital, id: usize, flag: char, value: bool) -> syscall::Result<()>;
+    /// Called when the window asks to be sent a frame event the next time it is composited.
+    /// The request is one-shot and has to be repeated for every frame.
+    fn handle_window_frame(&mut self, orb: &mut Orbital, id: usize) -> syscall::Result<()>;
+    /// Called when the window asks to be kept directly above or below another window, or to drop
+    /// that constraint when `relation` is `None`
+    fn handle_window_stack(&mut self, orb: &mut Orbital, id: usize, relation: Option<StackRelation>) -> syscall::Result<()>;
+    /// Called when the window starts (`begin`) or commits a batch of changes to any windows.
+    /// Redraws are deferred until the batch is committed, so that all of its changes show up in a
+    /// single composited frame. Every change in the batch still succeeds or fails on its own, with
+    /// its error returned to the write making it, and committing applies the changes that
+    /// succeeded; there is no rollback.
+    fn handle_window_batch(&mut self, orb: &mut Orbital, id: usize, begin: bool) -> syscall::Result<()>;
+    /// Called when the window asks to receive `combo` even when it isn't focused, instead of the
+    /// focused window.
+    ///
+    /// This lets any client see those key presses, so only combinations that aren't used for
+    /// typing can be grabbed: they have to include Ctrl or Alt, or be a function key. Super
+    /// combinations are kept for the built-in shortcuts, and a combination can only be grabbed
+    /// by one window at a time. Grabs are released when the window closes.
+    fn handle_window_grab_key(&mut self, orb: &mut Orbital, id: usize, combo: KeyCombo) -> syscall::Result<()>;
+    /// Called when the window reports the sizes it can be resized to, which every resize then
+    /// keeps to, whether it is asked for by the window, done by dragging a border or by tiling
+    fn handle_window_size_hints(&mut self, orb: &mut Orbital, id: usize, hints: SizeHints) -> syscall::Result<()>;
+    /// Called when the window asks for its buffer to be drawn rotated. Input sent to the window
+    /// stays in buffer coordinates
+    fn handle_window_rotate(&mut self, orb: &mut Orbital, id: usize, rotation: Rotation) -> syscall::Result<()>;
+    /// Called when the window asks to swap places with the window `other`, each taking the
+    /// position and size of the other
+    fn handle_window_swap(&mut self, orb: &mut Orbital, id: usize, other: usize) -> syscall::Result<()>;
+    /// Called when the window sets the factor its scroll events are multiplied by
+    fn handle_window_scroll_factor(&mut self, orb: &mut Orbital, id: usize, factor: f32) -> syscall::Result<()>;
+    /// Called when the window starts or stops inhibiting the screen from blanking when idle.
+    /// The inhibitor is released when the window closes
+    fn handle_window_inhibit_idle(&mut self, orb: &mut Orbital, id: usize, inhibit: bool) -> syscall::Result<()>;
+    /// Called when the window asks to join the tab group of the window `other`, which is
+    /// created if `other` isn't in one yet. With no `other` the window leaves its tab group
+    fn handle_window_tab(&mut self, orb: &mut Orbital, id: usize, other: Option<usize>) -> syscall::Result<()>;
+    /// Called when the window asks for the whole screen to be composited and synced again, to
+    /// recover from a display showing something else than what was last drawn
+    fn handle_window_redraw_all(&mut self, orb: &mut Orbital, id: usize) -> syscall::Result<()>;
+    /// Called when the window reports where its text cursor is, relative to the window contents,
+    /// or that it has none when `position` is `None`
+    fn handle_window_text_cursor(&mut self, orb: &mut Orbital, id: usize, position: Option<(i32, i32)>) -> syscall::Result<()>;
+    /// Called when the window acknowledges the resize with `serial`, having redrawn at its new
+    /// size
+    fn handle_window_ack_size(&mut self, orb: &mut Orbital, id: usize, serial: u32) -> syscall::Result<()>;
+    /// Called when the window sets the parts of its contents that take mouse input, relative to
+    /// the contents. Clicks on the rest pass through to what is below. No rects take input
+    /// everywhere, as before
+    fn handle_window_input_shape(&mut self, orb: &mut Orbital, id: usize, rects: Vec<Rect>) -> syscall::Result<()>;
+    /// Called when the window asks to be minimized, or restored when `minimized` is false.
+    /// `target` is where the window's button is on a taskbar, as given by the panel, which
+    /// minimizing is animated toward and restoring from. Without it the bottom center of the
+    /// window's display is used
+    fn handle_window_minimize(&mut self, orb: &mut Orbital, id: usize, minimized: bool, target: Option<Rect>) -> syscall::Result<()>;
+    /// Called when the window asks to show the contents of the window `source` in place of its
+    /// own, scaled to fit, or to show its own contents again when `source` is None
+    fn handle_window_mirror(&mut self, orb: &mut Orbital, id: usize, source: Option<usize>) -> syscall::Result<()>;
+    /// Called when the window asks to show `text` as a notification toast, which is dismissed
+    /// after a while
+    fn handle_window_notify(&mut self, orb: &mut Orbital, id: usize, text: String) -> syscall::Result<()>;
+    /// Called when the window asks to drive the window switcher, so that it can be opened by other
+    /// means than Super-Tab
+    fn handle_window_switcher(&mut self, orb: &mut Orbital, id: usize, command: SwitcherCommand) -> syscall::Result<()>;
+    /// Called when the window asks to stop being drawn, without being closed or minimized, or
+    /// to be drawn again when `suspended` is false
+    fn handle_window_suspend(&mut self, orb: &mut Orbital, id: usize, suspended: bool) -> syscall::Result<()>;
+    /// Called when the window asks to be drawn with `opacity`, from 0 for invisible to 255 for
+    /// as it is
+    fn handle_window_opacity(&mut self, orb: &mut Orbital, id: usize, opacity: u8) -> syscall::Result<()>;
     /// Called when the window asks to change title
     fn handle_window_title(&mut self, orb: &mut Orbital, id: usize, title: String) -> syscall::Result<()>;
+    /// Called when the window asks to change the secondary text of its title bar
+    fn handle_window_subtitle(&mut self, orb: &mut Orbital, id: usize, subtitle: String) -> syscall::Result<()>;
     /// Called by fevent to clear notified status, assuming you're sending edge-triggered notifications
     /// TODO: Abstract event system away completely.
     fn handle_window_clear_notified(&mut self, orb: &mut Orbital, id: usize) -> syscall::Result<()>;
@@ -138,7 +444,9 @@ pub trait Handler {
     fn handle_window_properties(&mut self, orb: &mut Orbital, id: usize) -> syscall::Result<Properties>;
     /// Called to flush a window. It's usually a good idea to redraw here.
     fn handle_window_sync(&mut self, orb: &mut Orbital, id: usize) -> syscall::Result<usize>;
-    /// Called when a window should be closed
+    /// Called when the client closes the window's handle, which is the only way a window is
+    /// removed. Closing it from orbital, such as with its close button, just sends it a quit
+    /// event that the client can decline by keeping the handle open
     fn handle_window_close(&mut self, orb: &mut Orbital, id: usize) -> syscall::Result<usize>;
 
     // Create a clipboard from a window
@@ -149,6 +457,52 @@ pub trait Handler {
     fn handle_clipboard_write(&mut self, orb: &mut Orbital, id: usize, buf: &[u8]) -> syscall::Result<usize>;
     // Close the window's clipboard access
     fn handle_clipboard_close(&mut self, orb: &mut Orbital, id: usize) -> syscall::Result<usize>;
+
+    /// Called when one of the reserved [QUERIES] paths is opened, and again to refresh the query
+    /// `handle` when it changed. `args` is the remainder of the path after the query name. Return
+    /// the contents that will be read from the query handle.
+    fn handle_query(&mut self, orb: &mut Orbital, handle: usize, query: &str, args: &str) -> syscall::Result<Vec<u8>>;
+    /// Called once the client has read all of the contents of the query `handle`, which it is
+    /// then up to date with
+    fn handle_query_read(&mut self, orb: &mut Orbital, handle: usize);
+    /// Called when a query handle is closed, to forget what was kept for it
+    fn handle_query_close(&mut self, orb: &mut Orbital, handle: usize);
+
+    /// Called when the reserved [RECORD] path is opened, to start streaming the composited
+    /// screen to a recording client. Returns the id of the recording
+    fn handle_record_new(&mut self, orb: &mut Orbital) -> syscall::Result<usize>;
+    /// Read the updates composited for a recording
+    fn handle_record_read(&mut self, orb: &mut Orbital, id: usize, buf: &mut [u8]) -> syscall::Result<usize>;
+    /// Write a command to a recording, such as a keyframe request
+    fn handle_record_write(&mut self, orb: &mut Orbital, id: usize, buf: &[u8]) -> syscall::Result<usize>;
+    /// Stop a recording
+    fn handle_record_close(&mut self, orb: &mut Orbital, id: usize) -> syscall::Result<usize>;
+}
+
+// Whether the query handle opened at `path` is one of those that `changed`, a query name or a
+// path of it with arguments
+fn query_matches(path: &str, changed: &str) -> bool {
+    path == changed || path.strip_prefix(changed).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// The contents of an open query handle and how much of it has been read
+struct QueryHandle {
+    path: String,
+    pid: usize,
+    data: Vec<u8>,
+    seek: usize,
+}
+
+impl QueryHandle {
+    /// Read on from where the last read stopped. A read past the end is short, and once all of
+    /// the contents were read reads return 0
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let remaining = &self.data[self.seek..];
+        let count = cmp::min(buf.len(), remaining.len());
+        buf[..count].copy_from_slice(&remaining[..count]);
+        self.seek += count;
+        count
+    }
 }
 
 pub struct Orbital {
@@ -159,6 +513,95 @@ pub struct Orbital {
 
     /// Handle to "/scheme/input/consumer" to recieve input events.
     pub input: File,
+
+    /// Handle to the monotonic clock of "/scheme/time", used for timeouts
+    pub timer: File,
+
+    /// Liveness of the event loop, for the heartbeat query
+    pub heartbeat: Heartbeat,
+
+    /// The pid of the client whose request is being handled, to tell which windows are its own
+    pub caller: usize,
+
+    /// Queries whose contents changed, see [Orbital::query_changed]
+    changed_queries: Vec<String>,
+}
+
+// The time `timeout` after `now`
+fn timeout_after(now: TimeSpec, timeout: Duration) -> TimeSpec {
+    let nanos = now.tv_nsec as u64 + timeout.subsec_nanos() as u64;
+    TimeSpec {
+        tv_sec: now.tv_sec + timeout.as_secs() as i64 + (nanos / 1_000_000_000) as i64,
+        tv_nsec: (nanos % 1_000_000_000) as i32,
+    }
+}
+
+/// How often the event loop wakes up to update its [Heartbeat], even when there are no events
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Counts the iterations of the event loop, and when the last one woken up by the heartbeat
+/// timeout completed. Looking at the clock on every iteration would cost a syscall each, so the
+/// time is only taken once [HEARTBEAT_INTERVAL] has passed. While the loop keeps running the
+/// time advances about that often; when it stops advancing, or the query can't be opened at all,
+/// the loop is stuck.
+pub struct Heartbeat {
+    start: Instant,
+    iterations: u64,
+    last: Duration,
+    due: Instant,
+}
+
+impl Heartbeat {
+    pub fn new(start: Instant) -> Self {
+        Heartbeat { start, iterations: 0, last: Duration::ZERO, due: start + HEARTBEAT_INTERVAL }
+    }
+
+    /// Count a completed iteration of the event loop
+    pub fn iteration(&mut self) {
+        self.iterations += 1;
+    }
+
+    /// Record an iteration completed at `now`, if the heartbeat is due. Returns whether it was,
+    /// in which case the next heartbeat is due [HEARTBEAT_INTERVAL] later
+    pub fn beat(&mut self, now: Instant) -> bool {
+        if now < self.due {
+            return false;
+        }
+        self.last = now.saturating_duration_since(self.start);
+        self.due = now + HEARTBEAT_INTERVAL;
+        true
+    }
+
+    /// The heartbeat query: the number of iterations of the event loop and when the last
+    /// heartbeat was, in nanoseconds since Orbital started
+    pub fn query(&self) -> Vec<u8> {
+        format!("{},{}\n", self.iterations, self.last.as_nanos()).into_bytes()
+    }
+}
+
+// Rects given as `x,y,w,h` each, all separated by commas. Nothing at all is no rects
+fn parse_rects(data: &str) -> Option<Vec<Rect>> {
+    if data.is_empty() {
+        return Some(Vec::new());
+    }
+    let values = data.split(',').map(|part| part.parse::<i32>().ok()).collect::<Option<Vec<_>>>()?;
+    if values.len() % 4 != 0 {
+        return None;
+    }
+    values.chunks(4)
+        .map(|rect| (rect[2] >= 0 && rect[3] >= 0).then(|| Rect::new(rect[0], rect[1], rect[2], rect[3])))
+        .collect()
+}
+
+// Where a mapping of `size` bytes at `offset` starts in a window buffer of `buffer_size` bytes.
+// The offset has to be page aligned and the mapping has to stay inside the buffer, so that no
+// memory before or after the window is leaked to the client
+fn map_offset(offset: u64, size: usize, buffer_size: usize) -> Option<usize> {
+    let offset = usize::try_from(offset).ok()?;
+    if offset % PAGE_SIZE != 0 || offset.checked_add(size)? > buffer_size {
+        return None;
+    }
+    Some(offset)
 }
 
 impl Orbital {
@@ -173,13 +616,17 @@ impl Orbital {
         Ok((scheme_name, path))
     }
 
-    fn parse_display_path(path: &str) -> (&str, i32, i32) {
+    /// Parse a display path of the form `vt.screen/width/height[/refresh_hz]`. The refresh rate
+    /// is only present if the display scheme reports it.
+    fn parse_display_path(path: &str) -> (&str, i32, i32, Option<u32>) {
         let mut path_parts = path.split('/');
         let vt_screen = path_parts.next().unwrap_or("");
         let width = path_parts.next().unwrap_or("").parse::<i32>().unwrap_or(0);
         let height = path_parts.next().unwrap_or("").parse::<i32>().unwrap_or(0);
+        let refresh_hz = path_parts.next().and_then(|part| part.parse::<u32>().ok())
+            .filter(|&hz| hz > 0);
 
-        (vt_screen, width, height)
+        (vt_screen, width, height, refresh_hz)
     }
 
     /// Open an orbital display and connect to the scheme
@@ -226,8 +673,8 @@ impl Orbital {
             .map_err(|_| io::Error::new(ErrorKind::Other,
                                         "Could not create Utf8 Url String"))?;
         let (scheme_name, path) = Self::url_parts(&url)?;
-        let (vt_screen, width, height) = Self::parse_display_path(path);
-        let mut displays = vec![Display::new(0, 0, width, height, display)?];
+        let (vt_screen, width, height, refresh_hz) = Self::parse_display_path(path);
+        let mut displays = vec![Display::new(0, 0, width, height, refresh_hz, display)?];
 
         // If display server supports multiple displays in a VT
         if vt_screen.contains('.') {
@@ -253,7 +700,7 @@ impl Orbital {
                                                 "Could not create Utf8 Url String"))?;
 
                 let (_scheme_name, path) = Self::url_parts(&url)?;
-                let (_vt_screen, width, height) = Self::parse_display_path(path);
+                let (_vt_screen, width, height, refresh_hz) = Self::parse_display_path(path);
 
                 let x = if let Some(last) = displays.last() {
                     last.x + last.image.width()
@@ -264,16 +711,29 @@ impl Orbital {
 
                 debug!("Extra display {} at {}, {}, {}, {}", screen_i, x, y, width, height);
 
-                displays.push(Display::new(x, y, width, height, extra_file)?);
+                displays.push(Display::new(x, y, width, height, refresh_hz, extra_file)?);
             }
         }
 
+        let timer = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!("/scheme/time/{}", CLOCK_MONOTONIC))
+            .map_err(|err| {
+                error!("failed to open the monotonic clock: {}", err);
+                err
+            })?;
+
         Ok(Orbital {
             scheme,
             todo: Vec::new(),
             displays,
             maps: BTreeMap::new(),
             input: input_handle,
+            timer,
+            heartbeat: Heartbeat::new(Instant::now()),
+            caller: 0,
+            changed_queries: Vec::new(),
         })
     }
 
@@ -289,6 +749,43 @@ impl Orbital {
         self.displays[0].screen_rect()
     }
 
+    /// Ask for [Handler::handle_timer] to be called once `timeout` has passed. Every timeout set
+    /// is delivered, so the handler has to check what is due when it is called.
+    pub fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        let mut now = TimeSpec::default();
+        self.timer.read_exact(&mut now)?;
+        self.timer.write_all(&timeout_after(now, timeout))
+    }
+
+    /// The time on CLOCK_MONOTONIC, the clock clients read from the time scheme
+    pub fn monotonic_time(&mut self) -> io::Result<Duration> {
+        let mut now = TimeSpec::default();
+        self.timer.read_exact(&mut now)?;
+        Ok(Duration::new(now.tv_sec as u64, now.tv_nsec as u32))
+    }
+
+    /// Mark the contents of the reserved query `query` as changed. Once the events being handled
+    /// are, its open handles are read again from [Handler::handle_query] from the start, and
+    /// their readers are notified with EVENT_READ. With arguments, as in `contents/3`, only the
+    /// handles opened at that path or below it are
+    pub fn query_changed(&mut self, query: &str) {
+        if ! self.changed_queries.iter().any(|changed| changed == query) {
+            self.changed_queries.push(query.to_string());
+        }
+    }
+
+    /// Notify the client of the recording `id` that `len` bytes of updates are ready to be read
+    /// from it, see [Handler::handle_record_new]
+    pub fn record_ready(&mut self, id: usize, len: usize) -> io::Result<()> {
+        self.scheme_write(&Packet {
+            a: SYS_FEVENT,
+            b: id | RECORD_FLAG,
+            c: EVENT_READ.bits(),
+            d: len,
+            ..Packet::default()
+        })
+    }
+
     /// Write a Packet to scheme I/O
     pub fn scheme_write(&mut self, packet: &Packet) -> io::Result<()> {
         self.scheme.write(packet).map(|_| ())
@@ -307,6 +804,7 @@ impl Orbital {
             enum Source {
                 Scheme,
                 Input,
+                Timer,
             }
         }
 
@@ -316,15 +814,23 @@ impl Orbital {
 
         let scheme_fd = self.scheme.as_raw_fd();
         let input_fd = self.input.as_raw_fd();
+        let timer_fd = self.timer.as_raw_fd();
 
         handler.handle_startup(&mut self)?;
 
         let mut me = OrbitalHandler {
             orb: self,
             handler,
+            queries: BTreeMap::new(),
+            next_query_id: 0,
         };
         event_queue.subscribe(scheme_fd as usize, Source::Scheme, event::EventFlags::READ)?;
         event_queue.subscribe(input_fd as usize, Source::Input, event::EventFlags::READ)?;
+        event_queue.subscribe(timer_fd as usize, Source::Timer, event::EventFlags::READ)?;
+
+        if let Err(err) = me.orb.set_timeout(HEARTBEAT_INTERVAL) {
+            error!("failed to set timeout for heartbeat: {}", err);
+        }
 
         'events: for event_res in event_queue.map(|e| e.map(|e| e.user_data)) {
             match event_res? {
@@ -338,6 +844,7 @@ impl Orbital {
                                 for packet in packets.iter_mut() {
                                     let delay = me.handler.should_delay(packet);
 
+                                    me.orb.caller = packet.pid;
                                     me.handle(packet);
 
                                     if delay && packet.a == 0 {
@@ -352,7 +859,7 @@ impl Orbital {
                                 me.handler.handle_after(&mut me.orb)?;
                             },
                             Err(err) => if err.kind() == ErrorKind::WouldBlock {
-                                continue 'events;
+                                break;
                             } else {
                                 return Err(err.into());
                             }
@@ -373,6 +880,7 @@ impl Orbital {
 
                                     let delay = me.handler.should_delay(&packet);
 
+                                    me.orb.caller = packet.pid;
                                     me.handle(&mut packet);
 
                                     if delay && packet.a == 0 {
@@ -390,7 +898,20 @@ impl Orbital {
                     me.handler.handle_display_after(&mut me.orb)?;
                     me.handler.handle_after(&mut me.orb)?;
                 }
+                Source::Timer => {
+                    me.handler.handle_timer(&mut me.orb)?;
+                    me.handler.handle_after(&mut me.orb)?;
+
+                    if me.orb.heartbeat.beat(Instant::now()) {
+                        if let Err(err) = me.orb.set_timeout(HEARTBEAT_INTERVAL) {
+                            error!("failed to set timeout for heartbeat: {}", err);
+                        }
+                    }
+                }
             }
+
+            me.notify_queries()?;
+            me.orb.heartbeat.iteration();
         }
 
         //TODO: Cleanup and handle TODO
@@ -399,10 +920,67 @@ impl Orbital {
 }
 pub struct OrbitalHandler<H: Handler> {
     orb: Orbital,
-    handler: H
+    handler: H,
+    queries: BTreeMap<usize, QueryHandle>,
+    next_query_id: usize,
+}
+impl<H: Handler> OrbitalHandler<H> {
+    fn open_query(&mut self, path: &str, query: &str, args: &str) -> syscall::Result<usize> {
+        let id = self.next_query_id | QUERY_FLAG;
+        let data = self.handler.handle_query(&mut self.orb, id, query, args)?;
+
+        self.next_query_id = (self.next_query_id + 1) & !(QUERY_FLAG | CLIPBOARD_FLAG | RECORD_FLAG);
+        self.queries.insert(id, QueryHandle {
+            path: path.to_string(),
+            pid: self.orb.caller,
+            data,
+            seek: 0,
+        });
+
+        Ok(id)
+    }
+
+    // Refresh the open handles of the queries that changed, so that they are read from the start
+    // again, and notify their readers
+    fn notify_queries(&mut self) -> io::Result<()> {
+        let changed = mem::take(&mut self.orb.changed_queries);
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        for (&id, query) in self.queries.iter_mut() {
+            if ! changed.iter().any(|changed| query_matches(&query.path, changed)) {
+                continue;
+            }
+            let (name, args) = query.path.split_once('/').unwrap_or((&query.path, ""));
+            // refreshed as the client that opened it
+            self.orb.caller = query.pid;
+            let Ok(data) = self.handler.handle_query(&mut self.orb, id, name, args) else {
+                continue;
+            };
+            query.data = data;
+            query.seek = 0;
+            self.orb.scheme_write(&Packet {
+                a: SYS_FEVENT,
+                b: id,
+                c: EVENT_READ.bits(),
+                d: query.data.len(),
+                ..Packet::default()
+            })?;
+        }
+        Ok(())
+    }
 }
 impl<H: Handler> SchemeMut for OrbitalHandler<H> {
     fn open(&mut self, path: &str, _: usize, _: u32, _: u32) -> syscall::Result<usize> {
+        let (query, args) = path.split_once('/').unwrap_or((path, ""));
+        if QUERIES.contains(&query) {
+            return self.open_query(path, query, args);
+        }
+        if path == RECORD {
+            return self.handler.handle_record_new(&mut self.orb).map(|id| id | RECORD_FLAG);
+        }
+
         let mut parts = path.split('/');
 
         let flags = parts.next().unwrap_or("");
@@ -434,6 +1012,19 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
             return self.handler.handle_clipboard_read(&mut self.orb, id & !CLIPBOARD_FLAG, buf);
         }
 
+        if id & RECORD_FLAG == RECORD_FLAG {
+            return self.handler.handle_record_read(&mut self.orb, id & !RECORD_FLAG, buf);
+        }
+
+        if id & QUERY_FLAG == QUERY_FLAG {
+            let query = self.queries.get_mut(&id).ok_or(syscall::Error::new(EBADF))?;
+            let count = query.read(buf);
+            if count > 0 && query.seek == query.data.len() {
+                self.handler.handle_query_read(&mut self.orb, id);
+            }
+            return Ok(count);
+        }
+
         let slice: &mut [Event] = unsafe {
             slice::from_raw_parts_mut(
                 buf.as_mut_ptr() as *mut Event,
@@ -449,6 +1040,15 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
             return self.handler.handle_clipboard_write(&mut self.orb, id & !CLIPBOARD_FLAG, buf);
         }
 
+        if id & RECORD_FLAG == RECORD_FLAG {
+            return self.handler.handle_record_write(&mut self.orb, id & !RECORD_FLAG, buf);
+        }
+
+        // Query handles are read-only
+        if id & QUERY_FLAG == QUERY_FLAG {
+            return Err(syscall::Error::new(EBADF));
+        }
+
         if let Ok(msg) = str::from_utf8(buf) {
             let (kind, data) = {
                 let mut parts = msg.splitn(2, ',');
@@ -457,6 +1057,26 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
                 (kind, data)
             };
             match kind {
+                "ACKSIZE" => {
+                    let serial = data.parse::<u32>().map_err(|_| syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_ack_size(&mut self.orb, id, serial)?;
+
+                    Ok(buf.len())
+                },
+                "ABOVE" | "BELOW" => {
+                    let relation = match data {
+                        "" => None,
+                        _ => {
+                            let other = data.parse::<usize>().map_err(|_| syscall::Error::new(EINVAL))?;
+                            Some(if kind == "ABOVE" { StackRelation::Above(other) } else { StackRelation::Below(other) })
+                        }
+                    };
+
+                    self.handler.handle_window_stack(&mut self.orb, id, relation)?;
+
+                    Ok(buf.len())
+                },
                 "A" => match data {
                     "0" => {
                         self.handler.handle_window_async(&mut self.orb, id, false)?;
@@ -468,6 +1088,43 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
                     },
                     _ => Err(syscall::Error::new(EINVAL)),
                 },
+                "m" => match data {
+                    "0" => {
+                        self.handler.handle_window_minimize(&mut self.orb, id, false, None)?;
+                        Ok(buf.len())
+                    },
+                    "1" => {
+                        self.handler.handle_window_minimize(&mut self.orb, id, true, None)?;
+                        Ok(buf.len())
+                    },
+                    _ => Err(syscall::Error::new(EINVAL)),
+                },
+                "BATCH" => match data {
+                    "0" => {
+                        self.handler.handle_window_batch(&mut self.orb, id, false)?;
+                        Ok(buf.len())
+                    },
+                    "1" => {
+                        self.handler.handle_window_batch(&mut self.orb, id, true)?;
+                        Ok(buf.len())
+                    },
+                    _ => Err(syscall::Error::new(EINVAL)),
+                },
+                "CURSORPOS" => {
+                    let position = match data.split_once(',') {
+                        None if data.is_empty() => None,
+                        Some((x, y)) => {
+                            let x = x.parse::<i32>().map_err(|_| syscall::Error::new(EINVAL))?;
+                            let y = y.parse::<i32>().map_err(|_| syscall::Error::new(EINVAL))?;
+                            Some((x, y))
+                        },
+                        None => return Err(syscall::Error::new(EINVAL)),
+                    };
+
+                    self.handler.handle_window_text_cursor(&mut self.orb, id, position)?;
+
+                    Ok(buf.len())
+                },
                 "D" => match data {
                     "" => {
                         self.handler.handle_window_drag(&mut self.orb, id)?;
@@ -494,6 +1151,43 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
                     }
                     Ok(buf.len())
                 }
+                "FRAME" => {
+                    self.handler.handle_window_frame(&mut self.orb, id)?;
+
+                    Ok(buf.len())
+                },
+                "GRABKEY" => {
+                    let combo = data.parse::<KeyCombo>().map_err(|_| syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_grab_key(&mut self.orb, id, combo)?;
+
+                    Ok(buf.len())
+                },
+                "HINTS" => {
+                    let hints = data.parse::<SizeHints>().map_err(|_| syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_size_hints(&mut self.orb, id, hints)?;
+
+                    Ok(buf.len())
+                },
+                "INHIBIT" => {
+                    let inhibit = match data {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(syscall::Error::new(EINVAL)),
+                    };
+
+                    self.handler.handle_window_inhibit_idle(&mut self.orb, id, inhibit)?;
+
+                    Ok(buf.len())
+                },
+                "INPUT" => {
+                    let rects = parse_rects(data).ok_or(syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_input_shape(&mut self.orb, id, rects)?;
+
+                    Ok(buf.len())
+                },
                 "M" => match data {
                     "C,0" => {
                         self.handler.handle_window_mouse_cursor(&mut self.orb, id, false)?;
@@ -512,15 +1206,58 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
                         Ok(buf.len())
                     },
                     "R,0" => {
-                        self.handler.handle_window_mouse_relative(&mut self.orb, id, false)?;
+                        self.handler.handle_window_mouse_relative(&mut self.orb, id, false, false)?;
                         Ok(buf.len())
                     },
                     "R,1" => {
-                        self.handler.handle_window_mouse_relative(&mut self.orb, id, true)?;
+                        self.handler.handle_window_mouse_relative(&mut self.orb, id, true, false)?;
+                        Ok(buf.len())
+                    },
+                    "R,1,visible" => {
+                        self.handler.handle_window_mouse_relative(&mut self.orb, id, true, true)?;
                         Ok(buf.len())
                     },
                     _ => Err(syscall::Error::new(EINVAL)),
                 },
+                "MIN" => {
+                    let mut parts = data.split(',');
+                    let minimized = match parts.next().unwrap_or("") {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(syscall::Error::new(EINVAL)),
+                    };
+                    let target = match parts.map(|part| part.parse::<i32>().ok()).collect::<Vec<_>>()[..] {
+                        [] => None,
+                        [Some(x), Some(y), Some(w), Some(h)] if w >= 0 && h >= 0 => Some(Rect::new(x, y, w, h)),
+                        _ => return Err(syscall::Error::new(EINVAL)),
+                    };
+
+                    self.handler.handle_window_minimize(&mut self.orb, id, minimized, target)?;
+
+                    Ok(buf.len())
+                },
+                "MIRROR" => {
+                    let source = match data {
+                        "" => None,
+                        source => Some(source.parse::<usize>().map_err(|_| syscall::Error::new(EINVAL))?),
+                    };
+
+                    self.handler.handle_window_mirror(&mut self.orb, id, source)?;
+
+                    Ok(buf.len())
+                },
+                "O" => {
+                    let opacity = data.parse::<u8>().map_err(|_| syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_opacity(&mut self.orb, id, opacity)?;
+
+                    Ok(buf.len())
+                },
+                "NOTIFY" => {
+                    self.handler.handle_window_notify(&mut self.orb, id, data.to_string())?;
+
+                    Ok(buf.len())
+                },
                 "P" => {
                     let mut parts = data.split(',');
                     let x = parts.next().unwrap_or("").parse::<i32>().ok();
@@ -530,12 +1267,90 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
 
                     Ok(buf.len())
                 },
+                "PD" => {
+                    let mut parts = data.split(',');
+                    let (Some(Ok(display)), Some(Ok(x)), Some(Ok(y)), None) = (
+                        parts.next().map(|part| part.parse::<usize>()),
+                        parts.next().map(|part| part.parse::<i32>()),
+                        parts.next().map(|part| part.parse::<i32>()),
+                        parts.next(),
+                    ) else {
+                        return Err(syscall::Error::new(EINVAL));
+                    };
+
+                    self.handler.handle_window_position_on_display(&mut self.orb, id, display, x, y)?;
+
+                    Ok(buf.len())
+                },
+                "PLACE" => {
+                    let mut parts = data.split(',').map(|part| part.parse::<i32>());
+                    let (Some(Ok(width_percent)), Some(Ok(height_percent)), None) = (parts.next(), parts.next(), parts.next()) else {
+                        return Err(syscall::Error::new(EINVAL));
+                    };
+
+                    self.handler.handle_window_place(&mut self.orb, id, width_percent, height_percent)?;
+
+                    Ok(buf.len())
+                },
+                "REDRAW" => {
+                    self.handler.handle_window_redraw_all(&mut self.orb, id)?;
+
+                    Ok(buf.len())
+                },
+                "ROTATE" => {
+                    let rotation = data.parse::<Rotation>().map_err(|_| syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_rotate(&mut self.orb, id, rotation)?;
+
+                    Ok(buf.len())
+                },
                 "S" => {
                     let mut parts = data.split(',');
                     let w = parts.next().unwrap_or("").parse::<i32>().ok();
                     let h = parts.next().unwrap_or("").parse::<i32>().ok();
+                    let anchor = parts.next().unwrap_or("").parse::<Anchor>()
+                        .map_err(|_| syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_resize(&mut self.orb, id, w, h, anchor)?;
+
+                    Ok(buf.len())
+                },
+                "SCROLL" => {
+                    let factor = data.parse::<f32>().ok()
+                        .filter(|factor| factor.is_finite() && *factor >= 0.0)
+                        .ok_or(syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_scroll_factor(&mut self.orb, id, factor)?;
+
+                    Ok(buf.len())
+                },
+                "SUSPEND" => {
+                    let suspended = match data {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(syscall::Error::new(EINVAL)),
+                    };
 
-                    self.handler.handle_window_resize(&mut self.orb, id, w, h)?;
+                    self.handler.handle_window_suspend(&mut self.orb, id, suspended)?;
+
+                    Ok(buf.len())
+                },
+                "SWAP" => {
+                    let other = data.parse::<usize>().map_err(|_| syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_swap(&mut self.orb, id, other)?;
+
+                    Ok(buf.len())
+                },
+                "SWITCHER" => {
+                    let command = match data {
+                        "open" => SwitcherCommand::Open,
+                        "next" => SwitcherCommand::Next,
+                        "close" => SwitcherCommand::Close,
+                        _ => return Err(syscall::Error::new(EINVAL)),
+                    };
+
+                    self.handler.handle_window_switcher(&mut self.orb, id, command)?;
 
                     Ok(buf.len())
                 },
@@ -544,6 +1359,19 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
 
                     Ok(buf.len())
                 },
+                "T2" => {
+                    self.handler.handle_window_subtitle(&mut self.orb, id, data.to_string())?;
+
+                    Ok(buf.len())
+                },
+                "TAB" => {
+                    // window ids start at 1, so 0 is for leaving the group
+                    let other = data.parse::<usize>().map_err(|_| syscall::Error::new(EINVAL))?;
+
+                    self.handler.handle_window_tab(&mut self.orb, id, Some(other).filter(|&other| other != 0))?;
+
+                    Ok(buf.len())
+                },
                 _ => Err(syscall::Error::new(EINVAL))
             }
         } else {
@@ -551,6 +1379,10 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
         }
     }
     fn fevent(&mut self, id: usize, _flags: EventFlags) -> syscall::Result<EventFlags> {
+        if id & (QUERY_FLAG | RECORD_FLAG) != 0 {
+            return Ok(EventFlags::empty());
+        }
+
         self.handler
             .handle_window_clear_notified(&mut self.orb, id)
             .and(Ok(EventFlags::empty()))
@@ -588,6 +1420,18 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
     }
     */
     fn fpath(&mut self, id: usize, mut buf: &mut [u8]) -> syscall::Result<usize> {
+        if id & QUERY_FLAG == QUERY_FLAG {
+            let query = self.queries.get(&id).ok_or(syscall::Error::new(EBADF))?;
+            let original_len = buf.len();
+            let _ = write!(buf, "orbital:{}", query.path);
+            return Ok(original_len - buf.len());
+        }
+        if id & RECORD_FLAG == RECORD_FLAG {
+            let original_len = buf.len();
+            let _ = write!(buf, "orbital:{}", RECORD);
+            return Ok(original_len - buf.len());
+        }
+
         let props = self.handler.handle_window_properties(&mut self.orb, id)?;
         let original_len = buf.len();
         #[allow(clippy::write_literal)] // TODO: Z order
@@ -598,6 +1442,10 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
         Ok(original_len - buf.len())
     }
     fn fsync(&mut self, id: usize) -> syscall::Result<usize> {
+        if id & (QUERY_FLAG | RECORD_FLAG) != 0 {
+            return Ok(0);
+        }
+
         self.handler.handle_window_sync(&mut self.orb, id)
     }
     fn close(&mut self, id: usize) -> syscall::Result<usize> {
@@ -606,17 +1454,33 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
             return self.handler.handle_clipboard_close(&mut self.orb, id & !CLIPBOARD_FLAG);
         }
 
+        if id & RECORD_FLAG == RECORD_FLAG {
+            return self.handler.handle_record_close(&mut self.orb, id & !RECORD_FLAG);
+        }
+
+        if id & QUERY_FLAG == QUERY_FLAG {
+            self.queries.remove(&id).ok_or(syscall::Error::new(EBADF))?;
+            self.handler.handle_query_close(&mut self.orb, id);
+            return Ok(0);
+        }
+
         self.handler.handle_window_close(&mut self.orb, id)
     }
     fn mmap_prep(&mut self, id: usize, offset: u64, size: usize, flags: syscall::MapFlags) -> syscall::Result<usize> {
-        //TODO: handle offset, size, flags?
+        //TODO: handle flags?
         let data = self.handler.handle_window_map(&mut self.orb, id, true)?;
+        let address = data.as_mut_ptr() as usize;
+        let buffer_size = data.len() * core::mem::size_of::<Color>();
 
-        if size > data.len() * core::mem::size_of::<Color>() {
-            return Err(syscall::Error::new(EINVAL));
+        match map_offset(offset, size, buffer_size) {
+            Some(offset) => Ok(address + offset),
+            None => {
+                // The mapping counted by handle_window_map is never made, so munmap won't be
+                // called for it
+                self.handler.handle_window_unmap(&mut self.orb, id)?;
+                Err(syscall::Error::new(EINVAL))
+            }
         }
-
-        Ok(data.as_mut_ptr() as usize)
     }
     fn munmap(&mut self, id: usize, offset: u64, size: usize, flags: syscall::MunmapFlags) -> syscall::Result<usize> {
         //TODO: handle offset, size, flags?
@@ -628,7 +1492,13 @@ impl<H: Handler> SchemeMut for OrbitalHandler<H> {
 
 #[cfg(test)]
 mod test {
-    use crate::core::Orbital;
+    use std::time::{Duration, Instant};
+    use syscall::data::TimeSpec;
+    use syscall::PAGE_SIZE;
+    use crate::core::{
+        Heartbeat, HEARTBEAT_INTERVAL, KeyCombo, map_offset, Orbital, parse_rects, query_matches, QueryHandle, Rotation, SizeHints, timeout_after,
+    };
+    use crate::core::rect::Rect;
 
     #[test]
     fn invalid_url_no_colon() {
@@ -659,6 +1529,17 @@ mod test {
         }
     }
 
+    #[test]
+    fn display_path_without_refresh_rate() {
+        assert_eq!(Orbital::parse_display_path("1.0/1280/800"), ("1.0", 1280, 800, None));
+    }
+
+    #[test]
+    fn display_path_with_refresh_rate() {
+        assert_eq!(Orbital::parse_display_path("1.0/1280/800/75"), ("1.0", 1280, 800, Some(75)));
+        assert_eq!(Orbital::parse_display_path("1.0/1280/800/0"), ("1.0", 1280, 800, None));
+    }
+
     #[test]
     fn valid_url() {
         match Orbital::url_parts("scheme:path") {
@@ -669,4 +1550,158 @@ mod test {
             _ => panic!("Could not parse url")
         }
     }
+
+    #[test]
+    fn parse_key_combo() {
+        assert_eq!("25,ctrl+shift".parse::<KeyCombo>(), Ok(KeyCombo { scancode: 25, shift: true, ctrl: true, ..KeyCombo::default() }));
+        assert_eq!("59".parse::<KeyCombo>(), Ok(KeyCombo { scancode: 59, ..KeyCombo::default() }));
+        assert_eq!("59,".parse::<KeyCombo>(), Ok(KeyCombo { scancode: 59, ..KeyCombo::default() }));
+        assert_eq!("16,alt+super".parse::<KeyCombo>(), Ok(KeyCombo { scancode: 16, alt: true, super_key: true, ..KeyCombo::default() }));
+        assert!("16,hyper".parse::<KeyCombo>().is_err());
+        assert!("q,ctrl".parse::<KeyCombo>().is_err());
+        assert!("256,ctrl".parse::<KeyCombo>().is_err());
+    }
+
+    #[test]
+    fn timeout_after_carries_nanoseconds() {
+        let now = TimeSpec { tv_sec: 10, tv_nsec: 900_000_000 };
+        let time = timeout_after(now, Duration::from_millis(250));
+        assert_eq!((time.tv_sec, time.tv_nsec), (11, 150_000_000));
+
+        let time = timeout_after(now, Duration::from_secs(4));
+        assert_eq!((time.tv_sec, time.tv_nsec), (14, 900_000_000));
+    }
+
+    #[test]
+    fn size_hints_snap_to_increments() {
+        let hints = SizeHints { increment: (8, 16), ..SizeHints::default() };
+        assert_eq!(hints.constrain(100, 100), (96, 96));
+        assert_eq!(hints.constrain(104, 111), (104, 96));
+        // never snapped to nothing
+        assert_eq!(hints.constrain(5, 5), (8, 16));
+
+        // the increments are counted from the base size
+        let terminal = SizeHints { base: (4, 4), increment: (8, 16), ..SizeHints::default() };
+        assert_eq!(terminal.constrain(100, 110), (100, 100));
+    }
+
+    #[test]
+    fn size_hints_clamp_to_min_and_max() {
+        let hints = SizeHints { min: (20, 20), max: (100, 0), base: (0, 0), increment: (8, 1), aspect: (0, 0) };
+        assert_eq!(hints.constrain(10, 10), (24, 20));
+        assert_eq!(hints.constrain(500, 500), (96, 500));
+        assert_eq!(SizeHints::default().constrain(0, -3), (1, 1));
+    }
+
+    #[test]
+    fn size_hints_with_huge_values_do_not_overflow() {
+        let hints = SizeHints { increment: (i32::MAX, i32::MAX), ..SizeHints::default() };
+        assert_eq!(hints.constrain(100, i32::MAX), (i32::MAX, i32::MAX));
+
+        let hints = SizeHints { min: (i32::MAX, 0), base: (-10, i32::MIN), increment: (i32::MAX, 3), ..SizeHints::default() };
+        assert_eq!(hints.constrain(100, 100), (i32::MAX, 1));
+    }
+
+    #[test]
+    fn parse_size_hints() {
+        assert_eq!("1,2,3,4,5,6,7,8".parse::<SizeHints>(), Ok(SizeHints {
+            min: (1, 2),
+            max: (3, 4),
+            base: (5, 6),
+            increment: (7, 8),
+            aspect: (0, 0),
+        }));
+        assert_eq!("1,2,3,4,5,6,7,8,16,9".parse::<SizeHints>().map(|hints| hints.aspect), Ok((16, 9)));
+        assert!("1,2,3,4,5,6,7".parse::<SizeHints>().is_err());
+        assert!("1,2,3,4,5,6,7,8,9".parse::<SizeHints>().is_err());
+        assert!("1,2,3,4,5,6,7,8,9,10,11".parse::<SizeHints>().is_err());
+        assert!("1,2,3,4,5,6,7,-8".parse::<SizeHints>().is_err());
+    }
+
+    #[test]
+    fn parse_input_rects() {
+        assert_eq!(parse_rects(""), Some(Vec::new()));
+        assert_eq!(parse_rects("8,8,84,84"), Some(vec![Rect::new(8, 8, 84, 84)]));
+        assert_eq!(parse_rects("0,10,100,80,10,0,80,100"), Some(vec![Rect::new(0, 10, 100, 80), Rect::new(10, 0, 80, 100)]));
+        assert_eq!(parse_rects("8,8,84"), None);
+        assert_eq!(parse_rects("8,8,-84,84"), None);
+        assert_eq!(parse_rects("8,8,84,x"), None);
+    }
+
+    #[test]
+    fn map_offset_stays_in_buffer() {
+        let buffer_size = 4 * PAGE_SIZE;
+        assert_eq!(map_offset(0, buffer_size, buffer_size), Some(0));
+        assert_eq!(map_offset(PAGE_SIZE as u64, PAGE_SIZE, buffer_size), Some(PAGE_SIZE));
+        assert_eq!(map_offset(3 * PAGE_SIZE as u64, PAGE_SIZE, buffer_size), Some(3 * PAGE_SIZE));
+    }
+
+    #[test]
+    fn map_offset_rejects_bad_requests() {
+        let buffer_size = 4 * PAGE_SIZE;
+        // oversized, from the start or past the end at a valid offset
+        assert_eq!(map_offset(0, buffer_size + 1, buffer_size), None);
+        assert_eq!(map_offset(2 * PAGE_SIZE as u64, 3 * PAGE_SIZE, buffer_size), None);
+        // unaligned or out of range offsets
+        assert_eq!(map_offset(100, PAGE_SIZE, buffer_size), None);
+        assert_eq!(map_offset(8 * PAGE_SIZE as u64, 0, buffer_size), None);
+        assert_eq!(map_offset(u64::MAX - PAGE_SIZE as u64 + 1, PAGE_SIZE, buffer_size), None);
+        assert_eq!(map_offset(PAGE_SIZE as u64, usize::MAX, buffer_size), None);
+    }
+
+    #[test]
+    fn parse_rotation() {
+        assert_eq!("0".parse::<Rotation>(), Ok(Rotation::None));
+        assert_eq!("270".parse::<Rotation>(), Ok(Rotation::Cw270));
+        assert!("45".parse::<Rotation>().is_err());
+        assert!("-90".parse::<Rotation>().is_err());
+        assert!(Rotation::Cw90.sideways() && ! Rotation::Cw180.sideways());
+    }
+
+    #[test]
+    fn heartbeat_only_takes_the_time_once_due() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new(start);
+        heartbeat.iteration();
+        heartbeat.iteration();
+        assert_eq!(heartbeat.query(), b"2,0\n");
+
+        // too early
+        assert!(!heartbeat.beat(start + HEARTBEAT_INTERVAL / 2));
+        assert_eq!(heartbeat.query(), b"2,0\n");
+
+        let now = start + HEARTBEAT_INTERVAL + Duration::from_millis(5);
+        assert!(heartbeat.beat(now));
+        heartbeat.iteration();
+        assert_eq!(heartbeat.query(), format!("3,{}\n", 1_005_000_000).into_bytes());
+
+        // the next one is due an interval after the last
+        assert!(!heartbeat.beat(start + HEARTBEAT_INTERVAL * 2));
+        assert!(heartbeat.beat(now + HEARTBEAT_INTERVAL));
+    }
+
+    #[test]
+    fn changed_queries_match_their_paths() {
+        assert!(query_matches("contents/3/dirty", "contents"));
+        assert!(query_matches("contents/3/dirty", "contents/3"));
+        assert!(query_matches("contents/3", "contents/3"));
+        assert!(!query_matches("contents/31", "contents/3"));
+        assert!(!query_matches("contents/4", "contents/3"));
+        assert!(!query_matches("contentsx", "contents"));
+    }
+
+    #[test]
+    fn query_reads_are_short_at_the_end_then_empty() {
+        let mut query = QueryHandle { path: "screenshot".to_string(), pid: 0, data: (0..10).collect(), seek: 0 };
+        let mut buf = [0; 4];
+        assert_eq!(query.read(&mut buf), 4);
+        assert_eq!(buf, [0, 1, 2, 3]);
+        assert_eq!(query.read(&mut buf), 4);
+        assert_eq!(buf, [4, 5, 6, 7]);
+        // only what is left
+        assert_eq!(query.read(&mut buf), 2);
+        assert_eq!(&buf[..2], [8, 9]);
+        assert_eq!(query.read(&mut buf), 0);
+        assert_eq!(query.read(&mut buf), 0);
+    }
 }
@@ -1,6 +1,6 @@
 use std::cmp::{min, max};
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub struct Rect {
     x: i32,
     y: i32,
@@ -44,7 +44,16 @@ impl Rect {
         self.h
     }
 
+    /// The smallest rect containing both rects. Empty rects have no area to contain, so the
+    /// other rect is returned as is
     pub fn container(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+
         let left = min(self.left(), other.left());
         let right = max(self.right(), other.right());
         let top = min(self.top(), other.top());
@@ -56,11 +65,13 @@ impl Rect {
         Rect::new(left, top, right - left, bottom - top)
     }
 
+    /// Whether the pixel at (x, y) is inside the rect. The right and bottom edges are not part of
+    /// the rect, so an empty rect contains nothing
     pub fn contains(&self, x: i32, y: i32) -> bool {
         self.left() <= x
-        && self.right() >= x
+        && self.right() > x
         && self.top() <= y
-        && self.bottom() >= y
+        && self.bottom() > y
     }
 
     pub fn is_empty(&self) -> bool {
@@ -80,3 +91,100 @@ impl Rect {
         Rect::new(self.x + x, self.y + y, self.w, self.h)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::core::rect::Rect;
+
+    fn edges(rect: Rect) -> (i32, i32, i32, i32) {
+        (rect.left(), rect.top(), rect.right(), rect.bottom())
+    }
+
+    #[test]
+    fn edges_and_area() {
+        let rect = Rect::new(-10, 5, 20, 30);
+        assert_eq!(edges(rect), (-10, 5, 10, 35));
+        assert_eq!((rect.width(), rect.height(), rect.area()), (20, 30, 600));
+        assert_eq!(edges(rect.offset(10, -5)), (0, 0, 20, 30));
+        assert!(!rect.is_empty());
+        assert!(Rect::new(3, 4, 0, 10).is_empty());
+        assert!(Rect::new(3, 4, 10, 0).is_empty());
+        assert!(Rect::default().is_empty());
+    }
+
+    #[test]
+    fn contains_excludes_right_and_bottom_edges() {
+        let rect = Rect::new(10, 20, 5, 5);
+        assert!(rect.contains(10, 20));
+        assert!(rect.contains(14, 24));
+        assert!(!rect.contains(15, 24));
+        assert!(!rect.contains(14, 25));
+        assert!(!rect.contains(9, 20));
+        assert!(!rect.contains(10, 19));
+
+        // adjacent rects don't both contain the pixels along their shared edge
+        let right = Rect::new(15, 20, 5, 5);
+        assert!(right.contains(15, 22) && !rect.contains(15, 22));
+
+        let negative = Rect::new(-5, -5, 5, 5);
+        assert!(negative.contains(-5, -5) && negative.contains(-1, -1));
+        assert!(!negative.contains(0, 0));
+    }
+
+    #[test]
+    fn empty_rect_contains_nothing() {
+        assert!(!Rect::new(10, 20, 0, 0).contains(10, 20));
+        assert!(!Rect::new(-1, -1, 0, 0).contains(-1, -1));
+        assert!(!Rect::new(10, 20, 0, 5).contains(10, 22));
+    }
+
+    #[test]
+    fn intersection() {
+        let rect = Rect::new(0, 0, 10, 10);
+
+        // overlapping
+        assert_eq!(edges(rect.intersection(&Rect::new(5, -5, 10, 10))), (5, 0, 10, 5));
+        // nested, in both directions
+        let inner = Rect::new(2, 3, 4, 5);
+        assert_eq!(edges(rect.intersection(&inner)), edges(inner));
+        assert_eq!(edges(inner.intersection(&rect)), edges(inner));
+        // touching along an edge or at a corner
+        assert!(rect.intersection(&Rect::new(10, 0, 10, 10)).is_empty());
+        assert!(rect.intersection(&Rect::new(0, 10, 10, 10)).is_empty());
+        assert!(rect.intersection(&Rect::new(10, 10, 5, 5)).is_empty());
+        assert!(rect.intersection(&Rect::new(-5, -5, 5, 5)).is_empty());
+        // disjoint
+        assert!(rect.intersection(&Rect::new(20, 20, 5, 5)).is_empty());
+        assert!(rect.intersection(&Rect::new(-20, 3, 5, 5)).is_empty());
+        // negative origins
+        assert_eq!(edges(Rect::new(-10, -10, 15, 15).intersection(&rect)), (0, 0, 5, 5));
+        // with an empty rect
+        assert!(rect.intersection(&Rect::new(5, 5, 0, 0)).is_empty());
+        assert!(Rect::new(5, 5, 0, 0).intersection(&rect).is_empty());
+    }
+
+    #[test]
+    fn intersection_is_never_negative() {
+        let intersection = Rect::new(0, 0, 10, 10).intersection(&Rect::new(100, -100, 10, 10));
+        assert_eq!((intersection.width(), intersection.height(), intersection.area()), (0, 0, 0));
+    }
+
+    #[test]
+    fn container() {
+        let rect = Rect::new(0, 0, 10, 10);
+        assert_eq!(edges(rect.container(&Rect::new(20, -5, 5, 5))), (0, -5, 25, 10));
+        assert_eq!(edges(rect.container(&Rect::new(2, 2, 3, 3))), edges(rect));
+        assert_eq!(edges(Rect::new(-10, -10, 5, 5).container(&rect)), (-10, -10, 10, 10));
+        // touching rects
+        assert_eq!(edges(rect.container(&Rect::new(10, 0, 10, 10))), (0, 0, 20, 10));
+    }
+
+    #[test]
+    fn container_ignores_empty_rects() {
+        let rect = Rect::new(10, 10, 10, 10);
+        let sentinel = Rect::new(-1, -1, 0, 0);
+        assert_eq!(edges(rect.container(&sentinel)), edges(rect));
+        assert_eq!(edges(sentinel.container(&rect)), edges(rect));
+        assert!(sentinel.container(&Rect::default()).is_empty());
+    }
+}
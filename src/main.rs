@@ -76,7 +76,11 @@ fn orbital(daemon: Daemon) -> Result<(), String> {
     daemon.ready().unwrap();
 
     debug!("found display {}x{}", orbital.image().width(), orbital.image().height());
-    let config = Rc::new(Config::from_path("/ui/orbital.toml"));
+    // The system config can be overridden per key by the user's config
+    let user_config = env::var("HOME")
+        .map(|home| format!("{}/.config/orbital.toml", home))
+        .unwrap_or_default();
+    let config = Rc::new(Config::from_paths(&["/ui/orbital.toml", &user_config]));
     let scheme = OrbitalScheme::new(
         &orbital.displays,
         config,
@@ -2,36 +2,45 @@ use std::{
     cmp,
     collections::{
         BTreeMap,
+        BTreeSet,
         VecDeque
     },
     fs,
     io::{self, Write},
     mem,
+    path::Path,
     slice,
     str
 };
 use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 use orbclient::{self, ButtonEvent, ClipboardEvent, Color, Event, EventOption, FocusEvent, HoverEvent,
-                KeyEvent, MouseEvent, MouseRelativeEvent, MoveEvent, QuitEvent, Renderer, ResizeEvent,
-                ScreenEvent, TextInputEvent};
+                KeyEvent, MouseEvent, MouseRelativeEvent, MoveEvent, Renderer, ResizeEvent,
+                ScreenEvent, ScrollEvent, TextInputEvent};
 use syscall::data::Packet;
-use syscall::error::{EBADF, Error, Result};
+use syscall::error::{EBADF, EBUSY, EINVAL, ENODEV, ENOSPC, EPERM, Error, Result};
 use syscall::number::SYS_READ;
 
-use crate::config::Config;
+use crate::config::{Config, DoubleClickAction, Easing, MaximizeAction, MAX_UI_SCALE, PlacementMargin, ShortcutAction};
 use crate::core::{
+    Anchor,
     display::Display,
     Handler,
     image::Image,
+    KeyCombo,
     Orbital,
     Properties,
-    rect::Rect
+    rect::Rect,
+    Rotation,
+    SizeHints,
+    StackRelation,
+    SwitcherCommand
 };
 use crate::core::image::ImageRef;
-use crate::scheme::TilePosition::{BottomHalf, FullScreen, LeftHalf, RightHalf, TopHalf};
-use crate::window::{Window, WindowZOrder};
+use crate::window::TilePosition::{self, BottomHalf, FullScreen, LeftHalf, RightHalf, TopHalf};
+use crate::window::{GroupId, ORBITAL_EVENT_FRAME, ORBITAL_EVENT_SCALE, ORBITAL_FLAG_MAXIMIZED, ORBITAL_FLAG_MINIMIZED, Window, WindowRegion, WindowZOrder, draw_text};
 
 fn schedule(redraws: &mut Vec<Rect>, request: Rect) {
     let mut push = true;
@@ -50,1522 +59,8460 @@ fn schedule(redraws: &mut Vec<Rect>, request: Rect) {
     }
 }
 
-#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
-enum CursorKind {
-    None,
-    LeftPtr,
-    BottomLeftCorner,
-    BottomRightCorner,
-    BottomSide,
-    LeftSide,
-    RightSide,
+// Schedule all of every screen for a redraw, dropping the pending redraws they cover instead of
+// drawing those parts twice
+fn damage_all(redraws: &mut Vec<Rect>, screens: &[Rect]) {
+    redraws.retain(|rect| ! screens.iter().any(|screen| screen.container(rect) == *screen));
+    for screen in screens {
+        schedule(redraws, *screen);
+    }
 }
 
-enum DragMode {
-    None,
-    Title(usize, i32, i32),
-    LeftBorder(usize, i32, i32),
-    RightBorder(usize, i32),
-    BottomBorder(usize, i32),
-    BottomLeftBorder(usize, i32, i32, i32),
-    BottomRightBorder(usize, i32, i32),
+// The windows from front to back: each z-order class above the next, and the windows within a
+// class in focus order. Each entry is the window id, its z-order and its index in `order`
+fn zbuffer(order: &VecDeque<usize>, windows: &BTreeMap<usize, Window>) -> Vec<(usize, WindowZOrder, usize)> {
+    let mut zbuffer: Vec<_> = order.iter().enumerate()
+        .filter_map(|(i, id)| windows.get(id).map(|window| (*id, window.zorder, i)))
+        .collect();
+    zbuffer.sort_by(|a, b| b.1.cmp(&a.1));
+    zbuffer
 }
 
-enum Volume {
-    Down,
-    Up,
-    Toggle,
+// A readable dump of the window state for debugging: a line for each window in id order with
+// its position, size, z-order, flags and place in the focus order, then the focus order and the
+// zbuffer from front to back. The format is kept stable so dumps can be compared
+fn state_dump(windows: &BTreeMap<usize, Window>, order: &VecDeque<usize>, zbuffer: &[(usize, WindowZOrder, usize)]) -> Vec<String> {
+    let ids = |ids: &mut dyn Iterator<Item = usize>| ids.map(|id| id.to_string()).collect::<Vec<_>>().join(" ");
+    let mut lines = vec![format!("{} windows", windows.len())];
+    for (id, window) in windows {
+        let properties = window.properties();
+        let focus = order.iter().position(|other| other == id)
+            .map_or_else(|| "-".to_string(), |i| i.to_string());
+        lines.push(format!(
+            "window {} {:?}: {},{} {}x{} {:?} flags '{}' focus {}",
+            id, window.title, properties.x, properties.y, properties.width, properties.height,
+            window.zorder, properties.flags, focus
+        ));
+    }
+    lines.push(format!("order: {}", ids(&mut order.iter().copied())));
+    lines.push(format!("zbuffer: {}", ids(&mut zbuffer.iter().map(|entry| entry.0))));
+    lines
 }
 
-#[derive(Debug)]
-enum TilePosition {
-    LeftHalf,
-    TopHalf,
-    RightHalf,
-    BottomHalf,
-    FullScreen,
-}
+// The largest gap, in pixels, between two redraw rects that compact() still merges
+const REDRAW_GAP: i32 = 4;
 
-const GRID_SIZE: i32 = 16;
+// Grow each redraw rect that covers part of one of the `blurred` window rects to cover all of it.
+// The blur behind a window only samples what is redrawn with it, so blurring just a part of the
+// window would leave seams along the edges of that part
+fn cover_blurred(redraws: &mut [Rect], blurred: &[Rect]) {
+    for rect in redraws.iter_mut() {
+        // covering one window can make the rect reach into another
+        let mut grown = true;
+        while grown {
+            grown = false;
+            for window in blurred {
+                if ! rect.intersection(window).is_empty() && rect.container(window) != *rect {
+                    *rect = rect.container(window);
+                    grown = true;
+                }
+            }
+        }
+    }
+}
 
-const SHIFT_LEFT_MODIFIER : u8 = 1 << 0;
-const SHIFT_RIGHT_MODIFIER : u8 = 1 << 1;
-const SHIFT_ANY_MODIFIER : u8 = 1 << 2;
-const CONTROL_MODIFIER : u8 = 1 << 3;
-const ALT_MODIFIER : u8 = 1 << 4;
-const ALT_GR_MODIFIER : u8 = 1 << 5;
-const ALT_ANY_MODIFIER : u8 = 1 << 6;
-const SUPER_MODIFIER : u8 = 1 << 7;
+// Merge redraw rects that overlap, share an edge or are separated by a small gap, so that a frame
+// with many small scattered redraws needs fewer passes over the windows. Two rects are only
+// merged if their container adds at most a strip of REDRAW_GAP pixels along it to the area that
+// has to be redrawn, so the result never has more rects, or much more area, than the input.
+fn compact(redraws: &mut Vec<Rect>) {
+    redraws.retain(|rect| ! rect.is_empty());
 
-pub struct OrbitalScheme {
-    window_max: Image,
-    window_max_unfocused: Image,
-    window_close: Image,
-    window_close_unfocused: Image,
-    cursors: BTreeMap<CursorKind, Image>,
-    cursor_i: CursorKind,
-    cursor_x: i32,
-    cursor_y: i32,
-    cursor_left: bool,
-    cursor_middle: bool,
-    cursor_right: bool,
-    dragging: DragMode,
-    modifier_state: u8,
-    volume_value: i32,
-    volume_toggle: i32,
-    next_id: isize,
-    hover: Option<usize>,
-    order: VecDeque<usize>,
-    zbuffer: Vec<(usize, WindowZOrder, usize)>,
-    pub windows: BTreeMap<usize, Window>,
-    redraws: Vec<Rect>,
-    font: orbfont::Font,
-    clipboard: Vec<u8>,
-    scale: i32,
-    config: Rc<Config>,
-    // Is the user currently switching windows with win-tab
-    // Set true when win-tab is pressed, set false when win is released.
-    // While it is true, redraw() calls draw_window_list()
-    win_tabbing: bool,
-    volume_osd: bool,
-    shortcuts_osd: bool,
-    popup_rect: Rect,
+    let mut merged = true;
+    while merged {
+        merged = false;
+        let mut i = 0;
+        while i < redraws.len() {
+            let mut j = i + 1;
+            while j < redraws.len() {
+                let container = redraws[i].container(&redraws[j]);
+                let slack = REDRAW_GAP * cmp::max(container.width(), container.height());
+                if container.area() <= redraws[i].area() + redraws[j].area() + slack {
+                    redraws[i] = container;
+                    redraws.swap_remove(j);
+                    merged = true;
+                } else {
+                    j += 1;
+                }
+            }
+            i += 1;
+        }
+    }
 }
 
-impl OrbitalScheme {
-    pub(crate) fn new(displays: &[Display], config: Rc<Config>) -> Result<OrbitalScheme, String> {
-        let mut redraws = Vec::new();
-        let mut scale = 1;
-        for display in displays.iter() {
-            redraws.push(display.screen_rect());
-            scale = cmp::max(scale, display.scale);
+// Apply the scroll preferences from the config to a scroll event before it is forwarded
+fn scroll_event(config: &Config, event: ScrollEvent) -> ScrollEvent {
+    if config.natural_scroll {
+        ScrollEvent {
+            x: -event.x,
+            y: -event.y,
         }
+    } else {
+        event
+    }
+}
 
-        let mut cursors = BTreeMap::new();
-        cursors.insert(CursorKind::None, Image::new(0, 0));
-        cursors.insert(CursorKind::LeftPtr, Image::from_path_scale(&config.cursor, scale).unwrap_or(Image::new(0, 0)));
-        cursors.insert(CursorKind::BottomLeftCorner, Image::from_path_scale(&config.bottom_left_corner, scale).unwrap_or(Image::new(0, 0)));
-        cursors.insert(CursorKind::BottomRightCorner, Image::from_path_scale(&config.bottom_right_corner, scale).unwrap_or(Image::new(0, 0)));
-        cursors.insert(CursorKind::BottomSide, Image::from_path_scale(&config.bottom_side, scale).unwrap_or(Image::new(0, 0)));
-        cursors.insert(CursorKind::LeftSide, Image::from_path_scale(&config.left_side, scale).unwrap_or(Image::new(0, 0)));
-        cursors.insert(CursorKind::RightSide, Image::from_path_scale(&config.right_side, scale).unwrap_or(Image::new(0, 0)));
+// `color` with its alpha multiplied by `opacity`, from 0.0 to 1.0
+fn osd_color(color: Color, opacity: f32) -> Color {
+    let alpha = (color.a() as f32 * opacity.clamp(0.0, 1.0)).round() as u8;
+    Color::rgba(color.r(), color.g(), color.b(), alpha)
+}
 
-        let font = orbfont::Font::find(Some("Sans"), None, None)?;
+/// Where a wallpaper of the given size is drawn on a display: centered on its screen, and cropped
+/// by it when the wallpaper is larger
+fn wallpaper_rect(screen: &Rect, width: i32, height: i32) -> Rect {
+    Rect::new(
+        screen.left() + (screen.width() - width) / 2,
+        screen.top() + (screen.height() - height) / 2,
+        width,
+        height,
+    )
+}
 
-        Ok(OrbitalScheme {
-            window_max: Image::from_path_scale(&config.window_max, scale).unwrap_or(Image::new(0, 0)),
-            window_max_unfocused: Image::from_path_scale(&config.window_max_unfocused, scale).unwrap_or(Image::new(0, 0)),
-            window_close: Image::from_path_scale(&config.window_close, scale).unwrap_or(Image::new(0, 0)),
-            window_close_unfocused: Image::from_path_scale(&config.window_close_unfocused, scale).unwrap_or(Image::new(0, 0)),
-            cursors,
-            cursor_i: CursorKind::LeftPtr,
-            cursor_x: 0,
-            cursor_y: 0,
-            cursor_left: false,
-            cursor_middle: false,
-            cursor_right: false,
-            dragging: DragMode::None,
-            modifier_state: 0,
-            volume_value: 0,
-            volume_toggle: 0,
-            next_id: 1,
-            hover: None,
-            order: VecDeque::new(),
-            zbuffer: Vec::new(),
-            windows: BTreeMap::new(),
-            redraws,
-            font,
-            clipboard: Vec::new(),
-            scale,
-            config: Rc::clone(&config),
-            win_tabbing: false,
-            volume_osd: false,
-            shortcuts_osd: false,
-            popup_rect: Rect::default(),
-        })
+// Center a new window of `width` and `height` in `area`, keeping its title bar, of height
+// `title_height`, inside the area too
+fn placement(area: &Rect, width: i32, height: i32, title_height: i32) -> (i32, i32) {
+    (
+        area.left() + cmp::max(0, (area.width() - width)/2),
+        area.top() + cmp::max(title_height, (area.height() - height)/2)
+    )
+}
+
+// The part of `area` inside the placement margin, which is empty if the margin takes all of it
+fn placement_area(area: &Rect, margin: &PlacementMargin) -> Rect {
+    let side = |margin: u32| i32::try_from(margin).unwrap_or(i32::MAX);
+    let (top, bottom, left, right) = (side(margin.top), side(margin.bottom), side(margin.left), side(margin.right));
+    let width = cmp::max(0, area.width().saturating_sub(left).saturating_sub(right));
+    let height = cmp::max(0, area.height().saturating_sub(top).saturating_sub(bottom));
+    Rect::new(
+        area.left().saturating_add(cmp::min(left, area.width())),
+        area.top().saturating_add(cmp::min(top, area.height())),
+        width,
+        height,
+    )
+}
+
+// The size of a window taking `width_percent` and `height_percent` of `area`, both clamped to
+// 1..=100. The height leaves room for the title bar, of height `title_height`, so that the window
+// still fits in the area once it is centered with placement()
+fn percent_size(area: &Rect, width_percent: i32, height_percent: i32, title_height: i32) -> (i32, i32) {
+    let width = area.width() * width_percent.clamp(1, 100) / 100;
+    let height = cmp::min(area.height() * height_percent.clamp(1, 100) / 100, area.height() - title_height);
+    (cmp::max(1, width), cmp::max(1, height))
+}
+
+// Tile a new window that asked to open maximized, or that was tiled when it was last closed, once
+// it is placed so that it is restored to that placement. Returns the size the window should take,
+// windows that can't be tiled are left as they are.
+fn tile_new_window(window: &mut Window, position: TilePosition, area: &Rect) -> Option<(u32, u32)> {
+    if ! window.tileable() {
+        return None;
     }
 
-    pub fn with_orbital<'a>(&'a mut self, orb: &'a mut Orbital) -> OrbitalSchemeEvent<'a> {
-        OrbitalSchemeEvent {
-            scheme: self,
-            orb
-        }
+    let rect = window.tile(position, window.tile_rect(position, area));
+    window.x = rect.left();
+    window.y = rect.top();
+    Some((rect.width() as u32, rect.height() as u32))
+}
+
+// Whether the window is shown tiled to `position` in `area`
+fn tiled_at(window: &Window, position: TilePosition, area: &Rect) -> bool {
+    let tile = position.rect(area);
+    window.restore.is_some_and(|(_, tiled)| tiled == position)
+        && ! window.minimized
+        && (window.x, window.y) == (tile.left(), tile.top())
+}
+
+// When a window tiled to `position` in `area` closes, maximize the window tiled to the other half
+// of that area so that it takes over the space, the front-most one if there are several. Nothing
+// is maximized while another window is still tiled to the freed half. Returns the window and the
+// geometry it should take
+fn reflow_tile(windows: &mut BTreeMap<usize, Window>, order: &VecDeque<usize>, position: TilePosition, area: &Rect) -> Option<(usize, Rect)> {
+    let opposite = position.opposite()?;
+    if windows.values().any(|window| tiled_at(window, position, area)) {
+        return None;
     }
+    let id = order.iter().copied().find(|id| windows.get(id).is_some_and(|window| {
+        tiled_at(window, opposite, area) && window.tileable()
+    }))?;
 
-    fn cursor_rect(&self) -> Rect {
-        let cursor = &self.cursors[&self.cursor_i];
-        let (off_x, off_y) = match self.cursor_i {
-            CursorKind::None => (0, 0),
-            CursorKind::LeftPtr => (0, 0),
-            CursorKind::BottomLeftCorner => (0, -cursor.height()),
-            CursorKind::BottomRightCorner => (-cursor.width(), -cursor.height()),
-            CursorKind::BottomSide => (-cursor.width()/2, -cursor.height()),
-            CursorKind::LeftSide => (0, -cursor.height()/2),
-            CursorKind::RightSide => (-cursor.width(), -cursor.height()/2),
-        };
-        Rect::new(self.cursor_x + off_x, self.cursor_y + off_y, cursor.width(), cursor.height())
+    let window = windows.get_mut(&id)?;
+    let rect = window.tile(TilePosition::FullScreen, window.tile_rect(TilePosition::FullScreen, area));
+    Some((id, rect))
+}
+
+// Whether the windows `a` and `b` were opened by the same client, so that one can act on the other
+fn same_owner(windows: &BTreeMap<usize, Window>, a: usize, b: usize) -> bool {
+    match (windows.get(&a), windows.get(&b)) {
+        (Some(a), Some(b)) => a.owner == b.owner,
+        _ => false,
     }
+}
 
-    fn focus(&mut self, id: usize, focused: bool) {
-        if let Some(window) = self.windows.get_mut(&id) {
-            schedule(&mut self.redraws, window.title_rect());
-            schedule(&mut self.redraws, window.rect());
-            window.event(FocusEvent { focused }.to_event());
+// Whether the window `id` shows the window `target`, itself or through the windows it mirrors
+fn mirrors(windows: &BTreeMap<usize, Window>, mut id: usize, target: usize) -> bool {
+    let mut steps = 0;
+    loop {
+        if id == target {
+            return true;
         }
+        match windows.get(&id).and_then(|window| window.mirror) {
+            // a loop that doesn't go through `target` was refused when it was asked for
+            Some(source) if steps < windows.len() => id = source,
+            _ => return false,
+        }
+        steps += 1;
     }
+}
 
-    fn rezbuffer(&mut self) {
-        self.zbuffer.clear();
+// Where a window was when it closed, for opening the next window with its title there
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SavedGeometry {
+    // The floating geometry, which a tiled window returns to when it is restored
+    rect: Rect,
+    tile: Option<TilePosition>,
+}
 
-        for (i, id) in self.order.iter().enumerate() {
-            if let Some(window) = self.windows.get(id) {
-                self.zbuffer.push((*id, window.zorder, i));
-            }
+impl SavedGeometry {
+    fn of(window: &Window) -> SavedGeometry {
+        match window.restore {
+            Some((rect, tile)) => SavedGeometry { rect, tile: Some(tile) },
+            None => SavedGeometry {
+                rect: Rect::new(window.x, window.y, window.width(), window.height()),
+                tile: None,
+            },
         }
-
-        self.zbuffer.sort_by(|a, b| b.1.cmp(&a.1));
     }
+}
 
-    //TODO: update cursor in more places to ensure consistency:
-    // - Window resizes
-    // - Window sets cursor on/off
-    // - Window moves
-    fn update_cursor(&mut self, x: i32, y: i32, kind: CursorKind) {
-        if kind != self.cursor_i {
-            let cursor_rect = self.cursor_rect();
-            schedule(&mut self.redraws, cursor_rect);
+// How many closed windows' geometry is remembered, so that clients opening windows with new titles
+// do not grow it without bound
+const MAX_SAVED_GEOMETRY: usize = 64;
 
-            self.cursor_i = kind;
+// Remember `geometry` for the title `title`, most recent first, forgetting the least recently
+// closed title once `MAX_SAVED_GEOMETRY` are remembered
+fn save_geometry(saved: &mut VecDeque<(String, SavedGeometry)>, title: &str, geometry: SavedGeometry) {
+    saved.retain(|(other, _)| other != title);
+    saved.push_front((title.to_string(), geometry));
+    saved.truncate(MAX_SAVED_GEOMETRY);
+}
 
-            let cursor_rect = self.cursor_rect();
-            schedule(&mut self.redraws, cursor_rect);
+// Place a new window where `saved` says, returning the position to tile it to. The window keeps
+// the size it asked for. If it would not be on any of the `screens`, such as when the display it
+// was on is gone, it goes to the top left of the first one instead
+fn restore_geometry(window: &mut Window, saved: &SavedGeometry, screens: &[Rect]) -> Option<TilePosition> {
+    window.x = saved.rect.left();
+    window.y = saved.rect.top();
+    if ! screens.iter().any(|screen| ! screen.intersection(&window.rect()).is_empty()) {
+        if let Some(screen) = screens.first() {
+            window.x = screen.left();
+            window.y = screen.top() + window.title_rect().height();
         }
+    }
+    saved.tile
+}
 
-        // Update saved mouse information
-        if x != self.cursor_x || y != self.cursor_y {
-            let cursor_rect = self.cursor_rect();
-            schedule(&mut self.redraws, cursor_rect);
+// Move the window `id` to the top of the zbuffer, keeping its order index so that it is not drawn
+// or treated as focused
+fn raise_in_zbuffer(zbuffer: &mut Vec<(usize, WindowZOrder, usize)>, id: usize) {
+    if let Some(index) = zbuffer.iter().position(|entry| entry.0 == id) {
+        let entry = zbuffer.remove(index);
+        zbuffer.insert(0, entry);
+    }
+}
 
-            self.cursor_x = x;
-            self.cursor_y = y;
+// Move the window `id` to the top of the windows of its z-order in the zbuffer, keeping its order
+// index so that it is not drawn or treated as focused
+fn raise_in_class(zbuffer: &mut Vec<(usize, WindowZOrder, usize)>, id: usize) {
+    if let Some(index) = zbuffer.iter().position(|entry| entry.0 == id) {
+        let entry = zbuffer.remove(index);
+        let top = zbuffer.iter().position(|other| other.1 <= entry.1).unwrap_or(zbuffer.len());
+        zbuffer.insert(top, entry);
+    }
+}
 
-            let cursor_rect = self.cursor_rect();
-            schedule(&mut self.redraws, cursor_rect);
+// Whether keeping `id` relative to `target` would create a cycle of stacking relations
+fn stack_cycle(relations: &BTreeMap<usize, StackRelation>, id: usize, target: usize) -> bool {
+    let mut current = target;
+    for _ in 0..=relations.len() {
+        if current == id {
+            return true;
+        }
+        match relations.get(&current) {
+            Some(relation) => current = relation.target(),
+            None => return false,
         }
     }
+    true
 }
 
-impl Handler for OrbitalScheme {
-    fn should_delay(&mut self, packet: &Packet) -> bool {
-        packet.a == SYS_READ &&
-            self.windows.get(&packet.b)
-                .map(|window| !window.asynchronous)
-                .unwrap_or(true)
+// Apply the stacking relations between windows to a zbuffer sorted by z-order, moving every
+// constrained window directly above or below its target. Relations are resolved targets first:
+// a window is only placed once the window it is relative to has taken its final place, so chains
+// of relations stay together, and relations with the same depth are resolved in window id order.
+// A relation to a window that is gone is ignored.
+fn stack_zbuffer(zbuffer: &mut Vec<(usize, WindowZOrder, usize)>, relations: &BTreeMap<usize, StackRelation>) {
+    let depth = |id: usize| {
+        let mut depth = 0;
+        let mut current = id;
+        while let Some(relation) = relations.get(&current) {
+            depth += 1;
+            current = relation.target();
+            if depth > relations.len() {
+                break;
+            }
+        }
+        depth
+    };
+
+    let mut constrained: Vec<(usize, usize, StackRelation)> = relations.iter()
+        .map(|(id, relation)| (depth(*id), *id, *relation))
+        .collect();
+    constrained.sort_by_key(|(depth, id, _)| (*depth, *id));
+
+    for (_, id, relation) in constrained {
+        let target = relation.target();
+        if ! zbuffer.iter().any(|entry| entry.0 == target) {
+            continue;
+        }
+        if let Some(index) = zbuffer.iter().position(|entry| entry.0 == id) {
+            let entry = zbuffer.remove(index);
+            if let Some(target_index) = zbuffer.iter().position(|entry| entry.0 == target) {
+                match relation {
+                    StackRelation::Above(_) => zbuffer.insert(target_index, entry),
+                    StackRelation::Below(_) => zbuffer.insert(target_index + 1, entry),
+                }
+            }
+        }
     }
+}
 
-    fn handle_scheme(&mut self, orb: &mut Orbital, packets: &mut [Packet]) -> io::Result<()> {
-        self.with_orbital(orb).scheme_event(packets)
-    }
+// Limits how often resize events are sent to a window while its border is dragged. Sizes that
+// arrive too soon after the last one sent are kept as pending, and the pending size is sent with
+// the next resize that is let through or when the drag ends.
+#[derive(Default)]
+struct ResizeDebounce {
+    interval: Duration,
+    last: Option<Instant>,
+    pending: Option<(usize, u32, u32)>,
+}
 
-    fn handle_display(&mut self, orb: &mut Orbital, events: &mut [Event]) -> io::Result<()> {
-        self.with_orbital(orb).display_event(events)
-    }
+impl ResizeDebounce {
+    // Returns the size to send now for a resize of window `id` to `width` and `height`, if any
+    fn resize(&mut self, now: Instant, id: usize, width: u32, height: u32) -> Option<(u32, u32)> {
+        let ready = match self.last {
+            Some(last) => now.saturating_duration_since(last) >= self.interval,
+            None => true,
+        };
 
-    fn handle_after(&mut self, orb: &mut Orbital) -> io::Result<()> {
-        self.with_orbital(orb).redraw();
-        Ok(())
+        if ready {
+            self.last = Some(now);
+            self.pending = None;
+            Some((width, height))
+        } else {
+            self.pending = Some((id, width, height));
+            None
+        }
     }
 
-    fn handle_window_new(&mut self, orb: &mut Orbital,
-                         x: i32, y: i32, width: i32, height: i32,
-                         parts: &str, title: String) -> Result<usize> {
-        self.with_orbital(orb).window_new(x, y, width, height, parts, title)
+    // Ends the drag, returning the final size if it was held back
+    fn finish(&mut self) -> Option<(usize, u32, u32)> {
+        self.last = None;
+        self.pending.take()
     }
+}
 
-    fn handle_window_read(&mut self, _orb: &mut Orbital, id: usize, buf: &mut [Event]) -> Result<usize> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        Ok(window.read(buf))
+// The index in `order` of a clicked window that should be moved to the front and focused, if any.
+// The front window already has focus and windows that never take focus stay where they are.
+fn click_focus(order: &VecDeque<usize>, windows: &BTreeMap<usize, Window>, focus: usize) -> Option<usize> {
+    let id = order.get(focus)?;
+    match windows.get(id) {
+        Some(window) if focus > 0 && window.focusable() => Some(focus),
+        _ => None,
     }
+}
 
-    fn handle_window_async(&mut self, _orb: &mut Orbital, id: usize, is_async: bool) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        window.asynchronous = is_async;
-        Ok(())
-    }
+/// Move the first selectable window in `order` behind the last one, for the window switcher.
+/// Returns the id of the window that was at the front and of the one brought to the front, or
+/// None when there aren't two windows to switch between.
+/// Note that the selectable windows may be interlaced in the stack with non-selectable windows,
+/// the first selectable window may not be the first in the stack and the bottom selectable
+/// window may not be the last in the stack
+fn switcher_next(order: &mut VecDeque<usize>, windows: &BTreeMap<usize, Window>) -> Option<(usize, usize)> {
+    let selectable: Vec<usize> = order.iter().enumerate()
+        .filter(|(_, id)| windows.get(id).is_some_and(|window| !window.title.is_empty() && window.focusable()))
+        .map(|(index, _)| index)
+        .collect();
+    let (&front_index, &next_index, &last_index) = (selectable.first()?, selectable.get(1)?, selectable.last()?);
+
+    let next_id = order[next_index];
+    let front_id = order.remove(front_index)?;
+    order.insert(last_index, front_id);
+    Some((front_id, next_id))
+}
 
-    fn handle_window_drag(&mut self, orb: &mut Orbital, id: usize /*TODO: resize sides */) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        if self.cursor_left {
-            self.dragging = DragMode::Title(id, self.cursor_x, self.cursor_y);
+// All window ids, the most recently focused first. Windows that were never focused come last
+fn mru_order(windows: &BTreeMap<usize, Window>) -> Vec<usize> {
+    let mut ids: Vec<usize> = windows.keys().copied().collect();
+    ids.sort_by_key(|id| cmp::Reverse(windows[id].focused_at));
+    ids
+}
+
+// List where the text cursor of each window that reported one is on the screen, as `id,x,y`
+// lines in most recently focused order, for an input method to place its candidate popup
+fn text_cursor_query(windows: &BTreeMap<usize, Window>) -> Vec<u8> {
+    let mut data = Vec::new();
+    for id in mru_order(windows) {
+        if let Some((x, y)) = windows[&id].screen_text_cursor() {
+            let _ = writeln!(data, "{},{},{}", id, x, y);
         }
-        Ok(())
     }
+    data
+}
 
-    fn handle_window_mouse_cursor(&mut self, _orb: &mut Orbital, id: usize, visible: bool) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        window.mouse_cursor = visible;
-        Ok(())
+// List the subtitle of each window that set one with T2, as `id,subtitle` lines in most recently
+// focused order. Subtitles aren't part of the window path, where the title is the rest of it
+fn subtitles_query(windows: &BTreeMap<usize, Window>) -> Vec<u8> {
+    let mut data = Vec::new();
+    for id in mru_order(windows) {
+        let subtitle = &windows[&id].subtitle;
+        if ! subtitle.is_empty() {
+            let _ = writeln!(data, "{},{}", id, subtitle);
+        }
     }
+    data
+}
 
-    fn handle_window_mouse_grab(&mut self, _orb: &mut Orbital, id: usize, grab: bool) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        window.mouse_grab = grab;
-        Ok(())
+// When window `id`, opened from `opened_from`, closes, give focus back to the window it was opened
+// from if it was `focused` and that window is still open and can take focus, by bringing it to
+// the front of `order`. Otherwise focus goes to the front of `order` as usual. The windows
+// opened from the closed one are from then on opened from that window in turn, so that closing
+// a chain of dialogs returns focus down the chain
+fn return_focus(order: &mut VecDeque<usize>, windows: &mut BTreeMap<usize, Window>, id: usize, opened_from: Option<usize>, focused: bool) {
+    for window in windows.values_mut().filter(|window| window.opened_from == Some(id)) {
+        window.opened_from = opened_from;
     }
 
-    fn handle_window_mouse_relative(&mut self, _orb: &mut Orbital, id: usize, relative: bool) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        window.mouse_relative = relative;
-        Ok(())
+    let Some(opened_from) = opened_from.filter(|_| focused) else {
+        return;
+    };
+    if ! windows.get(&opened_from).is_some_and(Window::focusable) {
+        return;
+    }
+    if let Some(i) = order.iter().position(|&other| other == opened_from) {
+        if let Some(opened_from) = order.remove(i) {
+            order.push_front(opened_from);
+        }
     }
+}
 
-    fn handle_window_position(&mut self, _orb: &mut Orbital, id: usize, x: Option<i32>, y: Option<i32>) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        schedule(&mut self.redraws, window.title_rect());
-        schedule(&mut self.redraws, window.rect());
+// The windows listed by the window switcher, in stacking order: the windows with a title that can
+// be switched to, and then the minimized ones, which are listed to show that they are still open
+// but are restored from the panel rather than switched to
+fn window_list(order: &VecDeque<usize>, windows: &BTreeMap<usize, Window>) -> (Vec<usize>, Vec<usize>) {
+    let titled = || order.iter().copied().filter_map(|id| windows.get(&id).filter(|window| ! window.title.is_empty()).map(|window| (id, window)));
+    let selectable = titled().filter(|(_, window)| window.focusable()).map(|(id, _)| id).collect();
+    let minimized = titled().filter(|(_, window)| window.minimized).map(|(id, _)| id).collect();
+    (selectable, minimized)
+}
 
-        window.x = x.unwrap_or(window.x);
-        window.y = y.unwrap_or(window.y);
-
-        schedule(&mut self.redraws, window.title_rect());
-        schedule(&mut self.redraws, window.rect());
+// A pass through the windows with Super-Tab in most recently used order, from when the pass
+// started, so that focusing windows on the way doesn't reorder them
+struct MruCycle {
+    ids: Vec<usize>,
+    index: usize,
+}
 
-        Ok(())
+// Bring the window after the current one in the MRU cycle to the front of `order`, starting a
+// cycle if there is none. The first step goes to the most recently used window other than the
+// focused one. Returns the (previous, new) front windows like [switcher_next].
+fn mru_next(cycle: &mut Option<MruCycle>, order: &mut VecDeque<usize>, windows: &BTreeMap<usize, Window>) -> Option<(usize, usize)> {
+    let cycle = cycle.get_or_insert_with(|| MruCycle { ids: mru_order(windows), index: 0 });
+    // windows closed during the pass are skipped, keeping the place of the current one, or of
+    // where it was if it is the one that closed
+    let listed = |id: &usize| windows.get(id).is_some_and(|window| !window.title.is_empty() && window.focusable());
+    let current = cycle.ids.get(cycle.index).copied();
+    let listed_before = cycle.ids.iter().take(cycle.index).filter(|id| listed(id)).count();
+    cycle.ids.retain(listed);
+    if cycle.ids.len() < 2 {
+        return None;
     }
 
-    fn handle_window_resize(&mut self, _orb: &mut Orbital, id: usize, w: Option<i32>, h: Option<i32>) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        schedule(&mut self.redraws, window.title_rect());
-        schedule(&mut self.redraws, window.rect());
+    let front_id = *order.front()?;
+    let next = match current.and_then(|current| cycle.ids.iter().position(|&id| id == current)) {
+        Some(index) => index + 1,
+        None => listed_before,
+    };
+    cycle.index = next % cycle.ids.len();
+    let next_id = cycle.ids[cycle.index];
+    order.retain(|&id| id != next_id);
+    order.push_front(next_id);
+    Some((front_id, next_id))
+}
 
-        let w = w.unwrap_or(window.width());
-        let h = h.unwrap_or(window.height());
+// Exchange the places of two windows, which may overlap. Each is moved to where the other was
+// right away, and sent the size of the other to resize to if it can be resized. Their tiling
+// stays with each window.
+fn swap_geometry(a: &mut Window, b: &mut Window) {
+    let a_rect = Rect::new(a.x, a.y, a.width(), a.height());
+    let b_rect = Rect::new(b.x, b.y, b.width(), b.height());
+    take_geometry(a, b_rect);
+    take_geometry(b, a_rect);
+}
 
-        window.set_size(w, h);
+// Move the window to `rect` right away, and send it the size of `rect` to resize to if it can be
+// resized
+fn take_geometry(window: &mut Window, rect: Rect) {
+    window.x = rect.left();
+    window.y = rect.top();
+    window.event(MoveEvent { x: window.x, y: window.y }.to_event());
+
+    if window.border_resizable() {
+        let (width, height) = window.size_hints.constrain(rect.width(), rect.height());
+        if width != window.width() || height != window.height() {
+            window.event(ResizeEvent { width: width as u32, height: height as u32 }.to_event());
+        }
+    }
+}
 
-        schedule(&mut self.redraws, window.title_rect());
-        schedule(&mut self.redraws, window.rect());
+// Windows grouped as tabs in one title bar, sharing its place on screen. Only the active tab is
+// shown, the others are hidden behind it until their tab is clicked, and a window joining the
+// group becomes the active tab. When the active tab leaves the group or closes, the tab after it
+// becomes active, or the one before it if it was the last. A group left with a single window is
+// dissolved, leaving it an ordinary window.
+struct TabGroup {
+    tabs: Vec<usize>,
+    active: usize,
+    // The tabs rendered for the title bar of the active tab, with what they were rendered from
+    strip: Option<(TabStrip, Image)>,
+}
 
-        Ok(())
+impl TabGroup {
+    fn new(id: usize) -> Self {
+        TabGroup { tabs: vec![id], active: id, strip: None }
     }
 
-    fn handle_window_set_flag(&mut self, orb: &mut Orbital, id: usize, flag: char, value: bool) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+    // Take the window out of the group, returning the tab that becomes active in its place if
+    // it was the active one
+    fn remove(&mut self, id: usize) -> Option<usize> {
+        let index = self.tabs.iter().position(|&tab| tab == id)?;
+        self.tabs.remove(index);
+        if self.active != id || self.tabs.is_empty() {
+            return None;
+        }
+        self.active = self.tabs[cmp::min(index, self.tabs.len() - 1)];
+        Some(self.active)
+    }
+}
 
-        // Handle maximized flag custom
-        if flag == crate::window::ORBITAL_FLAG_MAXIMIZED {
-            let toggle_tile = if value {
-                window.restore = None;
-                true
-            } else {
-                window.restore.is_some()
-            };
-            if toggle_tile {
-                self.with_orbital(orb).tile_window(Some(&id), TilePosition::FullScreen);
-            }
-        } else {
-            // Setting flag may change visibility, make sure to queue redraws both before and after
-            schedule(&mut self.redraws, window.title_rect());
-            schedule(&mut self.redraws, window.rect());
+// What the tabs in a title bar show, they are rendered again when any of it changes
+#[derive(PartialEq)]
+struct TabStrip {
+    area: Rect,
+    scale: i32,
+    titles: Vec<String>,
+    active: usize,
+    focused: bool,
+}
 
-            window.set_flag(flag, value);
+// Height of the titles on tabs before display scaling, the same as in a title bar
+const TAB_TEXT_HEIGHT: i32 = 16;
 
-            // Setting flag may change visibility, make sure to queue redraws both before and after
-            schedule(&mut self.redraws, window.title_rect());
-            schedule(&mut self.redraws, window.rect());
+// The part of a title bar the tabs are drawn in, leaving out the buttons at its right
+fn tab_area(title: &Rect, scale: i32) -> Rect {
+    Rect::new(title.left(), title.top(), cmp::max(0, title.width() - 36 * scale), title.height())
+}
+
+// The tabs of `count` windows, sharing `area` evenly
+fn tab_rects(area: &Rect, count: usize) -> Vec<Rect> {
+    let count = cmp::max(1, count) as i32;
+    (0..count).map(|i| {
+        let left = area.left() + area.width() * i / count;
+        let right = area.left() + area.width() * (i + 1) / count;
+        Rect::new(left, area.top(), right - left, area.height())
+    }).collect()
+}
+
+fn render_tab_strip(strip: &TabStrip, font: &orbfont::Font, config: &Config) -> Image {
+    let area = Rect::new(0, 0, strip.area.width(), strip.area.height());
+    let mut image = Image::from_color(area.width(), area.height(), config.bar_color.into());
+    for (i, (tab, title)) in tab_rects(&area, strip.titles.len()).iter().zip(strip.titles.iter()).enumerate() {
+        let active = i == strip.active;
+        if active {
+            let color = if strip.focused { config.bar_highlight_color } else { config.bar_color };
+            image.rect(tab.left(), tab.top(), tab.width() as u32, tab.height() as u32, color.into());
+        }
+        if i > 0 {
+            image.rect(tab.left(), tab.top(), strip.scale as u32, tab.height() as u32, config.bar_highlight_color.into());
         }
 
-        Ok(())
+        let color = if active && strip.focused { config.text_highlight_color } else { config.text_color };
+        let text = font.render(title, (TAB_TEXT_HEIGHT * strip.scale) as f32);
+        let padding = 6 * strip.scale;
+        let text_rect = Rect::new(tab.left() + padding, padding, tab.width() - padding * 2, text.height() as i32);
+        draw_clipped_text(&mut image, &text, &text_rect, color.into(), config.font_antialias);
     }
+    image
+}
 
-    fn handle_window_title(&mut self, _orb: &mut Orbital, id: usize, title: String) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        window.title = title;
-        window.render_title(&self.font);
+// Draw `text` on `image` in `rect`, cut off where it is too wide
+fn draw_clipped_text(image: &mut Image, text: &orbfont::Text, rect: &Rect, color: Color, antialias: bool) {
+    let width = cmp::min(rect.width(), text.width() as i32);
+    if width <= 0 || rect.height() <= 0 {
+        return;
+    }
+    let mut text_image = Image::from_color(width, rect.height(), Color::rgba(0, 0, 0, 0));
+    text_image.mode().set(orbclient::Mode::Overwrite);
+    draw_text(text, &mut text_image, 0, 0, color, antialias);
+    image.roi(&Rect::new(rect.left(), rect.top(), width, rect.height()))
+        .blend(&text_image.roi(&Rect::new(0, 0, width, rect.height())));
+}
 
-        schedule(&mut self.redraws, window.title_rect());
+// The id, order index and region under (x, y) of the topmost window with its contents, title bar
+// or borders there. Input transparent windows are skipped, so that the window below them gets the
+// input instead.
+fn input_target(zbuffer: &[(usize, WindowZOrder, usize)], windows: &BTreeMap<usize, Window>, x: i32, y: i32) -> Option<(usize, usize, WindowRegion)> {
+    zbuffer.iter().find_map(|&(id, _, i)| {
+        let window = windows.get(&id).filter(|window| ! window.input_transparent)?;
+        window.region_at(x, y).map(|region| (id, i, region))
+    })
+}
 
-        Ok(())
+// The window that can be drawn to `screen` as it is, without compositing anything below it:
+// the topmost window drawn there, if it covers all of the screen, is opaque and not faded by its
+// opacity, isn't dimmed and has been painted, so that no loading spinner is drawn over it either
+fn scanout_window(zbuffer: &[(usize, WindowZOrder, usize)], windows: &BTreeMap<usize, Window>, screen: &Rect, unfocused_dim: u8) -> Option<usize> {
+    let &(id, _, i) = zbuffer.iter().find(|(id, _, _)| windows.get(id).is_some_and(|window| {
+        window.visible() && ! window.frame_rect().intersection(screen).is_empty()
+    }))?;
+    let window = windows.get(&id)?;
+    let covers = window.rect().intersection(screen) == *screen;
+    let undimmed = i == 0 || unfocused_dim == 0;
+    (covers && undimmed && ! window.transparent && window.opacity == 255 && window.painted).then_some(id)
+}
+
+// The topmost window at (x, y) that takes input, the one the mouse would hit there
+fn window_at(zbuffer: &[(usize, WindowZOrder, usize)], windows: &BTreeMap<usize, Window>, x: i32, y: i32) -> Option<usize> {
+    input_target(zbuffer, windows, x, y).map(|(id, _, _)| id)
+}
+
+// Write `buf` to the clipboard at `seek`, dropping what was after it. At most `max` bytes are
+// kept, a write past that is cut short and one with no room left at all fails with ENOSPC
+fn clipboard_write(clipboard: &mut Vec<u8>, seek: &mut usize, buf: &[u8], max: usize) -> Result<usize> {
+    clipboard.truncate(*seek);
+    let room = max.saturating_sub(clipboard.len());
+    if room == 0 && ! buf.is_empty() {
+        return Err(Error::new(ENOSPC));
     }
+    let written = cmp::min(buf.len(), room);
+    clipboard.extend_from_slice(&buf[..written]);
+    *seek += written;
+    Ok(written)
+}
 
-    fn handle_window_clear_notified(&mut self, _orb: &mut Orbital, id: usize) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        window.notified_read = false;
-        Ok(())
+// The drag that a left click on one of the borders of a window starts
+fn border_drag(window: &Window, id: usize, region: WindowRegion, x: i32, y: i32) -> DragMode {
+    match region {
+        WindowRegion::LeftBorder => DragMode::LeftBorder(id, x - window.x, window.x + window.width()),
+        WindowRegion::RightBorder => DragMode::RightBorder(id, x - (window.x + window.width())),
+        WindowRegion::BottomBorder => DragMode::BottomBorder(id, y - (window.y + window.height())),
+        WindowRegion::BottomLeftCorner => DragMode::BottomLeftBorder(id, x - window.x, y - (window.y + window.height()), window.x + window.width()),
+        WindowRegion::BottomRightCorner => DragMode::BottomRightBorder(id, x - (window.x + window.width()), y - (window.y + window.height())),
+        WindowRegion::Content | WindowRegion::Title => DragMode::None,
     }
+}
 
-    fn handle_window_map(&mut self, _orb: &mut Orbital, id: usize, create_new: bool) -> Result<&mut [Color]> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        if create_new {
-            window.maps += 1;
-        }
-        Ok(window.map())
+// How long a batch can defer redraws before it is committed anyway, so that a client that never
+// commits can't freeze the screen
+const BATCH_TIMEOUT: Duration = Duration::from_millis(100);
+
+// A batch of window changes started by the window `owner`, during which redraws are deferred
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Batch {
+    owner: usize,
+    started: Instant,
+}
+
+// Start a batch for window `id`, only one batch can be open at a time. Beginning a batch that is
+// already open keeps it as it was, so that it still times out when it would have
+fn begin_batch(batch: &mut Option<Batch>, id: usize, now: Instant) -> Result<()> {
+    if batch_active(batch, now) {
+        return match batch {
+            Some(open) if open.owner == id => Ok(()),
+            _ => Err(Error::new(EBUSY)),
+        };
     }
 
-    fn handle_window_unmap(&mut self, _orb: &mut Orbital, id: usize) -> Result<()> {
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        if window.maps > 0 {
-            window.maps -= 1;
-        } else {
-            warn!("attempted unmap when there are no mappings");
-        }
-        Ok(())
+    *batch = Some(Batch { owner: id, started: now });
+    Ok(())
+}
+
+// Commit the batch started by window `id`
+fn end_batch(batch: &mut Option<Batch>, id: usize) -> Result<()> {
+    match batch {
+        Some(open) if open.owner == id => {
+            *batch = None;
+            Ok(())
+        },
+        _ => Err(Error::new(EINVAL)),
     }
+}
 
-    fn handle_window_properties(&mut self, _orb: &mut Orbital, id: usize) -> Result<Properties> {
-        let window = self.windows.get(&id).ok_or(Error::new(EBADF))?;
-        Ok(window.properties())
+// Whether redraws are deferred for an open batch, a batch left open for too long is dropped
+fn batch_active(batch: &mut Option<Batch>, now: Instant) -> bool {
+    match batch {
+        Some(open) if now.saturating_duration_since(open.started) < BATCH_TIMEOUT => true,
+        Some(open) => {
+            warn!("batch from window {} not committed in time", open.owner);
+            *batch = None;
+            false
+        },
+        None => false,
     }
+}
 
-    fn handle_window_sync(&mut self, _orb: &mut Orbital, id: usize) -> Result<usize> {
-        let window = self.windows.get(&id).ok_or(Error::new(EBADF))?;
-        schedule(&mut self.redraws, window.rect());
-        Ok(0)
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+enum CursorKind {
+    None,
+    LeftPtr,
+    BottomLeftCorner,
+    BottomRightCorner,
+    BottomSide,
+    LeftSide,
+    RightSide,
+}
+
+// The cursor shown at the center of a window in relative mode: hidden, unless the window asked
+// to keep it visible and hasn't hidden the cursor itself
+fn relative_cursor(window: &Window) -> CursorKind {
+    if window.mouse_relative_cursor && window.mouse_cursor {
+        CursorKind::LeftPtr
+    } else {
+        CursorKind::None
     }
+}
 
-    fn handle_window_close(&mut self, orb: &mut Orbital, id: usize) -> Result<usize> {
-        // Unfocus current front window
-        if let Some(id) = self.order.front() {
-            self.focus(*id, false);
-        }
+#[derive(Debug, PartialEq)]
+enum DragMode {
+    None,
+    Title(usize, i32, i32),
+    LeftBorder(usize, i32, i32),
+    RightBorder(usize, i32),
+    BottomBorder(usize, i32),
+    BottomLeftBorder(usize, i32, i32, i32),
+    BottomRightBorder(usize, i32, i32),
+}
 
-        self.order.retain(|&e| e != id);
+enum Volume {
+    Down,
+    Up,
+    Toggle,
+}
 
-        let res = if let Some(window) = self.windows.remove(&id) {
-            schedule(&mut self.redraws, window.title_rect());
-            schedule(&mut self.redraws, window.rect());
-            Ok(0)
-        } else {
-            Err(Error::new(EBADF))
-        };
+/// How many times the configured volume step is used when Shift is held
+const VOLUME_STEP_LARGE: i32 = 4;
 
-        // Focus current front window
-        if let Some(id) = self.order.front() {
-            self.focus(*id, true);
-        }
+// The amount a volume shortcut changes the volume by
+fn volume_step(config: &Config, large: bool) -> i32 {
+    let step = cmp::min(config.volume_step, 100) as i32;
+    if large { step * VOLUME_STEP_LARGE } else { step }
+}
 
-        // Ensure mouse cursor is correct
-        let event = MouseEvent {
-            x: self.cursor_x,
-            y: self.cursor_y,
-        };
-        self.with_orbital(orb).mouse_event(event);
+// Change the volume by `delta`, keeping it within 0..=100
+fn step_volume(value: i32, delta: i32) -> i32 {
+    (value + delta).clamp(0, 100)
+}
 
-        res
+const GRID_SIZE: i32 = 16;
+
+// The grid window drags snap to: the configured one with snap_to_grid, or 1, which doesn't snap,
+// without it or while Control is held for fine control
+fn drag_grid(config: &Config, modifiers: u8) -> i32 {
+    if ! config.snap_to_grid || modifiers & CONTROL_MODIFIER != 0 {
+        return 1;
     }
-    fn handle_clipboard_new(&mut self, _orb: &mut Orbital, id: usize) -> Result<usize> {
-        //TODO: implement better clipboard mechanism
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        window.clipboard_seek = 0;
-        Ok(id)
+    cmp::max(1, config.snap_grid_size as i32)
+}
+
+// `value` rounded to the nearest multiple of `grid`
+fn snap_to_grid(value: i32, grid: i32) -> i32 {
+    if grid <= 1 {
+        return value;
     }
+    (value + grid / 2).div_euclid(grid) * grid
+}
 
-    fn handle_clipboard_read(&mut self, _orb: &mut Orbital, id: usize, buf: &mut [u8]) -> Result<usize> {
-        //TODO: implement better clipboard mechanism
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        let mut i = 0;
-        while i < buf.len() && window.clipboard_seek < self.clipboard.len() {
-            buf[i] = self.clipboard[i];
-            i += 1;
-            window.clipboard_seek += 1;
+// The edges of the windows other than `id` that are shown, which its borders snap to when it is
+// resized: the left and right edges, and the top and bottom edges, with their title bars
+fn snap_edges(zbuffer: &[(usize, WindowZOrder, usize)], windows: &BTreeMap<usize, Window>, id: usize) -> (Vec<i32>, Vec<i32>) {
+    let frames: Vec<Rect> = zbuffer.iter()
+        .filter(|entry| entry.0 != id)
+        .filter_map(|entry| windows.get(&entry.0))
+        .filter(|window| window.visible())
+        .map(Window::frame_rect)
+        .collect();
+    let x = frames.iter().flat_map(|frame| [frame.left(), frame.right()]).collect();
+    let y = frames.iter().flat_map(|frame| [frame.top(), frame.bottom()]).collect();
+    (x, y)
+}
+
+// `value` moved to the nearest of `edges` within `distance`, or as it is when none is
+fn snap_to_edge(value: i32, edges: &[i32], distance: i32) -> i32 {
+    edges.iter().copied()
+        .filter(|edge| (edge - value).abs() <= distance)
+        .min_by_key(|edge| (edge - value).abs())
+        .unwrap_or(value)
+}
+
+// Where a window at `position` that is dragged by its title from `drag` to `cursor` moves to on
+// `grid`, and the point to drag on from. That point keeps what snapping took off the move, so
+// that small moves add up until they reach the next grid line
+fn title_drag(position: (i32, i32), drag: (i32, i32), cursor: (i32, i32), grid: i32) -> ((i32, i32), (i32, i32)) {
+    let x = snap_to_grid(position.0 + cursor.0 - drag.0, grid);
+    let y = snap_to_grid(position.1 + cursor.1 - drag.1, grid);
+    ((x, y), (drag.0 + x - position.0, drag.1 + y - position.1))
+}
+
+// Images used when the ones from the config are missing. There is only a default pointer, which
+// is used for the resize cursors too.
+const DEFAULT_LEFT_PTR: &[u8] = include_bytes!("../res/left_ptr.png");
+const DEFAULT_WINDOW_MAX: &[u8] = include_bytes!("../res/window_max.png");
+const DEFAULT_WINDOW_MAX_UNFOCUSED: &[u8] = include_bytes!("../res/window_max_unfocused.png");
+const DEFAULT_WINDOW_CLOSE: &[u8] = include_bytes!("../res/window_close.png");
+const DEFAULT_WINDOW_CLOSE_UNFOCUSED: &[u8] = include_bytes!("../res/window_close_unfocused.png");
+
+// Load an image at `scale`, falling back to the image built into Orbital when the configured one
+// can't be loaded
+fn load_image(path: &str, default: &[u8], scale: i32) -> Image {
+    Image::from_path_scale(path, scale)
+        .or_else(|| Image::from_bytes(default).and_then(|image| image.scale(scale)))
+        .unwrap_or(Image::new(0, 0))
+}
+
+// The wallpapers of the first `displays` displays at `scale`, by path, each loaded once
+fn load_wallpapers(config: &Config, displays: usize, scale: i32) -> BTreeMap<String, Image> {
+    let mut wallpapers = BTreeMap::new();
+    for index in 0..displays {
+        let path = config.wallpaper(index);
+        if path.is_empty() || wallpapers.contains_key(path) {
+            continue;
+        }
+        match Image::from_path_scale(path, scale) {
+            Some(wallpaper) => {
+                wallpapers.insert(path.to_string(), wallpaper);
+            },
+            None => warn!("failed to load wallpaper '{}'", path),
         }
-        Ok(i)
     }
+    wallpapers
+}
 
-    fn handle_clipboard_write(&mut self, _orb: &mut Orbital, id: usize, buf: &[u8]) -> Result<usize> {
-        //TODO: implement better clipboard mechanism
-        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
-        let mut i = 0;
-        self.clipboard.truncate(window.clipboard_seek);
-        while i < buf.len() {
-            self.clipboard.push(buf[i]);
-            i += 1;
-            window.clipboard_seek += 1;
-        }
-        Ok(i)
+// The cursors from the config, at `scale` times cursor_scale. Their hotspots are found from their
+// size, so they follow along
+fn load_cursors(config: &Config, scale: i32) -> BTreeMap<CursorKind, Image> {
+    let cursor_scale = cmp::max(config.cursor_scale, 1).min(MAX_UI_SCALE as u32) as i32;
+    let scale = scale.checked_mul(cursor_scale).unwrap_or(scale);
+    let mut cursors = BTreeMap::new();
+    cursors.insert(CursorKind::None, Image::new(0, 0));
+    cursors.insert(CursorKind::LeftPtr, load_image(&config.cursor, DEFAULT_LEFT_PTR, scale));
+    cursors.insert(CursorKind::BottomLeftCorner, load_image(&config.bottom_left_corner, DEFAULT_LEFT_PTR, scale));
+    cursors.insert(CursorKind::BottomRightCorner, load_image(&config.bottom_right_corner, DEFAULT_LEFT_PTR, scale));
+    cursors.insert(CursorKind::BottomSide, load_image(&config.bottom_side, DEFAULT_LEFT_PTR, scale));
+    cursors.insert(CursorKind::LeftSide, load_image(&config.left_side, DEFAULT_LEFT_PTR, scale));
+    cursors.insert(CursorKind::RightSide, load_image(&config.right_side, DEFAULT_LEFT_PTR, scale));
+    cursors
+}
+
+/// Typefaces tried in order when the configured UI font can't be loaded
+const FALLBACK_UI_FONTS: &[&str] = &["Sans", "Mono"];
+/// Font built into orbital, used when none of the installed ones can be loaded so that title bars
+/// and pop-ups still have something to draw with
+const BUILTIN_UI_FONT: &[u8] = include_bytes!("../res/DejaVuSansMono.ttf");
+/// Where orbfont finds fonts on Redox, as `<typeface>/<family>/<style>.ttf`. Orbital leaves the
+/// family to orbfont, which takes [UI_FONT_FAMILY]
+const UI_FONTS_DIR: &str = "/ui/fonts";
+const UI_FONT_FAMILY: &str = "Fira";
+
+/// Name of the font file of a typeface with the given style
+fn font_style(bold: bool, italic: bool) -> &'static str {
+    match (bold, italic) {
+        (false, false) => "Regular",
+        (true, false) => "Bold",
+        (false, true) => "Italic",
+        (true, true) => "BoldItalic",
     }
+}
 
-    fn handle_clipboard_close(&mut self, _orb: &mut Orbital, id: usize) -> Result<usize> {
-        //TODO: implement better clipboard mechanism
-        if self.windows.contains_key(&id) {
-            Ok(0)
-        } else {
-            Err(Error::new(EBADF))
+/// Load the configured UI font with `find`, which is given a typeface and a style. Falls back to
+/// the regular style of each of [FALLBACK_UI_FONTS], then to the font returned by `builtin`
+fn find_ui_font<T>(
+    config: &Config,
+    find: impl Fn(&str, &str) -> Result<T, String>,
+    builtin: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let style = font_style(config.ui_font_bold, config.ui_font_italic);
+    let mut err = match find(&config.ui_font, style) {
+        Ok(font) => return Ok(font),
+        Err(err) => err,
+    };
+    warn!("failed to load font {} {}: {}", config.ui_font, style, err);
+
+    for typeface in FALLBACK_UI_FONTS {
+        if *typeface == config.ui_font && style == font_style(false, false) {
+            continue;
+        }
+        match find(typeface, font_style(false, false)) {
+            Ok(font) => {
+                warn!("falling back to font {}", typeface);
+                return Ok(font);
+            },
+            Err(next) => err = next,
         }
     }
+
+    warn!("no installed font could be loaded, falling back to the built-in font: {}", err);
+    builtin()
 }
-pub struct OrbitalSchemeEvent<'a> {
-    scheme: &'a mut OrbitalScheme,
-    orb: &'a mut Orbital,
+
+/// The typefaces in `root` that have a font of `style`, which are the ones that can be set as the
+/// UI font, sorted by name
+fn font_typefaces(root: &Path, style: &str) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut typefaces: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join(UI_FONT_FAMILY).join(format!("{}.ttf", style)).is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    typefaces.sort();
+    typefaces
 }
 
-impl<'a> OrbitalSchemeEvent<'a> {
-    pub fn redraw(&mut self) {
-        self.scheme.rezbuffer();
+/// The fonts query: a line for every typeface that can be set as the UI font, starting with the
+/// one in use, which is listed even where the installed fonts can't be listed. The built-in font
+/// isn't one that can be set, so it is not listed
+fn fonts_query(current: Option<&str>, typefaces: &[String]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for typeface in current.into_iter().chain(typefaces.iter().map(String::as_str).filter(|&typeface| Some(typeface) != current)) {
+        let _ = writeln!(data, "{}", typeface);
+    }
+    data
+}
 
-        let cursor_rect = self.scheme.cursor_rect();
+const SHIFT_LEFT_MODIFIER : u8 = 1 << 0;
+const SHIFT_RIGHT_MODIFIER : u8 = 1 << 1;
+const SHIFT_ANY_MODIFIER : u8 = 1 << 2;
+const CONTROL_MODIFIER : u8 = 1 << 3;
+const ALT_MODIFIER : u8 = 1 << 4;
+const ALT_GR_MODIFIER : u8 = 1 << 5;
+const ALT_ANY_MODIFIER : u8 = 1 << 6;
+const SUPER_MODIFIER : u8 = 1 << 7;
 
-        // go through the list of rectangles pending a redraw and expand the total redraw rectangle
-        // to encompass all of them
-        let mut total_redraw_opt: Option<Rect> = None;
-        for original_rect in self.scheme.redraws.drain(..) {
-            if ! original_rect.is_empty() {
-                total_redraw_opt = match total_redraw_opt {
-                    Some(total_redraw) => Some(total_redraw.container(&original_rect)),
-                    None => Some(original_rect),
-                };
-            }
+// The modifier keys and the bit they set in the modifier state
+const MODIFIER_KEYS: &[(u8, u8, &str)] = &[
+    (orbclient::K_SUPER, SUPER_MODIFIER, "Super"),
+    (orbclient::K_LEFT_SHIFT, SHIFT_LEFT_MODIFIER, "Shift"),
+    (orbclient::K_RIGHT_SHIFT, SHIFT_RIGHT_MODIFIER, "Shift"),
+    (orbclient::K_CTRL, CONTROL_MODIFIER, "Ctrl"),
+    (orbclient::K_ALT, ALT_MODIFIER, "Alt"),
+    (orbclient::K_ALT_GR, ALT_GR_MODIFIER, "AltGr"),
+];
+
+// Num Lock and Scroll Lock, which orbclient has no scancodes for
+const K_NUM_LOCK: u8 = 0x45;
+const K_SCROLL_LOCK: u8 = 0x46;
+
+const CAPS_LOCK: u8 = 1 << 0;
+const NUM_LOCK: u8 = 1 << 1;
+const SCROLL_LOCK: u8 = 1 << 2;
+
+// The lock keys and the bit they toggle in the lock state
+const LOCK_KEYS: &[(u8, u8)] = &[
+    (orbclient::K_CAPS, CAPS_LOCK),
+    (K_NUM_LOCK, NUM_LOCK),
+    (K_SCROLL_LOCK, SCROLL_LOCK),
+];
+
+// Caps, Num and Scroll Lock, toggled by pressing their keys. The keyboard doesn't tell what they
+// were when orbital started, so they start off
+#[derive(Default)]
+struct LockKeys {
+    locked: u8,
+    // Lock keys held down, whose repeated presses don't toggle them again
+    held: u8,
+}
 
-            for display in self.orb.displays.iter_mut() {
-                let rect = original_rect.intersection(&display.screen_rect());
-                if ! rect.is_empty() {
-                    display.rect(&rect, self.scheme.config.background_color.into());
+impl LockKeys {
+    // Track a key press or release, returning whether it toggled a lock
+    fn key(&mut self, scancode: u8, pressed: bool) -> bool {
+        let Some(&(_, bit)) = LOCK_KEYS.iter().find(|(key, _)| *key == scancode) else {
+            return false;
+        };
+        if ! pressed {
+            self.held &= !bit;
+            return false;
+        }
+        if self.held & bit != 0 {
+            return false;
+        }
+        self.held |= bit;
+        self.locked ^= bit;
+        true
+    }
+}
 
-                    for entry in self.scheme.zbuffer.iter().rev() {
-                        let id = entry.0;
-                        let i = entry.2;
-                        if let Some(window) = self.scheme.windows.get_mut(&id) {
-                            window.draw_title(display, &rect, i == 0, if i == 0 {
-                                &mut self.scheme.window_max
-                            } else {
-                                &mut self.scheme.window_max_unfocused
-                            }, if i == 0 {
-                                &mut self.scheme.window_close
-                            } else {
-                                &mut self.scheme.window_close_unfocused
-                            });
-                            window.draw(display, &rect);
-                        }
-                    }
+// The modifiers query: the modifier state and the lock state, as bitfields
+fn modifiers_query(modifier_state: u8, locks: u8) -> Vec<u8> {
+    format!("{},{}\n", modifier_state, locks).into_bytes()
+}
 
-                    let cursor_intersect = rect.intersection(&cursor_rect);
-                    if ! cursor_intersect.is_empty() {
-                        if let Some(cursor) = self.scheme.cursors.get_mut(&self.scheme.cursor_i) {
-                            display.roi(&cursor_intersect)
-                                .blend(
-                                    &cursor.roi(
-                                        &cursor_intersect.offset(-cursor_rect.left(), -cursor_rect.top())
-                                    )
-                                );
-                        }
-                    }
-                }
-            }
-        }
+// Track a key press or release in the held modifiers, the lock keys and sticky keys, and the
+// modifier state they make up. Returns whether what the modifiers query reads changed, and when
+// the event switched sticky keys on or off, the modifiers that were latched, whose releases were
+// held back and are due now
+fn track_modifiers(
+    held: &mut u8,
+    locks: &mut LockKeys,
+    sticky: &mut StickyKeys,
+    state: &mut u8,
+    scancode: u8,
+    pressed: bool,
+) -> (bool, Option<u8>) {
+    let old_state = *state;
+    let toggled = locks.key(scancode, pressed);
+    let modifier = modifier_bit(scancode);
+    let repeat = modifier.is_some_and(|bit| pressed && *held & bit != 0);
+
+    match (modifier, pressed) {
+        (Some(bit), true) => *held |= bit,
+        (Some(bit), false) => *held &= !bit,
+        (None, _) => {}
+    }
 
-        if self.scheme.win_tabbing {
-            //TODO: add to total_redraw?
-            self.draw_window_list_osd();
+    let mut released = None;
+    if pressed && ! repeat {
+        let latched = sticky.latched;
+        if sticky.press(modifier) {
+            sticky.latched = 0;
+            released = Some(latched);
         }
+    }
 
-        if self.scheme.volume_osd {
-            //TODO: add to total_redraw?
-            self.draw_volume_osd();
-        }
+    *state = modifier_state(*held | sticky.latched);
+    (toggled || *state != old_state, released)
+}
 
-        if self.scheme.shortcuts_osd {
-            //TODO: add to total_redraw?
-            self.draw_shortcuts_osd();
-        }
+// Number of Shift presses in a row that toggle sticky keys
+const STICKY_KEYS_SHIFT_PRESSES: u8 = 5;
 
-        // Add any redraws from OSD's
-        for original_rect in self.scheme.redraws.drain(..) {
-            if ! original_rect.is_empty() {
-                total_redraw_opt = match total_redraw_opt {
-                    Some(total_redraw) => Some(total_redraw.container(&original_rect)),
-                    None => Some(original_rect),
-                };
+fn modifier_bit(scancode: u8) -> Option<u8> {
+    MODIFIER_KEYS.iter().find(|(key, _, _)| *key == scancode).map(|(_, bit, _)| *bit)
+}
+
+// Add the "any" bits for modifiers that have a left and right (or Gr) variant
+fn modifier_state(modifiers: u8) -> u8 {
+    let mut state = modifiers & !(SHIFT_ANY_MODIFIER | ALT_ANY_MODIFIER);
+    if state & (SHIFT_LEFT_MODIFIER | SHIFT_RIGHT_MODIFIER) != 0 {
+        state |= SHIFT_ANY_MODIFIER;
+    }
+    if state & (ALT_MODIFIER | ALT_GR_MODIFIER) != 0 {
+        state |= ALT_ANY_MODIFIER;
+    }
+    state
+}
+
+// The Super shortcut of `shortcuts` bound to `scancode` with the modifiers of `modifier_state`.
+// Modifiers held beyond those of a binding are allowed, so that Super-Shift-Q quits like Super-Q,
+// unless another binding uses them: the binding with the most of the held modifiers wins
+fn shortcut_action(shortcuts: &BTreeMap<ShortcutAction, Vec<KeyCombo>>, scancode: u8, modifier_state: u8) -> Option<ShortcutAction> {
+    let held = KeyCombo {
+        scancode,
+        shift: modifier_state & SHIFT_ANY_MODIFIER != 0,
+        ctrl: modifier_state & CONTROL_MODIFIER != 0,
+        alt: modifier_state & ALT_ANY_MODIFIER != 0,
+        super_key: modifier_state & SUPER_MODIFIER != 0,
+    };
+    let mut found: Option<(ShortcutAction, usize)> = None;
+    for (action, combos) in shortcuts {
+        for combo in combos.iter().filter(|combo| combo.scancode == scancode) {
+            let modifiers = [(combo.shift, held.shift), (combo.ctrl, held.ctrl), (combo.alt, held.alt), (combo.super_key, held.super_key)];
+            if modifiers.iter().any(|&(bound, held)| bound && ! held) {
+                continue;
+            }
+            let count = modifiers.iter().filter(|&&(bound, _)| bound).count();
+            if found.is_none_or(|(_, most)| count > most) {
+                found = Some((*action, count));
             }
         }
+    }
+    found.map(|(action, _)| action)
+}
 
-        // Sync any parts of displays that changed
-        if let Some(total_redraw) = total_redraw_opt {
-            for (i, display) in self.orb.displays.iter_mut().enumerate() {
-                let display_redraw = total_redraw.intersection(&display.screen_rect());
-                if ! display_redraw.is_empty() {
-                    // Keep synced with vesad
-                    #[allow(dead_code)]
-                    #[repr(packed)]
-                    struct SyncRect {
-                        x: i32,
-                        y: i32,
-                        w: i32,
-                        h: i32,
-                    }
-
-                    let sync_rect = SyncRect {
-                        x: display_redraw.left() - display.x,
-                        y: display_redraw.top() - display.y,
-                        w: display_redraw.width(),
-                        h: display_redraw.height(),
-                    };
+// The name of the key with `scancode` in the shortcuts overlay, its scancode for keys without one
+fn key_name(scancode: u8) -> String {
+    const LETTERS: [u8; 26] = [
+        orbclient::K_A, orbclient::K_B, orbclient::K_C, orbclient::K_D, orbclient::K_E, orbclient::K_F,
+        orbclient::K_G, orbclient::K_H, orbclient::K_I, orbclient::K_J, orbclient::K_K, orbclient::K_L,
+        orbclient::K_M, orbclient::K_N, orbclient::K_O, orbclient::K_P, orbclient::K_Q, orbclient::K_R,
+        orbclient::K_S, orbclient::K_T, orbclient::K_U, orbclient::K_V, orbclient::K_W, orbclient::K_X,
+        orbclient::K_Y, orbclient::K_Z,
+    ];
+    if let Some(i) = LETTERS.iter().position(|&letter| letter == scancode) {
+        return char::from(b'A' + i as u8).to_string();
+    }
+    let name = match scancode {
+        orbclient::K_1..=orbclient::K_9 => return (scancode - orbclient::K_1 + 1).to_string(),
+        orbclient::K_F1..=orbclient::K_F10 => return format!("F{}", scancode - orbclient::K_F1 + 1),
+        orbclient::K_0 => "0",
+        orbclient::K_MINUS => "-",
+        orbclient::K_EQUALS => "=",
+        orbclient::K_BRACE_OPEN => "{",
+        orbclient::K_BRACE_CLOSE => "}",
+        orbclient::K_BACKSLASH => "\\",
+        orbclient::K_SEMICOLON => ";",
+        orbclient::K_QUOTE => "'",
+        orbclient::K_TICK => "`",
+        orbclient::K_COMMA => ",",
+        orbclient::K_PERIOD => ".",
+        orbclient::K_SLASH => "/",
+        orbclient::K_TAB => "TAB",
+        orbclient::K_ENTER => "ENTER",
+        orbclient::K_SPACE => "Space",
+        orbclient::K_BKSP => "Backspace",
+        orbclient::K_ESC => "Esc",
+        orbclient::K_UP => "up_arrow",
+        orbclient::K_DOWN => "down_arrow",
+        orbclient::K_LEFT => "left_arrow",
+        orbclient::K_RIGHT => "right_arrow",
+        orbclient::K_HOME => "Home",
+        orbclient::K_END => "End",
+        orbclient::K_PGUP => "PageUp",
+        orbclient::K_PGDN => "PageDown",
+        orbclient::K_DEL => "Del",
+        orbclient::K_F11 => "F11",
+        orbclient::K_F12 => "F12",
+        _ => return format!("<{}>", scancode),
+    };
+    name.to_string()
+}
 
-                    match display.file.write(unsafe {
-                        slice::from_raw_parts(
-                            &sync_rect as *const SyncRect as *const u8,
-                            mem::size_of::<SyncRect>()
-                        )
-                    }) {
-                        Ok(_) => (),
-                        Err(err) => error!("failed to sync display {}: {}", i, err),
-                    }
+// The lines of the shortcuts overlay: the Super shortcuts with the keys `shortcuts` binds them
+// to, as `Super-Shift-up_arrow / Super-K: What it does`, leaving out the actions bound to none,
+// then the shortcuts that can't be rebound
+fn shortcuts_list(shortcuts: &BTreeMap<ShortcutAction, Vec<KeyCombo>>) -> Vec<String> {
+    let mut list = Vec::new();
+    for (_, action) in ShortcutAction::NAMES {
+        let Some(combos) = shortcuts.get(action).filter(|combos| ! combos.is_empty()) else {
+            continue;
+        };
+        let keys: Vec<String> = combos.iter().map(|combo| {
+            let mut key = String::new();
+            for (held, name) in [(combo.super_key, "Super-"), (combo.ctrl, "Ctrl-"), (combo.alt, "Alt-"), (combo.shift, "Shift-")] {
+                if held {
+                    key.push_str(name);
                 }
             }
-        }
+            key + &key_name(combo.scancode)
+        }).collect();
+        list.push(format!("{}: {}", keys.join(" / "), action.description()));
     }
+    list.push("Alt-Space: Open the window menu".to_string());
+    list.push("Shift five times: Toggle sticky keys".to_string());
+    list
+}
 
-    fn volume(&mut self, volume: Volume) {
-        let value = match fs::read_to_string("audio:volume") {
-            Ok(string) => match string.parse::<i32>() {
-                Ok(value) => value,
-                Err(err) => {
-                    error!("failed to parse volume '{}': {}", string, err);
-                    return;
-                }
-            },
-            Err(err) => {
-                error!("failed to read volume: {}", err);
-                return;
-            }
-        };
+// Key combinations grabbed by windows, and the grabbed keys that are still held so that their
+// release goes to the same window as their press
+#[derive(Default)]
+struct KeyGrabs {
+    grabs: BTreeMap<KeyCombo, usize>,
+    held: BTreeMap<u8, usize>,
+}
 
-        self.scheme.volume_value = match volume {
-            Volume::Down => cmp::max(0, value - 5),
-            Volume::Up => cmp::min(100, value + 5),
-            Volume::Toggle => if value == 0 {
-                self.scheme.volume_toggle
-            } else {
-                self.scheme.volume_toggle = value;
-                0
-            }
-        };
+impl KeyGrabs {
+    fn grab(&mut self, combo: KeyCombo, id: usize) -> Result<()> {
+        let function_key = matches!(combo.scancode, orbclient::K_F1..=orbclient::K_F10 | orbclient::K_F11 | orbclient::K_F12);
+        if modifier_bit(combo.scancode).is_some() || ! (combo.ctrl || combo.alt || function_key) {
+            return Err(Error::new(EINVAL));
+        }
+        // the built-in shortcuts win
+        if combo.super_key || matches!(combo.scancode, orbclient::K_VOLUME_TOGGLE | orbclient::K_VOLUME_DOWN | orbclient::K_VOLUME_UP) {
+            return Err(Error::new(EBUSY));
+        }
 
-        match fs::write("audio:volume", format!("{}", self.scheme.volume_value)) {
-            Ok(()) => (),
-            Err(err) => {
-                error!("failed to write volume: {}", err);
-                return;
+        match self.grabs.get(&combo) {
+            Some(&owner) if owner != id => Err(Error::new(EBUSY)),
+            _ => {
+                self.grabs.insert(combo, id);
+                Ok(())
             }
         }
+    }
 
-        self.scheme.volume_osd = true;
+    fn release_window(&mut self, id: usize) {
+        self.grabs.retain(|_, owner| *owner != id);
+        self.held.retain(|_, owner| *owner != id);
     }
 
-    // set the focus to be on a window by id, and redraw it
-    fn focus(&mut self, id: usize, focused: bool) {
-        self.scheme.focus(id, focused);
+    // The window that grabbed a key event with the current `modifier_state`, if any. Only the left
+    // Alt counts for "alt" grabs, AltGr types text on many layouts
+    fn route(&mut self, scancode: u8, pressed: bool, modifier_state: u8) -> Option<usize> {
+        if pressed {
+            let combo = KeyCombo {
+                scancode,
+                shift: modifier_state & SHIFT_ANY_MODIFIER != 0,
+                ctrl: modifier_state & CONTROL_MODIFIER != 0,
+                alt: modifier_state & ALT_MODIFIER != 0,
+                super_key: modifier_state & SUPER_MODIFIER != 0,
+            };
+            let id = *self.grabs.get(&combo)?;
+            self.held.insert(scancode, id);
+            Some(id)
+        } else {
+            self.held.remove(&scancode)
+        }
     }
+}
 
-    // Tab through the list of selectable windows, changing window order and focus to bring
-    // the next one to the front and push the previous one to the back.
-    // Note that the selectable windows maybe interlaced in the stack with non-selectable windows,
-    // the first selectable window may not be the first in the stack and the bottom selectable
-    // window may not be the last in the stack
-    fn super_tab(&mut self) {
-        // Enter win_tabbing mode
-        self.scheme.win_tabbing = true;
+// Most notifications waiting or shown at once, more are refused until some are dismissed
+const TOAST_QUEUE_LIMIT: usize = 16;
 
-        let mut selectable_window_indexes: Vec<usize> = vec![];
-        for (index, id) in self.scheme.order.iter().enumerate() {
-            if let Some(window) = self.scheme.windows.get(id) {
-                if !window.title.is_empty() {
-                    selectable_window_indexes.push(index);
-                }
-            }
-        }
+// Space between notification toasts, and between them and the edges of the screen
+const TOAST_MARGIN: i32 = 8;
 
-        if selectable_window_indexes.len() > 1 {
-            // Disable dragging
-            self.scheme.dragging = DragMode::None;
+// A notification shown for a while in a corner of the screen
+struct Toast {
+    text: String,
+    // When the toast is dismissed, set once it is shown
+    expires: Option<Instant>,
+}
 
-            // remove focus from the first selectable window in the window stack and make it
-            // the last selectable window in the stack. Indexes are the indexes of windows
-            // in self.scheme.order
-            let front_index = selectable_window_indexes[0];
-            let next_index = selectable_window_indexes[1];
-            let last_index = selectable_window_indexes[selectable_window_indexes.len()-1];
-            if let Some(front_id) = self.scheme.order.remove(front_index) {
-                self.scheme.order.insert(last_index, front_id);
-                self.focus(front_id, false); // remove focus from it
-
-                // move to the front and give focus to the next selectable window in the stack
-                if let Some(next_id) = self.scheme.order.get(next_index) {
-                    self.focus(*next_id, true); // move focus to next in stack
-                }
-            }
+// The notifications waiting or being shown, oldest first
+#[derive(Default)]
+struct Toasts {
+    queue: VecDeque<Toast>,
+    // Where the toasts were last drawn
+    rect: Rect,
+    // The time the pending timeout was set for
+    timeout: Option<Instant>,
+}
+
+impl Toasts {
+    // Queue a toast, returns false if the queue is full
+    fn push(&mut self, text: String) -> bool {
+        if self.queue.len() >= TOAST_QUEUE_LIMIT {
+            return false;
         }
+        self.queue.push_back(Toast { text, expires: None });
+        true
     }
 
-    // Create a [Rect][orbital-core::rect::Rect] that places a popup in the middle of the display
-    fn popup_rect(image: &ImageRef, width: i32, height: i32) -> Rect {
-        Rect::new(image.width()/2 - width/2,
-                                    image.height()/2 - height/2,
-                                    width, height)
+    // Dismiss the toasts that expired by `now`, and show waiting ones while fewer than
+    // `max_visible` are shown. Returns true if the shown toasts changed.
+    fn update(&mut self, now: Instant, duration: Duration, max_visible: usize) -> bool {
+        let len = self.queue.len();
+        self.queue.retain(|toast| toast.expires.is_none_or(|expires| expires > now));
+        let mut changed = self.queue.len() != len;
+
+        for toast in self.queue.iter_mut().take(max_visible) {
+            if toast.expires.is_none() {
+                toast.expires = Some(now + duration);
+                changed = true;
+            }
+        }
+        changed
     }
 
-    // Called by redraw() to draw the list of currently open windows in the middle of the screen.
-    // Filter out app windows with no title.
-    // If there are no windows to select, nothing is drawn.
-    fn draw_window_list_osd(&mut self) {
-        const SELECT_POPUP_TOP_BOTTOM_MARGIN: u32 = 2;
-        const SELECT_POPUP_SIDE_MARGIN: i32 = 4;
-        const SELECT_ROW_HEIGHT: u32 = 20;
-        const SELECT_ROW_WIDTH: i32 = 400;
-        const FONT_HEIGHT : f32 = 16.0;
-
-        //TODO: HiDPI
+    fn visible(&self) -> impl Iterator<Item = &Toast> {
+        self.queue.iter().take_while(|toast| toast.expires.is_some())
+    }
 
-        let selectable_window_ids: Vec<usize>= self.scheme.order.iter().filter(|id| {
-            if let Some(window) = self.scheme.windows.get(id) {
-                !window.title.is_empty()
-            } else {
-                false
-            }
-        }).copied().collect();
+    // When the next shown toast expires
+    fn next_expiry(&self) -> Option<Instant> {
+        self.visible().filter_map(|toast| toast.expires).min()
+    }
+}
 
-        if selectable_window_ids.len() > 1 {
-            // follow the look of the current config - in terms of colors
-            let Config { bar_color, bar_highlight_color, text_color, text_highlight_color, .. } = *self.scheme.config;
+// Stack toasts of the given sizes in `corner` of `area`, the first one closest to the corner
+fn toast_rects(area: &Rect, corner: Anchor, sizes: &[(i32, i32)]) -> Vec<Rect> {
+    let mut offset = TOAST_MARGIN;
+    sizes.iter().map(|&(width, height)| {
+        let x = match corner {
+            Anchor::TopLeft | Anchor::BottomLeft => area.left() + TOAST_MARGIN,
+            Anchor::TopRight | Anchor::BottomRight => area.right() - TOAST_MARGIN - width,
+        };
+        let y = match corner {
+            Anchor::TopLeft | Anchor::TopRight => area.top() + offset,
+            Anchor::BottomLeft | Anchor::BottomRight => area.bottom() - offset - height,
+        };
+        offset += height + TOAST_MARGIN;
+        Rect::new(x, y, width, height)
+    }).collect()
+}
 
-            let list_h = (selectable_window_ids.len() as u32 * SELECT_ROW_HEIGHT + (SELECT_POPUP_TOP_BOTTOM_MARGIN * 2)) as i32;
-            let list_w = SELECT_ROW_WIDTH;
-            let popup_rect = Self::popup_rect(self.orb.image(), list_w, list_h);
-            let mut image = Image::from_color(list_w, list_h, bar_color.into());
+// How long minimizing or restoring a window is animated for
+const ANIMATION_DURATION: Duration = Duration::from_millis(200);
+// Time between the frames of a window animation
+const ANIMATION_FRAME: Duration = Duration::from_millis(16);
+// Thickness of the outline drawn by window animations, before display scaling
+const ANIMATION_OUTLINE: i32 = 2;
+
+// A window minimizing to its taskbar button or restoring from it, drawn as an outline moving
+// between the two. A restored window is only shown once the animation is over.
+struct WindowAnimation {
+    id: usize,
+    from: Rect,
+    to: Rect,
+    start: Instant,
+    restore: bool,
+    easing: Easing,
+}
 
-            for (selectable_index, window_id) in selectable_window_ids.iter().enumerate() {
-                if let Some(window) = self.scheme.windows.get(window_id) {
-                    let vertical_offset = selectable_index as i32 * SELECT_ROW_HEIGHT as i32 + SELECT_POPUP_TOP_BOTTOM_MARGIN as i32;
-                    let text = self.scheme.font.render(&window.title, FONT_HEIGHT);
-                    if selectable_index == 0 {
-                        image.rect(0, vertical_offset, list_w as u32, SELECT_ROW_HEIGHT, bar_highlight_color.into());
-                        text.draw(&mut image, SELECT_POPUP_SIDE_MARGIN, vertical_offset + SELECT_POPUP_TOP_BOTTOM_MARGIN as i32, text_highlight_color.into());
-                    } else {
-                        text.draw(&mut image, SELECT_POPUP_SIDE_MARGIN, vertical_offset + SELECT_POPUP_TOP_BOTTOM_MARGIN as i32, text_color.into());
-                    }
-                }
-            }
-            self.orb.image_mut().roi(&popup_rect).blit(&image.roi(&Rect::new(0, 0, list_w, list_h)));
-            self.scheme.popup_rect = popup_rect;
-            schedule(&mut self.scheme.redraws, popup_rect);
+impl WindowAnimation {
+    // The outline at `now`, or None once the animation is over
+    fn outline(&self, now: Instant) -> Option<Rect> {
+        let progress = now.saturating_duration_since(self.start).as_secs_f32() / ANIMATION_DURATION.as_secs_f32();
+        if progress < 1.0 {
+            Some(lerp_rect(&self.from, &self.to, self.easing.apply(progress)))
+        } else {
+            None
         }
     }
+}
 
-    // Draw an on screen display (overlay) for volume control
-    fn draw_volume_osd(&mut self) {
-        let Config { bar_color, bar_highlight_color, .. } = *self.scheme.config;
+// The rect `progress` of the way from `from` to `to`, where progress goes from 0.0 to 1.0
+fn lerp_rect(from: &Rect, to: &Rect, progress: f32) -> Rect {
+    let lerp = |from: i32, to: i32| from + ((to - from) as f32 * progress).round() as i32;
+    Rect::new(
+        lerp(from.left(), to.left()),
+        lerp(from.top(), to.top()),
+        lerp(from.width(), to.width()),
+        lerp(from.height(), to.height()),
+    )
+}
 
-        const BAR_HEIGHT : i32 = 20;
-        const BAR_WIDTH : i32 = 100;
-        const POPUP_MARGIN: i32 = 2;
+// The top, bottom, left and right edges of an outline `thickness` pixels wide along the inside
+// of `rect`
+fn outline_edges(rect: &Rect, thickness: i32) -> [Rect; 4] {
+    let horizontal = cmp::min(thickness, rect.height());
+    let vertical = cmp::min(thickness, rect.width());
+    [
+        Rect::new(rect.left(), rect.top(), rect.width(), horizontal),
+        Rect::new(rect.left(), rect.bottom() - horizontal, rect.width(), horizontal),
+        Rect::new(rect.left(), rect.top(), vertical, rect.height()),
+        Rect::new(rect.right() - vertical, rect.top(), vertical, rect.height()),
+    ]
+}
 
-        //TODO: HiDPI
-        let list_h = BAR_HEIGHT + (2 * POPUP_MARGIN);
-        let list_w = BAR_WIDTH + (2 * POPUP_MARGIN);
-        let popup_rect = Self::popup_rect(self.orb.image(), list_w, list_h);
-        // Color copied over from orbtk's window background
-        let mut image = Image::from_color(list_w, list_h, bar_color.into());
-        image.rect(POPUP_MARGIN, POPUP_MARGIN, self.scheme.volume_value as u32, BAR_HEIGHT as u32, bar_highlight_color.into());
-        self.orb.image_mut().roi(&popup_rect).blit(&image.roi(&Rect::new(0, 0, list_w, list_h)));
-        self.scheme.popup_rect = popup_rect;
-        schedule(&mut self.scheme.redraws, popup_rect);
+// Counts the frames composited to the displays, for clients correlating their input with the
+// frame it showed up in. The time is taken from CLOCK_MONOTONIC by the compositor when a frame
+// is synced, so it is only good for comparing against other times on that clock, not for the
+// time it was scanned out by the display.
+#[derive(Default)]
+struct FrameClock {
+    count: u64,
+    last: Duration,
+}
+
+impl FrameClock {
+    fn tick(&mut self, now: Duration) {
+        self.count += 1;
+        self.last = now;
     }
 
-    const SHORTCUTS_LIST: &'static [&'static str] = &[
-        "Super-Q: Quit current window",
-        "Super-TAB: Cycle through active windows bringing to the front of the stack",
-        "Super-{: Volume down",
-        "Super-}: Volume up",
-        "Super-\\: Volume toggle (mute / unmute)",
-        "Super-Shift-left: Tile window to left",
-        "Super-Shift-right: Tile window to right",
-        "Super-Shift-up: Tile window to top",
-        "Super-Shift-down: Tile window to bottom",
-        "Super-left_arrow: Move window left",
-        "Super-right_arrow: Move window right",
-        "Super-up_arrow: Move window up",
-        "Super-down_arrow: Move window down",
-        "Super-C: Copy to copy buffer",
-        "Super-X: Cut to copy buffer",
-        "Super-V: Paste from the copy buffer",
-        "Super-M: Toggle window max (maximize or restore)",
-        "Super-ENTER: Toggle window max (maximize or restore)",
-    ];
+    // The frame query: the number of the last frame and when it was synced, in nanoseconds
+    // on CLOCK_MONOTONIC
+    fn query(&self) -> Vec<u8> {
+        format!("{},{}\n", self.count, self.last.as_nanos()).into_bytes()
+    }
+}
 
-    // Draw an on screen display (overlay) of available SUPER keyboard shortcuts
-    fn draw_shortcuts_osd(&mut self) {
-        const ROW_HEIGHT: u32 = 20;
-        const ROW_WIDTH: i32 = 400;
-        const POPUP_BORDER: u32 = 2;
-        const FONT_HEIGHT : f32 = 16.0;
+// When each display was last synced, for pacing the frames of each display to its own refresh
+// rate with frame_pacing
+#[derive(Default)]
+struct FramePacer {
+    synced: Vec<Option<Instant>>,
+    // When the pending timeout for the next deferred frame is due, None when there is none
+    due: Option<Instant>,
+}
 
-        // follow the look of the current config - in terms of colors
-        let Config { bar_color, bar_highlight_color, text_highlight_color, .. } = *self.scheme.config;
+impl FramePacer {
+    // When the next frame of `display`, refreshing at `refresh_hz`, is due. None if it can be
+    // synced right away, because it wasn't synced yet or its refresh rate isn't known
+    fn next_frame(&self, display: usize, refresh_hz: u32) -> Option<Instant> {
+        let synced = self.synced.get(display).copied().flatten()?;
+        (refresh_hz > 0).then(|| synced + Duration::from_secs(1) / refresh_hz)
+    }
 
-        let list_h = (Self::SHORTCUTS_LIST.len() as u32 * ROW_HEIGHT + (POPUP_BORDER * 2)) as i32;
-        let list_w = ROW_WIDTH;
-        let popup_rect = Self::popup_rect(self.orb.image(), list_w, list_h);
-        let mut image = Image::from_color(list_w, list_h, bar_color.into());
+    fn is_due(&self, display: usize, refresh_hz: u32, now: Instant) -> bool {
+        self.next_frame(display, refresh_hz).is_none_or(|next| now >= next)
+    }
 
-        for (index, shortcut) in Self::SHORTCUTS_LIST.iter().enumerate() {
-            let vertical_offset = index as i32 * ROW_HEIGHT as i32 + POPUP_BORDER as i32;
-            let text = self.scheme.font.render(shortcut, FONT_HEIGHT);
-            image.rect(0, vertical_offset, list_w as u32, ROW_HEIGHT, bar_highlight_color.into());
-            text.draw(&mut image, POPUP_BORDER as i32, vertical_offset + POPUP_BORDER as i32, text_highlight_color.into());
+    fn synced(&mut self, display: usize, now: Instant) {
+        if self.synced.len() <= display {
+            self.synced.resize(display + 1, None);
         }
-
-        self.orb.image_mut().roi(&popup_rect).blit(&image.roi(&Rect::new(0, 0, list_w, list_h)));
-        self.scheme.popup_rect = popup_rect;
-        schedule(&mut self.scheme.redraws, popup_rect);
+        self.synced[display] = Some(now);
     }
+}
 
-    // Keep track of the modifier keys state based on past keydown/keyup events
-    fn track_modifier_state(&mut self, scancode: u8, pressed: bool) {
-        match (scancode, pressed) {
-            (orbclient::K_SUPER, true) => self.scheme.modifier_state |= SUPER_MODIFIER,
-            (orbclient::K_SUPER, false) => self.scheme.modifier_state &= !SUPER_MODIFIER,
-            (orbclient::K_LEFT_SHIFT, true) => self.scheme.modifier_state |= SHIFT_LEFT_MODIFIER,
-            (orbclient::K_LEFT_SHIFT, false) => self.scheme.modifier_state &= !SHIFT_LEFT_MODIFIER,
-            (orbclient::K_RIGHT_SHIFT, true) => self.scheme.modifier_state |= SHIFT_RIGHT_MODIFIER,
-            (orbclient::K_RIGHT_SHIFT, false) => self.scheme.modifier_state &= !SHIFT_RIGHT_MODIFIER,
-            (orbclient::K_CTRL, true) => self.scheme.modifier_state |= CONTROL_MODIFIER,
-            (orbclient::K_CTRL, false) => self.scheme.modifier_state &= !CONTROL_MODIFIER,
-            (orbclient::K_ALT, true) => self.scheme.modifier_state |= ALT_MODIFIER,
-            (orbclient::K_ALT, false) => self.scheme.modifier_state &= !ALT_MODIFIER,
-            (orbclient::K_ALT_GR, true) => self.scheme.modifier_state |= ALT_GR_MODIFIER,
-            (orbclient::K_ALT_GR, false) => self.scheme.modifier_state &= !ALT_GR_MODIFIER,
-            _ => {}
-        }
+// When each window last synced, for capping the rate at which windows that aren't focused are
+// redrawn with bg_window_max_fps. A sync that comes too soon after the last one is deferred, and
+// all the syncs a window makes until then are coalesced into the one done when its interval is up
+#[derive(Default)]
+struct SyncLimiter {
+    synced: BTreeMap<usize, Instant>,
+    deferred: BTreeSet<usize>,
+    // When the pending timeout for the next deferred sync is due, None when there is none
+    due: Option<Instant>,
+}
 
-        if self.scheme.modifier_state & SHIFT_LEFT_MODIFIER != 0 ||
-            self.scheme.modifier_state & SHIFT_RIGHT_MODIFIER != 0 {
-            self.scheme.modifier_state |= SHIFT_ANY_MODIFIER;
+impl SyncLimiter {
+    // Whether window `id` syncing at `now` can be redrawn right away, with `interval` the least
+    // time between its redraws, None for no limit. If not, the sync is deferred
+    fn sync(&mut self, id: usize, now: Instant, interval: Option<Duration>) -> bool {
+        let due = interval.is_none_or(|interval| {
+            self.synced.get(&id).is_none_or(|&synced| now >= synced + interval)
+        });
+        if due {
+            self.synced.insert(id, now);
+            self.deferred.remove(&id);
         } else {
-            self.scheme.modifier_state &= !SHIFT_ANY_MODIFIER;
+            self.deferred.insert(id);
         }
+        due
+    }
 
-        if self.scheme.modifier_state & ALT_MODIFIER != 0 ||
-            self.scheme.modifier_state & ALT_GR_MODIFIER != 0 {
-            self.scheme.modifier_state |= ALT_ANY_MODIFIER;
-        } else {
-            self.scheme.modifier_state &= !ALT_ANY_MODIFIER;
+    // When the next deferred sync is due, None when there is none
+    fn next_sync(&self, interval: Duration) -> Option<Instant> {
+        self.deferred.iter()
+            .filter_map(|id| self.synced.get(id))
+            .map(|&synced| synced + interval)
+            .min()
+    }
+
+    // The deferred syncs that are due at `now`, which are done from then on
+    fn flush(&mut self, now: Instant, interval: Duration) -> Vec<usize> {
+        let due: Vec<usize> = self.deferred.iter().copied()
+            .filter(|id| self.synced.get(id).is_none_or(|&synced| now >= synced + interval))
+            .collect();
+        for &id in &due {
+            self.deferred.remove(&id);
+            self.synced.insert(id, now);
         }
+        due
     }
 
-    // Move the front-most window horizontally and vertically by the number of pixels passed
-    fn move_front_window(&mut self, h_movement: i32, v_movement: i32) {
-        if let Some(id) = self.scheme.order.front() {
-            if let Some(window) = self.scheme.windows.get_mut(id) {
-                schedule(&mut self.scheme.redraws, window.title_rect());
-                schedule(&mut self.scheme.redraws, window.rect());
+    fn remove(&mut self, id: usize) {
+        self.synced.remove(&id);
+        self.deferred.remove(&id);
+    }
+}
 
-                // Align location to grid
-                window.x -= window.x % GRID_SIZE;
-                window.y -= window.y % GRID_SIZE;
+/// Bytes of updates a recording client can fall behind by before they are dropped for a keyframe,
+/// on top of the room for the keyframe itself, see [record_limit]
+const RECORD_QUEUE_BYTES: usize = 64 * 1024 * 1024;
+
+// Damage all of the contents of a window that synced, and schedule its redraw
+fn window_synced(redraws: &mut Vec<Rect>, window: &mut Window) {
+    let (width, height) = window.buffer_size();
+    window.damage_contents(Rect::new(0, 0, width, height));
+    // a title bar deferred until the first paint is drawn along with it
+    if ! window.title_shown() {
+        schedule(redraws, window.title_rect());
+    }
+    // The whole window is redrawn, which also clears the loading spinner
+    window.painted = true;
+    schedule(redraws, window.rect());
+}
 
-                window.x += h_movement;
-                window.y += v_movement;
+// Queue the composited `rect` of a display at (x, y) for every recording, returning whether one
+// fell behind and has to catch up with a keyframe
+fn record_display(recorders: &mut BTreeMap<usize, Recorder>, image: &impl Renderer, x: i32, y: i32, rect: &Rect, limit: usize) -> bool {
+    if recorders.is_empty() {
+        return false;
+    }
+    let update = record_update(image, x, y, rect);
+    let mut keyframe = false;
+    for recorder in recorders.values_mut() {
+        keyframe |= ! recorder.push(update.clone(), limit);
+    }
+    keyframe
+}
 
-                // Ensure window remains visible
-                window.x = cmp::max(
-                    -window.width() + GRID_SIZE,
-                    cmp::min(
-                        self.orb.image().width() - GRID_SIZE,
-                        window.x
-                    )
-                );
-                window.y = cmp::max(
-                    -window.height() + GRID_SIZE,
-                    cmp::min(
-                        self.orb.image().height() - GRID_SIZE,
-                        window.y
-                    )
-                );
+/// How many recordings can be open at once, as each one keeps its own queue of updates
+const MAX_RECORDERS: usize = 4;
 
-                let move_event = MoveEvent {
-                    x: window.x,
-                    y: window.y
-                }.to_event();
-                window.event(move_event);
+// How many bytes of updates a recording can queue with the displays at `screens`: a keyframe of
+// all of them, however large they are, and RECORD_QUEUE_BYTES more for falling behind after it
+fn record_limit(screens: &[Rect]) -> usize {
+    let keyframe: usize = screens.iter().map(|screen| 16 + screen.area() as usize * 4).sum();
+    keyframe + RECORD_QUEUE_BYTES
+}
 
-                schedule(&mut self.scheme.redraws, window.title_rect());
-                schedule(&mut self.scheme.redraws, window.rect());
-            }
-        }
+// An update of a recording: `rect` of the screen, which `image` shows from (x, y) on. The rect
+// as four little endian i32s, x, y, width and height, followed by its pixels row by row as
+// little endian u32s
+fn record_update(image: &impl Renderer, x: i32, y: i32, rect: &Rect) -> Vec<u8> {
+    let image_rect = rect.offset(-x, -y);
+    let mut update = Vec::with_capacity(16 + image_rect.area() as usize * 4);
+    for value in [rect.left(), rect.top(), rect.width(), rect.height()] {
+        update.extend_from_slice(&value.to_le_bytes());
     }
+    extend_pixels(&mut update, image, &image_rect);
+    update
+}
 
-    fn clipboard_event(&mut self, kind: u8) {
-        if let Some(id) = self.scheme.order.front() {
-            if let Some(window) = self.scheme.windows.get_mut(id) {
-                //TODO: set window's clipboard to primary
-                let clipboard_event = ClipboardEvent { kind, size: 0}.to_event();
-                window.event(clipboard_event);
-            }
+// Append the pixels of `rect` of `image` to `data` row by row, as little endian u32s, which are
+// the bytes of each pixel in BGRA order
+fn extend_pixels(data: &mut Vec<u8>, image: &impl Renderer, rect: &Rect) {
+    let stride = image.width() as usize;
+    for row in rect.top()..rect.bottom() {
+        let start = row as usize * stride + rect.left() as usize;
+        for pixel in &image.data()[start..start + rect.width() as usize] {
+            data.extend_from_slice(&pixel.data.to_le_bytes());
         }
     }
+}
 
-    fn quit_front_window(&mut self) {
-        if let Some(id) = self.scheme.order.front() {
-            if let Some(window) = self.scheme.windows.get_mut(id) {
-                window.event(QuitEvent.to_event());
-            }
-        }
-    }
+// The screenshot query: the pixels of the whole image, see extend_pixels
+fn screenshot(image: &impl Renderer) -> Vec<u8> {
+    let rect = Rect::new(0, 0, image.width() as i32, image.height() as i32);
+    let mut data = Vec::with_capacity(rect.area() as usize * 4);
+    extend_pixels(&mut data, image, &rect);
+    data
+}
 
-    // tile a window to a defined position. If no window id is provided it will use the front window
-    fn tile_window(&mut self, window_id: Option<&usize>, position: TilePosition) {
-        if let Some(id) = window_id.or(self.scheme.order.front()) {
-            if let Some(window) = self.scheme.windows.get_mut(id) {
-                let display_index = Self::get_display_index(&self.orb.displays, &window.rect());
-                schedule(&mut self.scheme.redraws, window.title_rect());
-                schedule(&mut self.scheme.redraws, window.rect());
+// A client recording the screen from `orbital:record`, with the updates composited that it
+// hasn't read yet
+struct Recorder {
+    queue: VecDeque<Vec<u8>>,
+    // How much of the update at the front of the queue was read
+    seek: usize,
+    // Whether the client was notified of the updates queued
+    notified: bool,
+    // Whether a keyframe was asked for to catch up, and hasn't been composited yet
+    keyframe: bool,
+}
 
-                let (x, y, width, height) =  match window.restore.take() {
-                    None => {
-                        // we are about to maximize window, so store current size for restore later
-                        window.restore = Some(window.rect());
-
-                        let top = self.orb.displays[display_index].y + window.title_rect().height();
-                        let left = self.orb.displays[display_index].x;
-                        let max_height = self.orb.displays[display_index].image.height() -
-                            window.title_rect().height();
-                        let max_width = self.orb.displays[display_index].image.width();
-                        let half_width = (max_width / 2) as u32;
-                        let half_height = (max_height / 2) as u32;
-
-                        match position {
-                            LeftHalf => (left, top, half_width, max_height as u32),
-                            RightHalf => (left + half_width as i32, top, half_width, max_height as u32),
-                            TopHalf => (left, top, max_width as u32, half_height),
-                            BottomHalf => (left, top + half_height as i32, max_width as u32, half_height),
-                            FullScreen => (left, top, max_width as u32, max_height as u32),
-                        }
-                    },
-                    Some(restore) => {
-                        (restore.left(), restore.top(), restore.width() as u32, restore.height() as u32)
-                    }
-                };
+impl Recorder {
+    fn new() -> Self {
+        Recorder { queue: VecDeque::new(), seek: 0, notified: false, keyframe: false }
+    }
 
-                // TODO understand why this is needed and why handle_window_position isn't enough
-                window.x = x;
-                window.y = y;
-                window.event(MoveEvent { x, y }.to_event());
+    // The bytes of updates left to read
+    fn queued(&self) -> usize {
+        self.queue.iter().map(Vec::len).sum::<usize>() - self.seek
+    }
 
-                window.event(ResizeEvent { width, height }.to_event());
-            };
+    // Queue `update`, unless that takes more than `limit` bytes. Then the client is too slow to
+    // keep up, and the updates it hasn't started reading are dropped along with `update`,
+    // returning false: what it missed is only caught up with a keyframe. The update being read is
+    // kept, so that the client still reads whole updates. Until the keyframe is composited, see
+    // Recorder::composited, updates are dropped without asking for another
+    fn push(&mut self, update: Vec<u8>, limit: usize) -> bool {
+        if self.keyframe {
+            return true;
+        }
+        if self.queued() + update.len() > limit {
+            self.queue.truncate(if self.seek > 0 { 1 } else { 0 });
+            self.keyframe = true;
+            return false;
         }
+        self.queue.push_back(update);
+        self.notified = false;
+        true
     }
 
-    // undraw any overlay that was being displayed and exit the mode causing it to be displayed
-    fn close_overlays(&mut self) {
-        // redraw the area that was occupied by the popup
-        schedule(&mut self.scheme.redraws, self.scheme.popup_rect);
-        // disable drawing of the win-tab or volume popup or shortcuts overlay on redraw
-        self.scheme.win_tabbing = false;
-        self.scheme.volume_osd = false;
-        self.scheme.shortcuts_osd = false;
+    // A frame is about to be composited, which includes the keyframe asked for by the last one
+    fn composited(&mut self) {
+        self.keyframe = false;
     }
 
-    // Process incoming key events
-    fn key_event(&mut self, event: KeyEvent) {
-        self.track_modifier_state(event.scancode, event.pressed);
-
-        match (event.scancode, event.pressed) {
-            (orbclient::K_SUPER, true) => self.scheme.shortcuts_osd = true,
-            (orbclient::K_SUPER, false) => self.close_overlays(),
-            (orbclient::K_VOLUME_TOGGLE, true) => self.volume(Volume::Toggle),
-            (orbclient::K_VOLUME_DOWN, true) => self.volume(Volume::Down),
-            (orbclient::K_VOLUME_UP, true) => self.volume(Volume::Up),
-            (orbclient::K_VOLUME_TOGGLE | orbclient::K_VOLUME_DOWN | orbclient::K_VOLUME_UP, false) =>
-                self.scheme.volume_osd = false,
-            _ => {}
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        while let Some(update) = self.queue.front() {
+            let remaining = &update[self.seek..];
+            let n = cmp::min(buf.len() - count, remaining.len());
+            buf[count..count + n].copy_from_slice(&remaining[..n]);
+            count += n;
+            self.seek += n;
+            if self.seek < update.len() {
+                break;
+            }
+            self.queue.pop_front();
+            self.seek = 0;
         }
+        count
+    }
+}
 
-        // process SUPER- key combinations
-        if self.scheme.modifier_state & SUPER_MODIFIER == SUPER_MODIFIER && event.pressed
-        && event.scancode != orbclient::K_SUPER {
-            self.close_overlays();
+// The pixel query: the color shown at (x, y) as `#AARRGGBB`, like the colors of the config, from
+// the screens and the images they show. A point off the screens is clamped to the nearest one
+fn pixel_query(screens: &[(Rect, &impl Renderer)], x: i32, y: i32) -> Option<Vec<u8>> {
+    let (x, y, image) = screens.iter()
+        .filter(|(screen, _)| ! screen.is_empty())
+        .map(|(screen, image)| {
+            let clamped_x = x.clamp(screen.left(), screen.right() - 1);
+            let clamped_y = y.clamp(screen.top(), screen.bottom() - 1);
+            // the point is from the client, so it can be far enough off for i32 to overflow
+            let distance = (clamped_x as i64 - x as i64).abs() + (clamped_y as i64 - y as i64).abs();
+            (distance, clamped_x - screen.left(), clamped_y - screen.top(), image)
+        })
+        .min_by_key(|&(distance, ..)| distance)
+        .map(|(_, x, y, image)| (x, y, image))?;
+    let color = image.data().get((y * image.width() as i32 + x) as usize)?;
+    Some(format!("#{:08X}\n", color.data).into_bytes())
+}
 
-            let shift = self.scheme.modifier_state & SHIFT_ANY_MODIFIER != 0;
-            match event.scancode {
-                orbclient::K_Q => self.quit_front_window(),
-                orbclient::K_TAB => self.super_tab(),
-                orbclient::K_BRACE_OPEN  => self.volume(Volume::Down),
-                orbclient::K_BRACE_CLOSE =>self.volume(Volume::Up),
-                orbclient::K_BACKSLASH => self.volume(Volume::Toggle),
-                orbclient::K_M => self.tile_window(None, FullScreen),
-                orbclient::K_ENTER => self.tile_window(None, FullScreen),
-                orbclient::K_UP if shift => self.tile_window(None, TopHalf),
-                orbclient::K_DOWN if shift => self.tile_window(None, BottomHalf),
-                orbclient::K_LEFT if shift => self.tile_window(None, LeftHalf),
-                orbclient::K_RIGHT if shift => self.tile_window(None, RightHalf),
-                orbclient::K_UP => self.move_front_window(0, -GRID_SIZE),
-                orbclient::K_DOWN => self.move_front_window(0, GRID_SIZE),
-                orbclient::K_LEFT => self.move_front_window(-GRID_SIZE, 0),
-                orbclient::K_RIGHT => self.move_front_window(GRID_SIZE, 0),
-                orbclient::K_C => self.clipboard_event(orbclient::CLIPBOARD_COPY),
-                orbclient::K_X => self.clipboard_event(orbclient::CLIPBOARD_CUT),
-                orbclient::K_V => self.clipboard_event(orbclient::CLIPBOARD_PASTE),
-                _ => {
-                    //TODO: remove hack for sending super events to lowest numbered window
-                    // ADM is this related to Launcher or Background or something?
-                    if let Some((id, window)) = self.scheme.windows.iter_mut().next() {
-                        info!("sending super {:?} to {}, {}", event, id, window.title);
-                        let mut super_event = event.to_event();
-                        super_event.code += 0x1000_0000;
-                        window.event(super_event);
-                    }
-                }
+// The parts of `damage` on `screen` to sync, a rect for each damaged one rather than a box around
+// all of them, so that changes far apart don't sync everything between them
+fn sync_rects(damage: &[Rect], screen: &Rect) -> Vec<Rect> {
+    damage.iter()
+        .map(|rect| rect.intersection(screen))
+        .filter(|rect| ! rect.is_empty())
+        .collect()
+}
+
+// Split the damage in `redraws` by display, keeping the parts on displays whose frame is `due`
+// and returning the parts on the others, which wait for their own next frame. That way content
+// shown on two displays is composited once for each, whenever that display's frame is due, and
+// never twice for the same one. Nothing is split when all frames are due
+fn split_due(redraws: &mut Vec<Rect>, screens: &[Rect], due: &[bool]) -> Vec<Rect> {
+    if due.iter().all(|&due| due) {
+        return Vec::new();
+    }
+
+    let mut deferred = Vec::new();
+    for rect in mem::take(redraws) {
+        for (screen, &due) in screens.iter().zip(due) {
+            let part = rect.intersection(screen);
+            if part.is_empty() {
+                continue;
+            }
+            if due {
+                redraws.push(part);
+            } else {
+                deferred.push(part);
             }
         }
+    }
+    deferred
+}
 
-        // send non-Super key events to the front window
-        if self.scheme.modifier_state & SUPER_MODIFIER == 0 {
-            if let Some(id) = self.scheme.order.front() {
-                if let Some(window) = self.scheme.windows.get_mut(id) {
-                    if event.pressed && event.character != '\0' {
-                        let text_input_event = TextInputEvent {
-                            character: event.character,
-                        }.to_event();
-                        window.event(text_input_event);
-                    }
-                    window.event(event.to_event());
-                }
+// Input latencies are counted in 1 ms buckets, the last of which also counts anything longer
+const LATENCY_BUCKETS: usize = 250;
+// How often the percentiles of the input latency are logged, when there was input
+const LATENCY_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+// A histogram of the time from input arriving to the next frame being composited, which is as
+// close to input-to-photon latency as the compositor gets, for debug_input_latency
+struct InputLatency {
+    // When the earliest input that no frame was composited after yet arrived
+    pending: Option<Instant>,
+    buckets: Vec<u64>,
+    count: u64,
+    last_log: Instant,
+}
+
+impl InputLatency {
+    fn new(now: Instant) -> Self {
+        InputLatency { pending: None, buckets: vec![0; LATENCY_BUCKETS], count: 0, last_log: now }
+    }
+
+    fn input(&mut self, now: Instant) {
+        self.pending.get_or_insert(now);
+    }
+
+    fn record(&mut self, latency: Duration) {
+        let bucket = cmp::min(latency.as_millis() as usize, LATENCY_BUCKETS - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+    }
+
+    // The latency `percent` of the recorded ones were within, rounded up to the millisecond
+    fn percentile(&self, percent: u64) -> Option<Duration> {
+        let rank = cmp::max(1, (self.count * percent).div_ceil(100));
+        let mut seen = 0;
+        for (millis, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(Duration::from_millis(millis as u64 + 1));
             }
         }
+        None
     }
 
-    fn mouse_event(&mut self, event: MouseEvent) {
-        let mut new_cursor = CursorKind::LeftPtr;
-        let mut new_hover = None;
+    // Forget the pending input when a redraw had nothing to composite, so that input which changed
+    // nothing on screen isn't counted against whatever frame comes next
+    fn unchanged(&mut self) {
+        self.pending = None;
+    }
 
-        // Check for focus switch, dragging, and forward mouse events to applications
-        match self.scheme.dragging {
-            DragMode::None => {
-                for entry in self.scheme.zbuffer.iter() {
-                    let id = entry.0;
-                    if let Some(window) = self.scheme.windows.get_mut(&id) {
-                        if window.rect().contains(event.x, event.y) {
-                            if ! window.mouse_cursor {
-                                new_cursor = CursorKind::None;
-                            }
+    // Record the latency of the pending input now that a frame was composited. Once
+    // LATENCY_LOG_INTERVAL has passed since the last one, returns the line to log and starts over
+    fn composited(&mut self, now: Instant) -> Option<String> {
+        if let Some(input) = self.pending.take() {
+            self.record(now.saturating_duration_since(input));
+        }
+        if self.count == 0 || now.saturating_duration_since(self.last_log) < LATENCY_LOG_INTERVAL {
+            return None;
+        }
 
-                            new_hover = Some(id);
-                            if new_hover != self.scheme.hover {
-                                let hover_event = HoverEvent {
-                                    entered: true
-                                }.to_event();
-                                window.event(hover_event);
-                            }
+        let [p50, p95, p99] = [50, 95, 99].map(|percent| self.percentile(percent).unwrap_or_default());
+        let line = format!("input latency over {} frames: p50 {:?}, p95 {:?}, p99 {:?}", self.count, p50, p95, p99);
+        *self = InputLatency::new(now);
+        Some(line)
+    }
+}
 
-                            if self.scheme.modifier_state & SUPER_MODIFIER == 0 {
-                                let mut window_event = event.to_event();
-                                window_event.a -= window.x as i64;
-                                window_event.b -= window.y as i64;
-                                window.event(window_event);
-                            }
-                            break;
-                        } else if window.title_rect().contains(event.x, event.y) {
-                            break;
-                        } else if window.left_border_rect().contains(event.x, event.y) {
-                            new_cursor = CursorKind::LeftSide;
-                            break;
-                        } else if window.right_border_rect().contains(event.x, event.y) {
-                            new_cursor = CursorKind::RightSide;
-                            break;
-                        } else if window.bottom_border_rect().contains(event.x, event.y) {
-                            new_cursor = CursorKind::BottomSide;
-                            break;
-                        } else if window.bottom_left_border_rect().contains(event.x, event.y) {
-                            new_cursor = CursorKind::BottomLeftCorner;
-                            break;
-                        } else if window.bottom_right_border_rect().contains(event.x, event.y) {
-                            new_cursor = CursorKind::BottomRightCorner;
-                            break;
-                        }
-                    }
-                }
-            },
-            DragMode::Title(window_id, drag_x, drag_y) => {
-                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
-                    if drag_x != event.x || drag_y != event.y {
-                        schedule(&mut self.scheme.redraws, window.title_rect());
-                        schedule(&mut self.scheme.redraws, window.rect());
+// Blanks the screen once there was no input for the configured timeout, unless a window
+// inhibits it
+struct IdleBlank {
+    last_input: Instant,
+    blanked: bool,
+    // Windows inhibiting blanking
+    inhibitors: BTreeSet<usize>,
+    // When the pending idle timeout is due, None when there is none
+    due: Option<Instant>,
+}
 
-                        //TODO: Min and max
-                        window.x += event.x - drag_x;
-                        window.y += event.y - drag_y;
+impl IdleBlank {
+    fn new(now: Instant) -> Self {
+        IdleBlank { last_input: now, blanked: false, inhibitors: BTreeSet::new(), due: None }
+    }
 
-                        let move_event = MoveEvent {
-                            x: window.x,
-                            y: window.y
-                        }.to_event();
-                        window.event(move_event);
+    // Restart the idle time, true if that unblanked the screen
+    fn input(&mut self, now: Instant) -> bool {
+        self.last_input = now;
+        mem::replace(&mut self.blanked, false)
+    }
 
-                        self.scheme.dragging = DragMode::Title(window_id, event.x, event.y);
+    fn inhibit(&mut self, id: usize, inhibit: bool) {
+        if inhibit {
+            self.inhibitors.insert(id);
+        } else {
+            self.inhibitors.remove(&id);
+        }
+    }
 
-                        schedule(&mut self.scheme.redraws, window.title_rect());
-                        schedule(&mut self.scheme.redraws, window.rect());
-                    }
-                } else {
-                    self.scheme.dragging = DragMode::None;
-                }
-            },
-            DragMode::LeftBorder(window_id, off_x, right_x) => {
-                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
-                    new_cursor = CursorKind::LeftSide;
+    // When the screen blanks, None if it won't: it is blanked already, inhibited or blanking
+    // is disabled with a zero timeout
+    fn deadline(&self, timeout: Duration) -> Option<Instant> {
+        if self.blanked || ! self.inhibitors.is_empty() || timeout.is_zero() {
+            return None;
+        }
+        Some(self.last_input + timeout)
+    }
 
-                    let x = event.x - off_x;
-                    let w = right_x - x;
+    // Blank the screen if the deadline has passed, true if it was blanked just now
+    fn check(&mut self, now: Instant, timeout: Duration) -> bool {
+        if self.deadline(timeout).is_some_and(|deadline| deadline <= now) {
+            self.blanked = true;
+            return true;
+        }
+        false
+    }
+}
 
-                    if w > 0 {
-                        if x != window.x {
-                            schedule(&mut self.scheme.redraws, window.title_rect());
-                            schedule(&mut self.scheme.redraws, window.rect());
+// How long modifiers can be held without key events before a change of focus takes them to be
+// stuck, such as when the release of Super was lost while switching windows. This is longer than
+// keyboards wait before repeating a held key
+const MODIFIER_FOCUS_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Notices modifiers that stay held without any key events for the configured timeout, which
+// means that their release was lost. Keyboards repeat the press of a key that is held down, so a
+// modifier that really is held keeps sending events
+struct ModifierWatch {
+    last_key: Instant,
+    // When the pending timeout for checking the modifiers is due, None when there is none
+    due: Option<Instant>,
+}
 
-                            window.x = x;
-                            let move_event = MoveEvent {
-                                x,
-                                y: window.y
-                            }.to_event();
-                            window.event(move_event);
+impl ModifierWatch {
+    fn new(now: Instant) -> Self {
+        ModifierWatch { last_key: now, due: None }
+    }
 
-                            schedule(&mut self.scheme.redraws, window.title_rect());
-                            schedule(&mut self.scheme.redraws, window.rect());
-                        }
+    fn key(&mut self, now: Instant) {
+        self.last_key = now;
+    }
 
-                        if w != window.width()  {
-                            let resize_event = ResizeEvent {
-                                width: w as u32,
-                                height: window.height() as u32
-                            }.to_event();
-                            window.event(resize_event);
-                        }
-                    }
-                } else {
-                    self.scheme.dragging = DragMode::None;
+    // When the `held` modifiers are taken to be stuck, None if none are held or resetting them
+    // is disabled with a zero timeout
+    fn deadline(&self, held: u8, timeout: Duration) -> Option<Instant> {
+        if held == 0 || timeout.is_zero() {
+            return None;
+        }
+        Some(self.last_key + timeout)
+    }
+
+    fn stuck(&self, held: u8, now: Instant, timeout: Duration) -> bool {
+        self.deadline(held, timeout).is_some_and(|deadline| deadline <= now)
+    }
+}
+
+// Raises the window under the cursor with autoraise_delay once the cursor has stayed over it that
+// long, and only again after the cursor has left it
+#[derive(Default)]
+struct AutoRaise {
+    // The window hovered, since when, and whether it was raised yet
+    hovered: Option<(usize, Instant, bool)>,
+    // When the pending timeout for the delay to end is due, None when there is none
+    due: Option<Instant>,
+}
+
+impl AutoRaise {
+    // Track the cursor being over the window `hovered` at `now`, None when it is over none.
+    // Returns the window to raise once it has been hovered for `delay`
+    fn update(&mut self, hovered: Option<usize>, now: Instant, delay: Duration) -> Option<usize> {
+        match (self.hovered, hovered) {
+            (Some((id, since, raised)), Some(hovered)) if id == hovered => {
+                if raised || now < since + delay {
+                    return None;
                 }
+                self.hovered = Some((id, since, true));
+                Some(id)
             },
-            DragMode::RightBorder(window_id, off_x) => {
-                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
-                    new_cursor = CursorKind::RightSide;
-                    let w = event.x - off_x - window.x;
-                    if w > 0 && w != window.width()  {
-                        let resize_event = ResizeEvent {
-                            width: w as u32,
-                            height: window.height() as u32
-                        }.to_event();
-                        window.event(resize_event);
-                    }
-                } else {
-                    self.scheme.dragging = DragMode::None;
-                }
+            (_, hovered) => {
+                self.hovered = hovered.map(|id| (id, now, false));
+                None
             },
-            DragMode::BottomBorder(window_id, off_y) => {
-                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
-                    new_cursor = CursorKind::BottomSide;
-                    let h = event.y - off_y - window.y;
-                    if h > 0 && h != window.height()  {
-                        let resize_event = ResizeEvent {
-                            width: window.width() as u32,
-                            height: h as u32
-                        }.to_event();
-                        window.event(resize_event);
-                    }
-                } else {
-                    self.scheme.dragging = DragMode::None;
-                }
+        }
+    }
+
+    // When the window hovered is raised, if it is still to be
+    fn deadline(&self, delay: Duration) -> Option<Instant> {
+        self.hovered.filter(|(_, _, raised)| ! raised).map(|(_, since, _)| since + delay)
+    }
+}
+
+// How long the size of a resized window stays shown with resize_osd once the resize ended
+const RESIZE_OSD_LINGER: Duration = Duration::from_secs(1);
+
+// The window whose size is shown with resize_osd while its borders are dragged, and for
+// RESIZE_OSD_LINGER after
+#[derive(Default)]
+struct ResizeOsd {
+    // The window being resized or that just was, None when the size isn't shown
+    id: Option<usize>,
+    // When the size is hidden once the resize ended, None while it goes on
+    hide_at: Option<Instant>,
+    // Where the size was last drawn, to redraw when it is hidden
+    rect: Rect,
+    // When the pending timeout for hiding it is due, None when there is none
+    due: Option<Instant>,
+}
+
+impl ResizeOsd {
+    // Follow the window `resizing` at `now`, None when no window is being resized. Once the
+    // resize ends the size stays shown until RESIZE_OSD_LINGER has passed. Returns whether the
+    // size was hidden
+    fn update(&mut self, resizing: Option<usize>, now: Instant) -> bool {
+        if resizing.is_some() {
+            self.id = resizing;
+            self.hide_at = None;
+            return false;
+        }
+        if self.id.is_none() {
+            return false;
+        }
+        match self.hide_at {
+            Some(hide_at) if now >= hide_at => {
+                self.id = None;
+                self.hide_at = None;
+                true
             },
-            DragMode::BottomLeftBorder(window_id, off_x, off_y, right_x) => {
-                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
-                    new_cursor = CursorKind::BottomLeftCorner;
+            Some(_) => false,
+            None => {
+                self.hide_at = Some(now + RESIZE_OSD_LINGER);
+                false
+            },
+        }
+    }
+}
 
-                    let x = event.x - off_x;
-                    let h = event.y - off_y - window.y;
-                    let w = right_x - x;
+// The window whose border is being dragged, if any
+fn resizing(dragging: &DragMode) -> Option<usize> {
+    match *dragging {
+        DragMode::LeftBorder(id, ..) | DragMode::RightBorder(id, ..) | DragMode::BottomBorder(id, ..)
+        | DragMode::BottomLeftBorder(id, ..) | DragMode::BottomRightBorder(id, ..) => Some(id),
+        DragMode::None | DragMode::Title(..) => None,
+    }
+}
 
-                    if w > 0 && h > 0 {
-                        if x != window.x {
-                            schedule(&mut self.scheme.redraws, window.title_rect());
-                            schedule(&mut self.scheme.redraws, window.rect());
+// Where the size of a window at `window` is shown, `width` and `height` in size, centered on it
+fn resize_osd_rect(window: &Rect, width: i32, height: i32) -> Rect {
+    Rect::new(
+        window.left() + (window.width() - width) / 2,
+        window.top() + (window.height() - height) / 2,
+        width,
+        height,
+    )
+}
 
-                            window.x = x;
-                            let move_event = MoveEvent {
-                                x,
-                                y: window.y
-                            }.to_event();
-                            window.event(move_event);
+// How long the cursor has to stay in a hot corner before its action fires
+const HOT_CORNER_DWELL: Duration = Duration::from_millis(250);
+// How far hot corners and edges reach into the displays, before display scaling
+const HOT_CORNER_SIZE: i32 = 2;
+
+// A corner or edge of the displays that can fire an action with the `hot_corners` config
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum HotCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
 
-                            schedule(&mut self.scheme.redraws, window.title_rect());
-                            schedule(&mut self.scheme.redraws, window.rect());
-                        }
+impl HotCorner {
+    fn from_config(config: &str) -> Option<HotCorner> {
+        Some(match config {
+            "top_left" => HotCorner::TopLeft,
+            "top_right" => HotCorner::TopRight,
+            "bottom_left" => HotCorner::BottomLeft,
+            "bottom_right" => HotCorner::BottomRight,
+            "top" => HotCorner::Top,
+            "bottom" => HotCorner::Bottom,
+            "left" => HotCorner::Left,
+            "right" => HotCorner::Right,
+            _ => return None,
+        })
+    }
 
-                        if w != window.width() || h != window.height() {
-                            let resize_event = ResizeEvent {
-                                width: w as u32,
-                                height: h as u32
-                            }.to_event();
-                            window.event(resize_event);
-                        }
-                    }
-                } else {
-                    self.scheme.dragging = DragMode::None;
-                }
-            },
-            DragMode::BottomRightBorder(window_id, off_x, off_y) => {
-                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
-                    new_cursor = CursorKind::BottomRightCorner;
-                    let w = event.x - off_x - window.x;
-                    let h = event.y - off_y - window.y;
-                    if w > 0 && h > 0 && (w != window.width() || h != window.height())  {
-                        let resize_event = ResizeEvent {
-                            width: w as u32,
-                            height: h as u32
-                        }.to_event();
-                        window.event(resize_event);
-                    }
-                } else {
-                    self.scheme.dragging = DragMode::None;
-                }
+    // The hot corner or edge at (x, y), within `size` pixels of a side of the display it is on
+    // that no other display continues past. Corners take precedence over edges
+    fn at(screens: &[Rect], x: i32, y: i32, size: i32) -> Option<HotCorner> {
+        let screen = screens.iter().find(|screen| screen.contains(x, y))?;
+        let outside = |x, y| ! screens.iter().any(|screen| screen.contains(x, y));
+        let left = x < screen.left() + size && outside(screen.left() - 1, y);
+        let right = x >= screen.right() - size && outside(screen.right(), y);
+        let top = y < screen.top() + size && outside(x, screen.top() - 1);
+        let bottom = y >= screen.bottom() - size && outside(x, screen.bottom());
+        Some(match (left, right, top, bottom) {
+            (true, _, true, _) => HotCorner::TopLeft,
+            (_, true, true, _) => HotCorner::TopRight,
+            (true, _, _, true) => HotCorner::BottomLeft,
+            (_, true, _, true) => HotCorner::BottomRight,
+            (_, _, true, _) => HotCorner::Top,
+            (_, _, _, true) => HotCorner::Bottom,
+            (true, _, _, _) => HotCorner::Left,
+            (_, true, _, _) => HotCorner::Right,
+            _ => return None,
+        })
+    }
+}
+
+// What a hot corner does
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum HotCornerAction {
+    // Bring the next window to the front with the window switcher shown, until the cursor leaves
+    // the corner
+    ShowSwitcher,
+    // Minimize all windows, or restore the windows minimized that way
+    ShowDesktop,
+    PointerHighlight,
+}
+
+impl HotCornerAction {
+    fn from_config(config: &str) -> Option<HotCornerAction> {
+        Some(match config {
+            "show_switcher" => HotCornerAction::ShowSwitcher,
+            "show_desktop" => HotCornerAction::ShowDesktop,
+            "pointer_highlight" => HotCornerAction::PointerHighlight,
+            _ => return None,
+        })
+    }
+}
+
+// A hot corner and the action bound to it
+type HotCornerBinding = (HotCorner, HotCornerAction);
+
+// Fires the action of a hot corner once the cursor has stayed in it for HOT_CORNER_DWELL, so
+// that passing through it doesn't, and only again after the cursor has left it
+struct HotCorners {
+    actions: Vec<HotCornerBinding>,
+    // The corner with an action the cursor is in, since when, and whether it fired yet
+    entered: Option<(HotCorner, Instant, bool)>,
+    // When the pending timeout for the dwell to end is due, None when there is none
+    due: Option<Instant>,
+    // The corner that showed the window switcher, which is closed when the cursor leaves it
+    switcher: Option<HotCorner>,
+}
+
+impl HotCorners {
+    // The hot corners of the `hot_corners` config, warning about the entries that are invalid
+    // and those binding a corner that an earlier entry already did
+    fn new(config: &[String]) -> Self {
+        let (actions, warnings) = Self::bindings(config);
+        for warning in warnings {
+            warn!("{}", warning);
+        }
+        HotCorners { actions, entered: None, due: None, switcher: None }
+    }
+
+    // The corners bound by the `hot_corners` config, and the warnings about the entries that are
+    // invalid or conflict with an earlier one. The first entry for a corner wins
+    fn bindings(config: &[String]) -> (Vec<HotCornerBinding>, Vec<String>) {
+        let mut actions: Vec<HotCornerBinding> = Vec::new();
+        let mut entries: Vec<&str> = Vec::new();
+        let mut warnings = Vec::new();
+        for entry in config {
+            let parsed = entry.split_once('=').and_then(|(corner, action)| {
+                Some((HotCorner::from_config(corner.trim())?, HotCornerAction::from_config(action.trim())?))
+            });
+            let Some((corner, action)) = parsed else {
+                warnings.push(format!("invalid hot_corners entry '{}'", entry));
+                continue;
+            };
+            match actions.iter().position(|(bound, _)| *bound == corner) {
+                Some(i) => warnings.push(format!("hot_corners entry '{}' ignored, the corner is already bound by '{}'", entry, entries[i])),
+                None => {
+                    actions.push((corner, action));
+                    entries.push(entry);
+                },
             }
         }
+        (actions, warnings)
+    }
 
-        if new_hover != self.scheme.hover {
-            if let Some(id) = self.scheme.hover {
-                if let Some(window) = self.scheme.windows.get_mut(&id) {
-                    let hover_event = HoverEvent {
-                        entered: false
-                    }.to_event();
-                    window.event(hover_event);
+    fn action(&self, corner: HotCorner) -> Option<HotCornerAction> {
+        self.actions.iter().find(|(configured, _)| *configured == corner).map(|(_, action)| *action)
+    }
+
+    // Track the cursor being in `corner` at `now`, None when it is in none. Returns the action
+    // that fires
+    fn update(&mut self, corner: Option<HotCorner>, now: Instant) -> Option<HotCornerAction> {
+        let corner = corner.filter(|&corner| self.action(corner).is_some());
+        match (self.entered, corner) {
+            (Some((entered, since, fired)), Some(corner)) if entered == corner => {
+                if fired || now < since + HOT_CORNER_DWELL {
+                    return None;
                 }
+                self.entered = Some((corner, since, true));
+                self.action(corner)
+            },
+            (_, corner) => {
+                self.entered = corner.map(|corner| (corner, now, false));
+                None
             }
-
-            self.scheme.hover = new_hover;
         }
+    }
 
-        self.scheme.update_cursor(event.x, event.y, new_cursor);
+    // When the action of the corner the cursor is in fires, None if it is in none or it fired
+    fn deadline(&self) -> Option<Instant> {
+        self.entered.filter(|&(_, _, fired)| ! fired).map(|(_, since, _)| since + HOT_CORNER_DWELL)
     }
+}
 
-    fn mouse_relative_event(&mut self, event: MouseRelativeEvent) {
-        let mut relative_cursor_opt = None;
-        if let Some(id) = self.scheme.order.front() {
-            if let Some(window) = self.scheme.windows.get_mut(id) {
-                //TODO: handle grab?
-                if window.mouse_relative {
-                    // Send relative event
-                    window.event(event.to_event());
+// Where windows on `screen` minimize to when no taskbar button is given: its bottom center
+fn minimize_target(screen: &Rect) -> Rect {
+    Rect::new(screen.left() + screen.width() / 2, screen.bottom(), 0, 0)
+}
+
+// What an entry of the window menu does to the window it was opened on
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum WindowMenuAction {
+    Move,
+    Resize,
+    Tile(TilePosition),
+    Close,
+}
+
+const WINDOW_MENU: &[(&str, WindowMenuAction)] = &[
+    ("Move", WindowMenuAction::Move),
+    ("Resize", WindowMenuAction::Resize),
+    ("Maximize / restore", WindowMenuAction::Tile(FullScreen)),
+    ("Tile left", WindowMenuAction::Tile(LeftHalf)),
+    ("Tile right", WindowMenuAction::Tile(RightHalf)),
+    ("Tile top", WindowMenuAction::Tile(TopHalf)),
+    ("Tile bottom", WindowMenuAction::Tile(BottomHalf)),
+    ("Close", WindowMenuAction::Close),
+];
+
+// The menu opened with Alt-Space on the focused window, listing WINDOW_MENU. Choosing Move or
+// Resize keeps the menu open in a mode where the arrow keys move or resize the window.
+#[derive(Debug, PartialEq)]
+struct WindowMenu {
+    id: usize,
+    selected: usize,
+    moving: bool,
+    resizing: bool,
+}
+
+impl WindowMenu {
+    fn new(id: usize) -> Self {
+        WindowMenu { id, selected: 0, moving: false, resizing: false }
+    }
+
+    // Move the selection `delta` entries down, wrapping around at either end
+    fn select(&mut self, delta: isize) {
+        let len = WINDOW_MENU.len() as isize;
+        self.selected = (self.selected as isize + delta).rem_euclid(len) as usize;
+    }
+
+    fn action(&self) -> WindowMenuAction {
+        WINDOW_MENU[self.selected].1
+    }
+}
+
+// The size a window is resized to from the keyboard, `steps` grid steps larger or smaller on each
+// axis, or whole size hint increments when those are larger
+fn keyboard_resize(window: &Window, steps: (i32, i32)) -> (i32, i32) {
+    let step_w = cmp::max(GRID_SIZE, window.size_hints.increment.0);
+    let step_h = cmp::max(GRID_SIZE, window.size_hints.increment.1);
+    let width = cmp::max(GRID_SIZE, window.width().saturating_add(steps.0.saturating_mul(step_w)));
+    let height = cmp::max(GRID_SIZE, window.height().saturating_add(steps.1.saturating_mul(step_h)));
+    window.size_hints.constrain(width, height)
+}
+
+// Where a window menu of `width` and `height` is drawn for a window whose content starts at
+// (x, y), just below its title bar: kept on `screen` as far as it fits
+fn window_menu_rect(screen: &Rect, x: i32, y: i32, width: i32, height: i32) -> Rect {
+    let x = cmp::max(screen.left(), cmp::min(x, screen.right() - width));
+    let y = cmp::max(screen.top(), cmp::min(y, screen.bottom() - height));
+    Rect::new(x, y, width, height)
+}
+
+// Answer the close confirmation shown for the window `pending` with a key press: Enter asks the
+// window to quit and Escape cancels, other keys leave the prompt open. Returns whether it was
+// answered
+fn answer_close_confirm(pending: &mut Option<usize>, windows: &mut BTreeMap<usize, Window>, scancode: u8) -> bool {
+    let Some(id) = *pending else {
+        return false;
+    };
+    match scancode {
+        orbclient::K_ENTER => if let Some(window) = windows.get_mut(&id) {
+            window.request_quit();
+        },
+        orbclient::K_ESC => {},
+        _ => return false,
+    }
+    *pending = None;
+    true
+}
+
+// Time between the frames of the loading spinner
+const SPINNER_FRAME: Duration = Duration::from_millis(100);
+// Number of dots in the loading spinner, one of which is highlighted
+const SPINNER_DOTS: usize = 8;
+// Radius of the circle of spinner dots and the size of each dot, before display scaling
+const SPINNER_RADIUS: i32 = 12;
+const SPINNER_DOT: i32 = 4;
+
+// The dots of the loading spinner centered on `rect`, clockwise from the top
+fn spinner_dots(rect: &Rect, scale: i32) -> Vec<Rect> {
+    let (center_x, center_y) = (rect.left() + rect.width() / 2, rect.top() + rect.height() / 2);
+    let (radius, size) = ((SPINNER_RADIUS * scale) as f32, SPINNER_DOT * scale);
+    (0..SPINNER_DOTS).map(|i| {
+        let angle = i as f32 * std::f32::consts::TAU / SPINNER_DOTS as f32;
+        let x = center_x + (angle.sin() * radius).round() as i32;
+        let y = center_y - (angle.cos() * radius).round() as i32;
+        Rect::new(x - size / 2, y - size / 2, size, size)
+    }).collect()
+}
+
+// Radius and thickness of the ring highlighting the pointer, before display scaling
+const HIGHLIGHT_RADIUS: i32 = 24;
+const HIGHLIGHT_RING: i32 = 3;
+
+// The area the ring highlighting the pointer at (x, y) is drawn in
+fn highlight_rect(x: i32, y: i32, scale: i32) -> Rect {
+    let radius = HIGHLIGHT_RADIUS * scale;
+    Rect::new(x - radius, y - radius, radius * 2 + 1, radius * 2 + 1)
+}
+
+// The ring highlighting the pointer at (x, y), as one span per row where the row passes over the
+// middle and one on each side of it where it crosses the hole
+fn highlight_ring(x: i32, y: i32, scale: i32) -> Vec<Rect> {
+    let outer = HIGHLIGHT_RADIUS * scale;
+    let inner = outer - HIGHLIGHT_RING * scale;
+    let half_width = |radius: i32, dy: i32| ((radius * radius - dy * dy) as f32).sqrt() as i32;
+
+    let mut spans = Vec::new();
+    for dy in -outer..=outer {
+        let outer_w = half_width(outer, dy);
+        if dy.abs() < inner {
+            let inner_w = half_width(inner, dy);
+            spans.push(Rect::new(x - outer_w, y + dy, outer_w - inner_w, 1));
+            spans.push(Rect::new(x + inner_w + 1, y + dy, outer_w - inner_w, 1));
+        } else {
+            spans.push(Rect::new(x - outer_w, y + dy, outer_w * 2 + 1, 1));
+        }
+    }
+    spans
+}
+
+// Height of the built-in panel, the width of its window buttons and the space around them,
+// before display scaling
+const PANEL_HEIGHT: i32 = 28;
+const PANEL_BUTTON_WIDTH: i32 = 160;
+const PANEL_PADDING: i32 = 4;
+const PANEL_TEXT_HEIGHT: i32 = 16;
+
+// The built-in panel along the top of the first display. The window list buttons start at its left
+// and take up at most half of it, shrinking when there are many windows. The clock is at its right
+// and the title of the focused window in the middle, in the space left between them. Clicking a
+// button focuses its window, restoring it if it was minimized, clicks anywhere else on the panel
+// are ignored.
+struct Panel {
+    state: PanelState,
+    image: Image,
+    // The window each button is for
+    buttons: Vec<(usize, Rect)>,
+}
+
+impl Panel {
+    fn button_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.buttons.iter().find(|(_, button)| button.contains(x, y)).map(|&(id, _)| id)
+    }
+}
+
+// What the built-in panel shows, it is rendered again when any of it changes
+#[derive(PartialEq)]
+struct PanelState {
+    rect: Rect,
+    scale: i32,
+    windows: Vec<(usize, String)>,
+    focused: Option<usize>,
+    clock: String,
+}
+
+// Whether the window gets a button on the built-in panel, minimized windows are listed so they
+// can be restored
+fn panel_lists(window: &Window) -> bool {
+    ! window.title.is_empty() && ! window.hidden && ! window.no_focus && ! window.input_transparent
+        && window.zorder != WindowZOrder::Back
+}
+
+// The tab shown in place of the window when it is a hidden tab, which a press on its panel
+// button switches from
+fn shown_tab(window: &Window, tab_groups: &BTreeMap<GroupId, TabGroup>) -> Option<usize> {
+    window.tab_group
+        .filter(|_| window.tab_hidden)
+        .and_then(|group| tab_groups.get(&group))
+        .map(|group| group.active)
+}
+
+// The window list buttons of the built-in panel, one for each of `count` windows
+fn panel_buttons(panel: &Rect, scale: i32, count: usize) -> Vec<Rect> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let padding = PANEL_PADDING * scale;
+    let space = cmp::max(0, panel.width() / 2 - padding);
+    let width = cmp::max(0, cmp::min(PANEL_BUTTON_WIDTH * scale, space / count as i32 - padding));
+    let height = cmp::max(0, panel.height() - padding * 2);
+    (0..count as i32)
+        .map(|i| Rect::new(panel.left() + padding + i * (width + padding), panel.top() + padding, width, height))
+        .collect()
+}
+
+// The time shown by the built-in panel, in UTC as there is no time zone to go by
+fn panel_clock(since_epoch: Duration) -> String {
+    let minutes = since_epoch.as_secs() / 60;
+    format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+}
+
+// Give the window the decorations of a new display scale, redrawing them where they were and
+// where they are now, and tell the client about the new scale
+fn rescale_window(redraws: &mut Vec<Rect>, window: &mut Window, scale: i32, font: &orbfont::Font) {
+    if window.scale == scale {
+        return;
+    }
+    schedule(redraws, window.title_rect());
+    schedule(redraws, window.rect());
+    window.scale = scale;
+    window.render_title(font);
+    schedule(redraws, window.title_rect());
+
+    let mut scale_event = Event::new();
+    scale_event.code = ORBITAL_EVENT_SCALE;
+    scale_event.a = scale as i64;
+    window.event(scale_event);
+}
+
+// The UI scale `delta` steps away from `scale`, within 1 and MAX_UI_SCALE
+fn step_scale(scale: i32, delta: i32) -> i32 {
+    (scale + delta).clamp(1, MAX_UI_SCALE)
+}
+
+// The scale a window at `rect` is drawn at: the UI scale set at runtime if there is one, or
+// else the scale of the display it is mostly on
+fn window_scale(scale_override: Option<i32>, screens: &[(Rect, i32)], rect: Rect) -> i32 {
+    scale_override.unwrap_or_else(|| display_scale_of(screens, rect))
+}
+
+// The scale of the display that most of `rect` is on, given the screen rect and scale of each
+// display. A window that is on no display keeps the scale of the first one
+fn display_scale_of(screens: &[(Rect, i32)], rect: Rect) -> i32 {
+    let mut scale = screens.first().map_or(1, |&(_, scale)| scale);
+    let mut max_area = 0;
+    for &(screen, screen_scale) in screens {
+        let area = screen.intersection(&rect).area();
+        if area > max_area {
+            scale = screen_scale;
+            max_area = area;
+        }
+    }
+    scale
+}
+
+// How often the drawn cursor is moved toward the pointer while it is smoothed
+const CURSOR_FRAME: Duration = Duration::from_millis(16);
+
+// One frame of smooth cursor movement from where it is drawn toward the pointer at `target`.
+// Each frame covers half of the distance left on each axis, easing out so that a jump is mostly
+// made up in the first frames, and the last pixel is snapped to.
+fn smooth_cursor_step(drawn: (i32, i32), target: (i32, i32)) -> (i32, i32) {
+    let step = |drawn: i32, target: i32| {
+        let distance = target - drawn;
+        if distance.abs() <= 1 { target } else { drawn + distance / 2 }
+    };
+    (step(drawn.0, target.0), step(drawn.1, target.1))
+}
+
+// The screen position of (x, y) relative to the top left of display `display`, clamped to
+// inside of it, or None if there is no such display
+fn display_position(screens: &[Rect], display: usize, x: i32, y: i32) -> Option<(i32, i32)> {
+    let screen = screens.get(display)?;
+    Some((
+        screen.left() + x.clamp(0, cmp::max(0, screen.width() - 1)),
+        screen.top() + y.clamp(0, cmp::max(0, screen.height() - 1)),
+    ))
+}
+
+// The action of the maximize button for the `maximize_button_action` config, Shift-clicking does
+// the other one. A maximized window is restored either way, by tiling it to FullScreen again
+fn maximize_action(action: MaximizeAction, shift: bool, maximized: bool) -> MaximizeAction {
+    if maximized {
+        return MaximizeAction::Maximize;
+    }
+    match (action, shift) {
+        (action, false) => action,
+        (MaximizeAction::Maximize, true) => MaximizeAction::Fullscreen,
+        (MaximizeAction::Fullscreen, true) => MaximizeAction::Maximize,
+    }
+}
+
+// Longest time between two presses on a title bar that still makes them a double-click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+// Whether a press on the title bar of window `id` at `now` completes a double-click. Otherwise it
+// is remembered as the first press of the next one
+fn double_click(last: &mut Option<(usize, Instant)>, id: usize, now: Instant) -> bool {
+    if last.take().is_some_and(|(last_id, at)| last_id == id && now.saturating_duration_since(at) < DOUBLE_CLICK) {
+        return true;
+    }
+    *last = Some((id, now));
+    false
+}
+
+// Sticky keys for users who can't press key combinations simultaneously: while enabled, a
+// modifier press stays latched after its key is released, until the next non-modifier key
+// press. Pressing a latched modifier again unlatches it.
+#[derive(Default)]
+struct StickyKeys {
+    enabled: bool,
+    latched: u8,
+    shift_presses: u8,
+}
+
+impl StickyKeys {
+    // Track a new (not repeated) key press, returns true if sticky keys were toggled by it
+    fn press(&mut self, modifier: Option<u8>) -> bool {
+        match modifier {
+            Some(bit) => {
+                if bit & (SHIFT_LEFT_MODIFIER | SHIFT_RIGHT_MODIFIER) != 0 {
+                    self.shift_presses += 1;
+                } else {
+                    self.shift_presses = 0;
+                }
+
+                if self.enabled {
+                    self.latched ^= bit;
+                }
+            },
+            None => self.shift_presses = 0,
+        }
+
+        if self.shift_presses >= STICKY_KEYS_SHIFT_PRESSES {
+            self.enabled = ! self.enabled;
+            self.latched = 0;
+            self.shift_presses = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub struct OrbitalScheme {
+    window_max: Image,
+    window_max_unfocused: Image,
+    window_close: Image,
+    window_close_unfocused: Image,
+    cursors: BTreeMap<CursorKind, Image>,
+    // Wallpapers of the displays by path, so that displays sharing one only load it once
+    wallpapers: BTreeMap<String, Image>,
+    cursor_i: CursorKind,
+    cursor_x: i32,
+    cursor_y: i32,
+    // Where the cursor is drawn, which trails behind the pointer while it is smoothed
+    cursor_drawn: (i32, i32),
+    // When the pending timeout for the next frame of cursor smoothing is due
+    cursor_due: Option<Instant>,
+    cursor_left: bool,
+    cursor_middle: bool,
+    cursor_right: bool,
+    dragging: DragMode,
+    resize_debounce: ResizeDebounce,
+    modifier_state: u8,
+    held_modifiers: u8,
+    lock_keys: LockKeys,
+    modifier_watch: ModifierWatch,
+    hot_corners: HotCorners,
+    resize_osd: ResizeOsd,
+    // Windows minimized by the show_desktop hot corner, in stacking order, to restore when it
+    // fires again
+    shown_desktop: Vec<usize>,
+    sticky_keys: StickyKeys,
+    pointer_highlight: bool,
+    tab_groups: BTreeMap<GroupId, TabGroup>,
+    next_tab_group: GroupId,
+    // The last press on a title bar, to tell double-clicks
+    title_click: Option<(usize, Instant)>,
+    panel: Option<Panel>,
+    // When the pending timeout to update the panel's clock is due
+    panel_due: Option<Instant>,
+    volume_value: i32,
+    volume_toggle: i32,
+    next_id: isize,
+    hover: Option<usize>,
+    order: VecDeque<usize>,
+    zbuffer: Vec<(usize, WindowZOrder, usize)>,
+    // Open batch of window changes, redraws are deferred until it is committed
+    batch: Option<Batch>,
+    key_grabs: KeyGrabs,
+    toasts: Toasts,
+    animation: Option<WindowAnimation>,
+    // When the pending timeout for the next frame of the window animation is due
+    animation_due: Option<Instant>,
+    // Outline drawn by the window animation, empty when there is none
+    animation_rect: Rect,
+    frame_clock: FrameClock,
+    input_latency: InputLatency,
+    frame_pacer: FramePacer,
+    sync_limiter: SyncLimiter,
+    // The frame of the loading spinner, which dot is highlighted
+    spinner_frame: usize,
+    // When the pending spinner timeout is due, None when the spinner isn't running
+    spinner_due: Option<Instant>,
+    idle: IdleBlank,
+    // Counts focus changes for Window::focused_at
+    focus_serial: u64,
+    // The Super-Tab pass in most recently used order, until Super is released
+    mru_cycle: Option<MruCycle>,
+    // The Alt-Space window menu, while it is open
+    window_menu: Option<WindowMenu>,
+    window_menu_rect: Rect,
+    // The window a close is waiting to be confirmed for, see ORBITAL_FLAG_CONFIRM_CLOSE
+    close_confirm: Option<usize>,
+    close_confirm_rect: Rect,
+    // Window temporarily raised to the top of the zbuffer while Super-P is held, without
+    // changing the order or focus
+    peek: Option<usize>,
+    // Window raised to the top of its z-order by autoraise_delay without being focused, until
+    // the focus changes
+    raised: Option<usize>,
+    autoraise: AutoRaise,
+    // The clients recording the screen, by recording id
+    recorders: BTreeMap<usize, Recorder>,
+    next_recorder_id: usize,
+    pub windows: BTreeMap<usize, Window>,
+    redraws: Vec<Rect>,
+    font: orbfont::Font,
+    // The typeface the UI font was loaded from, None for the built-in font
+    font_typeface: Option<String>,
+    clipboard: Vec<u8>,
+    // Where the windows that closed were, by title
+    saved_geometry: VecDeque<(String, SavedGeometry)>,
+    // The UI scale: the largest display scale, unless overridden at runtime
+    scale: i32,
+    scale_override: Option<i32>,
+    config: Rc<Config>,
+    // Is the user currently switching windows with win-tab
+    // Set true when win-tab is pressed, set false when win is released.
+    // While it is true, redraw() calls draw_window_list()
+    win_tabbing: bool,
+    volume_osd: bool,
+    shortcuts_osd: bool,
+    // Show whether sticky keys were just toggled, until the next key press
+    sticky_keys_osd: bool,
+    popup_rect: Rect,
+    sticky_keys_rect: Rect,
+}
+
+impl OrbitalScheme {
+    pub(crate) fn new(displays: &[Display], config: Rc<Config>) -> Result<OrbitalScheme, String> {
+        let mut redraws = Vec::new();
+        let mut scale = 1;
+        for display in displays.iter() {
+            redraws.push(display.screen_rect());
+            scale = cmp::max(scale, display.scale);
+        }
+
+        let cursors = load_cursors(&config, scale);
+
+        let wallpapers = load_wallpapers(&config, displays.len(), scale);
+
+        let (font, font_typeface) = find_ui_font(
+            &config,
+            |typeface, style| orbfont::Font::find(Some(typeface), None, Some(style)).map(|font| (font, Some(typeface.to_string()))),
+            || orbfont::Font::from_data(BUILTIN_UI_FONT).map(|font| (font, None)),
+        )?;
+
+        Ok(OrbitalScheme {
+            window_max: load_image(&config.window_max, DEFAULT_WINDOW_MAX, scale),
+            window_max_unfocused: load_image(&config.window_max_unfocused, DEFAULT_WINDOW_MAX_UNFOCUSED, scale),
+            window_close: load_image(&config.window_close, DEFAULT_WINDOW_CLOSE, scale),
+            window_close_unfocused: load_image(&config.window_close_unfocused, DEFAULT_WINDOW_CLOSE_UNFOCUSED, scale),
+            cursors,
+            wallpapers,
+            cursor_i: CursorKind::LeftPtr,
+            cursor_x: 0,
+            cursor_y: 0,
+            cursor_drawn: (0, 0),
+            cursor_due: None,
+            cursor_left: false,
+            cursor_middle: false,
+            cursor_right: false,
+            dragging: DragMode::None,
+            resize_debounce: ResizeDebounce {
+                interval: Duration::from_millis(config.resize_debounce_ms as u64),
+                ..ResizeDebounce::default()
+            },
+            modifier_state: 0,
+            held_modifiers: 0,
+            lock_keys: LockKeys::default(),
+            modifier_watch: ModifierWatch::new(Instant::now()),
+            hot_corners: HotCorners::new(&config.hot_corners),
+            resize_osd: ResizeOsd::default(),
+            shown_desktop: Vec::new(),
+            pointer_highlight: config.pointer_highlight,
+            tab_groups: BTreeMap::new(),
+            next_tab_group: 1,
+            title_click: None,
+            panel: None,
+            panel_due: None,
+            sticky_keys: StickyKeys {
+                enabled: config.sticky_keys,
+                ..StickyKeys::default()
+            },
+            volume_value: 0,
+            volume_toggle: 0,
+            next_id: 1,
+            hover: None,
+            order: VecDeque::new(),
+            zbuffer: Vec::new(),
+            batch: None,
+            key_grabs: KeyGrabs::default(),
+            toasts: Toasts::default(),
+            animation: None,
+            animation_due: None,
+            animation_rect: Rect::default(),
+            frame_clock: FrameClock::default(),
+            input_latency: InputLatency::new(Instant::now()),
+            frame_pacer: FramePacer::default(),
+            sync_limiter: SyncLimiter::default(),
+            spinner_frame: 0,
+            spinner_due: None,
+            idle: IdleBlank::new(Instant::now()),
+            focus_serial: 0,
+            mru_cycle: None,
+            window_menu: None,
+            window_menu_rect: Rect::default(),
+            close_confirm: None,
+            close_confirm_rect: Rect::default(),
+            peek: None,
+            raised: None,
+            autoraise: AutoRaise::default(),
+            recorders: BTreeMap::new(),
+            next_recorder_id: 0,
+            windows: BTreeMap::new(),
+            redraws,
+            font,
+            font_typeface,
+            clipboard: Vec::new(),
+            saved_geometry: VecDeque::new(),
+            scale,
+            scale_override: None,
+            config: Rc::clone(&config),
+            win_tabbing: false,
+            volume_osd: false,
+            shortcuts_osd: false,
+            sticky_keys_osd: false,
+            popup_rect: Rect::default(),
+            sticky_keys_rect: Rect::default(),
+        })
+    }
+
+    pub fn with_orbital<'a>(&'a mut self, orb: &'a mut Orbital) -> OrbitalSchemeEvent<'a> {
+        OrbitalSchemeEvent {
+            scheme: self,
+            orb
+        }
+    }
+
+    fn cursor_rect(&self) -> Rect {
+        let cursor = &self.cursors[&self.cursor_i];
+        let (off_x, off_y) = match self.cursor_i {
+            CursorKind::None => (0, 0),
+            CursorKind::LeftPtr => (0, 0),
+            CursorKind::BottomLeftCorner => (0, -cursor.height()),
+            CursorKind::BottomRightCorner => (-cursor.width(), -cursor.height()),
+            CursorKind::BottomSide => (-cursor.width()/2, -cursor.height()),
+            CursorKind::LeftSide => (0, -cursor.height()/2),
+            CursorKind::RightSide => (-cursor.width(), -cursor.height()/2),
+        };
+        let (x, y) = self.cursor_drawn;
+        Rect::new(x + off_x, y + off_y, cursor.width(), cursor.height())
+    }
+
+    fn focus(&mut self, id: usize, focused: bool) {
+        if let Some(window) = self.windows.get_mut(&id) {
+            if focused {
+                self.focus_serial += 1;
+                window.focused_at = self.focus_serial;
+            }
+            schedule(&mut self.redraws, window.title_rect());
+            schedule(&mut self.redraws, window.rect());
+            window.event(FocusEvent { focused }.to_event());
+        }
+
+        // a window raised on hover goes back to its place once another one takes focus
+        if focused {
+            if let Some(window) = self.raised.take().and_then(|raised| self.windows.get(&raised)) {
+                schedule(&mut self.redraws, window.title_rect());
+                schedule(&mut self.redraws, window.rect());
+            }
+        }
+    }
+
+    fn rezbuffer(&mut self) {
+        self.zbuffer = zbuffer(&self.order, &self.windows);
+
+        if let Some(id) = self.raised {
+            raise_in_class(&mut self.zbuffer, id);
+        }
+
+        let relations = self.stack_relations();
+        if ! relations.is_empty() {
+            stack_zbuffer(&mut self.zbuffer, &relations);
+        }
+
+        if let Some(id) = self.peek {
+            raise_in_zbuffer(&mut self.zbuffer, id);
+        }
+    }
+
+    fn stack_relations(&self) -> BTreeMap<usize, StackRelation> {
+        self.windows.iter()
+            .filter_map(|(id, window)| window.stack.map(|relation| (*id, relation)))
+            .collect()
+    }
+
+    // List the displays, one per line, as `x,y,width,height,scale,refresh_hz`.
+    // Displays that don't report a refresh rate use the `refresh_rate` from the config.
+    fn displays_query(&self, displays: &[Display]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for display in displays.iter() {
+            let _ = writeln!(data, "{},{},{},{},{},{}",
+                display.x, display.y, display.image.width(), display.image.height(), display.scale,
+                display.refresh_hz.unwrap_or(self.config.refresh_rate));
+        }
+        data
+    }
+
+    // The windows query: a line of `id,title` for every window, the most recently focused first
+    fn windows_query(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for id in mru_order(&self.windows) {
+            let _ = writeln!(data, "{},{}", id, self.windows[&id].title);
+        }
+        data
+    }
+
+    // Which window is at the point `x/y` in `args`, as `id,title`, or nothing over the background
+    fn hittest_query(&mut self, args: &str) -> Result<Vec<u8>> {
+        let (x, y) = args.split_once('/').ok_or(Error::new(EINVAL))?;
+        let x = x.parse::<i32>().map_err(|_| Error::new(EINVAL))?;
+        let y = y.parse::<i32>().map_err(|_| Error::new(EINVAL))?;
+
+        self.rezbuffer();
+        let mut data = Vec::new();
+        if let Some(id) = window_at(&self.zbuffer, &self.windows, x, y) {
+            let _ = writeln!(data, "{},{}", id, self.windows[&id].title);
+        }
+        Ok(data)
+    }
+
+    // Read back the contents of the window with the id in `args`, for remote display. With a
+    // trailing `/dirty` only the part changed since the client last read all of the query
+    // `handle` is returned, see Window::read_contents for the format. The contents are copied when the query
+    // is opened or refreshed, so they can't be torn by the window being resized while they are
+    // read.
+    fn contents_query(&mut self, handle: usize, args: &str) -> Result<Vec<u8>> {
+        let (id, dirty_only) = match args.split_once('/') {
+            Some((id, "dirty")) => (id, true),
+            Some(_) => return Err(Error::new(EINVAL)),
+            None => (args, false),
+        };
+        let id = id.parse::<usize>().map_err(|_| Error::new(EINVAL))?;
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        Ok(window.read_contents(handle, dirty_only))
+    }
+
+    // Composite and sync all displays again on the next redraw
+    fn force_full_redraw(&mut self, displays: &[Display]) {
+        let screens: Vec<Rect> = displays.iter().map(Display::screen_rect).collect();
+        damage_all(&mut self.redraws, &screens);
+    }
+
+    // The least time between the redraws of windows that aren't focused, None for no limit
+    fn sync_interval(&self) -> Option<Duration> {
+        let fps = self.config.bg_window_max_fps;
+        (fps > 0).then(|| Duration::from_secs(1) / fps)
+    }
+
+    // Redraw the whole window with the contents it synced
+    fn sync_window(&mut self, id: usize) {
+        if let Some(window) = self.windows.get_mut(&id) {
+            window_synced(&mut self.redraws, window);
+        }
+    }
+
+    // Log the window state at debug level, see state_dump()
+    fn dump_state(&self) {
+        for line in state_dump(&self.windows, &self.order, &self.zbuffer) {
+            debug!("state: {}", line);
+        }
+    }
+
+    // Override the UI scale taken from the displays, or follow them again with None. The title
+    // bar buttons, cursors and wallpapers are loaded again at the new scale, and the windows are
+    // rescaled on the next redraw
+    fn set_scale_override(&mut self, displays: &[Display], scale_override: Option<i32>) {
+        self.scale_override = scale_override;
+        self.scale = scale_override.unwrap_or_else(|| displays.iter().fold(1, |scale, display| cmp::max(scale, display.scale)));
+
+        let scale = self.scale;
+        self.window_max = load_image(&self.config.window_max, DEFAULT_WINDOW_MAX, scale);
+        self.window_max_unfocused = load_image(&self.config.window_max_unfocused, DEFAULT_WINDOW_MAX_UNFOCUSED, scale);
+        self.window_close = load_image(&self.config.window_close, DEFAULT_WINDOW_CLOSE, scale);
+        self.window_close_unfocused = load_image(&self.config.window_close_unfocused, DEFAULT_WINDOW_CLOSE_UNFOCUSED, scale);
+        self.cursors = load_cursors(&self.config, scale);
+        self.wallpapers = load_wallpapers(&self.config, displays.len(), scale);
+        self.force_full_redraw(displays);
+    }
+
+    //TODO: update cursor in more places to ensure consistency:
+    // - Window resizes
+    // - Window sets cursor on/off
+    // - Window moves
+    fn update_cursor(&mut self, x: i32, y: i32, kind: CursorKind) {
+        if kind != self.cursor_i {
+            let cursor_rect = self.cursor_rect();
+            schedule(&mut self.redraws, cursor_rect);
+
+            self.cursor_i = kind;
+
+            let cursor_rect = self.cursor_rect();
+            schedule(&mut self.redraws, cursor_rect);
+        }
+
+        // Update saved mouse information
+        if x != self.cursor_x || y != self.cursor_y {
+            self.cursor_x = x;
+            self.cursor_y = y;
+
+            // without smoothing the drawn cursor goes straight to the pointer
+            if ! self.config.smooth_cursor {
+                self.move_drawn_cursor((x, y));
+            }
+        }
+    }
+
+    fn move_drawn_cursor(&mut self, position: (i32, i32)) {
+        for (x, y) in [self.cursor_drawn, position] {
+            self.cursor_drawn = (x, y);
+            let cursor_rect = self.cursor_rect();
+            schedule(&mut self.redraws, cursor_rect);
+            if self.pointer_highlight {
+                schedule(&mut self.redraws, highlight_rect(x, y, self.scale));
+            }
+        }
+    }
+
+    // Make the window `to` the active tab of its group in place of `from`, taking its place on
+    // screen, and the focus if `from` had it
+    fn show_tab(&mut self, from: usize, to: usize) {
+        let Some(group) = self.windows.get(&to).and_then(|window| window.tab_group).and_then(|group| self.tab_groups.get_mut(&group)) else {
+            return;
+        };
+        group.active = to;
+        if from == to {
+            return;
+        }
+
+        let Some(mut window) = self.windows.remove(&to) else {
+            return;
+        };
+        if let Some(from_window) = self.windows.get_mut(&from) {
+            schedule(&mut self.redraws, from_window.title_rect());
+            schedule(&mut self.redraws, from_window.rect());
+            take_geometry(&mut window, Rect::new(from_window.x, from_window.y, from_window.width(), from_window.height()));
+            from_window.tab_hidden = true;
+        }
+        window.tab_hidden = false;
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+        self.windows.insert(to, window);
+
+        if self.order.front() == Some(&from) {
+            self.order.retain(|&e| e != to);
+            self.order.push_front(to);
+            self.focus(from, false);
+            self.focus(to, true);
+        }
+    }
+
+    // Take the window out of its tab group, see TabGroup for which tab takes its place
+    fn leave_tab_group(&mut self, id: usize) {
+        let Some(group_id) = self.windows.get(&id).and_then(|window| window.tab_group) else {
+            return;
+        };
+        let next = self.tab_groups.get_mut(&group_id).and_then(|group| group.remove(id));
+        if let Some(next) = next {
+            self.show_tab(id, next);
+        }
+        if let Some(window) = self.windows.get_mut(&id) {
+            window.tab_group = None;
+            window.tab_hidden = false;
+            schedule(&mut self.redraws, window.title_rect());
+            schedule(&mut self.redraws, window.rect());
+        }
+
+        if self.tab_groups.get(&group_id).is_some_and(|group| group.tabs.len() < 2) {
+            if let Some(group) = self.tab_groups.remove(&group_id) {
+                for tab in group.tabs {
+                    if let Some(window) = self.windows.get_mut(&tab) {
+                        window.tab_group = None;
+                        window.tab_hidden = false;
+                        schedule(&mut self.redraws, window.title_rect());
+                    }
+                }
+            }
+        }
+    }
+
+    // The tab of another window in the title bar of the active tab `id` at (x, y)
+    fn tab_at(&self, id: usize, x: i32, y: i32) -> Option<usize> {
+        let window = self.windows.get(&id)?;
+        let group = self.tab_groups.get(&window.tab_group?)?;
+        let tabs = tab_rects(&tab_area(&window.title_rect(), window.scale), group.tabs.len());
+        let index = tabs.iter().position(|tab| tab.contains(x, y))?;
+        group.tabs.get(index).copied().filter(|&tab| tab != id)
+    }
+}
+
+impl Handler for OrbitalScheme {
+    fn should_delay(&mut self, packet: &Packet) -> bool {
+        packet.a == SYS_READ &&
+            self.windows.get(&packet.b)
+                .map(|window| !window.asynchronous)
+                .unwrap_or(true)
+    }
+
+    fn handle_scheme(&mut self, orb: &mut Orbital, packets: &mut [Packet]) -> io::Result<()> {
+        self.with_orbital(orb).scheme_event(packets)
+    }
+
+    fn handle_display(&mut self, orb: &mut Orbital, events: &mut [Event]) -> io::Result<()> {
+        self.with_orbital(orb).display_event(events)
+    }
+
+    fn handle_after(&mut self, orb: &mut Orbital) -> io::Result<()> {
+        let batching = batch_active(&mut self.batch, Instant::now());
+        let mut scheme = self.with_orbital(orb);
+        if ! batching {
+            scheme.redraw();
+        }
+        // Motion held back for frame events goes out once a pass is done, whether or not it drew
+        // anything, so that it isn't stuck until something else is redrawn
+        for window in scheme.scheme.windows.values_mut() {
+            window.release_events();
+        }
+
+        // Notify windows of events queued while redrawing, such as frame events
+        scheme.scheme_event(&mut [])
+    }
+
+    fn handle_timer(&mut self, orb: &mut Orbital) -> io::Result<()> {
+        let mut scheme = self.with_orbital(orb);
+        scheme.update_toasts();
+        scheme.update_animation();
+        scheme.update_spinner();
+        scheme.update_idle();
+        scheme.update_panel_timer();
+        scheme.update_smooth_cursor();
+        scheme.update_modifiers();
+        scheme.update_hot_corners();
+        scheme.update_syncs();
+        scheme.update_resize_osd();
+        scheme.update_autoraise();
+        Ok(())
+    }
+
+    fn handle_window_new(&mut self, orb: &mut Orbital,
+                         x: i32, y: i32, width: i32, height: i32,
+                         parts: &str, title: String) -> Result<usize> {
+        self.with_orbital(orb).window_new(x, y, width, height, parts, title)
+    }
+
+    fn handle_window_read(&mut self, _orb: &mut Orbital, id: usize, buf: &mut [Event]) -> Result<usize> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        Ok(window.read(buf))
+    }
+
+    fn handle_window_async(&mut self, _orb: &mut Orbital, id: usize, is_async: bool) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.asynchronous = is_async;
+        Ok(())
+    }
+
+    fn handle_window_drag(&mut self, orb: &mut Orbital, id: usize /*TODO: resize sides */) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        if self.cursor_left {
+            self.dragging = DragMode::Title(id, self.cursor_x, self.cursor_y);
+        }
+        Ok(())
+    }
+
+    fn handle_window_mouse_cursor(&mut self, _orb: &mut Orbital, id: usize, visible: bool) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.mouse_cursor = visible;
+        Ok(())
+    }
+
+    fn handle_window_mouse_grab(&mut self, _orb: &mut Orbital, id: usize, grab: bool) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.mouse_grab = grab;
+        Ok(())
+    }
+
+    fn handle_window_mouse_relative(&mut self, _orb: &mut Orbital, id: usize, relative: bool, cursor_visible: bool) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.mouse_relative = relative;
+        window.mouse_relative_cursor = relative && cursor_visible;
+        Ok(())
+    }
+
+    fn handle_window_position(&mut self, _orb: &mut Orbital, id: usize, x: Option<i32>, y: Option<i32>) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+
+        window.x = x.unwrap_or(window.x);
+        window.y = y.unwrap_or(window.y);
+
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+
+        Ok(())
+    }
+
+    fn handle_window_position_on_display(&mut self, orb: &mut Orbital, id: usize, display: usize, x: i32, y: i32) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        let screens: Vec<Rect> = orb.displays.iter().map(|display| display.screen_rect()).collect();
+        let (x, y) = display_position(&screens, display, x, y).ok_or(Error::new(EINVAL))?;
+        self.handle_window_position(orb, id, Some(x), Some(y))
+    }
+
+    fn handle_window_place(&mut self, orb: &mut Orbital, id: usize, width_percent: i32, height_percent: i32) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        self.with_orbital(orb).place_window(id, width_percent, height_percent);
+
+        Ok(())
+    }
+
+    fn handle_window_resize(&mut self, _orb: &mut Orbital, id: usize, w: Option<i32>, h: Option<i32>, anchor: Anchor) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+
+        let (width, height) = window.buffer_size();
+        let (w, h) = window.size_hints.constrain(w.unwrap_or(width), h.unwrap_or(height));
+
+        let (x, y) = (window.x, window.y);
+        window.set_size(w, h, anchor);
+        if window.x != x || window.y != y {
+            window.event(MoveEvent { x: window.x, y: window.y }.to_event());
+        }
+
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+
+        Ok(())
+    }
+
+    fn handle_window_set_flag(&mut self, orb: &mut Orbital, id: usize, flag: char, value: bool) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+
+        // Handle maximized flag custom, windows that aren't resizable keep their size like they do
+        // when their title bar is clicked, where they have no maximize button
+        if flag == ORBITAL_FLAG_MAXIMIZED {
+            if value != window.maximized() {
+                if window.tileable() {
+                    self.with_orbital(orb).tile_window(Some(&id), TilePosition::FullScreen);
+                } else {
+                    debug!("ignoring maximize of window {} that isn't resizable", id);
+                }
+            }
+        } else if flag == ORBITAL_FLAG_MINIMIZED {
+            // like MIN, the window is hidden but keeps its place in the order
+            self.with_orbital(orb).minimize(id, value, None);
+        } else {
+            // Setting flag may change visibility, make sure to queue redraws both before and after
+            schedule(&mut self.redraws, window.title_rect());
+            schedule(&mut self.redraws, window.rect());
+
+            window.set_flag(flag, value);
+
+            // Setting flag may change visibility, make sure to queue redraws both before and after
+            schedule(&mut self.redraws, window.title_rect());
+            schedule(&mut self.redraws, window.rect());
+
+            let mut scheme = self.with_orbital(orb);
+
+            // The window content stays in place when decorations are toggled, but a tiled window
+            // has to make room for (or reclaim) its title bar
+            if flag == crate::window::ORBITAL_FLAG_BORDERLESS {
+                scheme.retile_window(id);
+            }
+
+            // Ensure mouse cursor is correct, the decorations under it may have changed
+            let event = MouseEvent {
+                x: scheme.scheme.cursor_x,
+                y: scheme.scheme.cursor_y,
+            };
+            scheme.mouse_event(event);
+        }
+
+        Ok(())
+    }
+
+    fn handle_window_frame(&mut self, _orb: &mut Orbital, id: usize) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.frame_requested = true;
+        Ok(())
+    }
+
+    fn handle_window_stack(&mut self, _orb: &mut Orbital, id: usize, relation: Option<StackRelation>) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        if let Some(relation) = relation {
+            let target = relation.target();
+            if ! self.windows.contains_key(&target) || stack_cycle(&self.stack_relations(), id, target) {
+                return Err(Error::new(EINVAL));
+            }
+            // a window kept above another follows it around the stack, so only the client's
+            // own can be chained to
+            if ! same_owner(&self.windows, id, target) {
+                return Err(Error::new(EPERM));
+            }
+        }
+
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.stack = relation;
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+
+        Ok(())
+    }
+
+    fn handle_window_batch(&mut self, orb: &mut Orbital, id: usize, begin: bool) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        if ! begin {
+            return end_batch(&mut self.batch, id);
+        }
+        let now = Instant::now();
+        begin_batch(&mut self.batch, id, now)?;
+        // a batch that isn't committed is redrawn when it times out, even if nothing else happens
+        if self.batch.is_some_and(|open| open.started == now) {
+            if let Err(err) = orb.set_timeout(BATCH_TIMEOUT) {
+                error!("failed to set timeout for batch: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_window_grab_key(&mut self, _orb: &mut Orbital, id: usize, combo: KeyCombo) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        self.key_grabs.grab(combo, id)
+    }
+
+    fn handle_window_size_hints(&mut self, _orb: &mut Orbital, id: usize, hints: SizeHints) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.size_hints = hints;
+        Ok(())
+    }
+
+    fn handle_window_rotate(&mut self, _orb: &mut Orbital, id: usize, rotation: Rotation) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+
+        window.rotation = rotation;
+
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+        Ok(())
+    }
+
+    fn handle_window_scroll_factor(&mut self, _orb: &mut Orbital, id: usize, factor: f32) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.scroll_factor = factor;
+        Ok(())
+    }
+
+    fn handle_window_inhibit_idle(&mut self, orb: &mut Orbital, id: usize, inhibit: bool) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+        self.idle.inhibit(id, inhibit);
+        self.with_orbital(orb).update_idle();
+        Ok(())
+    }
+
+    fn handle_window_opacity(&mut self, _orb: &mut Orbital, id: usize, opacity: u8) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        if window.opacity != opacity {
+            window.opacity = opacity;
+            schedule(&mut self.redraws, window.rect());
+        }
+        Ok(())
+    }
+
+    fn handle_window_suspend(&mut self, _orb: &mut Orbital, id: usize, suspended: bool) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+
+        window.suspended = suspended;
+
+        schedule(&mut self.redraws, window.title_rect());
+        schedule(&mut self.redraws, window.rect());
+        Ok(())
+    }
+
+    fn handle_window_swap(&mut self, _orb: &mut Orbital, id: usize, other: usize) -> Result<()> {
+        if ! self.windows.contains_key(&id) || ! self.windows.contains_key(&other) {
+            return Err(Error::new(EBADF));
+        }
+        // swapping moves and resizes the other window, so only the client's own can be
+        if ! same_owner(&self.windows, id, other) {
+            return Err(Error::new(EPERM));
+        }
+        if id == other {
+            return Ok(());
+        }
+
+        let mut window = self.windows.remove(&id).ok_or(Error::new(EBADF))?;
+        if let Some(other_window) = self.windows.get_mut(&other) {
+            for window in [&window, &*other_window] {
+                schedule(&mut self.redraws, window.title_rect());
+                schedule(&mut self.redraws, window.rect());
+            }
+
+            swap_geometry(&mut window, other_window);
+
+            for window in [&window, &*other_window] {
+                schedule(&mut self.redraws, window.title_rect());
+                schedule(&mut self.redraws, window.rect());
+            }
+        }
+        self.windows.insert(id, window);
+        Ok(())
+    }
+
+    fn handle_window_tab(&mut self, _orb: &mut Orbital, id: usize, other: Option<usize>) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+        let Some(other) = other else {
+            self.leave_tab_group(id);
+            return Ok(());
+        };
+        let other_group = self.windows.get(&other).ok_or(Error::new(EBADF))?.tab_group;
+        // tabbing onto a window hides it and takes its place, so only the client's own can be
+        if ! same_owner(&self.windows, id, other) {
+            return Err(Error::new(EPERM));
+        }
+        if id == other || other_group.is_some() && other_group == self.windows[&id].tab_group {
+            return Ok(());
+        }
+
+        self.leave_tab_group(id);
+        let group_id = match self.windows[&other].tab_group {
+            Some(group_id) => group_id,
+            None => {
+                let group_id = self.next_tab_group;
+                self.next_tab_group += 1;
+                self.tab_groups.insert(group_id, TabGroup::new(other));
+                if let Some(window) = self.windows.get_mut(&other) {
+                    window.tab_group = Some(group_id);
+                }
+                group_id
+            }
+        };
+        let Some(group) = self.tab_groups.get_mut(&group_id) else {
+            return Ok(());
+        };
+        let active = group.active;
+        group.tabs.push(id);
+        if let Some(window) = self.windows.get_mut(&id) {
+            window.tab_group = Some(group_id);
+        }
+        self.show_tab(active, id);
+        Ok(())
+    }
+
+    fn handle_window_redraw_all(&mut self, orb: &mut Orbital, id: usize) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        self.force_full_redraw(&orb.displays);
+        Ok(())
+    }
+
+    fn handle_window_text_cursor(&mut self, _orb: &mut Orbital, id: usize, position: Option<(i32, i32)>) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.text_cursor = position;
+        Ok(())
+    }
+
+    fn handle_window_ack_size(&mut self, _orb: &mut Orbital, id: usize, serial: u32) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        let (title_rect, rect) = (window.title_rect(), window.rect());
+        if window.ack_resize(serial) {
+            schedule(&mut self.redraws, title_rect);
+            schedule(&mut self.redraws, rect);
+            schedule(&mut self.redraws, window.title_rect());
+            schedule(&mut self.redraws, window.rect());
+        }
+        Ok(())
+    }
+
+    fn handle_window_input_shape(&mut self, _orb: &mut Orbital, id: usize, rects: Vec<Rect>) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.input_shape = rects;
+        Ok(())
+    }
+
+    fn handle_window_mirror(&mut self, _orb: &mut Orbital, id: usize, source: Option<usize>) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+        if let Some(source) = source {
+            if ! self.windows.contains_key(&source) || mirrors(&self.windows, source, id) {
+                return Err(Error::new(EINVAL));
+            }
+            // showing another client's contents would let it read them
+            if ! same_owner(&self.windows, id, source) {
+                return Err(Error::new(EPERM));
+            }
+        }
+
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.mirror = source;
+        window.mirrored = None;
+        schedule(&mut self.redraws, window.rect());
+        Ok(())
+    }
+
+    fn handle_window_minimize(&mut self, orb: &mut Orbital, id: usize, minimized: bool, target: Option<Rect>) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        self.with_orbital(orb).minimize(id, minimized, target);
+        Ok(())
+    }
+
+    fn handle_window_notify(&mut self, orb: &mut Orbital, id: usize, text: String) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        self.with_orbital(orb).notify(text)
+    }
+
+    fn handle_window_switcher(&mut self, orb: &mut Orbital, id: usize, command: SwitcherCommand) -> Result<()> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        self.with_orbital(orb).switcher(command);
+        Ok(())
+    }
+
+    fn handle_window_title(&mut self, _orb: &mut Orbital, id: usize, title: String) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.title = title;
+        window.render_title(&self.font);
+
+        schedule(&mut self.redraws, window.title_rect());
+
+        Ok(())
+    }
+
+    fn handle_window_subtitle(&mut self, _orb: &mut Orbital, id: usize, subtitle: String) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.subtitle = subtitle;
+        window.render_title(&self.font);
+
+        schedule(&mut self.redraws, window.title_rect());
+
+        Ok(())
+    }
+
+    fn handle_window_clear_notified(&mut self, _orb: &mut Orbital, id: usize) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.notified_read = false;
+        Ok(())
+    }
+
+    fn handle_window_map(&mut self, _orb: &mut Orbital, id: usize, create_new: bool) -> Result<&mut [Color]> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        if create_new {
+            window.maps += 1;
+        }
+        Ok(window.map())
+    }
+
+    fn handle_window_unmap(&mut self, _orb: &mut Orbital, id: usize) -> Result<()> {
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        if window.maps > 0 {
+            window.maps -= 1;
+        } else {
+            warn!("attempted unmap when there are no mappings");
+        }
+        Ok(())
+    }
+
+    fn handle_window_properties(&mut self, _orb: &mut Orbital, id: usize) -> Result<Properties> {
+        let window = self.windows.get(&id).ok_or(Error::new(EBADF))?;
+        Ok(window.properties())
+    }
+
+    fn handle_window_sync(&mut self, orb: &mut Orbital, id: usize) -> Result<usize> {
+        if ! self.windows.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+        let interval = self.sync_interval().filter(|_| self.order.front() != Some(&id));
+        if self.sync_limiter.sync(id, Instant::now(), interval) {
+            self.sync_window(id);
+            orb.query_changed(&format!("contents/{}", id));
+        } else {
+            self.with_orbital(orb).update_syncs();
+        }
+        Ok(0)
+    }
+
+    fn handle_window_close(&mut self, orb: &mut Orbital, id: usize) -> Result<usize> {
+        // Unfocus current front window
+        if let Some(id) = self.order.front() {
+            self.focus(*id, false);
+        }
+
+        let focused = self.order.front() == Some(&id);
+        self.order.retain(|&e| e != id);
+        if self.peek == Some(id) {
+            self.peek = None;
+        }
+        if self.raised == Some(id) {
+            self.raised = None;
+        }
+        // Commit the batch of a window that closes without doing so
+        let _ = end_batch(&mut self.batch, id);
+        if self.close_confirm == Some(id) {
+            self.close_confirm = None;
+            schedule(&mut self.redraws, self.close_confirm_rect);
+        }
+        self.key_grabs.release_window(id);
+        self.sync_limiter.remove(id);
+        self.idle.inhibit(id, false);
+        self.leave_tab_group(id);
+
+        // the windows mirroring it show a placeholder until they stop
+        let placeholder = self.config.background_color.into();
+        for mirror in self.windows.values_mut().filter(|window| window.mirror == Some(id)) {
+            mirror.mirror = None;
+            mirror.mirrored = Some(Image::from_color(mirror.width(), mirror.height(), placeholder));
+            schedule(&mut self.redraws, mirror.rect());
+        }
+
+        let res = if let Some(window) = self.windows.remove(&id) {
+            return_focus(&mut self.order, &mut self.windows, id, window.opened_from, focused);
+            schedule(&mut self.redraws, window.title_rect());
+            schedule(&mut self.redraws, window.rect());
+            if self.config.remember_geometry {
+                save_geometry(&mut self.saved_geometry, &window.title, SavedGeometry::of(&window));
+            }
+
+            // the window tiled next to it takes over its half
+            if let Some((_, position)) = window.restore {
+                let area = OrbitalSchemeEvent::tile_area(&orb.displays, &window);
+                if let Some((sibling, rect)) = reflow_tile(&mut self.windows, &self.order, position, &area) {
+                    if let Some(sibling) = self.windows.get_mut(&sibling) {
+                        schedule(&mut self.redraws, sibling.title_rect());
+                        schedule(&mut self.redraws, sibling.rect());
+                        sibling.shaded = false;
+                        sibling.configure(rect);
+                    }
+                }
+            }
+            Ok(0)
+        } else {
+            Err(Error::new(EBADF))
+        };
+
+        // Focus current front window
+        if let Some(id) = self.order.front() {
+            self.focus(*id, true);
+        }
+
+        // Ensure mouse cursor is correct
+        let event = MouseEvent {
+            x: self.cursor_x,
+            y: self.cursor_y,
+        };
+        self.with_orbital(orb).mouse_event(event);
+
+        res
+    }
+    fn handle_clipboard_new(&mut self, _orb: &mut Orbital, id: usize) -> Result<usize> {
+        //TODO: implement better clipboard mechanism
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        window.clipboard_seek = 0;
+        Ok(id)
+    }
+
+    fn handle_clipboard_read(&mut self, _orb: &mut Orbital, id: usize, buf: &mut [u8]) -> Result<usize> {
+        //TODO: implement better clipboard mechanism
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        let mut i = 0;
+        while i < buf.len() && window.clipboard_seek < self.clipboard.len() {
+            buf[i] = self.clipboard[i];
+            i += 1;
+            window.clipboard_seek += 1;
+        }
+        Ok(i)
+    }
+
+    fn handle_clipboard_write(&mut self, _orb: &mut Orbital, id: usize, buf: &[u8]) -> Result<usize> {
+        //TODO: implement better clipboard mechanism
+        let window = self.windows.get_mut(&id).ok_or(Error::new(EBADF))?;
+        clipboard_write(&mut self.clipboard, &mut window.clipboard_seek, buf, self.config.max_clipboard_bytes)
+    }
+
+    fn handle_clipboard_close(&mut self, _orb: &mut Orbital, id: usize) -> Result<usize> {
+        //TODO: implement better clipboard mechanism
+        if self.windows.contains_key(&id) {
+            Ok(0)
+        } else {
+            Err(Error::new(EBADF))
+        }
+    }
+
+    fn handle_record_new(&mut self, orb: &mut Orbital) -> Result<usize> {
+        if ! self.config.screen_capture {
+            return Err(Error::new(EPERM));
+        }
+        if self.recorders.len() >= MAX_RECORDERS {
+            return Err(Error::new(EBUSY));
+        }
+        let id = self.next_recorder_id;
+        self.next_recorder_id += 1;
+        self.recorders.insert(id, Recorder::new());
+        // the recording starts with a keyframe
+        self.force_full_redraw(&orb.displays);
+        Ok(id)
+    }
+
+    fn handle_record_read(&mut self, _orb: &mut Orbital, id: usize, buf: &mut [u8]) -> Result<usize> {
+        let recorder = self.recorders.get_mut(&id).ok_or(Error::new(EBADF))?;
+        Ok(recorder.read(buf))
+    }
+
+    fn handle_record_write(&mut self, orb: &mut Orbital, id: usize, buf: &[u8]) -> Result<usize> {
+        if ! self.recorders.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+        match buf {
+            b"KEYFRAME" => {
+                self.force_full_redraw(&orb.displays);
+                Ok(buf.len())
+            },
+            _ => Err(Error::new(EINVAL)),
+        }
+    }
+
+    fn handle_record_close(&mut self, _orb: &mut Orbital, id: usize) -> Result<usize> {
+        self.recorders.remove(&id).map(|_| 0).ok_or(Error::new(EBADF))
+    }
+
+    fn handle_query(&mut self, orb: &mut Orbital, handle: usize, query: &str, args: &str) -> Result<Vec<u8>> {
+        // what is shown can only be read back with screen_capture
+        if matches!(query, "contents" | "pixel" | "screenshot") && ! self.config.screen_capture {
+            return Err(Error::new(EPERM));
+        }
+        match query {
+            "clipboard" => Ok(format!("{},{}\n", self.clipboard.len(), self.config.max_clipboard_bytes).into_bytes()),
+            "contents" => self.contents_query(handle, args),
+            "displays" => Ok(self.displays_query(&orb.displays)),
+            "flags" => {
+                let id = args.parse::<usize>().map_err(|_| Error::new(EINVAL))?;
+                let window = self.windows.get(&id).ok_or(Error::new(EBADF))?;
+                Ok(format!("{}\n", window.flags().0).into_bytes())
+            },
+            "fonts" => {
+                let style = font_style(self.config.ui_font_bold, self.config.ui_font_italic);
+                Ok(fonts_query(self.font_typeface.as_deref(), &font_typefaces(Path::new(UI_FONTS_DIR), style)))
+            },
+            "frame" => Ok(self.frame_clock.query()),
+            "heartbeat" => Ok(orb.heartbeat.query()),
+            "hittest" => self.hittest_query(args),
+            "modifiers" => Ok(modifiers_query(self.modifier_state, self.lock_keys.locked)),
+            "pixel" => {
+                let (x, y) = args.split_once('/').ok_or(Error::new(EINVAL))?;
+                let x = x.parse::<i32>().map_err(|_| Error::new(EINVAL))?;
+                let y = y.parse::<i32>().map_err(|_| Error::new(EINVAL))?;
+                let screens: Vec<(Rect, &ImageRef)> = orb.displays.iter().map(|display| (display.screen_rect(), &display.image)).collect();
+                pixel_query(&screens, x, y).ok_or(Error::new(ENODEV))
+            },
+            "scale" => Ok(format!("{}\n", self.scale).into_bytes()),
+            "screenshot" => orb.displays.first().map(|display| screenshot(&display.image)).ok_or(Error::new(ENODEV)),
+            "windows" => match args {
+                "" => Ok(self.windows_query()),
+                "cursorpos" => Ok(text_cursor_query(&self.windows)),
+                "subtitles" => Ok(subtitles_query(&self.windows)),
+                _ => Err(Error::new(EINVAL)),
+            },
+            _ => Err(Error::new(EINVAL)),
+        }
+    }
+
+    fn handle_query_read(&mut self, _orb: &mut Orbital, handle: usize) {
+        for window in self.windows.values_mut() {
+            window.contents_read(handle);
+        }
+    }
+
+    fn handle_query_close(&mut self, _orb: &mut Orbital, handle: usize) {
+        for window in self.windows.values_mut() {
+            window.contents_dirty.remove(&handle);
+        }
+    }
+}
+pub struct OrbitalSchemeEvent<'a> {
+    scheme: &'a mut OrbitalScheme,
+    orb: &'a mut Orbital,
+}
+
+impl<'a> OrbitalSchemeEvent<'a> {
+    pub fn redraw(&mut self) {
+        self.scheme.rezbuffer();
+        self.update_panel();
+        self.update_tabs();
+        self.update_mirrors();
+        self.update_window_scales();
+        self.update_smooth_cursor();
+        // a see-through overlay is blended over the windows below it, which are composited again
+        // each time so that it isn't blended over itself
+        if self.scheme.config.osd_opacity < 1.0
+        && (self.scheme.win_tabbing || self.scheme.volume_osd || self.scheme.shortcuts_osd) {
+            schedule(&mut self.scheme.redraws, self.scheme.popup_rect);
+        }
+        // the size of a window being resized is composited over before it is drawn again, in case
+        // it moved or got narrower
+        if self.scheme.resize_osd.id.is_some() {
+            schedule(&mut self.scheme.redraws, self.scheme.resize_osd.rect);
+        }
+        let blurred: Vec<Rect> = self.scheme.windows.values()
+            .filter(|window| window.visible() && window.blurred())
+            .map(Window::rect)
+            .collect();
+        // after compacting, which can merge rects on both sides of a window into one across it
+        compact(&mut self.scheme.redraws);
+        cover_blurred(&mut self.scheme.redraws, &blurred);
+
+        let cursor_rect = self.scheme.cursor_rect();
+        let highlight = if self.scheme.pointer_highlight {
+            let (x, y) = self.scheme.cursor_drawn;
+            highlight_ring(x, y, self.scheme.scale)
+        } else {
+            Vec::new()
+        };
+
+        // windows that requested a frame event and were composited in this redraw
+        let mut composited = Vec::new();
+
+        // the window each display shows as it is, if any, see scanout_window
+        let scanout: Vec<Option<usize>> = self.orb.displays.iter().map(|display| {
+            let screen = display.screen_rect();
+            let overlaid = self.scheme.panel.as_ref().is_some_and(|panel| ! panel.state.rect.intersection(&screen).is_empty())
+                || ! self.scheme.animation_rect.intersection(&screen).is_empty();
+            if ! self.scheme.config.fullscreen_scanout || overlaid {
+                return None;
+            }
+            scanout_window(&self.scheme.zbuffer, &self.scheme.windows, &screen, self.scheme.config.unfocused_dim)
+        }).collect();
+
+        // with frame pacing, the damage on displays whose next frame isn't due yet waits for it
+        let now = Instant::now();
+        let screens: Vec<Rect> = self.orb.displays.iter().map(Display::screen_rect).collect();
+        let refresh_rates: Vec<u32> = self.orb.displays.iter()
+            .map(|display| display.refresh_hz.unwrap_or(self.scheme.config.refresh_rate))
+            .collect();
+        let due: Vec<bool> = refresh_rates.iter().enumerate()
+            .map(|(i, &refresh_hz)| ! self.scheme.config.frame_pacing || self.scheme.frame_pacer.is_due(i, refresh_hz, now))
+            .collect();
+        let mut deferred = split_due(&mut self.scheme.redraws, &screens, &due);
+
+        // go through the list of rectangles pending a redraw, keeping each of them to sync
+        let mut damage: Vec<Rect> = Vec::new();
+        for original_rect in self.scheme.redraws.drain(..) {
+            if ! original_rect.is_empty() {
+                damage.push(original_rect);
+            }
+
+            for (display_i, display) in self.orb.displays.iter_mut().enumerate() {
+                let rect = original_rect.intersection(&display.screen_rect());
+                if ! rect.is_empty() {
+                    display.rect(&rect, self.scheme.config.background_color.into());
+
+                    if self.scheme.idle.blanked {
+                        display.rect(&rect, Color::rgb(0, 0, 0));
+                        continue;
+                    }
+                    let scanout_window = scanout[display_i].and_then(|id| self.scheme.windows.get_mut(&id).map(|window| (id, window)));
+                    if let Some((id, window)) = scanout_window {
+                        // nothing below the window shows, and nothing but the cursor is drawn
+                        // over it
+                        window.draw(display, &rect, 0);
+                        if window.frame_requested {
+                            window.frame_requested = false;
+                            composited.push(id);
+                        }
+                    } else {
+                        if let Some(wallpaper) = self.scheme.wallpapers.get_mut(self.scheme.config.wallpaper(display_i)) {
+                            let wallpaper_rect = wallpaper_rect(&display.screen_rect(), wallpaper.width(), wallpaper.height());
+                            let wallpaper_intersect = rect.intersection(&wallpaper_rect);
+                            if ! wallpaper_intersect.is_empty() {
+                                display.roi(&wallpaper_intersect)
+                                    .blend(&wallpaper.roi(&wallpaper_intersect.offset(-wallpaper_rect.left(), -wallpaper_rect.top())));
+                            }
+                        }
+
+                        for entry in self.scheme.zbuffer.iter().rev() {
+                            let id = entry.0;
+                            let i = entry.2;
+                            if let Some(window) = self.scheme.windows.get_mut(&id) {
+                                if window.title_shown() {
+                                    window.draw_title(display, &rect, i == 0, if i == 0 {
+                                        &mut self.scheme.window_max
+                                    } else {
+                                        &mut self.scheme.window_max_unfocused
+                                    }, if i == 0 {
+                                        &mut self.scheme.window_close
+                                    } else {
+                                        &mut self.scheme.window_close_unfocused
+                                    });
+                                    let strip = window.tab_group
+                                        .and_then(|group| self.scheme.tab_groups.get_mut(&group))
+                                        .and_then(|group| group.strip.as_mut());
+                                    if let Some((_, strip)) = strip {
+                                        let area = tab_area(&window.title_rect(), window.scale);
+                                        let strip_intersect = rect.intersection(&Rect::new(area.left(), area.top(), strip.width(), strip.height()));
+                                        if ! strip_intersect.is_empty() {
+                                            display.roi(&strip_intersect).blit(&strip.roi(&strip_intersect.offset(-area.left(), -area.top())));
+                                        }
+                                    }
+                                }
+                                window.draw(display, &rect, if i == 0 { 0 } else { self.scheme.config.unfocused_dim });
+
+                                if ! window.painted && self.scheme.config.loading_spinner {
+                                    let window_rect = window.rect().intersection(&rect);
+                                    for (dot_i, dot) in spinner_dots(&window.rect(), window.scale).iter().enumerate() {
+                                        let dot = dot.intersection(&window_rect);
+                                        if ! dot.is_empty() {
+                                            let color = if dot_i == self.scheme.spinner_frame % SPINNER_DOTS {
+                                                self.scheme.config.text_highlight_color
+                                            } else {
+                                                self.scheme.config.bar_highlight_color
+                                            };
+                                            display.rect(&dot, color.into());
+                                        }
+                                    }
+                                }
+
+                                if window.frame_requested && ! window.rect().intersection(&rect).is_empty() {
+                                    window.frame_requested = false;
+                                    composited.push(id);
+                                }
+                            }
+                        }
+
+                        if let Some(panel) = self.scheme.panel.as_mut() {
+                            let panel_intersect = rect.intersection(&panel.state.rect);
+                            if ! panel_intersect.is_empty() {
+                                display.roi(&panel_intersect)
+                                    .blit(&panel.image.roi(&panel_intersect.offset(-panel.state.rect.left(), -panel.state.rect.top())));
+                            }
+                        }
+
+                        if ! self.scheme.animation_rect.is_empty() {
+                            for edge in outline_edges(&self.scheme.animation_rect, ANIMATION_OUTLINE * self.scheme.scale) {
+                                let edge = edge.intersection(&rect);
+                                if ! edge.is_empty() {
+                                    display.rect(&edge, self.scheme.config.bar_highlight_color.into());
+                                }
+                            }
+                        }
+                    }
+
+                    for span in highlight.iter() {
+                        let span = span.intersection(&rect);
+                        if ! span.is_empty() {
+                            display.rect(&span, self.scheme.config.bar_highlight_color.into());
+                        }
+                    }
+
+                    let cursor_intersect = rect.intersection(&cursor_rect);
+                    if ! cursor_intersect.is_empty() {
+                        if let Some(cursor) = self.scheme.cursors.get_mut(&self.scheme.cursor_i) {
+                            display.roi(&cursor_intersect)
+                                .blend(
+                                    &cursor.roi(
+                                        &cursor_intersect.offset(-cursor_rect.left(), -cursor_rect.top())
+                                    )
+                                );
+                        }
+                    }
+                }
+            }
+        }
+
+        // nothing is drawn over a blanked screen until input wakes it up
+        if ! self.scheme.idle.blanked {
+            if self.scheme.win_tabbing {
+                self.draw_window_list_osd();
+            }
+
+            if self.scheme.volume_osd {
+                self.draw_volume_osd();
+            }
+
+            if self.scheme.shortcuts_osd {
+                self.draw_shortcuts_osd();
+            }
+
+            if self.scheme.window_menu.is_some() {
+                self.draw_window_menu_osd();
+            }
+
+            if self.scheme.close_confirm.is_some() {
+                self.draw_close_confirm_osd();
+            }
+
+            if self.scheme.toasts.visible().next().is_some() {
+                self.draw_toasts_osd();
+            }
+
+            if self.scheme.sticky_keys_osd || self.scheme.sticky_keys.latched != 0 {
+                self.draw_sticky_keys_osd();
+            }
+
+            if self.scheme.resize_osd.id.is_some() {
+                self.draw_resize_osd();
+            }
+        }
+
+        // Add any redraws from OSD's
+        deferred.extend(split_due(&mut self.scheme.redraws, &screens, &due));
+        for original_rect in self.scheme.redraws.drain(..) {
+            if ! original_rect.is_empty() {
+                schedule(&mut damage, original_rect);
+            }
+        }
+        compact(&mut damage);
+
+        // Sync any parts of displays that changed
+        if ! damage.is_empty() {
+            // the frame is still counted, with the time of the one before it
+            let time = match self.orb.monotonic_time() {
+                Ok(time) => time,
+                Err(err) => {
+                    error!("failed to read the monotonic clock: {}", err);
+                    self.scheme.frame_clock.last
+                }
+            };
+            self.scheme.frame_clock.tick(time);
+            let mut keyframe = false;
+            let record_limit = record_limit(&screens);
+            for recorder in self.scheme.recorders.values_mut() {
+                recorder.composited();
+            }
+            for (i, display) in self.orb.displays.iter_mut().enumerate() {
+                let display_redraws = sync_rects(&damage, &display.screen_rect());
+                if display_redraws.is_empty() || ! due[i] {
+                    continue;
+                }
+                self.scheme.frame_pacer.synced(i, now);
+
+                for display_redraw in display_redraws {
+                    keyframe |= record_display(&mut self.scheme.recorders, &display.image, display.x, display.y, &display_redraw, record_limit);
+
+                    // Keep synced with vesad
+                    #[allow(dead_code)]
+                    #[repr(packed)]
+                    struct SyncRect {
+                        x: i32,
+                        y: i32,
+                        w: i32,
+                        h: i32,
+                    }
+
+                    let sync_rect = SyncRect {
+                        x: display_redraw.left() - display.x,
+                        y: display_redraw.top() - display.y,
+                        w: display_redraw.width(),
+                        h: display_redraw.height(),
+                    };
+
+                    match display.file.write(unsafe {
+                        slice::from_raw_parts(
+                            &sync_rect as *const SyncRect as *const u8,
+                            mem::size_of::<SyncRect>()
+                        )
+                    }) {
+                        Ok(_) => (),
+                        Err(err) => error!("failed to sync display {}: {}", i, err),
+                    }
+                }
+            }
+
+            // recordings that fell behind catch up with the whole screen composited again
+            if keyframe {
+                damage_all(&mut self.scheme.redraws, &screens);
+            }
+
+            if let Some(latency) = self.scheme.input_latency.composited(Instant::now()) {
+                info!("{}", latency);
+            }
+        } else if deferred.is_empty() {
+            self.scheme.input_latency.unchanged();
+        }
+
+        if ! deferred.is_empty() {
+            self.scheme.redraws.extend(deferred);
+            let next = (0..screens.len())
+                .filter(|&i| ! due[i])
+                .filter_map(|i| self.scheme.frame_pacer.next_frame(i, refresh_rates[i]))
+                .min();
+            if let Some(next) = next.filter(|&next| self.scheme.frame_pacer.due.is_none_or(|due| now >= due || next < due)) {
+                match self.orb.set_timeout(next.saturating_duration_since(now)) {
+                    Ok(()) => self.scheme.frame_pacer.due = Some(next),
+                    Err(err) => error!("failed to set timeout for the next frame: {}", err),
+                }
+            }
+        }
+
+        for id in composited {
+            if let Some(window) = self.scheme.windows.get_mut(&id) {
+                let mut frame_event = Event::new();
+                frame_event.code = ORBITAL_EVENT_FRAME;
+                window.event(frame_event);
+            }
+        }
+    }
+
+    fn volume(&mut self, volume: Volume) {
+        let value = match fs::read_to_string("audio:volume") {
+            Ok(string) => match string.parse::<i32>() {
+                Ok(value) => value,
+                Err(err) => {
+                    error!("failed to parse volume '{}': {}", string, err);
+                    return;
+                }
+            },
+            Err(err) => {
+                error!("failed to read volume: {}", err);
+                return;
+            }
+        };
+
+        let step = volume_step(&self.scheme.config, self.scheme.modifier_state & SHIFT_ANY_MODIFIER != 0);
+        self.scheme.volume_value = match volume {
+            Volume::Down => step_volume(value, -step),
+            Volume::Up => step_volume(value, step),
+            Volume::Toggle => if value == 0 {
+                self.scheme.volume_toggle
+            } else {
+                self.scheme.volume_toggle = value;
+                0
+            }
+        };
+
+        match fs::write("audio:volume", format!("{}", self.scheme.volume_value)) {
+            Ok(()) => (),
+            Err(err) => {
+                error!("failed to write volume: {}", err);
+                return;
+            }
+        }
+
+        self.scheme.volume_osd = true;
+    }
+
+    // set the focus to be on a window by id, and redraw it
+    fn focus(&mut self, id: usize, focused: bool) {
+        self.scheme.focus(id, focused);
+
+        // the new window would otherwise get every key as a shortcut, until the timeout
+        let timeout = Duration::from_secs(self.scheme.config.modifier_timeout_secs.into());
+        let timeout = cmp::min(timeout, MODIFIER_FOCUS_TIMEOUT);
+        let held = self.scheme.held_modifiers;
+        if focused && self.scheme.modifier_watch.stuck(held, Instant::now(), timeout) {
+            warn!("resetting modifiers {:#x} held without key events on focus change", held);
+            self.reset_modifiers();
+        }
+    }
+
+    // Tab through the list of selectable windows, changing window order and focus to bring
+    // the next one to the front and push the previous one to the back.
+    fn super_tab(&mut self) {
+        // Enter win_tabbing mode
+        self.scheme.win_tabbing = true;
+
+        let next = match self.scheme.config.switcher_order.as_str() {
+            "mru" => mru_next(&mut self.scheme.mru_cycle, &mut self.scheme.order, &self.scheme.windows),
+            order => {
+                if order != "stack" {
+                    warn!("invalid switcher_order '{}'", order);
+                }
+                switcher_next(&mut self.scheme.order, &self.scheme.windows)
+            },
+        };
+        if let Some((front_id, next_id)) = next {
+            // Disable dragging
+            self.scheme.dragging = DragMode::None;
+
+            self.focus(front_id, false); // remove focus from it
+            self.focus(next_id, true); // move focus to next in stack
+        }
+    }
+
+    // Drive the window switcher for a client, as if Super-Tab was pressed and Super released
+    fn switcher(&mut self, command: SwitcherCommand) {
+        match command {
+            SwitcherCommand::Open => self.scheme.win_tabbing = true,
+            SwitcherCommand::Next => self.super_tab(),
+            SwitcherCommand::Close => {
+                self.close_overlays();
+                self.end_peek();
+                self.scheme.mru_cycle = None;
+            },
+        }
+    }
+
+    // Create a [Rect][orbital-core::rect::Rect] that places a popup in the middle of the display
+    fn popup_rect(image: &ImageRef, width: i32, height: i32) -> Rect {
+        Rect::new(image.width()/2 - width/2,
+                                    image.height()/2 - height/2,
+                                    width, height)
+    }
+
+    // Called by redraw() to draw the list of currently open windows in the middle of the screen.
+    // Filter out app windows with no title. Minimized windows are listed last, dimmed.
+    // If there are no windows to select, nothing is drawn.
+    fn draw_window_list_osd(&mut self) {
+        const SELECT_POPUP_TOP_BOTTOM_MARGIN: u32 = 2;
+        const SELECT_POPUP_SIDE_MARGIN: i32 = 4;
+        const SELECT_ROW_HEIGHT: u32 = 20;
+        const SELECT_ROW_WIDTH: i32 = 400;
+        const FONT_HEIGHT : f32 = 16.0;
+
+        //TODO: HiDPI
+
+        let (selectable_window_ids, minimized_window_ids) = window_list(&self.scheme.order, &self.scheme.windows);
+
+        if selectable_window_ids.len() > 1 {
+            // follow the look of the current config - in terms of colors
+            let Config { bar_color, bar_highlight_color, text_color, text_highlight_color, font_antialias, .. } = *self.scheme.config;
+
+            let listed_window_ids: Vec<usize> = selectable_window_ids.iter().chain(&minimized_window_ids).copied().collect();
+            let list_h = (listed_window_ids.len() as u32 * SELECT_ROW_HEIGHT + (SELECT_POPUP_TOP_BOTTOM_MARGIN * 2)) as i32;
+            let list_w = SELECT_ROW_WIDTH;
+            let popup_rect = Self::popup_rect(self.orb.image(), list_w, list_h);
+            let mut image = Image::from_color(list_w, list_h, osd_color(bar_color.into(), self.scheme.config.osd_opacity));
+
+            for (selectable_index, window_id) in listed_window_ids.iter().enumerate() {
+                if let Some(window) = self.scheme.windows.get(window_id) {
+                    let vertical_offset = selectable_index as i32 * SELECT_ROW_HEIGHT as i32 + SELECT_POPUP_TOP_BOTTOM_MARGIN as i32;
+                    let text = self.scheme.font.render(&window.title, FONT_HEIGHT);
+                    if window.minimized {
+                        draw_text(&text, &mut image, SELECT_POPUP_SIDE_MARGIN, vertical_offset + SELECT_POPUP_TOP_BOTTOM_MARGIN as i32, osd_color(text_color.into(), 0.5), font_antialias);
+                    } else if selectable_index == 0 {
+                        image.rect(0, vertical_offset, list_w as u32, SELECT_ROW_HEIGHT, bar_highlight_color.into());
+                        draw_text(&text, &mut image, SELECT_POPUP_SIDE_MARGIN, vertical_offset + SELECT_POPUP_TOP_BOTTOM_MARGIN as i32, text_highlight_color.into(), font_antialias);
+                    } else {
+                        draw_text(&text, &mut image, SELECT_POPUP_SIDE_MARGIN, vertical_offset + SELECT_POPUP_TOP_BOTTOM_MARGIN as i32, text_color.into(), font_antialias);
+                    }
+                }
+            }
+            self.draw_osd(popup_rect, &mut image);
+        }
+    }
+
+    // Draw an on screen display (overlay) for volume control
+    fn draw_volume_osd(&mut self) {
+        let Config { bar_color, bar_highlight_color, .. } = *self.scheme.config;
+
+        const BAR_HEIGHT : i32 = 20;
+        const BAR_WIDTH : i32 = 100;
+        const POPUP_MARGIN: i32 = 2;
+
+        //TODO: HiDPI
+        let list_h = BAR_HEIGHT + (2 * POPUP_MARGIN);
+        let list_w = BAR_WIDTH + (2 * POPUP_MARGIN);
+        let popup_rect = Self::popup_rect(self.orb.image(), list_w, list_h);
+        // Color copied over from orbtk's window background
+        let mut image = Image::from_color(list_w, list_h, osd_color(bar_color.into(), self.scheme.config.osd_opacity));
+        image.rect(POPUP_MARGIN, POPUP_MARGIN, self.scheme.volume_value as u32, BAR_HEIGHT as u32, bar_highlight_color.into());
+        self.draw_osd(popup_rect, &mut image);
+    }
+
+    // Draw the image of the window switcher, volume or shortcuts overlay at `popup_rect`,
+    // blended over the windows if it is see-through with osd_opacity
+    fn draw_osd(&mut self, popup_rect: Rect, image: &mut Image) {
+        let image = image.roi(&Rect::new(0, 0, popup_rect.width(), popup_rect.height()));
+        if self.scheme.config.osd_opacity < 1.0 {
+            self.orb.image_mut().roi(&popup_rect).blend(&image);
+        } else {
+            self.orb.image_mut().roi(&popup_rect).blit(&image);
+        }
+        self.scheme.popup_rect = popup_rect;
+        schedule(&mut self.scheme.redraws, popup_rect);
+    }
+
+    // Draw an on screen display (overlay) of available SUPER keyboard shortcuts
+    fn draw_shortcuts_osd(&mut self) {
+        const ROW_HEIGHT: u32 = 20;
+        const ROW_WIDTH: i32 = 400;
+        const POPUP_BORDER: u32 = 2;
+        const FONT_HEIGHT : f32 = 16.0;
+
+        // follow the look of the current config - in terms of colors
+        let Config { bar_color, bar_highlight_color, text_highlight_color, font_antialias, .. } = *self.scheme.config;
+
+        let shortcuts = shortcuts_list(&self.scheme.config.shortcuts);
+        let list_h = (shortcuts.len() as u32 * ROW_HEIGHT + (POPUP_BORDER * 2)) as i32;
+        let list_w = ROW_WIDTH;
+        let popup_rect = Self::popup_rect(self.orb.image(), list_w, list_h);
+        let mut image = Image::from_color(list_w, list_h, osd_color(bar_color.into(), self.scheme.config.osd_opacity));
+
+        for (index, shortcut) in shortcuts.iter().enumerate() {
+            let vertical_offset = index as i32 * ROW_HEIGHT as i32 + POPUP_BORDER as i32;
+            let text = self.scheme.font.render(shortcut, FONT_HEIGHT);
+            image.rect(0, vertical_offset, list_w as u32, ROW_HEIGHT, bar_highlight_color.into());
+            draw_text(&text, &mut image, POPUP_BORDER as i32, vertical_offset + POPUP_BORDER as i32, text_highlight_color.into(), font_antialias);
+        }
+
+        self.draw_osd(popup_rect, &mut image);
+    }
+
+    // Draw the window menu just below the title bar of the window it was opened on, with the
+    // selected entry highlighted. It closes instead if that window lost focus.
+    fn draw_window_menu_osd(&mut self) {
+        const ROW_HEIGHT: i32 = 20;
+        const ROW_WIDTH: i32 = 200;
+        const POPUP_BORDER: i32 = 2;
+        const FONT_HEIGHT : f32 = 16.0;
+
+        let Some(menu) = &self.scheme.window_menu else {
+            return;
+        };
+        let Some(window) = self.scheme.windows.get(&menu.id)
+            .filter(|window| self.scheme.order.front() == Some(&menu.id) && window.focusable()) else {
+            self.close_window_menu();
+            return;
+        };
+
+        let Config { bar_color, bar_highlight_color, text_color, text_highlight_color, font_antialias, .. } = *self.scheme.config;
+
+        //TODO: HiDPI
+        let rows: Vec<(&str, bool)> = if menu.moving {
+            vec![("Arrows move, Enter or Esc when done", true)]
+        } else if menu.resizing {
+            vec![("Arrows resize, Enter or Esc when done", true)]
+        } else {
+            WINDOW_MENU.iter().enumerate().map(|(index, (label, _))| (*label, index == menu.selected)).collect()
+        };
+        let list_w = if menu.moving || menu.resizing { 2 * ROW_WIDTH } else { ROW_WIDTH };
+        let list_h = rows.len() as i32 * ROW_HEIGHT + 2 * POPUP_BORDER;
+        let screen = Rect::new(0, 0, self.orb.image().width(), self.orb.image().height());
+        let popup_rect = window_menu_rect(&screen, window.x, window.y, list_w, list_h);
+        let mut image = Image::from_color(list_w, list_h, bar_color.into());
+
+        for (index, (label, selected)) in rows.iter().enumerate() {
+            let vertical_offset = index as i32 * ROW_HEIGHT + POPUP_BORDER;
+            let text = self.scheme.font.render(label, FONT_HEIGHT);
+            let color = if *selected {
+                image.rect(0, vertical_offset, list_w as u32, ROW_HEIGHT as u32, bar_highlight_color.into());
+                text_highlight_color
+            } else {
+                text_color
+            };
+            draw_text(&text, &mut image, POPUP_BORDER, vertical_offset + POPUP_BORDER, color.into(), font_antialias);
+        }
+
+        // Undraw the menu where it was before the window moved
+        schedule(&mut self.scheme.redraws, self.scheme.window_menu_rect);
+        self.orb.image_mut().roi(&popup_rect).blit(&image.roi(&Rect::new(0, 0, list_w, list_h)));
+        self.scheme.window_menu_rect = popup_rect;
+        schedule(&mut self.scheme.redraws, popup_rect);
+    }
+
+    // Draw the close confirmation centered on the window it was shown for
+    fn draw_close_confirm_osd(&mut self) {
+        const ROW_HEIGHT: i32 = 20;
+        const POPUP_BORDER: i32 = 4;
+        const FONT_HEIGHT : f32 = 16.0;
+
+        let Some(window) = self.scheme.close_confirm.and_then(|id| self.scheme.windows.get(&id)) else {
+            return;
+        };
+
+        let Config { bar_color, text_color, text_highlight_color, font_antialias, .. } = *self.scheme.config;
+
+        //TODO: HiDPI
+        let title = self.scheme.font.render(&format!("Close {}?", window.title), FONT_HEIGHT);
+        let hint = self.scheme.font.render("Enter to close, Esc to cancel", FONT_HEIGHT);
+        let popup_w = cmp::max(title.width(), hint.width()) as i32 + 2 * POPUP_BORDER;
+        let popup_h = 2 * ROW_HEIGHT + 2 * POPUP_BORDER;
+        let screen = self.orb.screen_rect();
+        let rect = window.rect();
+        let popup_rect = window_menu_rect(
+            &screen,
+            rect.left() + (rect.width() - popup_w) / 2,
+            rect.top() + (rect.height() - popup_h) / 2,
+            popup_w,
+            popup_h,
+        );
+        let mut image = Image::from_color(popup_w, popup_h, bar_color.into());
+        draw_text(&title, &mut image, POPUP_BORDER, POPUP_BORDER, text_highlight_color.into(), font_antialias);
+        draw_text(&hint, &mut image, POPUP_BORDER, POPUP_BORDER + ROW_HEIGHT, text_color.into(), font_antialias);
+
+        // Undraw the prompt where it was before the window moved
+        schedule(&mut self.scheme.redraws, self.scheme.close_confirm_rect);
+        self.orb.image_mut().roi(&popup_rect).blit(&image.roi(&Rect::new(0, 0, popup_w, popup_h)));
+        self.scheme.close_confirm_rect = popup_rect;
+        schedule(&mut self.scheme.redraws, popup_rect);
+    }
+
+    // Draw a small on screen display at the top of the screen showing the latched sticky
+    // modifiers, or whether sticky keys were just turned on or off
+    fn draw_sticky_keys_osd(&mut self) {
+        const ROW_HEIGHT: i32 = 20;
+        const POPUP_BORDER: i32 = 2;
+        const FONT_HEIGHT : f32 = 16.0;
+
+        let Config { bar_color, text_highlight_color, font_antialias, .. } = *self.scheme.config;
+
+        let latched = self.scheme.sticky_keys.latched;
+        let label = if latched != 0 {
+            let mut names: Vec<&str> = Vec::new();
+            for (_, bit, name) in MODIFIER_KEYS.iter() {
+                if latched & bit != 0 && ! names.contains(name) {
+                    names.push(name);
+                }
+            }
+            format!("Sticky: {}", names.join("+"))
+        } else if self.scheme.sticky_keys.enabled {
+            "Sticky keys on".to_string()
+        } else {
+            "Sticky keys off".to_string()
+        };
+
+        //TODO: HiDPI
+        let text = self.scheme.font.render(&label, FONT_HEIGHT);
+        let w = text.width() as i32 + 2 * POPUP_BORDER;
+        let h = ROW_HEIGHT + 2 * POPUP_BORDER;
+        let image_w = self.orb.image().width();
+        let popup_rect = Rect::new(image_w / 2 - w / 2, ROW_HEIGHT, w, h);
+        let mut image = Image::from_color(w, h, bar_color.into());
+        draw_text(&text, &mut image, POPUP_BORDER, POPUP_BORDER, text_highlight_color.into(), font_antialias);
+
+        // Undraw the previous label if it was wider
+        schedule(&mut self.scheme.redraws, self.scheme.sticky_keys_rect);
+        self.orb.image_mut().roi(&popup_rect).blit(&image.roi(&Rect::new(0, 0, w, h)));
+        self.scheme.sticky_keys_rect = popup_rect;
+        schedule(&mut self.scheme.redraws, popup_rect);
+    }
+
+    // Draw the size of the window being resized on top of it, see resize_osd
+    fn draw_resize_osd(&mut self) {
+        const ROW_HEIGHT: i32 = 20;
+        const POPUP_BORDER: i32 = 2;
+        const FONT_HEIGHT : f32 = 16.0;
+
+        let Config { bar_color, text_highlight_color, font_antialias, .. } = *self.scheme.config;
+        let Some(window) = self.scheme.resize_osd.id.and_then(|id| self.scheme.windows.get(&id)) else {
+            return;
+        };
+
+        //TODO: HiDPI
+        let text = self.scheme.font.render(&format!("{} x {}", window.width(), window.height()), FONT_HEIGHT);
+        let w = text.width() as i32 + 2 * POPUP_BORDER;
+        let h = ROW_HEIGHT + 2 * POPUP_BORDER;
+        let popup_rect = resize_osd_rect(&window.rect(), w, h);
+        let mut image = Image::from_color(w, h, bar_color.into());
+        draw_text(&text, &mut image, POPUP_BORDER, POPUP_BORDER, text_highlight_color.into(), font_antialias);
+
+        let screen = Rect::new(0, 0, self.orb.image().width(), self.orb.image().height());
+        let visible = popup_rect.intersection(&screen);
+        self.orb.image_mut().roi(&visible).blit(&image.roi(&visible.offset(-popup_rect.left(), -popup_rect.top())));
+        self.scheme.resize_osd.rect = visible;
+        schedule(&mut self.scheme.redraws, visible);
+    }
+
+    // Show a notification toast, once fewer than the maximum number of toasts are shown
+    fn notify(&mut self, text: String) -> Result<()> {
+        if ! self.scheme.toasts.push(text) {
+            return Err(Error::new(EBUSY));
+        }
+        self.update_toasts();
+        Ok(())
+    }
+
+    // Dismiss expired toasts, show waiting ones, and set a timeout for when the next one expires
+    fn update_toasts(&mut self) {
+        let now = Instant::now();
+        let duration = Duration::from_millis(self.scheme.config.toast_duration_ms as u64);
+        let max_visible = cmp::max(1, self.scheme.config.toast_max_visible as usize);
+        if self.scheme.toasts.update(now, duration, max_visible) {
+            // undraw the toasts, the ones that are still shown are drawn again on top
+            schedule(&mut self.scheme.redraws, self.scheme.toasts.rect);
+            self.scheme.toasts.rect = Rect::default();
+        }
+
+        // the timeout that was pending has passed, other timers leave it pending
+        if self.scheme.toasts.timeout.is_some_and(|timeout| timeout <= now) {
+            self.scheme.toasts.timeout = None;
+        }
+        if let Some(expires) = self.scheme.toasts.next_expiry() {
+            if self.scheme.toasts.timeout.is_none_or(|timeout| expires < timeout) {
+                // round up, so that the toast has expired when the timeout is handled
+                let timeout = expires.saturating_duration_since(now) + Duration::from_millis(1);
+                match self.orb.set_timeout(timeout) {
+                    Ok(()) => self.scheme.toasts.timeout = Some(expires),
+                    Err(err) => error!("failed to set timeout for toasts: {}", err),
+                }
+            }
+        }
+    }
+
+    // Blank the screen when the idle timeout has passed, otherwise keep a timeout pending for
+    // when it will. An earlier timeout left over from before the last input just sets it again
+    fn update_idle(&mut self) {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(self.scheme.config.idle_blank_secs.into());
+        if self.scheme.idle.check(now, timeout) {
+            let screen = self.orb.screen_rect();
+            schedule(&mut self.scheme.redraws, screen);
+            return;
+        }
+
+        let Some(deadline) = self.scheme.idle.deadline(timeout) else {
+            return;
+        };
+        if self.scheme.idle.due.is_some_and(|due| now < due) {
+            return;
+        }
+        match self.orb.set_timeout(deadline.saturating_duration_since(now)) {
+            Ok(()) => self.scheme.idle.due = Some(deadline),
+            Err(err) => error!("failed to set timeout for idle blanking: {}", err),
+        }
+    }
+
+    // Reset the held modifiers once they are stuck, and set a timeout for when they would be
+    fn update_modifiers(&mut self) {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(self.scheme.config.modifier_timeout_secs.into());
+        let held = self.scheme.held_modifiers;
+        if self.scheme.modifier_watch.stuck(held, now, timeout) {
+            warn!("resetting modifiers {:#x} held without key events for {:?}", held, timeout);
+            self.reset_modifiers();
+            return;
+        }
+
+        let Some(deadline) = self.scheme.modifier_watch.deadline(held, timeout) else {
+            return;
+        };
+        if self.scheme.modifier_watch.due.is_some_and(|due| now < due) {
+            return;
+        }
+        match self.orb.set_timeout(deadline.saturating_duration_since(now)) {
+            Ok(()) => self.scheme.modifier_watch.due = Some(deadline),
+            Err(err) => error!("failed to set timeout for stuck modifiers: {}", err),
+        }
+    }
+
+    // Fire the action of the hot corner the cursor is in once it has stayed there long enough,
+    // waking up when it has if the cursor doesn't move. Dragging a window into a corner doesn't
+    fn update_hot_corners(&mut self) {
+        if self.scheme.hot_corners.actions.is_empty() {
+            return;
+        }
+
+        let corner = if matches!(self.scheme.dragging, DragMode::None) {
+            let screens: Vec<Rect> = self.orb.displays.iter().map(|display| display.screen_rect()).collect();
+            HotCorner::at(&screens, self.scheme.cursor_x, self.scheme.cursor_y, HOT_CORNER_SIZE * self.scheme.scale)
+        } else {
+            None
+        };
+        if self.scheme.hot_corners.switcher.is_some_and(|switcher| corner != Some(switcher)) {
+            self.scheme.hot_corners.switcher = None;
+            self.switcher(SwitcherCommand::Close);
+        }
+
+        let now = Instant::now();
+        match self.scheme.hot_corners.update(corner, now) {
+            Some(HotCornerAction::ShowSwitcher) => {
+                self.scheme.hot_corners.switcher = corner;
+                self.super_tab();
+            },
+            Some(HotCornerAction::ShowDesktop) => self.show_desktop(),
+            Some(HotCornerAction::PointerHighlight) => self.toggle_pointer_highlight(),
+            None => {},
+        }
+
+        let Some(deadline) = self.scheme.hot_corners.deadline() else {
+            return;
+        };
+        if self.scheme.hot_corners.due.is_some_and(|due| now < due && due <= deadline) {
+            return;
+        }
+        match self.orb.set_timeout(deadline.saturating_duration_since(now)) {
+            Ok(()) => self.scheme.hot_corners.due = Some(deadline),
+            Err(err) => error!("failed to set timeout for hot corner: {}", err),
+        }
+    }
+
+    // Raise the window under the cursor once it has been hovered for autoraise_delay, and set a
+    // timeout for when it has been
+    fn update_autoraise(&mut self) {
+        if self.scheme.config.autoraise_delay == 0 {
+            return;
+        }
+        let delay = Duration::from_millis(self.scheme.config.autoraise_delay);
+        let hovered = self.scheme.hover.filter(|_| matches!(self.scheme.dragging, DragMode::None));
+        let now = Instant::now();
+        if let Some(id) = self.scheme.autoraise.update(hovered, now, delay) {
+            self.autoraise(id);
+        }
+
+        let Some(deadline) = self.scheme.autoraise.deadline(delay) else {
+            return;
+        };
+        if self.scheme.autoraise.due.is_some_and(|due| now < due && due <= deadline) {
+            return;
+        }
+        match self.orb.set_timeout(deadline.saturating_duration_since(now)) {
+            Ok(()) => self.scheme.autoraise.due = Some(deadline),
+            Err(err) => error!("failed to set timeout for autoraise: {}", err),
+        }
+    }
+
+    // Raise the hovered window `id` above the others of its z-order, focusing it too with
+    // autoraise_focus. The focused window and windows kept at the back stay where they are
+    fn autoraise(&mut self, id: usize) {
+        if self.scheme.order.front() == Some(&id) || self.scheme.raised == Some(id) {
+            return;
+        }
+        let Some(window) = self.scheme.windows.get(&id).filter(|window| window.zorder != WindowZOrder::Back) else {
+            return;
+        };
+
+        if self.scheme.config.autoraise_focus {
+            if ! window.focusable() {
+                return;
+            }
+            if let Some(&front) = self.scheme.order.front() {
+                self.focus(front, false);
+            }
+            self.scheme.order.retain(|&e| e != id);
+            self.scheme.order.push_front(id);
+            self.focus(id, true);
+        } else {
+            schedule(&mut self.scheme.redraws, window.title_rect());
+            schedule(&mut self.scheme.redraws, window.rect());
+            self.scheme.raised = Some(id);
+        }
+    }
+
+    // Show the size of the window being resized with resize_osd, and hide it once the resize
+    // has been over for RESIZE_OSD_LINGER, with a timeout for when it is
+    fn update_resize_osd(&mut self) {
+        if ! self.scheme.config.resize_osd {
+            return;
+        }
+        let now = Instant::now();
+        if self.scheme.resize_osd.update(resizing(&self.scheme.dragging), now) {
+            schedule(&mut self.scheme.redraws, self.scheme.resize_osd.rect);
+            self.scheme.resize_osd.rect = Rect::default();
+        }
+
+        let Some(hide_at) = self.scheme.resize_osd.hide_at else {
+            return;
+        };
+        if self.scheme.resize_osd.due.is_some_and(|due| now < due && due <= hide_at) {
+            return;
+        }
+        match self.orb.set_timeout(hide_at.saturating_duration_since(now)) {
+            Ok(()) => self.scheme.resize_osd.due = Some(hide_at),
+            Err(err) => error!("failed to set timeout for resize size: {}", err),
+        }
+    }
+
+    // Do the deferred window syncs that are due, and set a timeout for the next one
+    fn update_syncs(&mut self) {
+        let Some(interval) = self.scheme.sync_interval() else {
+            return;
+        };
+        let now = Instant::now();
+        for id in self.scheme.sync_limiter.flush(now, interval) {
+            self.scheme.sync_window(id);
+            self.orb.query_changed(&format!("contents/{}", id));
+        }
+
+        let Some(next) = self.scheme.sync_limiter.next_sync(interval) else {
+            return;
+        };
+        if self.scheme.sync_limiter.due.is_some_and(|due| now < due && due <= next) {
+            return;
+        }
+        match self.orb.set_timeout(next.saturating_duration_since(now)) {
+            Ok(()) => self.scheme.sync_limiter.due = Some(next),
+            Err(err) => error!("failed to set timeout for window sync: {}", err),
+        }
+    }
+
+    // Minimize all windows to show the desktop, or restore the windows minimized that way if
+    // they still are
+    fn show_desktop(&mut self) {
+        let shown = mem::take(&mut self.scheme.shown_desktop);
+        if ! shown.is_empty() {
+            // from the back, so that the window that was at the front is again
+            for id in shown.into_iter().rev() {
+                if self.scheme.windows.get(&id).is_some_and(|window| window.minimized) {
+                    self.minimize(id, false, None);
+                }
+            }
+            return;
+        }
+
+        let ids: Vec<usize> = self.scheme.order.iter().copied().filter(|id| {
+            self.scheme.windows.get(id).is_some_and(|window| window.focusable() && window.zorder != WindowZOrder::Back)
+        }).collect();
+        for &id in ids.iter() {
+            self.minimize(id, true, None);
+        }
+        self.scheme.shown_desktop = ids;
+    }
+
+    // Render the copy mirroring windows show again when the window they mirror is redrawn, or
+    // when they were resized
+    fn update_mirrors(&mut self) {
+        let ids: Vec<usize> = self.scheme.windows.iter()
+            .filter(|(_, window)| window.mirrored.is_some() || window.mirror.is_some())
+            .map(|(&id, _)| id)
+            .collect();
+        for id in ids {
+            let Some(window) = self.scheme.windows.get(&id) else {
+                continue;
+            };
+            let (width, height) = (window.width(), window.height());
+            let resized = window.mirrored.as_ref().is_none_or(|image| (image.width(), image.height()) != (width, height));
+            let image = match window.mirror.and_then(|source| self.scheme.windows.get(&source)) {
+                Some(source) => {
+                    let source_rect = source.rect();
+                    let damaged = self.scheme.redraws.iter().any(|rect| ! rect.intersection(&source_rect).is_empty());
+                    if ! resized && ! damaged {
+                        continue;
+                    }
+                    source.mirror_image(width, height)
+                },
+                None if resized => Image::from_color(width, height, self.scheme.config.background_color.into()),
+                None => continue,
+            };
+
+            if let Some(window) = self.scheme.windows.get_mut(&id) {
+                window.mirrored = Some(image);
+                schedule(&mut self.scheme.redraws, window.rect());
+            }
+        }
+    }
+
+    // Render the built-in panel again if what it shows has changed since it was last rendered
+    fn update_panel(&mut self) {
+        if ! self.scheme.config.builtin_panel {
+            return;
+        }
+
+        let display = &mut self.orb.displays[0];
+        let screen = display.screen_rect();
+        let scale = self.scheme.scale_override.unwrap_or(display.scale);
+        let rect = Rect::new(screen.left(), screen.top(), screen.width(), cmp::min(PANEL_HEIGHT * scale, screen.height()));
+        display.reserved_top = rect.height();
+
+        let state = PanelState {
+            rect,
+            scale,
+            windows: self.scheme.windows.iter()
+                .filter(|(_, window)| panel_lists(window))
+                .map(|(&id, window)| (id, window.title.clone()))
+                .collect(),
+            focused: self.scheme.order.front().copied(),
+            clock: panel_clock(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()),
+        };
+        if self.scheme.panel.as_ref().is_some_and(|panel| panel.state == state) {
+            return;
+        }
+
+        let Config { bar_color, bar_highlight_color, text_color, text_highlight_color, font_antialias, .. } = *self.scheme.config;
+        let font = &self.scheme.font;
+        let (scale, padding) = (state.scale, PANEL_PADDING * state.scale);
+        let text_height = PANEL_TEXT_HEIGHT * scale;
+        let text_y = (rect.height() - text_height) / 2;
+        let mut image = Image::from_color(rect.width(), rect.height(), bar_color.into());
+
+        // a text drawn at x on the panel image, cut off at `width`
+        let label = |image: &mut Image, text: &str, x: i32, width: i32, color: Color| {
+            let text = font.render(text, text_height as f32);
+            draw_clipped_text(image, &text, &Rect::new(x, text_y, width, text_height), color, font_antialias);
+        };
+
+        let buttons: Vec<(usize, Rect)> = state.windows.iter()
+            .map(|&(id, _)| id)
+            .zip(panel_buttons(&rect, scale, state.windows.len()))
+            .collect();
+        for ((id, button), (_, title)) in buttons.iter().zip(state.windows.iter()) {
+            let local = button.offset(-rect.left(), -rect.top());
+            let focused = state.focused == Some(*id);
+            if focused {
+                image.rect(local.left(), local.top(), local.width() as u32, local.height() as u32, bar_highlight_color.into());
+            } else {
+                for edge in outline_edges(&local, scale) {
+                    image.rect(edge.left(), edge.top(), edge.width() as u32, edge.height() as u32, bar_highlight_color.into());
+                }
+            }
+            let color = if focused { text_highlight_color } else { text_color };
+            label(&mut image, title, local.left() + padding, local.width() - padding * 2, color.into());
+        }
+
+        let clock_width = font.render(&state.clock, text_height as f32).width() as i32;
+        let clock_x = rect.width() - padding * 2 - clock_width;
+        label(&mut image, &state.clock, clock_x, clock_width, text_color.into());
+
+        let focused_title = state.focused
+            .and_then(|id| state.windows.iter().find(|&&(window_id, _)| window_id == id))
+            .map(|(_, title)| title.as_str());
+        if let Some(title) = focused_title {
+            let buttons_right = buttons.last().map_or(0, |(_, button)| button.right() - rect.left());
+            let (left, right) = (buttons_right + padding * 2, clock_x - padding * 2);
+            let title_width = font.render(title, text_height as f32).width() as i32;
+            let x = cmp::max(left, (rect.width() - title_width) / 2);
+            label(&mut image, title, x, right - x, text_highlight_color.into());
+        }
+
+        schedule(&mut self.scheme.redraws, rect);
+        if let Some(panel) = self.scheme.panel.as_ref() {
+            schedule(&mut self.scheme.redraws, panel.state.rect);
+        }
+        self.scheme.panel = Some(Panel { state, image, buttons });
+        self.update_panel_timer();
+    }
+
+    // Give each window the scale of the display it is mostly on, wherever it was moved or resized
+    fn update_window_scales(&mut self) {
+        let screens: Vec<(Rect, i32)> = self.orb.displays.iter()
+            .map(|display| (display.screen_rect(), display.scale))
+            .collect();
+        // windows that aren't shown keep their scale until they are, and shaded ones go by the
+        // size they have when they are rolled down again
+        for window in self.scheme.windows.values_mut().filter(|window| window.visible()) {
+            let rect = Rect::new(window.x, window.y, window.width(), window.height());
+            let scale = window_scale(self.scheme.scale_override, &screens, rect);
+            rescale_window(&mut self.scheme.redraws, window, scale, &self.scheme.font);
+        }
+    }
+
+    // Move the drawn cursor a frame further toward the pointer, keeping a timeout pending for the
+    // next frame until it is there
+    fn update_smooth_cursor(&mut self) {
+        let now = Instant::now();
+        if self.scheme.cursor_due.is_some_and(|due| now < due) {
+            return;
+        }
+        let ticked = self.scheme.cursor_due.take().is_some();
+
+        let target = (self.scheme.cursor_x, self.scheme.cursor_y);
+        if self.scheme.cursor_drawn == target {
+            return;
+        }
+        if ! self.scheme.config.smooth_cursor {
+            self.scheme.move_drawn_cursor(target);
+            return;
+        }
+        if ticked {
+            let step = smooth_cursor_step(self.scheme.cursor_drawn, target);
+            self.scheme.move_drawn_cursor(step);
+            if step == target {
+                return;
+            }
+        }
+
+        match self.orb.set_timeout(CURSOR_FRAME) {
+            Ok(()) => self.scheme.cursor_due = Some(now + CURSOR_FRAME),
+            Err(err) => error!("failed to set timeout for cursor smoothing: {}", err),
+        }
+    }
+
+    // Render the tabs of each tab group again if what they show has changed since they were last
+    // rendered
+    fn update_tabs(&mut self) {
+        let front = self.scheme.order.front().copied();
+        for group in self.scheme.tab_groups.values_mut() {
+            let Some(window) = self.scheme.windows.get(&group.active) else {
+                continue;
+            };
+            let strip = TabStrip {
+                area: tab_area(&window.title_rect(), window.scale),
+                scale: window.scale,
+                titles: group.tabs.iter()
+                    .map(|id| self.scheme.windows.get(id).map_or(String::new(), |window| window.title.clone()))
+                    .collect(),
+                active: group.tabs.iter().position(|&id| id == group.active).unwrap_or(0),
+                focused: front == Some(group.active),
+            };
+            if group.strip.as_ref().is_some_and(|(rendered, _)| *rendered == strip) {
+                continue;
+            }
+
+            let image = render_tab_strip(&strip, &self.scheme.font, &self.scheme.config);
+            schedule(&mut self.scheme.redraws, window.title_rect());
+            group.strip = Some((strip, image));
+        }
+    }
+
+    // Keep a timeout pending for when the clock on the built-in panel turns over to the next
+    // minute, redrawing it then
+    fn update_panel_timer(&mut self) {
+        let now = Instant::now();
+        if ! self.scheme.config.builtin_panel || self.scheme.panel_due.is_some_and(|due| now < due) {
+            return;
+        }
+
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let wait = Duration::from_secs(60 - since_epoch.as_secs() % 60) - Duration::from_nanos(since_epoch.subsec_nanos().into());
+        match self.orb.set_timeout(wait) {
+            Ok(()) => self.scheme.panel_due = Some(now + wait),
+            Err(err) => error!("failed to set timeout for the panel clock: {}", err),
+        }
+    }
+
+    // Advance the loading spinner of the windows that haven't been drawn yet, once its frame is
+    // due. Only one spinner timeout is pending at a time, and none is set once no window is
+    // loading, so the spinner costs nothing while it isn't shown.
+    fn update_spinner(&mut self) {
+        let now = Instant::now();
+        if self.scheme.spinner_due.is_some_and(|due| now < due) {
+            return;
+        }
+        let ticked = self.scheme.spinner_due.take().is_some();
+
+        if ! self.scheme.config.loading_spinner {
+            return;
+        }
+        let loading: Vec<(Rect, i32)> = self.scheme.windows.values()
+            .filter(|window| ! window.painted && window.visible())
+            .map(|window| (window.rect(), window.scale))
+            .collect();
+        if loading.is_empty() {
+            return;
+        }
+
+        if ticked {
+            self.scheme.spinner_frame = self.scheme.spinner_frame.wrapping_add(1);
+        }
+        for (rect, scale) in loading {
+            for dot in spinner_dots(&rect, scale) {
+                schedule(&mut self.scheme.redraws, dot.intersection(&rect));
+            }
+        }
+
+        match self.orb.set_timeout(SPINNER_FRAME) {
+            Ok(()) => self.scheme.spinner_due = Some(now + SPINNER_FRAME),
+            Err(err) => error!("failed to set timeout for loading spinner: {}", err),
+        }
+    }
+
+    // Minimize the window, or restore it when `minimized` is false. The change is animated between
+    // the window and `target`, or the bottom center of its display without one, when
+    // animate_windows is enabled
+    fn minimize(&mut self, id: usize, minimized: bool, target: Option<Rect>) {
+        self.finish_animation();
+
+        let Some(window) = self.scheme.windows.get_mut(&id) else {
+            return;
+        };
+        if window.minimized == minimized {
+            return;
+        }
+
+        let frame = window.frame_rect();
+        let target = target.unwrap_or_else(|| {
+            let (x, y) = (frame.left() + frame.width() / 2, frame.top() + frame.height() / 2);
+            let screen = self.orb.displays.iter()
+                .map(|display| display.screen_rect())
+                .find(|screen| screen.contains(x, y))
+                .unwrap_or_else(|| self.orb.screen_rect());
+            minimize_target(&screen)
+        });
+
+        let easing = self.scheme.config.animation_easing;
+        let animation = if minimized {
+            schedule(&mut self.scheme.redraws, window.title_rect());
+            schedule(&mut self.scheme.redraws, window.rect());
+            window.minimized = true;
+
+            // send it to the back and move the focus to the window now at the front
+            let focused = self.scheme.order.front() == Some(&id);
+            self.scheme.order.retain(|&e| e != id);
+            self.scheme.order.push_back(id);
+            if focused {
+                self.focus(id, false);
+                if let Some(front) = self.scheme.order.front().copied().filter(|&front| front != id) {
+                    self.focus(front, true);
+                }
+            }
+
+            WindowAnimation { id, from: frame, to: target, start: Instant::now(), restore: false, easing }
+        } else {
+            WindowAnimation { id, from: target, to: frame, start: Instant::now(), restore: true, easing }
+        };
+
+        self.scheme.animation = Some(animation);
+        if self.scheme.config.animate_windows {
+            self.update_animation();
+        } else {
+            self.finish_animation();
+        }
+    }
+
+    // Move the window animation to its next frame, redrawn once the events have been handled
+    fn update_animation(&mut self) {
+        let now = Instant::now();
+        // a timer that isn't the animation's leaves it waiting for its own
+        if self.scheme.animation_due.is_some_and(|due| now < due) {
+            return;
+        }
+        self.scheme.animation_due = None;
+        let Some(animation) = &self.scheme.animation else {
+            return;
+        };
+
+        match animation.outline(now) {
+            Some(outline) => {
+                self.schedule_animation_outline();
+                self.scheme.animation_rect = outline;
+                self.schedule_animation_outline();
+                match self.orb.set_timeout(ANIMATION_FRAME) {
+                    Ok(()) => self.scheme.animation_due = Some(now + ANIMATION_FRAME),
+                    Err(err) => error!("failed to set timeout for window animation: {}", err),
+                }
+            },
+            None => self.finish_animation(),
+        }
+    }
+
+    // Undraw the outline of the window animation and show the window it was restoring
+    fn finish_animation(&mut self) {
+        self.schedule_animation_outline();
+        self.scheme.animation_rect = Rect::default();
+
+        let Some(animation) = self.scheme.animation.take() else {
+            return;
+        };
+        if ! animation.restore {
+            return;
+        }
+
+        let id = animation.id;
+        if let Some(window) = self.scheme.windows.get_mut(&id) {
+            window.minimized = false;
+            schedule(&mut self.scheme.redraws, window.title_rect());
+            schedule(&mut self.scheme.redraws, window.rect());
+
+            if let Some(front) = self.scheme.order.front() {
+                self.focus(*front, false);
+            }
+            self.scheme.order.retain(|&e| e != id);
+            self.scheme.order.push_front(id);
+            self.focus(id, true);
+        }
+    }
+
+    fn schedule_animation_outline(&mut self) {
+        for edge in outline_edges(&self.scheme.animation_rect, ANIMATION_OUTLINE * self.scheme.scale) {
+            schedule(&mut self.scheme.redraws, edge);
+        }
+    }
+
+    // Draw the shown notification toasts stacked in the configured corner of the screen
+    fn draw_toasts_osd(&mut self) {
+        const ROW_HEIGHT: i32 = 20;
+        const POPUP_BORDER: i32 = 4;
+        const FONT_HEIGHT : f32 = 16.0;
+
+        let Config { toast_color, toast_text_color, font_antialias, .. } = *self.scheme.config;
+        let corner = self.scheme.config.toast_corner;
+
+        //TODO: HiDPI
+        let screen = self.orb.screen_rect();
+        let texts: Vec<_> = self.scheme.toasts.visible()
+            .map(|toast| self.scheme.font.render(&toast.text, FONT_HEIGHT))
+            .collect();
+        let sizes: Vec<_> = texts.iter()
+            .map(|text| (cmp::min(text.width() as i32 + 2 * POPUP_BORDER, screen.width() - 2 * TOAST_MARGIN), ROW_HEIGHT + 2 * POPUP_BORDER))
+            .collect();
+
+        let mut drawn = Rect::default();
+        for (text, rect) in texts.iter().zip(toast_rects(&screen, corner, &sizes)) {
+            let visible = rect.intersection(&screen);
+            if visible.is_empty() {
+                continue;
+            }
+
+            let mut image = Image::from_color(rect.width(), rect.height(), toast_color.into());
+            draw_text(text, &mut image, POPUP_BORDER, POPUP_BORDER, toast_text_color.into(), font_antialias);
+            self.orb.image_mut().roi(&visible).blit(&image.roi(&visible.offset(-rect.left(), -rect.top())));
+            drawn = drawn.container(&visible);
+        }
+
+        self.scheme.toasts.rect = drawn;
+        schedule(&mut self.scheme.redraws, drawn);
+    }
+
+    // Keep track of the modifier keys state based on past keydown/keyup events. Modifiers
+    // latched by sticky keys stay in the state after their key is released.
+    fn track_modifier_state(&mut self, scancode: u8, pressed: bool) {
+        let scheme = &mut *self.scheme;
+        let (changed, released) = track_modifiers(
+            &mut scheme.held_modifiers,
+            &mut scheme.lock_keys,
+            &mut scheme.sticky_keys,
+            &mut scheme.modifier_state,
+            scancode,
+            pressed,
+        );
+
+        if let Some(latched) = released {
+            info!("sticky keys {}", if self.scheme.sticky_keys.enabled { "enabled" } else { "disabled" });
+            self.scheme.sticky_keys_osd = true;
+            self.send_sticky_releases(latched);
+        }
+        if changed {
+            self.orb.query_changed("modifiers");
+        }
+    }
+
+    // Clear the modifiers latched by sticky keys after a non-modifier key press, sending the
+    // front window the key releases that were held back while they were latched
+    fn release_sticky_modifiers(&mut self) {
+        let latched = mem::take(&mut self.scheme.sticky_keys.latched);
+        if latched == 0 {
+            return;
+        }
+        self.send_sticky_releases(latched);
+        self.scheme.modifier_state = modifier_state(self.scheme.held_modifiers);
+    }
+
+    // Send the front window the releases held back for the `latched` modifiers that are no
+    // longer held
+    fn send_sticky_releases(&mut self, latched: u8) {
+        if latched == 0 {
+            return;
+        }
+
+        if let Some(id) = self.scheme.order.front() {
+            if let Some(window) = self.scheme.windows.get_mut(id) {
+                // Super key events are never sent to windows
+                for (scancode, bit, _) in MODIFIER_KEYS.iter() {
+                    if latched & bit != 0 && self.scheme.held_modifiers & bit == 0 && *bit != SUPER_MODIFIER {
+                        window.event(KeyEvent {
+                            character: '\0',
+                            scancode: *scancode,
+                            pressed: false,
+                        }.to_event());
+                    }
+                }
+            }
+        }
+
+        schedule(&mut self.scheme.redraws, self.scheme.sticky_keys_rect);
+    }
+
+    // Forget the modifiers that are held, so that keys are no longer taken as shortcuts after the
+    // release of Super was lost, and close the overlays it left open. The front window gets the
+    // releases of the other modifiers, which it saw the presses of
+    fn reset_modifiers(&mut self) {
+        let held = mem::take(&mut self.scheme.held_modifiers);
+        let latched = self.scheme.sticky_keys.latched;
+        self.release_sticky_modifiers();
+
+        if let Some(id) = self.scheme.order.front() {
+            if let Some(window) = self.scheme.windows.get_mut(id) {
+                for (scancode, bit, _) in MODIFIER_KEYS.iter() {
+                    if held & bit != 0 && latched & bit == 0 && *bit != SUPER_MODIFIER {
+                        window.event(KeyEvent {
+                            character: '\0',
+                            scancode: *scancode,
+                            pressed: false,
+                        }.to_event());
+                    }
+                }
+            }
+        }
+
+        self.scheme.modifier_state = modifier_state(0);
+        self.orb.query_changed("modifiers");
+        self.close_overlays();
+        self.end_peek();
+        self.scheme.mru_cycle = None;
+    }
+
+    // Keep the focused window on top of normal windows, or stop keeping it there
+    fn toggle_front_front_window(&mut self) {
+        let Some(&id) = self.scheme.order.front() else {
+            return;
+        };
+        let Some(window) = self.scheme.windows.get_mut(&id) else {
+            return;
+        };
+        let Some(front) = window.toggle_front() else {
+            return;
+        };
+        schedule(&mut self.scheme.redraws, window.title_rect());
+        schedule(&mut self.scheme.redraws, window.rect());
+
+        let text = if front {
+            format!("{} is kept on top", window.title)
+        } else {
+            format!("{} is no longer kept on top", window.title)
+        };
+        if let Err(err) = self.notify(text) {
+            debug!("no toast for always on top of window {}: {}", id, err);
+        }
+    }
+
+    // Move the front-most window horizontally and vertically by the number of pixels passed
+    fn move_front_window(&mut self, h_movement: i32, v_movement: i32) {
+        if let Some(id) = self.scheme.order.front() {
+            if let Some(window) = self.scheme.windows.get_mut(id) {
+                schedule(&mut self.scheme.redraws, window.title_rect());
+                schedule(&mut self.scheme.redraws, window.rect());
+
+                // Align location to grid
+                window.x -= window.x % GRID_SIZE;
+                window.y -= window.y % GRID_SIZE;
+
+                window.x += h_movement;
+                window.y += v_movement;
+
+                // Ensure window remains visible
+                window.x = cmp::max(
+                    -window.width() + GRID_SIZE,
+                    cmp::min(
+                        self.orb.image().width() - GRID_SIZE,
+                        window.x
+                    )
+                );
+                window.y = cmp::max(
+                    -window.height() + GRID_SIZE,
+                    cmp::min(
+                        self.orb.image().height() - GRID_SIZE,
+                        window.y
+                    )
+                );
+
+                let move_event = MoveEvent {
+                    x: window.x,
+                    y: window.y
+                }.to_event();
+                window.event(move_event);
+
+                schedule(&mut self.scheme.redraws, window.title_rect());
+                schedule(&mut self.scheme.redraws, window.rect());
+            }
+        }
+    }
+
+    // Ask the front window to resize by `steps` grid steps, see keyboard_resize
+    fn resize_front_window(&mut self, steps: (i32, i32)) {
+        if let Some(window) = self.scheme.order.front().and_then(|id| self.scheme.windows.get_mut(id)) {
+            let (width, height) = keyboard_resize(window, steps);
+            if width != window.width() || height != window.height() {
+                window.event(ResizeEvent { width: width as u32, height: height as u32 }.to_event());
+            }
+        }
+    }
+
+    fn clipboard_event(&mut self, kind: u8) {
+        if let Some(id) = self.scheme.order.front() {
+            if let Some(window) = self.scheme.windows.get_mut(id) {
+                //TODO: set window's clipboard to primary
+                let clipboard_event = ClipboardEvent { kind, size: 0}.to_event();
+                window.event(clipboard_event);
+            }
+        }
+    }
+
+    // Make the UI `delta` steps larger or smaller than it is, whatever the displays' scale
+    fn step_ui_scale(&mut self, delta: i32) {
+        let scale = step_scale(self.scheme.scale, delta);
+        if self.scheme.scale_override == Some(scale) {
+            return;
+        }
+        self.scheme.set_scale_override(&self.orb.displays, Some(scale));
+        if let Err(err) = self.notify(format!("UI scale {}x", scale)) {
+            debug!("no toast for UI scale {}: {}", scale, err);
+        }
+    }
+
+    // Have the UI follow the scale of the displays again
+    fn reset_ui_scale(&mut self) {
+        if self.scheme.scale_override.is_none() {
+            return;
+        }
+        self.scheme.set_scale_override(&self.orb.displays, None);
+        if let Err(err) = self.notify(format!("UI scale {}x, as the displays", self.scheme.scale)) {
+            debug!("no toast for UI scale {}: {}", self.scheme.scale, err);
+        }
+    }
+
+    // Ask the front window to quit, see Window::request_quit
+    fn quit_front_window(&mut self) {
+        if let Some(&id) = self.scheme.order.front() {
+            self.request_close(id);
+        }
+    }
+
+    // Ask a window to quit, or with ORBITAL_FLAG_CONFIRM_CLOSE ask the user to confirm that first
+    fn request_close(&mut self, id: usize) {
+        let Some(window) = self.scheme.windows.get_mut(&id) else {
+            return;
+        };
+        if ! window.confirm_close || window.unclosable {
+            window.request_quit();
+            return;
+        }
+
+        self.close_overlays();
+        if self.scheme.window_menu.is_some() {
+            self.close_window_menu();
+        }
+        self.scheme.close_confirm = Some(id);
+    }
+
+    // The area of the display a window mostly overlaps, below the height of its title bar, that
+    // the window can be tiled into
+    fn tile_area(displays: &[Display], window: &Window) -> Rect {
+        let display_index = Self::get_display_index(displays, &window.rect());
+        let area = displays[display_index].work_area();
+        let title_height = window.title_rect().height();
+        Rect::new(
+            area.left(),
+            area.top() + title_height,
+            area.width(),
+            cmp::max(0, area.height() - title_height)
+        )
+    }
+
+    // tile a window to a defined position. If no window id is provided it will use the front window
+    fn tile_window(&mut self, window_id: Option<&usize>, position: TilePosition) {
+        if let Some(id) = window_id.or(self.scheme.order.front()) {
+            if let Some(window) = self.scheme.windows.get_mut(id) {
+                if ! window.tileable() {
+                    return;
+                }
+
+                schedule(&mut self.scheme.redraws, window.title_rect());
+                schedule(&mut self.scheme.redraws, window.rect());
+                window.shaded = false;
+
+                let area = Self::tile_area(&self.orb.displays, window);
+                let rect = window.tile(position, window.tile_rect(position, &area));
+                window.configure(rect);
+            };
+        }
+    }
+
+    // Make a window cover its whole display without decorations, tiling it to FullScreen again
+    // restores it
+    fn fullscreen_window(&mut self, id: usize) {
+        if let Some(window) = self.scheme.windows.get_mut(&id) {
+            if ! window.tileable() {
+                return;
+            }
+
+            schedule(&mut self.scheme.redraws, window.title_rect());
+            schedule(&mut self.scheme.redraws, window.rect());
+            window.shaded = false;
+
+            let screen = self.orb.displays[Self::get_display_index(&self.orb.displays, &window.rect())].screen_rect();
+            let rect = window.fullscreen(screen);
+            window.configure(rect);
+        }
+    }
+
+    fn titlebar_double_click(&mut self, id: usize) {
+        match self.scheme.config.titlebar_doubleclick_action {
+            DoubleClickAction::None => {},
+            DoubleClickAction::Maximize => self.tile_window(Some(&id), FullScreen),
+            DoubleClickAction::Shade => self.shade_window(id),
+        }
+    }
+
+    // Roll the window up to just its title bar, or back down if it already is
+    fn shade_window(&mut self, id: usize) {
+        if let Some(window) = self.scheme.windows.get_mut(&id) {
+            schedule(&mut self.scheme.redraws, window.rect());
+            window.shaded = ! window.shaded;
+            schedule(&mut self.scheme.redraws, window.rect());
+        }
+    }
+
+    // Size a window to a percentage of the work area of the display under the cursor, and center
+    // it there
+    fn place_window(&mut self, id: usize, width_percent: i32, height_percent: i32) {
+        let cursor = Rect::new(self.scheme.cursor_x, self.scheme.cursor_y, 1, 1);
+        let area = self.orb.displays[Self::get_display_index(&self.orb.displays, &cursor)].work_area();
+
+        if let Some(window) = self.scheme.windows.get_mut(&id) {
+            schedule(&mut self.scheme.redraws, window.title_rect());
+            schedule(&mut self.scheme.redraws, window.rect());
+
+            let title_height = window.title_rect().height();
+            let (width, height) = percent_size(&area, width_percent, height_percent, title_height);
+            // the buffer of a window on its side is turned against the area
+            let (width, height) = if window.rotation.sideways() { (height, width) } else { (width, height) };
+            let (width, height) = window.size_hints.constrain(width, height);
+            let (shown_width, shown_height) = if window.rotation.sideways() { (height, width) } else { (width, height) };
+            let (x, y) = placement(&area, shown_width, shown_height, title_height);
+            // the client resizes the window, like when it is tiled
+            window.configure(Rect::new(x, y, width, height));
+
+            schedule(&mut self.scheme.redraws, window.title_rect());
+            schedule(&mut self.scheme.redraws, window.rect());
+        }
+    }
+
+    // Recompute the geometry of a tiled window for the position it is tiled to, without
+    // toggling it back to its floating geometry
+    fn retile_window(&mut self, id: usize) {
+        if let Some(window) = self.scheme.windows.get_mut(&id) {
+            if let Some((_, position)) = window.restore.filter(|_| window.tileable()) {
+                schedule(&mut self.scheme.redraws, window.title_rect());
+                schedule(&mut self.scheme.redraws, window.rect());
+
+                let rect = window.constrain_rect(window.tile_rect(position, &Self::tile_area(&self.orb.displays, window)));
+                if rect.left() != window.x || rect.top() != window.y {
+                    window.x = rect.left();
+                    window.y = rect.top();
+                    window.event(MoveEvent { x: window.x, y: window.y }.to_event());
+                }
+                if rect.width() != window.width() || rect.height() != window.height() {
+                    window.event(ResizeEvent {
+                        width: rect.width() as u32,
+                        height: rect.height() as u32
+                    }.to_event());
+                }
+
+                schedule(&mut self.scheme.redraws, window.title_rect());
+                schedule(&mut self.scheme.redraws, window.rect());
+            }
+        }
+    }
+
+    fn toggle_pointer_highlight(&mut self) {
+        self.scheme.pointer_highlight = ! self.scheme.pointer_highlight;
+        let (x, y) = self.scheme.cursor_drawn;
+        let rect = highlight_rect(x, y, self.scheme.scale);
+        schedule(&mut self.scheme.redraws, rect);
+    }
+
+    // Temporarily raise the window under the cursor above all others, until end_peek() is called
+    fn peek_window(&mut self) {
+        if self.scheme.peek.is_some() {
+            return;
+        }
+
+        let (x, y) = (self.scheme.cursor_x, self.scheme.cursor_y);
+        for entry in self.scheme.zbuffer.iter() {
+            if let Some(window) = self.scheme.windows.get(&entry.0).filter(|window| ! window.input_transparent) {
+                if window.rect().contains(x, y) || window.title_rect().contains(x, y) {
+                    self.scheme.peek = Some(entry.0);
+                    schedule(&mut self.scheme.redraws, window.title_rect());
+                    schedule(&mut self.scheme.redraws, window.rect());
+                    break;
+                }
+            }
+        }
+    }
+
+    // Restore the window raised by peek_window() to its place in the zbuffer
+    fn end_peek(&mut self) {
+        if let Some(id) = self.scheme.peek.take() {
+            if let Some(window) = self.scheme.windows.get(&id) {
+                schedule(&mut self.scheme.redraws, window.title_rect());
+                schedule(&mut self.scheme.redraws, window.rect());
+            }
+        }
+    }
+
+    // Open the window menu on the focused window, returns false if there is none to open it on
+    fn open_window_menu(&mut self) -> bool {
+        let Some(&id) = self.scheme.order.front() else {
+            return false;
+        };
+        if ! self.scheme.windows.get(&id).is_some_and(|window| window.focusable()) {
+            return false;
+        }
+
+        self.close_overlays();
+        self.scheme.window_menu = Some(WindowMenu::new(id));
+        true
+    }
+
+    fn close_window_menu(&mut self) {
+        self.scheme.window_menu = None;
+        schedule(&mut self.scheme.redraws, self.scheme.window_menu_rect);
+        self.scheme.window_menu_rect = Rect::default();
+    }
+
+    // Handle a key press while the window menu is open: the arrow keys select an entry, or move
+    // the window once Move was chosen, Enter runs the selected entry and Escape closes the menu
+    fn window_menu_key(&mut self, scancode: u8) {
+        let Some(menu) = self.scheme.window_menu.as_mut() else {
+            return;
+        };
+        let id = menu.id;
+
+        if menu.moving {
+            match scancode {
+                orbclient::K_UP => self.move_front_window(0, -GRID_SIZE),
+                orbclient::K_DOWN => self.move_front_window(0, GRID_SIZE),
+                orbclient::K_LEFT => self.move_front_window(-GRID_SIZE, 0),
+                orbclient::K_RIGHT => self.move_front_window(GRID_SIZE, 0),
+                orbclient::K_ENTER | orbclient::K_ESC => self.close_window_menu(),
+                _ => {}
+            }
+            return;
+        }
+
+        if menu.resizing {
+            match scancode {
+                orbclient::K_UP => self.resize_front_window((0, -1)),
+                orbclient::K_DOWN => self.resize_front_window((0, 1)),
+                orbclient::K_LEFT => self.resize_front_window((-1, 0)),
+                orbclient::K_RIGHT => self.resize_front_window((1, 0)),
+                orbclient::K_ENTER | orbclient::K_ESC => self.close_window_menu(),
+                _ => {}
+            }
+            return;
+        }
+
+        match scancode {
+            orbclient::K_UP => menu.select(-1),
+            orbclient::K_DOWN => menu.select(1),
+            orbclient::K_ENTER => match menu.action() {
+                WindowMenuAction::Move => {
+                    menu.moving = true;
+                    // The move hint is wider than the menu
+                    schedule(&mut self.scheme.redraws, self.scheme.window_menu_rect);
+                },
+                WindowMenuAction::Resize => {
+                    if self.scheme.windows.get(&id).is_some_and(|window| window.resizable) {
+                        menu.resizing = true;
+                        schedule(&mut self.scheme.redraws, self.scheme.window_menu_rect);
+                    } else {
+                        self.close_window_menu();
+                    }
+                },
+                WindowMenuAction::Tile(position) => {
+                    self.close_window_menu();
+                    self.tile_window(Some(&id), position);
+                },
+                WindowMenuAction::Close => {
+                    self.close_window_menu();
+                    self.quit_front_window();
+                },
+            },
+            orbclient::K_ESC => self.close_window_menu(),
+            _ => {}
+        }
+    }
+
+    // undraw any overlay that was being displayed and exit the mode causing it to be displayed
+    fn close_overlays(&mut self) {
+        // redraw the area that was occupied by the popup
+        schedule(&mut self.scheme.redraws, self.scheme.popup_rect);
+        // disable drawing of the win-tab or volume popup or shortcuts overlay on redraw
+        self.scheme.win_tabbing = false;
+        self.scheme.volume_osd = false;
+        self.scheme.shortcuts_osd = false;
+    }
+
+    // Process incoming key events
+    fn key_event(&mut self, event: KeyEvent) {
+        if event.pressed && self.scheme.sticky_keys_osd {
+            self.scheme.sticky_keys_osd = false;
+            schedule(&mut self.scheme.redraws, self.scheme.sticky_keys_rect);
+        }
+
+        self.track_modifier_state(event.scancode, event.pressed);
+        self.scheme.modifier_watch.key(Instant::now());
+        self.update_modifiers();
+        let modifier = modifier_bit(event.scancode);
+
+        // grabbed key combinations go to the window that grabbed them, even if it isn't focused
+        if let Some(id) = self.scheme.key_grabs.route(event.scancode, event.pressed, self.scheme.modifier_state) {
+            if let Some(window) = self.scheme.windows.get_mut(&id) {
+                window.event(event.to_event());
+            }
+            if event.pressed {
+                self.release_sticky_modifiers();
+            }
+            return;
+        }
+
+        // the close confirmation and the window menu take all key presses while they are open,
+        // modifiers still go to the window so it sees them released
+        if modifier.is_none() && self.scheme.close_confirm.is_some() {
+            if event.pressed {
+                if answer_close_confirm(&mut self.scheme.close_confirm, &mut self.scheme.windows, event.scancode) {
+                    schedule(&mut self.scheme.redraws, self.scheme.close_confirm_rect);
+                    self.scheme.close_confirm_rect = Rect::default();
+                }
+                self.release_sticky_modifiers();
+            }
+            return;
+        }
+        if modifier.is_none() {
+            let alt_space = event.scancode == orbclient::K_SPACE
+                && self.scheme.modifier_state & (ALT_MODIFIER | CONTROL_MODIFIER | SUPER_MODIFIER) == ALT_MODIFIER;
+            let consumed = if self.scheme.window_menu.is_some() {
+                true
+            } else {
+                event.pressed && alt_space && self.open_window_menu()
+            };
+            if consumed {
+                if event.pressed {
+                    if ! alt_space {
+                        self.window_menu_key(event.scancode);
+                    }
+                    self.release_sticky_modifiers();
+                }
+                return;
+            }
+        }
+
+        match (event.scancode, event.pressed) {
+            (orbclient::K_SUPER, true) => self.scheme.shortcuts_osd = true,
+            (orbclient::K_SUPER, false) => {
+                self.close_overlays();
+                self.end_peek();
+                self.scheme.mru_cycle = None;
+            },
+            (scancode, false) if self.scheme.config.shortcuts[&ShortcutAction::Peek].iter().any(|combo| combo.scancode == scancode) =>
+                self.end_peek(),
+            (orbclient::K_VOLUME_TOGGLE, true) => self.volume(Volume::Toggle),
+            (orbclient::K_VOLUME_DOWN, true) => self.volume(Volume::Down),
+            (orbclient::K_VOLUME_UP, true) => self.volume(Volume::Up),
+            (orbclient::K_VOLUME_TOGGLE | orbclient::K_VOLUME_DOWN | orbclient::K_VOLUME_UP, false) =>
+                self.scheme.volume_osd = false,
+            _ => {}
+        }
+
+        // process SUPER- key combinations
+        if self.scheme.modifier_state & SUPER_MODIFIER == SUPER_MODIFIER && event.pressed
+        && event.scancode != orbclient::K_SUPER {
+            self.close_overlays();
+
+            match shortcut_action(&self.scheme.config.shortcuts, event.scancode, self.scheme.modifier_state) {
+                Some(ShortcutAction::QuitWindow) => self.quit_front_window(),
+                Some(ShortcutAction::CycleWindows) => self.super_tab(),
+                Some(ShortcutAction::VolumeDown) => self.volume(Volume::Down),
+                Some(ShortcutAction::VolumeUp) => self.volume(Volume::Up),
+                Some(ShortcutAction::VolumeToggle) => self.volume(Volume::Toggle),
+                Some(ShortcutAction::Maximize) => self.tile_window(None, FullScreen),
+                Some(ShortcutAction::TileTop) => self.tile_window(None, TopHalf),
+                Some(ShortcutAction::TileBottom) => self.tile_window(None, BottomHalf),
+                Some(ShortcutAction::TileLeft) => self.tile_window(None, LeftHalf),
+                Some(ShortcutAction::TileRight) => self.tile_window(None, RightHalf),
+                Some(ShortcutAction::MoveUp) => self.move_front_window(0, -GRID_SIZE),
+                Some(ShortcutAction::MoveDown) => self.move_front_window(0, GRID_SIZE),
+                Some(ShortcutAction::MoveLeft) => self.move_front_window(-GRID_SIZE, 0),
+                Some(ShortcutAction::MoveRight) => self.move_front_window(GRID_SIZE, 0),
+                Some(ShortcutAction::Copy) => self.clipboard_event(orbclient::CLIPBOARD_COPY),
+                Some(ShortcutAction::Cut) => self.clipboard_event(orbclient::CLIPBOARD_CUT),
+                Some(ShortcutAction::Paste) => self.clipboard_event(orbclient::CLIPBOARD_PASTE),
+                Some(ShortcutAction::LogState) => self.scheme.dump_state(),
+                Some(ShortcutAction::Peek) => self.peek_window(),
+                Some(ShortcutAction::PointerHighlight) => self.toggle_pointer_highlight(),
+                Some(ShortcutAction::Redraw) => self.scheme.force_full_redraw(&self.orb.displays),
+                Some(ShortcutAction::ToggleOnTop) => self.toggle_front_front_window(),
+                Some(ShortcutAction::ScaleUp) => self.step_ui_scale(1),
+                Some(ShortcutAction::ScaleDown) => self.step_ui_scale(-1),
+                Some(ShortcutAction::ScaleReset) => self.reset_ui_scale(),
+                None => {
+                    //TODO: remove hack for sending super events to lowest numbered window
+                    // ADM is this related to Launcher or Background or something?
+                    if let Some((id, window)) = self.scheme.windows.iter_mut().next() {
+                        info!("sending super {:?} to {}, {}", event, id, window.title);
+                        let mut super_event = event.to_event();
+                        super_event.code += 0x1000_0000;
+                        window.event(super_event);
+                    }
+                }
+            }
+        }
+
+        // send non-Super key events to the front window
+        if self.scheme.modifier_state & SUPER_MODIFIER == 0 {
+            if let Some(id) = self.scheme.order.front() {
+                if let Some(window) = self.scheme.windows.get_mut(id).filter(|window| window.focusable()) {
+                    if event.pressed && event.character != '\0' {
+                        let text_input_event = TextInputEvent {
+                            character: event.character,
+                        }.to_event();
+                        window.event(text_input_event);
+                    }
+                    // the release of a latched modifier is sent once it is unlatched
+                    let latched = modifier.is_some_and(|bit| self.scheme.sticky_keys.latched & bit != 0);
+                    if event.pressed || ! latched {
+                        window.event(event.to_event());
+                    }
+                }
+            }
+        }
+
+        if event.pressed && modifier.is_none() {
+            self.release_sticky_modifiers();
+        }
+    }
+
+    fn mouse_event(&mut self, event: MouseEvent) {
+        let mut new_cursor = CursorKind::LeftPtr;
+        let mut new_hover = None;
+        let grid = drag_grid(&self.scheme.config, self.scheme.modifier_state);
+        // the edges the borders being dragged snap to, Control resizes freely like with the grid
+        let snap_distance = self.scheme.config.snap_distance as i32;
+        let (snap_x, snap_y) = match resizing(&self.scheme.dragging) {
+            Some(id) if snap_distance > 0 && self.scheme.modifier_state & CONTROL_MODIFIER == 0 => {
+                snap_edges(&self.scheme.zbuffer, &self.scheme.windows, id)
+            },
+            _ => (Vec::new(), Vec::new()),
+        };
+
+        // Check for focus switch, dragging, and forward mouse events to applications
+        match self.scheme.dragging {
+            DragMode::None => {
+                if let Some((id, _, region)) = input_target(&self.scheme.zbuffer, &self.scheme.windows, event.x, event.y) {
+                    if let Some(window) = self.scheme.windows.get_mut(&id) {
+                        match region {
+                            WindowRegion::Content => {
+                                if ! window.mouse_cursor {
+                                    new_cursor = CursorKind::None;
+                                }
+
+                                new_hover = Some(id);
+                                if new_hover != self.scheme.hover {
+                                    let hover_event = HoverEvent {
+                                        entered: true
+                                    }.to_event();
+                                    window.event(hover_event);
+                                }
+
+                                if self.scheme.modifier_state & SUPER_MODIFIER == 0 {
+                                    let mut window_event = event.to_event();
+                                    let (x, y) = window.buffer_point(event.x - window.x, event.y - window.y);
+                                    window_event.a = x as i64;
+                                    window_event.b = y as i64;
+                                    window.event(window_event);
+                                }
+                            },
+                            WindowRegion::Title => (),
+                            WindowRegion::BottomLeftCorner => new_cursor = CursorKind::BottomLeftCorner,
+                            WindowRegion::BottomRightCorner => new_cursor = CursorKind::BottomRightCorner,
+                            WindowRegion::LeftBorder => new_cursor = CursorKind::LeftSide,
+                            WindowRegion::RightBorder => new_cursor = CursorKind::RightSide,
+                            WindowRegion::BottomBorder => new_cursor = CursorKind::BottomSide,
+                        }
+                    }
+                }
+            },
+            DragMode::Title(window_id, drag_x, drag_y) => {
+                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
+                    if drag_x != event.x || drag_y != event.y {
+                        //TODO: Min and max
+                        let ((x, y), (drag_x, drag_y)) = title_drag((window.x, window.y), (drag_x, drag_y), (event.x, event.y), grid);
+                        self.scheme.dragging = DragMode::Title(window_id, drag_x, drag_y);
+
+                        if x != window.x || y != window.y {
+                            schedule(&mut self.scheme.redraws, window.title_rect());
+                            schedule(&mut self.scheme.redraws, window.rect());
+
+                            window.x = x;
+                            window.y = y;
+
+                            let move_event = MoveEvent {
+                                x: window.x,
+                                y: window.y
+                            }.to_event();
+                            window.event(move_event);
+
+                            schedule(&mut self.scheme.redraws, window.title_rect());
+                            schedule(&mut self.scheme.redraws, window.rect());
+                        }
+                    }
+                } else {
+                    self.scheme.dragging = DragMode::None;
+                }
+            },
+            DragMode::LeftBorder(window_id, off_x, right_x) => {
+                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
+                    new_cursor = CursorKind::LeftSide;
+
+                    let x = snap_to_edge(snap_to_grid(event.x - off_x, grid), &snap_x, snap_distance);
+                    let w = right_x - x;
+
+                    if w > 0 {
+                        let (w, _) = window.size_hints.constrain(w, window.height());
+                        let x = right_x - w;
+                        if x != window.x {
+                            schedule(&mut self.scheme.redraws, window.title_rect());
+                            schedule(&mut self.scheme.redraws, window.rect());
+
+                            window.x = x;
+                            let move_event = MoveEvent {
+                                x,
+                                y: window.y
+                            }.to_event();
+                            window.event(move_event);
+
+                            schedule(&mut self.scheme.redraws, window.title_rect());
+                            schedule(&mut self.scheme.redraws, window.rect());
+                        }
+
+                        if w != window.width()  {
+                            if let Some((width, height)) = self.scheme.resize_debounce.resize(Instant::now(), window_id, w as u32, window.height() as u32) {
+                                window.event(ResizeEvent { width, height }.to_event());
+                            }
+                        }
+                    }
+                } else {
+                    self.scheme.dragging = DragMode::None;
+                }
+            },
+            DragMode::RightBorder(window_id, off_x) => {
+                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
+                    new_cursor = CursorKind::RightSide;
+                    let w = snap_to_edge(snap_to_grid(event.x - off_x, grid), &snap_x, snap_distance) - window.x;
+                    if w > 0 {
+                        let (w, _) = window.size_hints.constrain(w, window.height());
+                        if w != window.width() {
+                            if let Some((width, height)) = self.scheme.resize_debounce.resize(Instant::now(), window_id, w as u32, window.height() as u32) {
+                                window.event(ResizeEvent { width, height }.to_event());
+                            }
+                        }
+                    }
+                } else {
+                    self.scheme.dragging = DragMode::None;
+                }
+            },
+            DragMode::BottomBorder(window_id, off_y) => {
+                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
+                    new_cursor = CursorKind::BottomSide;
+                    let h = snap_to_edge(snap_to_grid(event.y - off_y, grid), &snap_y, snap_distance) - window.y;
+                    if h > 0 {
+                        let (_, h) = window.size_hints.constrain(window.width(), h);
+                        if h != window.height() {
+                            if let Some((width, height)) = self.scheme.resize_debounce.resize(Instant::now(), window_id, window.width() as u32, h as u32) {
+                                window.event(ResizeEvent { width, height }.to_event());
+                            }
+                        }
+                    }
+                } else {
+                    self.scheme.dragging = DragMode::None;
+                }
+            },
+            DragMode::BottomLeftBorder(window_id, off_x, off_y, right_x) => {
+                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
+                    new_cursor = CursorKind::BottomLeftCorner;
+
+                    let x = snap_to_edge(snap_to_grid(event.x - off_x, grid), &snap_x, snap_distance);
+                    let h = snap_to_edge(snap_to_grid(event.y - off_y, grid), &snap_y, snap_distance) - window.y;
+                    let w = right_x - x;
+
+                    if w > 0 && h > 0 {
+                        let (w, h) = window.size_hints.constrain(w, h);
+                        let x = right_x - w;
+                        if x != window.x {
+                            schedule(&mut self.scheme.redraws, window.title_rect());
+                            schedule(&mut self.scheme.redraws, window.rect());
+
+                            window.x = x;
+                            let move_event = MoveEvent {
+                                x,
+                                y: window.y
+                            }.to_event();
+                            window.event(move_event);
+
+                            schedule(&mut self.scheme.redraws, window.title_rect());
+                            schedule(&mut self.scheme.redraws, window.rect());
+                        }
+
+                        if w != window.width() || h != window.height() {
+                            if let Some((width, height)) = self.scheme.resize_debounce.resize(Instant::now(), window_id, w as u32, h as u32) {
+                                window.event(ResizeEvent { width, height }.to_event());
+                            }
+                        }
+                    }
+                } else {
+                    self.scheme.dragging = DragMode::None;
+                }
+            },
+            DragMode::BottomRightBorder(window_id, off_x, off_y) => {
+                if let Some(window) = self.scheme.windows.get_mut(&window_id) {
+                    new_cursor = CursorKind::BottomRightCorner;
+                    let w = snap_to_edge(snap_to_grid(event.x - off_x, grid), &snap_x, snap_distance) - window.x;
+                    let h = snap_to_edge(snap_to_grid(event.y - off_y, grid), &snap_y, snap_distance) - window.y;
+                    if w > 0 && h > 0 {
+                        let (w, h) = window.size_hints.constrain(w, h);
+                        if w != window.width() || h != window.height() {
+                            if let Some((width, height)) = self.scheme.resize_debounce.resize(Instant::now(), window_id, w as u32, h as u32) {
+                                window.event(ResizeEvent { width, height }.to_event());
+                            }
+                        }
+                    }
+                } else {
+                    self.scheme.dragging = DragMode::None;
+                }
+            }
+        }
+
+        if new_hover != self.scheme.hover {
+            if let Some(id) = self.scheme.hover {
+                if let Some(window) = self.scheme.windows.get_mut(&id) {
+                    let hover_event = HoverEvent {
+                        entered: false
+                    }.to_event();
+                    window.event(hover_event);
+                }
+            }
+
+            self.scheme.hover = new_hover;
+        }
+
+        self.scheme.update_cursor(event.x, event.y, new_cursor);
+        self.update_hot_corners();
+        self.update_resize_osd();
+        self.update_autoraise();
+    }
+
+    fn mouse_relative_event(&mut self, event: MouseRelativeEvent) {
+        let mut relative_cursor_opt = None;
+        if let Some(id) = self.scheme.order.front() {
+            if let Some(window) = self.scheme.windows.get_mut(id) {
+                //TODO: handle grab?
+                if window.mouse_relative {
+                    // Send relative event
+                    window.event(event.to_event());
+
+                    // Update cursor to center of this window
+                    relative_cursor_opt = Some((
+                        window.x + window.width() / 2,
+                        window.y + window.height() / 2,
+                        relative_cursor(window)
+                    ));
+                }
+            }
+        }
+
+        // Handle relative window cursor
+        if let Some((x, y, kind)) = relative_cursor_opt {
+            self.scheme.update_cursor(x, y, kind);
+            return;
+        }
+
+        //TODO: more advanced logic for keeping mouse on screen.
+        // This logic assumes horizontal and touching, but not overlapping, screens
+        let mut max_x = 0;
+        let mut max_y = 0;
+        for display in self.orb.displays.iter() {
+            let rect = display.screen_rect();
+            max_x = cmp::max(max_x, rect.right() - 1);
+            max_y = cmp::max(max_y, rect.bottom() - 1);
+        }
+
+        let x = cmp::max(0, cmp::min(max_x, self.scheme.cursor_x + event.dx));
+        let mut y = cmp::max(0, cmp::min(max_y, self.scheme.cursor_y + event.dy));
+        for display in self.orb.displays.iter() {
+            let rect = display.screen_rect();
+            if x >= rect.left() && x < rect.right() {
+                y = cmp::max(rect.top(), cmp::min(rect.bottom() - 1, y));
+            }
+        }
+
+        self.mouse_event(MouseEvent { x, y });
+    }
+
+    // Find the display that a window (`rect`) most overlaps and return the index of it
+    fn get_display_index(displays: &[Display], rect: &Rect) -> usize {
+        // Find the index of the Display this window has the most overlap with
+        let mut display_index = 0;
+        let mut max_intersection_area = 0;
+        for (display_i, display) in displays.iter().enumerate() {
+            let intersect = display.screen_rect().intersection(rect);
+            let area = intersect.area();
+            if area > max_intersection_area {
+                display_index = display_i;
+                max_intersection_area = area;
+            }
+        }
+
+        display_index
+    }
+
+    fn button_event(&mut self, event: ButtonEvent) {
+        // Check for focus switch, dragging, and forward mouse events to applications
+        match self.scheme.dragging {
+            DragMode::None => {
+                let mut focus = 0;
+                let panel_click = self.scheme.panel.as_ref()
+                    .filter(|panel| panel.state.rect.contains(self.scheme.cursor_x, self.scheme.cursor_y))
+                    .map(|panel| panel.button_at(self.scheme.cursor_x, self.scheme.cursor_y));
+                if let Some(button) = panel_click {
+                    // the panel is above all windows, a press on one of its buttons focuses the window
+                    if let Some(id) = button.filter(|_| event.left && ! self.scheme.cursor_left) {
+                        // a hidden tab is shown in place of the active one first
+                        let shown = self.scheme.windows.get(&id).and_then(|window| shown_tab(window, &self.scheme.tab_groups));
+                        if let Some(shown) = shown {
+                            self.scheme.show_tab(shown, id);
+                        }
+                        self.minimize(id, false, None);
+                        focus = self.scheme.order.iter().position(|&e| e == id).unwrap_or(0);
+                    }
+                } else if let Some((id, i, region)) = input_target(&self.scheme.zbuffer, &self.scheme.windows, self.scheme.cursor_x, self.scheme.cursor_y) {
+                    if let Some(window) = self.scheme.windows.get(&id) {
+                        let pressed_left = event.left && ! self.scheme.cursor_left;
+                        match region {
+                            WindowRegion::Content => {
+                                if self.scheme.modifier_state & SUPER_MODIFIER == SUPER_MODIFIER {
+                                    if pressed_left {
+                                        focus = i;
+                                        self.scheme.dragging = DragMode::Title(id, self.scheme.cursor_x, self.scheme.cursor_y);
+                                    }
+                                } else if let Some(window) = self.scheme.windows.get_mut(&id) {
+                                    window.event(event.to_event());
+                                    if pressed_left
+                                        || event.middle && !self.scheme.cursor_middle
+                                        || event.right && !self.scheme.cursor_right {
+                                        focus = i;
+                                    }
+                                }
+                            },
+                            //TODO: Trigger max and exit on release
+                            WindowRegion::Title => if pressed_left {
+                                focus = i;
+                                if let Some(tab) = self.scheme.tab_at(id, self.scheme.cursor_x, self.scheme.cursor_y) {
+                                    self.scheme.show_tab(id, tab);
+                                    focus = self.scheme.order.iter().position(|&e| e == tab).unwrap_or(0);
+                                } else if (window.max_contains(self.scheme.cursor_x, self.scheme.cursor_y)) && (window.resizable) {
+                                    let shift = self.scheme.modifier_state & SHIFT_ANY_MODIFIER != 0;
+                                    match maximize_action(self.scheme.config.maximize_button_action, shift, window.maximized()) {
+                                        MaximizeAction::Maximize => self.tile_window(Some(&id), FullScreen),
+                                        MaximizeAction::Fullscreen => self.fullscreen_window(id),
+                                    }
+                                } else if (window.close_contains(self.scheme.cursor_x, self.scheme.cursor_y)) && (!window.unclosable) {
+                                    self.request_close(id);
+                                } else if self.scheme.config.titlebar_doubleclick_action != DoubleClickAction::None
+                                && double_click(&mut self.scheme.title_click, id, Instant::now()) {
+                                    self.titlebar_double_click(id);
+                                } else {
+                                    self.scheme.dragging = DragMode::Title(id, self.scheme.cursor_x, self.scheme.cursor_y);
+                                }
+                            },
+                            border => if pressed_left {
+                                focus = i;
+                                self.scheme.dragging = border_drag(window, id, border, self.scheme.cursor_x, self.scheme.cursor_y);
+                            },
+                        }
+                    }
+                }
+
+                if let Some(focus) = click_focus(&self.scheme.order, &self.scheme.windows, focus) {
+                    // Redraw old focused window
+                    if let Some(id) = self.scheme.order.front() {
+                        self.focus(*id, false);
+                    }
+
+                    // Reorder windows
+                    if let Some(id) = self.scheme.order.remove(focus) {
+                        if let Some(window) = self.scheme.windows.get(&id){
+                            match window.zorder {
+                                WindowZOrder::Front | WindowZOrder::Normal => {
+                                    // Transfer focus if a front or normal window
+                                    self.scheme.order.push_front(id);
+                                },
+                                WindowZOrder::Back => {
+                                    // Return to original position if a background window
+                                    self.scheme.order.insert(focus, id);
+                                }
+                            }
+                        }
+                    }
+
+                    // Redraw new focused window
+                    if let Some(id) = self.scheme.order.front() {
+                        self.focus(*id, true);
+                    }
+                }
+            },
+            _ => if ! event.left {
+                self.scheme.dragging = DragMode::None;
+
+                // always send the final size of a debounced border drag
+                if let Some((id, width, height)) = self.scheme.resize_debounce.finish() {
+                    if let Some(window) = self.scheme.windows.get_mut(&id) {
+                        window.event(ResizeEvent { width, height }.to_event());
+                    }
+                }
+                self.update_resize_osd();
+            }
+        }
+
+        self.scheme.cursor_left = event.left;
+        self.scheme.cursor_middle = event.middle;
+        self.scheme.cursor_right = event.right;
+    }
+
+    fn resize_event(&mut self, event: ResizeEvent) {
+        self.orb.resize(event.width as i32, event.height as i32);
+
+        let screen_rect = self.orb.screen_rect();
+        schedule(&mut self.scheme.redraws, screen_rect);
+
+        // the images loaded at startup, such as cursors and title bar buttons, keep their scale.
+        // Windows get the scale of their display on the next redraw
+        if self.scheme.scale_override.is_none() {
+            self.scheme.scale = self.orb.displays.iter().fold(1, |scale, display| cmp::max(scale, display.scale));
+        }
+
+        let screen_event = ScreenEvent {
+            width: self.orb.image().width() as u32,
+            height: self.orb.image().height() as u32,
+        }.to_event();
+        for (_window_id, window) in self.scheme.windows.iter_mut() {
+            window.event(screen_event);
+        }
+    }
+
+    pub fn event(&mut self, event_union: Event){
+        self.scheme.rezbuffer();
+
+        if matches!(event_union.to_option(), EventOption::Key(_) | EventOption::Mouse(_)
+            | EventOption::MouseRelative(_) | EventOption::Button(_) | EventOption::Scroll(_))
+        {
+            let woken = self.scheme.idle.input(Instant::now());
+            if woken {
+                let screen = self.orb.screen_rect();
+                schedule(&mut self.scheme.redraws, screen);
+            }
+            self.update_idle();
+            // the input that wakes the screen up only does that, it isn't sent on
+            if woken {
+                return;
+            }
+            if self.scheme.config.debug_input_latency {
+                self.scheme.input_latency.input(Instant::now());
+            }
+        }
+
+        match event_union.to_option() {
+            EventOption::Key(event) => self.key_event(event),
+            EventOption::Mouse(MouseEvent { x, y }) => {
+                // ps2d gives us absolute mouse events with x and y in the range 0..65535.
+                // We need to translate this back to screen coordinates. We are using the
+                // size of the first display here as the only multi-display system supported
+                // by qemu doesn't produce absolute mouse events using vmmouse at all.
+                // FIXME once we have usb tablet support add a new event like MouseEvent
+                // which indicates the input device from which the event originated to use
+                // the correct display for getting the size.
+                self.mouse_event(MouseEvent {
+                    x: x * self.orb.displays[0].image.width() / 65536,
+                    y: y * self.orb.displays[0].image.height() / 65536,
+                });
+            }
+            EventOption::MouseRelative(event) => self.mouse_relative_event(event),
+            EventOption::Button(event) => self.button_event(event),
+            EventOption::Scroll(event) => {
+                if let Some(entry) = self.scheme.zbuffer.first() {
+                    let id = entry.0;
+                    if let Some(window) = self.scheme.windows.get_mut(&id) {
+                        let event = window.scale_scroll(scroll_event(&self.scheme.config, event), self.scheme.config.scroll_sensitivity);
+                        if event.x != 0 || event.y != 0 {
+                            window.event(event.to_event());
+                        }
+                    }
+                }
+            },
+            EventOption::Resize(event) => self.resize_event(event),
+            event => error!("unexpected event: {:?}", event)
+        }
+    }
+
+    pub fn display_event(&mut self, events: &[Event]) -> io::Result<()> {
+        for &event in events {
+            self.event(event);
+        }
+
+        self.scheme_event(&mut [])?;
+
+        // redrawn by handle_after
+
+        Ok(())
+    }
+
+    pub fn scheme_event(&mut self, _packets: &mut [Packet]) -> io::Result<()> {
+        for (id, window) in self.scheme.windows.iter_mut() {
+            if ! window.events.is_empty() {
+                if !window.notified_read || window.asynchronous {
+                    window.notified_read = true;
+                    self.orb.scheme_write(&Packet {
+                        id: 0,
+                        pid: 0,
+                        uid: 0,
+                        gid: 0,
+                        a: syscall::number::SYS_FEVENT,
+                        b: *id,
+                        c: syscall::flag::EVENT_READ.bits(),
+                        d: window.events.len() * mem::size_of::<Event>()
+                    })?;
+                }
+            } else {
+                window.notified_read = false;
+            }
+        }
+
+        for (&id, recorder) in self.scheme.recorders.iter_mut() {
+            let queued = recorder.queued();
+            if queued > 0 && ! recorder.notified {
+                recorder.notified = true;
+                self.orb.record_ready(id, queued)?;
+            }
+        }
+
+        // redrawn by handle_after
+
+        Ok(())
+    }
+
+    fn window_new(&mut self, mut x: i32, mut y: i32,
+                  width: i32, height: i32,
+                  flags: &str,
+                  title: String) -> Result<usize> {
+        let id = self.scheme.next_id as usize;
+        self.scheme.next_id += 1;
+        if self.scheme.next_id < 0 {
+            //TODO: should this be an error?
+            self.scheme.next_id = 1;
+        }
+
+        let mut window = Window::new(x, y, width, height, self.scheme.scale, Rc::clone(&self.scheme.config));
+        window.owner = self.orb.caller;
+
+        // the maximized flag is applied once the window is placed
+        for flag in flags.chars().filter(|&flag| flag != ORBITAL_FLAG_MAXIMIZED) {
+            window.set_flag(flag, true);
+        }
+
+        // Unfocus previous top window, which gets focus back when this one closes
+        if window.focusable() {
+            if let Some(&id) = self.scheme.order.front() {
+                self.focus(id, false);
+                window.opened_from = Some(id);
+            }
+        }
+
+        window.title = title;
+        window.render_title(&self.scheme.font);
+
+        // a position the client asked for wins over the remembered one
+        let saved = self.scheme.saved_geometry.iter()
+            .find(|(title, _)| *title == window.title)
+            .filter(|_| self.scheme.config.remember_geometry && x < 0 && y < 0)
+            .map(|&(_, saved)| saved);
+        let mut tile = None;
+        if let Some(saved) = saved {
+            let screens: Vec<Rect> = self.orb.displays.iter().map(Display::screen_rect).collect();
+            tile = restore_geometry(&mut window, &saved, &screens);
+        } else if x < 0 && y < 0 {
+            // Automatic placement
+            let area = if self.scheme.config.confine_to_work_area {
+                let cursor = Rect::new(self.scheme.cursor_x, self.scheme.cursor_y, 1, 1);
+                let display_index = Self::get_display_index(&self.orb.displays, &cursor);
+                self.orb.displays[display_index].work_area()
+            } else {
+                Rect::new(0, 0, self.orb.image().width(), self.orb.image().height())
+            };
+            let area = placement_area(&area, &self.scheme.config.placement_margin);
+            (window.x, window.y) = placement(&area, width, height, window.title_rect().height());
+        }
+
+        if flags.contains(ORBITAL_FLAG_MAXIMIZED) {
+            tile = Some(FullScreen);
+        }
+        if let Some(position) = tile {
+            let area = Self::tile_area(&self.orb.displays, &window);
+            if let Some((width, height)) = tile_new_window(&mut window, position, &area) {
+                window.event(MoveEvent { x: window.x, y: window.y }.to_event());
+                window.event(ResizeEvent { width, height }.to_event());
+            }
+        }
+
+        // Redraw new window
+        schedule(&mut self.scheme.redraws, window.title_rect());
+        schedule(&mut self.scheme.redraws, window.rect());
+
+        // Add to zorder as appropriate, a window that never takes focus goes behind the front
+        // window so that it keeps focus
+        let no_focus = ! window.focusable();
+        match window.zorder {
+            WindowZOrder::Front | WindowZOrder::Normal if no_focus && ! self.scheme.order.is_empty() => {
+                self.scheme.order.insert(1, id);
+            },
+            WindowZOrder::Front | WindowZOrder::Normal => {
+                self.scheme.order.push_front(id);
+            },
+            WindowZOrder::Back => {
+                self.scheme.order.push_back(id);
+            }
+        }
+
+        self.scheme.windows.insert(id, window);
+
+        // Focus new top window
+        if ! no_focus {
+            if let Some(id) = self.scheme.order.front() {
+                self.focus(*id, true);
+            }
+        }
+
+        // Ensure mouse cursor is correct
+        let event = MouseEvent {
+            x: self.scheme.cursor_x,
+            y: self.scheme.cursor_y,
+        };
+        self.mouse_event(event);
+
+        // Start the spinner for the new window if it isn't running yet
+        self.update_spinner();
+
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use orbclient::{Color, Renderer, ScrollEvent};
+    use crate::config::{Config, Easing, MaximizeAction, MAX_UI_SCALE, PlacementMargin, ShortcutAction};
+    use crate::scheme::{
+        ALT_ANY_MODIFIER,
+        ALT_GR_MODIFIER,
+        ALT_MODIFIER,
+        ANIMATION_DURATION,
+        answer_close_confirm,
+        AutoRaise,
+        batch_active,
+        BATCH_TIMEOUT,
+        begin_batch,
+        border_drag,
+        BUILTIN_UI_FONT,
+        CAPS_LOCK,
+        click_focus,
+        clipboard_write,
+        compact,
+        CONTROL_MODIFIER,
+        cover_blurred,
+        CursorKind,
+        damage_all,
+        display_position,
+        display_scale_of,
+        double_click,
+        drag_grid,
+        DragMode,
+        end_batch,
+        find_ui_font,
+        font_typefaces,
+        fonts_query,
+        FrameClock,
+        FramePacer,
+        GRID_SIZE,
+        highlight_rect,
+        highlight_ring,
+        HOT_CORNER_DWELL,
+        HotCorner,
+        HotCornerAction,
+        HotCorners,
+        IdleBlank,
+        input_target,
+        InputLatency,
+        K_NUM_LOCK,
+        keyboard_resize,
+        KeyGrabs,
+        LATENCY_BUCKETS,
+        LATENCY_LOG_INTERVAL,
+        lerp_rect,
+        load_cursors,
+        LockKeys,
+        MAX_SAVED_GEOMETRY,
+        maximize_action,
+        minimize_target,
+        mirrors,
+        modifier_bit,
+        MODIFIER_FOCUS_TIMEOUT,
+        modifier_state,
+        modifiers_query,
+        ModifierWatch,
+        mru_next,
+        mru_order,
+        MruCycle,
+        NUM_LOCK,
+        ORBITAL_EVENT_SCALE,
+        osd_color,
+        outline_edges,
+        panel_buttons,
+        panel_clock,
+        panel_lists,
+        percent_size,
+        pixel_query,
+        placement,
+        placement_area,
+        raise_in_class,
+        raise_in_zbuffer,
+        record_display,
+        record_limit,
+        RECORD_QUEUE_BYTES,
+        record_update,
+        Recorder,
+        reflow_tile,
+        relative_cursor,
+        rescale_window,
+        RESIZE_OSD_LINGER,
+        resize_osd_rect,
+        ResizeDebounce,
+        ResizeOsd,
+        resizing,
+        restore_geometry,
+        return_focus,
+        same_owner,
+        save_geometry,
+        SavedGeometry,
+        scanout_window,
+        schedule,
+        screenshot,
+        scroll_event,
+        SHIFT_ANY_MODIFIER,
+        SHIFT_LEFT_MODIFIER,
+        shortcut_action,
+        shortcuts_list,
+        shown_tab,
+        smooth_cursor_step,
+        snap_edges,
+        snap_to_edge,
+        snap_to_grid,
+        SPINNER_DOTS,
+        spinner_dots,
+        split_due,
+        stack_cycle,
+        stack_zbuffer,
+        state_dump,
+        step_scale,
+        step_volume,
+        StickyKeys,
+        subtitles_query,
+        SUPER_MODIFIER,
+        swap_geometry,
+        switcher_next,
+        sync_rects,
+        SyncLimiter,
+        tab_area,
+        tab_rects,
+        TabGroup,
+        text_cursor_query,
+        tile_new_window,
+        title_drag,
+        toast_rects,
+        Toasts,
+        track_modifiers,
+        volume_step,
+        wallpaper_rect,
+        window_at,
+        window_list,
+        WINDOW_MENU,
+        window_menu_rect,
+        window_scale,
+        window_synced,
+        WindowAnimation,
+        WindowMenu,
+        WindowMenuAction,
+        zbuffer,
+    };
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, VecDeque};
+    use std::fs;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+    use syscall::error::ENOSPC;
+    use crate::core::{Anchor, KeyCombo, SizeHints, StackRelation};
+    use crate::window::{
+        ORBITAL_FLAG_BORDERLESS, ORBITAL_FLAG_INPUT_TRANSPARENT, ORBITAL_FLAG_MAXIMIZED, ORBITAL_FLAG_NO_FOCUS,
+        ORBITAL_FLAG_RESIZABLE, ORBITAL_FLAG_SUSPENDED, ORBITAL_FLAG_TRANSPARENT, TilePosition, Window, WindowRegion,
+        WindowZOrder,
+    };
+    use crate::core::{display::work_area, image::Image, rect::Rect};
+
+    fn edges(rect: Rect) -> (i32, i32, i32, i32) {
+        (rect.left(), rect.top(), rect.right(), rect.bottom())
+    }
+
+    #[test]
+    fn scroll_forwarded_unchanged_by_default() {
+        let config = Config::default();
+        let event = scroll_event(&config, ScrollEvent { x: 1, y: -3 });
+        assert_eq!((event.x, event.y), (1, -3));
+    }
+
+    #[test]
+    fn natural_scroll_inverts_delta() {
+        let config = Config {
+            natural_scroll: true,
+            ..Config::default()
+        };
+        let event = scroll_event(&config, ScrollEvent { x: 1, y: -3 });
+        assert_eq!((event.x, event.y), (-1, 3));
+    }
+
+    #[test]
+    fn volume_steps_by_configured_step_within_bounds() {
+        let config = Config {
+            volume_step: 3,
+            ..Config::default()
+        };
+        let step = volume_step(&config, false);
+        assert_eq!(step_volume(50, step), 53);
+        assert_eq!(step_volume(50, -step), 47);
+        assert_eq!(step_volume(99, step), 100);
+        assert_eq!(step_volume(1, -step), 0);
+        assert_eq!(step_volume(100, step), 100);
+        assert_eq!(step_volume(0, -step), 0);
+
+        let large = volume_step(&config, true);
+        assert_eq!(step_volume(50, large), 62);
+        assert_eq!(step_volume(95, large), 100);
+    }
+
+    #[test]
+    fn volume_step_is_limited() {
+        let config = Config {
+            volume_step: 1000,
+            ..Config::default()
+        };
+        assert_eq!(step_volume(50, -volume_step(&config, false)), 0);
+        assert_eq!(step_volume(50, volume_step(&config, true)), 100);
+    }
+
+    #[test]
+    fn lost_super_release_is_reset() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(10);
+        let mut watch = ModifierWatch::new(start);
+
+        // Super is pressed and its release is lost
+        let held = modifier_bit(orbclient::K_SUPER).unwrap_or_default();
+        assert_eq!(modifier_state(held) & SUPER_MODIFIER, SUPER_MODIFIER);
+        assert_eq!(watch.deadline(held, timeout), Some(start + timeout));
+        assert!(!watch.stuck(held, start + Duration::from_secs(9), timeout));
+
+        // a key repeat while it is held keeps it from being taken as stuck
+        watch.key(start + Duration::from_secs(5));
+        assert!(!watch.stuck(held, start + timeout, timeout));
+        assert!(watch.stuck(held, start + Duration::from_secs(15), timeout));
+
+        // a change of focus doesn't wait for the timeout
+        assert!(watch.stuck(held, start + Duration::from_secs(5) + MODIFIER_FOCUS_TIMEOUT, MODIFIER_FOCUS_TIMEOUT));
+
+        // nothing is stuck without held modifiers, or with resetting disabled
+        assert_eq!(watch.deadline(0, timeout), None);
+        assert!(!watch.stuck(held, start + Duration::from_secs(60), Duration::ZERO));
+    }
+
+    #[test]
+    fn maximize_button_action_and_shift_click() {
+        assert_eq!(maximize_action(MaximizeAction::Maximize, false, false), MaximizeAction::Maximize);
+        assert_eq!(maximize_action(MaximizeAction::Maximize, true, false), MaximizeAction::Fullscreen);
+        assert_eq!(maximize_action(MaximizeAction::Fullscreen, false, false), MaximizeAction::Fullscreen);
+        assert_eq!(maximize_action(MaximizeAction::Fullscreen, true, false), MaximizeAction::Maximize);
+    }
+
+    #[test]
+    fn maximize_button_restores_maximized_window() {
+        let mut window = Window::new(10, 40, 100, 50, 1, Rc::new(Config::default()));
+        window.resizable = true;
+        let area = Rect::new(0, 0, 800, 600);
+        let rect = window.tile(TilePosition::FullScreen, area);
+        window.configure(rect);
+
+        // clicking the button again restores it, whichever action it is set to or Shift
+        for (action, shift) in [(MaximizeAction::Maximize, true), (MaximizeAction::Fullscreen, false)] {
+            assert_eq!(maximize_action(action, shift, window.maximized()), MaximizeAction::Maximize);
+        }
+        assert_eq!(window.tile(TilePosition::FullScreen, area), Rect::new(10, 40, 100, 50));
+        assert!(!window.maximized());
+    }
+
+    #[test]
+    fn modifier_state_sets_any_bits() {
+        assert_eq!(modifier_state(SHIFT_LEFT_MODIFIER), SHIFT_LEFT_MODIFIER | SHIFT_ANY_MODIFIER);
+        assert_eq!(modifier_state(ALT_GR_MODIFIER), ALT_GR_MODIFIER | ALT_ANY_MODIFIER);
+        assert_eq!(modifier_state(SHIFT_ANY_MODIFIER | CONTROL_MODIFIER), CONTROL_MODIFIER);
+    }
+
+    #[test]
+    fn sticky_keys_latch_modifiers_when_enabled() {
+        let mut sticky = StickyKeys { enabled: true, ..StickyKeys::default() };
+        assert!(!sticky.press(modifier_bit(orbclient::K_CTRL)));
+        assert_eq!(sticky.latched, CONTROL_MODIFIER);
+        // pressing a latched modifier again unlatches it
+        sticky.press(modifier_bit(orbclient::K_CTRL));
+        assert_eq!(sticky.latched, 0);
+
+        let mut sticky = StickyKeys::default();
+        sticky.press(modifier_bit(orbclient::K_CTRL));
+        assert_eq!(sticky.latched, 0);
+    }
+
+    #[test]
+    fn five_shift_presses_toggle_sticky_keys() {
+        let mut sticky = StickyKeys::default();
+        for _ in 0..4 {
+            assert!(!sticky.press(modifier_bit(orbclient::K_LEFT_SHIFT)));
+        }
+        // another key in between starts the count again
+        sticky.press(modifier_bit(orbclient::K_A));
+        for _ in 0..4 {
+            assert!(!sticky.press(modifier_bit(orbclient::K_RIGHT_SHIFT)));
+        }
+        assert!(sticky.press(modifier_bit(orbclient::K_LEFT_SHIFT)));
+        assert!(sticky.enabled);
+        assert_eq!(sticky.latched, 0);
+
+        for _ in 0..5 {
+            sticky.press(modifier_bit(orbclient::K_LEFT_SHIFT));
+        }
+        assert!(!sticky.enabled);
+        assert_eq!(sticky.latched, 0);
+    }
+
+    #[test]
+    fn place_half_of_display() {
+        let area = Rect::new(0, 0, 1024, 768);
+        let (width, height) = percent_size(&area, 50, 50, 28);
+        assert_eq!((width, height), (512, 384));
+        assert_eq!(placement(&area, width, height, 28), (256, 192));
+
+        // on a second display
+        let area = Rect::new(1024, 0, 800, 600);
+        let (width, height) = percent_size(&area, 50, 50, 28);
+        assert_eq!(placement(&area, width, height, 28), (1224, 150));
+    }
+
+    #[test]
+    fn place_percentages_are_clamped() {
+        let area = Rect::new(0, 0, 1000, 800);
+        let (width, height) = percent_size(&area, 150, 100, 28);
+        // the title bar still fits above the window
+        assert_eq!((width, height), (1000, 772));
+        assert_eq!(placement(&area, width, height, 28), (0, 28));
+
+        assert_eq!(percent_size(&area, 0, -20, 28), (10, 8));
+    }
+
+    #[test]
+    fn reopened_window_is_maximized_again() {
+        let config = Rc::new(Config::default());
+        let screens = [Rect::new(0, 0, 1024, 768)];
+        let area = Rect::new(0, 28, 1024, 740);
+        let mut closed = Window::new(212, 184, 600, 400, 1, Rc::clone(&config));
+        closed.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        assert!(tile_new_window(&mut closed, TilePosition::FullScreen, &area).is_some());
+        let saved = SavedGeometry::of(&closed);
+        assert_eq!(saved, SavedGeometry { rect: Rect::new(212, 184, 600, 400), tile: Some(TilePosition::FullScreen) });
+
+        let mut window = Window::new(-1, -1, 600, 400, 1, Rc::clone(&config));
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        let tile = restore_geometry(&mut window, &saved, &screens);
+        assert_eq!(tile, Some(TilePosition::FullScreen));
+        assert_eq!(tile_new_window(&mut window, TilePosition::FullScreen, &area), Some((1024, 740)));
+        assert!(window.maximized());
+        // and is restored to where the closed window was before it was maximized
+        assert_eq!(window.restore.map(|(rect, _)| rect), Some(Rect::new(212, 184, 600, 400)));
+    }
+
+    #[test]
+    fn saved_geometry_keeps_the_most_recently_closed_titles() {
+        let geometry = SavedGeometry { rect: Rect::new(10, 20, 300, 200), tile: None };
+        let mut saved = VecDeque::new();
+        for index in 0..MAX_SAVED_GEOMETRY + 1 {
+            save_geometry(&mut saved, &format!("window {}", index), geometry);
+        }
+        assert_eq!(saved.len(), MAX_SAVED_GEOMETRY);
+        assert!(! saved.iter().any(|(title, _)| title == "window 0"));
+
+        // closing a window with a remembered title again replaces it and makes it the most recent
+        let moved = SavedGeometry { rect: Rect::new(40, 50, 300, 200), tile: None };
+        save_geometry(&mut saved, "window 1", moved);
+        assert_eq!(saved.len(), MAX_SAVED_GEOMETRY);
+        assert_eq!(saved.front(), Some(&("window 1".to_string(), moved)));
+        assert_eq!(saved.iter().filter(|(title, _)| title == "window 1").count(), 1);
+    }
+
+    #[test]
+    fn window_saved_on_a_display_that_is_gone_opens_on_the_first() {
+        let config = Rc::new(Config::default());
+        let saved = SavedGeometry { rect: Rect::new(1500, 300, 600, 400), tile: Some(TilePosition::LeftHalf) };
+        let mut window = Window::new(-1, -1, 600, 400, 1, config);
+        let tile = restore_geometry(&mut window, &saved, &[Rect::new(0, 0, 1024, 768)]);
+        assert_eq!((window.x, window.y), (0, 28));
+        // it is tiled again on that display
+        assert_eq!(tile, Some(TilePosition::LeftHalf));
+
+        let tile = restore_geometry(&mut window, &saved, &[Rect::new(0, 0, 1024, 768), Rect::new(1024, 0, 1920, 1080)]);
+        assert_eq!((window.x, window.y, tile), (1500, 300, Some(TilePosition::LeftHalf)));
+    }
+
+    #[test]
+    fn window_opened_maximized_fills_tile_area() {
+        let config = Rc::new(Config::default());
+        let area = Rect::new(0, 28, 1024, 740);
+        let mut window = Window::new(212, 184, 600, 400, 1, Rc::clone(&config));
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+
+        assert_eq!(tile_new_window(&mut window, TilePosition::FullScreen, &area), Some((1024, 740)));
+        assert_eq!((window.x, window.y), (0, 28));
+        assert!(window.maximized());
+        assert!(window.properties().flags.contains(ORBITAL_FLAG_MAXIMIZED));
+        // un-maximizing restores the placement the window would have had
+        assert_eq!(window.restore.map(|(rect, _)| (rect.left(), rect.top(), rect.width(), rect.height())), Some((212, 184, 600, 400)));
+
+        // a window that isn't resizable keeps its geometry, and has nothing to be restored to
+        let mut fixed = Window::new(212, 184, 600, 400, 1, config);
+        assert_eq!(tile_new_window(&mut fixed, TilePosition::FullScreen, &area), None);
+        assert!(!fixed.maximized());
+        assert_eq!((fixed.x, fixed.y, fixed.width(), fixed.height()), (212, 184, 600, 400));
+        assert_eq!(fixed.restore, None);
+        assert_eq!(tile_new_window(&mut fixed, TilePosition::LeftHalf, &area), None);
+        assert_eq!((fixed.x, fixed.y, fixed.width(), fixed.height()), (212, 184, 600, 400));
+    }
+
+    #[test]
+    fn placement_clears_the_reserved_panel() {
+        // a fixed size window on a 1024x768 display whose top 48 pixels are reserved by a panel
+        let config = Rc::new(Config::default());
+        let screen = Rect::new(0, 0, 1024, 768);
+        let area = work_area(&screen, 48);
+        assert_eq!(edges(area), (0, 48, 1024, 768));
+
+        let mut window = Window::new(-1, -1, 600, 680, 1, config);
+        window.resizable = false;
+        (window.x, window.y) = placement(&area, window.width(), window.height(), window.title_rect().height());
+        assert_eq!(window.title_rect().top(), 48);
+        assert!(window.rect().bottom() <= area.bottom());
+
+        // a reservation larger than the display leaves an empty work area, not a negative one
+        assert_eq!(edges(work_area(&screen, 1000)), (0, 768, 1024, 768));
+    }
+
+    #[test]
+    fn placement_keeps_window_inside_work_area() {
+        // a 1024x768 screen with a 48 pixel high panel reserved at the bottom
+        let area = Rect::new(0, 0, 1024, 768 - 48);
+        let (x, y) = placement(&area, 600, 680, 28);
+        assert_eq!((x, y), (212, 28));
+        assert!(y + 680 <= area.bottom());
+
+        // centered in an area that doesn't start at the origin, such as a second display
+        let area = Rect::new(1024, 100, 800, 600);
+        assert_eq!(placement(&area, 400, 300, 28), (1224, 250));
+    }
+
+    #[test]
+    fn compact_merges_adjacent_and_nearby_rects() {
+        // sharing an edge
+        let mut redraws = vec![Rect::new(0, 0, 10, 10), Rect::new(10, 0, 10, 10)];
+        compact(&mut redraws);
+        assert_eq!(redraws.len(), 1);
+        assert_eq!((redraws[0].left(), redraws[0].width(), redraws[0].height()), (0, 20, 10));
+
+        // separated by a small gap
+        let mut redraws = vec![Rect::new(0, 0, 10, 10), Rect::new(0, 13, 10, 10)];
+        compact(&mut redraws);
+        assert_eq!(redraws.len(), 1);
+        assert_eq!(redraws[0].height(), 23);
+
+        // overlapping
+        let mut redraws = vec![Rect::new(0, 0, 10, 10), Rect::new(5, 5, 10, 10)];
+        compact(&mut redraws);
+        assert_eq!(redraws.len(), 1);
+    }
+
+    #[test]
+    fn redraws_cover_all_of_a_blurred_window() {
+        let blurred = [Rect::new(100, 100, 200, 100), Rect::new(280, 150, 100, 100)];
+        let mut redraws = vec![Rect::new(50, 50, 100, 100), Rect::new(0, 0, 20, 20), Rect::new(120, 120, 10, 10)];
+        cover_blurred(&mut redraws, &blurred);
+        // the first window reaches into the second, which is covered too
+        assert_eq!(redraws[0], Rect::new(50, 50, 330, 200));
+        assert_eq!(redraws[1], Rect::new(0, 0, 20, 20));
+        assert_eq!(redraws[2], Rect::new(100, 100, 280, 150));
+    }
+
+    #[test]
+    fn compact_keeps_distant_rects_apart() {
+        let mut redraws = vec![Rect::new(0, 0, 10, 10), Rect::new(500, 500, 10, 10), Rect::new(0, 0, 0, 0)];
+        compact(&mut redraws);
+        assert_eq!(redraws.len(), 2);
+        assert_eq!(redraws.iter().map(Rect::area).sum::<i32>(), 200);
+    }
+
+    #[test]
+    fn damage_all_covers_every_screen_once() {
+        let screens = [Rect::new(0, 0, 800, 600), Rect::new(800, 0, 1024, 768)];
+        let mut redraws = vec![Rect::new(10, 10, 20, 20), Rect::new(900, 700, 50, 50)];
+        damage_all(&mut redraws, &screens);
+        assert_eq!(redraws, screens);
+
+        // a redraw across both screens is still covered by them
+        let mut redraws = vec![Rect::new(790, 10, 20, 20)];
+        damage_all(&mut redraws, &screens);
+        for screen in screens {
+            assert!(redraws.iter().any(|redraw| redraw.container(&screen) == *redraw));
+        }
+    }
+
+    #[test]
+    fn compact_scattered_pixels() {
+        // 200 single pixel redraws, in clusters of 20 along a row every 64 pixels
+        let mut redraws = Vec::new();
+        for cluster in 0..10 {
+            for i in 0..20 {
+                redraws.push(Rect::new(cluster * 64 + i * 2, cluster * 64, 1, 1));
+            }
+        }
+        let original = redraws.clone();
+
+        compact(&mut redraws);
+        assert_eq!(redraws.len(), 10);
+        for rect in original {
+            assert!(redraws.iter().any(|redraw| redraw.contains(rect.left(), rect.top())));
+        }
+        assert!(redraws.iter().map(Rect::area).sum::<i32>() <= 10 * 39);
+    }
+
+    #[test]
+    fn toggling_front_moves_window_between_zorder_classes() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        let mut pinned = Window::new(0, 0, 100, 100, 1, Rc::clone(&config));
+        pinned.zorder = WindowZOrder::Front;
+        windows.insert(1, pinned);
+        windows.insert(2, Window::new(0, 0, 100, 100, 1, Rc::clone(&config)));
+        let mut desktop = Window::new(0, 0, 100, 100, 1, Rc::clone(&config));
+        desktop.zorder = WindowZOrder::Back;
+        windows.insert(3, desktop);
+        windows.insert(4, Window::new(0, 0, 100, 100, 1, Rc::clone(&config)));
+        let order: VecDeque<usize> = [4, 2, 3, 1].into();
+        assert_eq!(zbuffer_ids(&zbuffer(&order, &windows)), [1, 4, 2, 3]);
+
+        let toggle = |windows: &mut BTreeMap<usize, Window>, id| {
+            windows.get_mut(&id).and_then(Window::toggle_front)
+        };
+        assert_eq!(toggle(&mut windows, 4), Some(true));
+        assert_eq!(zbuffer(&order, &windows)[0], (4, WindowZOrder::Front, 0));
+        assert_eq!(zbuffer_ids(&zbuffer(&order, &windows)), [4, 1, 2, 3]);
+
+        assert_eq!(toggle(&mut windows, 4), Some(false));
+        assert_eq!(zbuffer_ids(&zbuffer(&order, &windows)), [1, 4, 2, 3]);
+        assert_eq!(toggle(&mut windows, 3), None);
+        assert_eq!(windows[&3].zorder, WindowZOrder::Back);
+    }
+
+    #[test]
+    fn peek_raises_window_without_changing_order() {
+        let mut zbuffer = vec![
+            (1, WindowZOrder::Front, 2),
+            (2, WindowZOrder::Normal, 0),
+            (3, WindowZOrder::Normal, 1),
+            (4, WindowZOrder::Back, 3),
+        ];
+        raise_in_zbuffer(&mut zbuffer, 3);
+        assert_eq!(zbuffer.iter().map(|entry| entry.0).collect::<Vec<_>>(), vec![3, 1, 2, 4]);
+        // the order index, used to draw the focused window, is kept
+        assert_eq!(zbuffer[0], (3, WindowZOrder::Normal, 1));
+
+        raise_in_zbuffer(&mut zbuffer, 5);
+        assert_eq!(zbuffer.len(), 4);
+    }
+
+    fn zbuffer_ids(zbuffer: &[(usize, WindowZOrder, usize)]) -> Vec<usize> {
+        zbuffer.iter().map(|entry| entry.0).collect()
+    }
+
+    fn test_zbuffer() -> Vec<(usize, WindowZOrder, usize)> {
+        vec![
+            (1, WindowZOrder::Front, 0),
+            (2, WindowZOrder::Normal, 1),
+            (3, WindowZOrder::Normal, 2),
+            (4, WindowZOrder::Back, 3),
+        ]
+    }
+
+    #[test]
+    fn stack_above_and_below_another_window() {
+        let mut zbuffer = test_zbuffer();
+        let relations = BTreeMap::from([(3, StackRelation::Above(2))]);
+        stack_zbuffer(&mut zbuffer, &relations);
+        assert_eq!(zbuffer_ids(&zbuffer), vec![1, 3, 2, 4]);
+
+        let mut zbuffer = test_zbuffer();
+        let relations = BTreeMap::from([(1, StackRelation::Below(3))]);
+        stack_zbuffer(&mut zbuffer, &relations);
+        assert_eq!(zbuffer_ids(&zbuffer), vec![2, 3, 1, 4]);
+    }
+
+    #[test]
+    fn stack_chain_resolved_targets_first() {
+        // 1 above 2, which is below 4: 2 is placed first, then 1 directly above it
+        let mut zbuffer = test_zbuffer();
+        let relations = BTreeMap::from([(1, StackRelation::Above(2)), (2, StackRelation::Below(4))]);
+        stack_zbuffer(&mut zbuffer, &relations);
+        assert_eq!(zbuffer_ids(&zbuffer), vec![3, 4, 1, 2]);
+    }
+
+    #[test]
+    fn stack_ignores_dangling_relations_and_rejects_cycles() {
+        let mut zbuffer = test_zbuffer();
+        let relations = BTreeMap::from([(3, StackRelation::Above(7))]);
+        stack_zbuffer(&mut zbuffer, &relations);
+        assert_eq!(zbuffer_ids(&zbuffer), vec![1, 2, 3, 4]);
+
+        let relations = BTreeMap::from([(1, StackRelation::Above(2)), (2, StackRelation::Below(3))]);
+        assert!(stack_cycle(&relations, 3, 1));
+        assert!(stack_cycle(&relations, 3, 3));
+        assert!(!stack_cycle(&relations, 4, 1));
+    }
+
+    #[test]
+    fn resize_debounce_disabled_by_default() {
+        let mut debounce = ResizeDebounce::default();
+        let now = Instant::now();
+        assert_eq!(debounce.resize(now, 1, 100, 100), Some((100, 100)));
+        assert_eq!(debounce.resize(now, 1, 110, 100), Some((110, 100)));
+        assert_eq!(debounce.finish(), None);
+    }
+
+    #[test]
+    fn resize_debounce_sends_final_size_on_release() {
+        let mut debounce = ResizeDebounce { interval: Duration::from_millis(50), ..ResizeDebounce::default() };
+        let start = Instant::now();
+        assert_eq!(debounce.resize(start, 1, 100, 100), Some((100, 100)));
+        assert_eq!(debounce.resize(start + Duration::from_millis(10), 1, 110, 100), None);
+        assert_eq!(debounce.resize(start + Duration::from_millis(20), 1, 120, 100), None);
+        assert_eq!(debounce.resize(start + Duration::from_millis(60), 1, 130, 100), Some((130, 100)));
+        assert_eq!(debounce.resize(start + Duration::from_millis(70), 1, 140, 100), None);
+        assert_eq!(debounce.finish(), Some((1, 140, 100)));
+
+        // the next drag starts without waiting
+        assert_eq!(debounce.resize(start + Duration::from_millis(80), 1, 150, 100), Some((150, 100)));
+    }
+
+    #[test]
+    fn clicking_no_focus_window_keeps_focus() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        windows.insert(1, Window::new(0, 0, 100, 100, 1, Rc::clone(&config)));
+        windows.insert(2, Window::new(50, 50, 100, 100, 1, Rc::clone(&config)));
+        let mut dock = Window::new(0, 500, 800, 48, 1, Rc::clone(&config));
+        dock.set_flag(ORBITAL_FLAG_NO_FOCUS, true);
+        windows.insert(3, dock);
+        let order = VecDeque::from([1, 3, 2]);
+
+        // the focused front window stays focused when the dock is clicked
+        assert_eq!(click_focus(&order, &windows, 1), None);
+        // other windows are brought to the front
+        assert_eq!(click_focus(&order, &windows, 2), Some(2));
+        assert_eq!(click_focus(&order, &windows, 0), None);
+        assert!(windows[&3].properties().flags.contains(ORBITAL_FLAG_NO_FOCUS));
+    }
+
+    #[test]
+    fn clipboard_write_stops_at_the_limit() {
+        let (mut clipboard, mut seek) = (Vec::new(), 0);
+        assert_eq!(clipboard_write(&mut clipboard, &mut seek, b"hello", 8).ok(), Some(5));
+        // cut short at the limit, the client sees the short write
+        assert_eq!(clipboard_write(&mut clipboard, &mut seek, b" world", 8).ok(), Some(3));
+        assert_eq!((clipboard.as_slice(), seek), (&b"hello wo"[..], 8));
+        // full, further writes fail
+        assert_eq!(clipboard_write(&mut clipboard, &mut seek, b"!", 8).map_err(|err| err.errno), Err(ENOSPC));
+        assert_eq!(clipboard_write(&mut clipboard, &mut seek, b"", 8).ok(), Some(0));
+
+        // writing from the start again replaces the contents
+        seek = 0;
+        assert_eq!(clipboard_write(&mut clipboard, &mut seek, b"bye", 8).ok(), Some(3));
+        assert_eq!(clipboard, b"bye");
+    }
+
+    #[test]
+    fn tab_group_activates_neighbor_of_closed_tab() {
+        let mut group = TabGroup::new(1);
+        group.tabs.extend([2, 3]);
+        group.active = 2;
+
+        // an inactive tab leaves without changing the active one
+        assert_eq!(group.remove(1), None);
+        assert_eq!((group.tabs.as_slice(), group.active), (&[2, 3][..], 2));
+        // the active tab is replaced by the one after it, or before it when it was the last
+        group.tabs.push(4);
+        assert_eq!(group.remove(2), Some(3));
+        assert_eq!(group.remove(4), None);
+        group.active = 5;
+        group.tabs.push(5);
+        assert_eq!(group.remove(5), Some(3));
+        assert_eq!(group.tabs, [3]);
+        // the last tab leaves nothing to activate
+        assert_eq!(group.remove(3), None);
+        assert_eq!(group.remove(3), None);
+    }
+
+    #[test]
+    fn tabs_share_title_bar_left_of_buttons() {
+        let area = tab_area(&Rect::new(100, 72, 336, 28), 1);
+        assert_eq!(edges(area), (100, 72, 400, 100));
+        let tabs = tab_rects(&area, 3);
+        assert_eq!(tabs.iter().map(|tab| edges(*tab)).collect::<Vec<_>>(), [
+            (100, 72, 200, 100),
+            (200, 72, 300, 100),
+            (300, 72, 400, 100),
+        ]);
+        // uneven widths leave no gaps
+        let tabs = tab_rects(&Rect::new(0, 0, 10, 28), 3);
+        assert_eq!(tabs.iter().map(|tab| tab.width()).sum::<i32>(), 10);
+        assert_eq!(tabs[2].right(), 10);
+
+        assert!(tab_area(&Rect::new(0, 0, 20, 28), 1).is_empty());
+    }
+
+    #[test]
+    fn smooth_cursor_eases_out_to_the_pointer() {
+        let mut drawn = (0, 100);
+        let mut frames = Vec::new();
+        while drawn != (100, 90) {
+            drawn = smooth_cursor_step(drawn, (100, 90));
+            frames.push(drawn.0);
+        }
+        // the larger steps come first, and the pointer is reached in a few frames
+        assert_eq!(frames, [50, 75, 87, 93, 96, 98, 99, 100]);
+        assert_eq!(smooth_cursor_step((0, 100), (100, 90)).1, 95);
+        assert_eq!(smooth_cursor_step((10, 10), (11, 9)), (11, 9));
+    }
+
+    #[test]
+    fn window_at_finds_topmost_window() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        windows.insert(1, Window::new(0, 30, 200, 200, 1, Rc::clone(&config)));
+        windows.insert(2, Window::new(100, 130, 200, 200, 1, Rc::clone(&config)));
+        let zbuffer = vec![(2, WindowZOrder::Normal, 0), (1, WindowZOrder::Normal, 1)];
+
+        // a point over only one window, including its title bar
+        assert_eq!(window_at(&zbuffer, &windows, 50, 50), Some(1));
+        assert_eq!(window_at(&zbuffer, &windows, 50, 10), Some(1));
+        // where they overlap the one in front wins
+        assert_eq!(window_at(&zbuffer, &windows, 150, 150), Some(2));
+        let zbuffer = vec![(1, WindowZOrder::Normal, 0), (2, WindowZOrder::Normal, 1)];
+        assert_eq!(window_at(&zbuffer, &windows, 150, 150), Some(1));
+        // the background
+        assert_eq!(window_at(&zbuffer, &windows, 500, 500), None);
+    }
+
+    #[test]
+    fn click_passes_through_input_transparent_window() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        windows.insert(1, Window::new(0, 0, 100, 100, 1, Rc::clone(&config)));
+        let mut overlay = Window::new(0, 0, 800, 600, 1, Rc::clone(&config));
+        overlay.set_flag(ORBITAL_FLAG_INPUT_TRANSPARENT, true);
+        windows.insert(2, overlay);
+        let zbuffer = vec![(2, WindowZOrder::Front, 0), (1, WindowZOrder::Normal, 1)];
+
+        assert_eq!(input_target(&zbuffer, &windows, 50, 50), Some((1, 1, WindowRegion::Content)));
+        // nothing below the overlay here
+        assert_eq!(input_target(&zbuffer, &windows, 400, 400), None);
+
+        assert!(!windows[&2].focusable());
+        assert!(windows[&2].properties().flags.contains(ORBITAL_FLAG_INPUT_TRANSPARENT));
+
+        windows.entry(2).and_modify(|window| window.set_flag(ORBITAL_FLAG_INPUT_TRANSPARENT, false));
+        assert_eq!(input_target(&zbuffer, &windows, 50, 50), Some((2, 0, WindowRegion::Content)));
+    }
+
+    #[test]
+    fn opaque_window_covering_display_is_scanned_out() {
+        let config = Rc::new(Config::default());
+        let screen = Rect::new(0, 0, 800, 600);
+        let mut windows = BTreeMap::new();
+        let mut game = Window::new(0, 0, 800, 600, 1, Rc::clone(&config));
+        game.set_flag(ORBITAL_FLAG_BORDERLESS, true);
+        game.painted = true;
+        windows.insert(1, game);
+        windows.insert(2, Window::new(900, 100, 200, 200, 1, Rc::clone(&config)));
+        let zbuffer = vec![(2, WindowZOrder::Normal, 0), (1, WindowZOrder::Normal, 1)];
+        assert_eq!(scanout_window(&zbuffer, &windows, &screen, 0), Some(1));
+        // dimmed while unfocused, the dim has to be composited over it
+        assert_eq!(scanout_window(&zbuffer, &windows, &screen, 64), None);
+
+        // a window over it on the same display
+        if let Some(window) = windows.get_mut(&2) {
+            window.x = 700;
+        }
+        assert_eq!(scanout_window(&zbuffer, &windows, &screen, 0), None);
+        let zbuffer = vec![(1, WindowZOrder::Normal, 0), (2, WindowZOrder::Normal, 1)];
+        assert_eq!(scanout_window(&zbuffer, &windows, &screen, 64), Some(1));
+
+        // what is below a transparent or faded window shows through, and an unpainted one gets a
+        // spinner
+        let changes: [fn(&mut Window); 3] = [
+            |window| window.set_flag(ORBITAL_FLAG_TRANSPARENT, true),
+            |window| window.opacity = 128,
+            |window| window.painted = false,
+        ];
+        for change in changes {
+            let mut windows = BTreeMap::new();
+            let mut game = Window::new(0, 0, 800, 600, 1, Rc::clone(&config));
+            game.painted = true;
+            change(&mut game);
+            windows.insert(1, game);
+            assert_eq!(scanout_window(&[(1, WindowZOrder::Normal, 0)], &windows, &screen, 0), None);
+        }
+
+        // one not covering all of the display
+        let mut windows = BTreeMap::new();
+        let mut window = Window::new(0, 10, 800, 590, 1, Rc::clone(&config));
+        window.painted = true;
+        windows.insert(1, window);
+        assert_eq!(scanout_window(&[(1, WindowZOrder::Normal, 0)], &windows, &screen, 0), None);
+    }
+
+    #[test]
+    fn click_outside_input_shape_reaches_window_below() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        windows.insert(1, Window::new(0, 30, 200, 200, 1, Rc::clone(&config)));
+        // a rounded widget drawing its content inset by 8 pixels in a transparent window
+        let mut widget = Window::new(50, 50, 100, 100, 1, Rc::clone(&config));
+        widget.set_flag(ORBITAL_FLAG_TRANSPARENT, true);
+        widget.set_flag(ORBITAL_FLAG_BORDERLESS, true);
+        widget.input_shape = vec![Rect::new(8, 8, 84, 84)];
+        windows.insert(2, widget);
+        let zbuffer = vec![(2, WindowZOrder::Normal, 0), (1, WindowZOrder::Normal, 1)];
+
+        // the transparent corner goes to the window below, the content to the widget
+        assert_eq!(input_target(&zbuffer, &windows, 52, 52), Some((1, 1, WindowRegion::Content)));
+        assert_eq!(input_target(&zbuffer, &windows, 60, 60), Some((2, 0, WindowRegion::Content)));
+        assert_eq!(input_target(&zbuffer, &windows, 147, 100), Some((1, 1, WindowRegion::Content)));
+
+        // without a shape the whole window takes input
+        if let Some(widget) = windows.get_mut(&2) {
+            widget.input_shape.clear();
+        }
+        assert_eq!(input_target(&zbuffer, &windows, 52, 52), Some((2, 0, WindowRegion::Content)));
+    }
+
+    #[test]
+    fn relative_mode_cursor_hidden_unless_asked_for() {
+        let mut window = Window::new(0, 0, 100, 100, 1, Rc::new(Config::default()));
+        window.mouse_relative = true;
+        assert_eq!(relative_cursor(&window), CursorKind::None);
+
+        window.mouse_relative_cursor = true;
+        assert_eq!(relative_cursor(&window), CursorKind::LeftPtr);
+
+        // a window that hid the cursor keeps it hidden
+        window.mouse_cursor = false;
+        assert_eq!(relative_cursor(&window), CursorKind::None);
+    }
+
+    #[test]
+    fn enlarged_corner_starts_corner_drag() {
+        let config = Rc::new(Config {
+            corner_grab_size: 24,
+            ..Config::default()
+        });
+        let mut window = Window::new(100, 100, 200, 200, 1, config);
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        let mut windows = BTreeMap::new();
+        windows.insert(1, window);
+        let zbuffer = vec![(1, WindowZOrder::Normal, 0)];
+
+        // on the bottom border, but within the corner handle that extends over it
+        let (x, y) = (290, 303);
+        let target = input_target(&zbuffer, &windows, x, y);
+        assert_eq!(target, Some((1, 0, WindowRegion::BottomRightCorner)));
+        assert_eq!(border_drag(&windows[&1], 1, WindowRegion::BottomRightCorner, x, y), DragMode::BottomRightBorder(1, -10, 3));
+
+        // further along the bottom border is a side drag
+        assert_eq!(input_target(&zbuffer, &windows, 260, 303), Some((1, 0, WindowRegion::BottomBorder)));
+        // and the corner handle doesn't cover the window contents
+        assert_eq!(input_target(&zbuffer, &windows, 290, 290), Some((1, 0, WindowRegion::Content)));
+        assert_eq!(input_target(&zbuffer, &windows, 303, 290), Some((1, 0, WindowRegion::BottomRightCorner)));
+        assert_eq!(input_target(&zbuffer, &windows, 303, 270), Some((1, 0, WindowRegion::RightBorder)));
+    }
+
+    #[test]
+    fn grabbed_combo_goes_to_grabbing_window() {
+        let mut grabs = KeyGrabs::default();
+        let combo = KeyCombo { scancode: orbclient::K_P, ctrl: true, alt: true, ..KeyCombo::default() };
+        assert!(grabs.grab(combo, 2).is_ok());
+
+        // window 2 gets the press and the release, whichever window is focused
+        let state = modifier_state(CONTROL_MODIFIER | ALT_MODIFIER);
+        assert_eq!(grabs.route(orbclient::K_P, true, state), Some(2));
+        assert_eq!(grabs.route(orbclient::K_P, false, 0), Some(2));
+        // other combinations go to the focused window as usual
+        assert_eq!(grabs.route(orbclient::K_P, true, modifier_state(CONTROL_MODIFIER)), None);
+        assert_eq!(grabs.route(orbclient::K_P, false, 0), None);
+
+        // AltGr is for typing, not for grabs with Alt
+        assert_eq!(grabs.route(orbclient::K_P, true, modifier_state(CONTROL_MODIFIER | ALT_GR_MODIFIER)), None);
+        assert_eq!(grabs.route(orbclient::K_P, false, 0), None);
+
+        grabs.release_window(2);
+        assert_eq!(grabs.route(orbclient::K_P, true, state), None);
+    }
+
+    #[test]
+    fn grabs_are_limited() {
+        let mut grabs = KeyGrabs::default();
+        let ctrl_q = KeyCombo { scancode: orbclient::K_Q, ctrl: true, ..KeyCombo::default() };
+        assert!(grabs.grab(ctrl_q, 1).is_ok());
+        assert!(grabs.grab(ctrl_q, 1).is_ok());
+        // one window per combination
+        assert!(grabs.grab(ctrl_q, 2).is_err());
+        // combinations used for typing
+        assert!(grabs.grab(KeyCombo { scancode: orbclient::K_A, ..KeyCombo::default() }, 2).is_err());
+        assert!(grabs.grab(KeyCombo { scancode: orbclient::K_A, shift: true, ..KeyCombo::default() }, 2).is_err());
+        assert!(grabs.grab(KeyCombo { scancode: orbclient::K_F5, ..KeyCombo::default() }, 2).is_ok());
+        // built-in shortcuts and modifier keys
+        assert!(grabs.grab(KeyCombo { scancode: orbclient::K_Q, ctrl: true, super_key: true, ..KeyCombo::default() }, 2).is_err());
+        assert!(grabs.grab(KeyCombo { scancode: orbclient::K_VOLUME_UP, ctrl: true, ..KeyCombo::default() }, 2).is_err());
+        assert!(grabs.grab(KeyCombo { scancode: orbclient::K_LEFT_SHIFT, ctrl: true, ..KeyCombo::default() }, 2).is_err());
+
+        // the Super key is never routed to a grab
+        assert_eq!(grabs.route(orbclient::K_Q, true, modifier_state(CONTROL_MODIFIER | SUPER_MODIFIER)), None);
+    }
+
+    fn shown(toasts: &Toasts) -> Vec<&str> {
+        toasts.visible().map(|toast| toast.text.as_str()).collect()
+    }
+
+    #[test]
+    fn toasts_are_dismissed_after_duration() {
+        let start = Instant::now();
+        let duration = Duration::from_millis(4000);
+        let mut toasts = Toasts::default();
+        assert!(toasts.push("first".to_string()));
+        assert!(toasts.update(start, duration, 2));
+        assert_eq!(shown(&toasts), vec!["first"]);
+        assert_eq!(toasts.next_expiry(), Some(start + duration));
+
+        let later = start + Duration::from_millis(1000);
+        assert!(toasts.push("second".to_string()));
+        assert!(toasts.push("third".to_string()));
+        assert!(toasts.update(later, duration, 2));
+        // only two are shown at once, the third waits
+        assert_eq!(shown(&toasts), vec!["first", "second"]);
+        assert!(!toasts.update(later + Duration::from_millis(10), duration, 2));
+
+        // the third is shown once the first is dismissed, with its own duration
+        let dismissed = start + duration;
+        assert!(toasts.update(dismissed, duration, 2));
+        assert_eq!(shown(&toasts), vec!["second", "third"]);
+        assert_eq!(toasts.next_expiry(), Some(later + duration));
+
+        assert!(toasts.update(dismissed + duration, duration, 2));
+        assert!(shown(&toasts).is_empty());
+        assert_eq!(toasts.next_expiry(), None);
+    }
+
+    #[test]
+    fn toast_queue_is_limited() {
+        let mut toasts = Toasts::default();
+        for i in 0..16 {
+            assert!(toasts.push(i.to_string()));
+        }
+        assert!(!toasts.push("one too many".to_string()));
+    }
+
+    #[test]
+    fn toasts_stack_from_corner() {
+        let screen = Rect::new(0, 0, 1024, 768);
+        let sizes = [(200, 28), (100, 28)];
+
+        let rects = toast_rects(&screen, Anchor::TopRight, &sizes);
+        assert_eq!((rects[0].left(), rects[0].top(), rects[0].right()), (816, 8, 1016));
+        assert_eq!((rects[1].left(), rects[1].top(), rects[1].right()), (916, 44, 1016));
+
+        let rects = toast_rects(&screen, Anchor::BottomLeft, &sizes);
+        assert_eq!((rects[0].left(), rects[0].bottom()), (8, 760));
+        assert_eq!((rects[1].left(), rects[1].bottom()), (8, 724));
+    }
+
+    #[test]
+    fn batch_defers_until_committed_by_its_owner() {
+        let start = Instant::now();
+        let mut batch = None;
+        assert!(!batch_active(&mut batch, start));
+
+        assert!(begin_batch(&mut batch, 1, start).is_ok());
+        assert!(batch_active(&mut batch, start + Duration::from_millis(10)));
+        // only one batch at a time, and only its owner commits it
+        assert!(begin_batch(&mut batch, 2, start).is_err());
+        assert!(end_batch(&mut batch, 2).is_err());
+        assert!(end_batch(&mut batch, 1).is_ok());
+        assert!(!batch_active(&mut batch, start + Duration::from_millis(10)));
+        assert!(end_batch(&mut batch, 1).is_err());
+    }
+
+    #[test]
+    fn batch_times_out() {
+        let start = Instant::now();
+        let mut batch = None;
+        assert!(begin_batch(&mut batch, 1, start).is_ok());
+        assert!(!batch_active(&mut batch, start + BATCH_TIMEOUT));
+        assert_eq!(batch, None);
+
+        // beginning it again doesn't put off the timeout
+        let mut batch = None;
+        assert!(begin_batch(&mut batch, 1, start).is_ok());
+        assert!(begin_batch(&mut batch, 1, start + Duration::from_millis(90)).is_ok());
+        assert!(!batch_active(&mut batch, start + BATCH_TIMEOUT));
+
+        // an expired batch doesn't block others
+        let mut batch = None;
+        assert!(begin_batch(&mut batch, 1, start).is_ok());
+        assert!(begin_batch(&mut batch, 2, start + BATCH_TIMEOUT).is_ok());
+    }
+
+    #[test]
+    fn ui_font_loads_configured_style() {
+        let config = Config {
+            ui_font: "Serif".to_string(),
+            ui_font_bold: true,
+            ui_font_italic: true,
+            ..Config::default()
+        };
+        let font = find_ui_font(&config, |typeface, style| Ok(format!("{}/{}", typeface, style)), || Ok("builtin".to_string()));
+        assert_eq!(font, Ok("Serif/BoldItalic".to_string()));
+    }
+
+    #[test]
+    fn missing_ui_font_falls_back_to_installed_fonts() {
+        let config = Config {
+            ui_font: "Missing".to_string(),
+            ui_font_bold: true,
+            ..Config::default()
+        };
+        let tried = RefCell::new(Vec::new());
+        let font = find_ui_font(&config, |typeface, style| {
+            tried.borrow_mut().push(format!("{}/{}", typeface, style));
+            if typeface == "Mono" { Ok("Mono") } else { Err("not found".to_string()) }
+        }, || Ok("builtin"));
+        assert_eq!(font, Ok("Mono"));
+        assert_eq!(tried.into_inner(), ["Missing/Bold", "Sans/Regular", "Mono/Regular"]);
+    }
+
+    #[test]
+    fn rescaled_window_gets_new_decorations() {
+        let font = orbfont::Font::from_data(BUILTIN_UI_FONT).unwrap_or_else(|err| panic!("{}", err));
+        let mut window = Window::new(100, 100, 300, 200, 1, Rc::new(Config::default()));
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        assert_eq!(window.title_rect().height(), 28);
+        assert_eq!(window.right_border_rect().width(), 8);
+
+        let mut redraws = Vec::new();
+        rescale_window(&mut redraws, &mut window, 2, &font);
+        assert_eq!(edges(window.title_rect()), (100, 44, 400, 100));
+        assert_eq!(window.right_border_rect().width(), 16);
+        // the old and new title bars are redrawn
+        assert!(redraws.iter().any(|rect| rect.contains(100, 72)));
+        assert!(redraws.iter().any(|rect| rect.contains(100, 44)));
+
+        // the same scale again changes nothing
+        let mut redraws = Vec::new();
+        rescale_window(&mut redraws, &mut window, 2, &font);
+        assert!(redraws.is_empty());
+    }
+
+    #[test]
+    fn window_moved_to_a_display_of_another_scale_is_rescaled() {
+        let font = orbfont::Font::from_data(BUILTIN_UI_FONT).unwrap_or_else(|err| panic!("{}", err));
+        let screens = [(Rect::new(0, 0, 1024, 768), 1), (Rect::new(1024, 0, 3840, 2160), 2)];
+        let mut window = Window::new(100, 100, 300, 200, 1, Rc::new(Config::default()));
+        assert_eq!(display_scale_of(&screens, window.rect()), 1);
+
+        // still mostly on the first display
+        window.x = 800;
+        let mut redraws = Vec::new();
+        let scale = display_scale_of(&screens, window.rect());
+        rescale_window(&mut redraws, &mut window, scale, &font);
+        assert_eq!(window.scale, 1);
+        assert!(window.events.is_empty());
+
+        window.x = 950;
+        let scale = display_scale_of(&screens, window.rect());
+        rescale_window(&mut redraws, &mut window, scale, &font);
+        assert_eq!(window.scale, 2);
+        assert_eq!(window.title_rect().height(), 56);
+        let events: Vec<(i64, i64)> = window.events.iter().map(|event| (event.code, event.a)).collect();
+        assert_eq!(events, [(ORBITAL_EVENT_SCALE, 2)]);
+
+        // off every display
+        assert_eq!(display_scale_of(&screens, Rect::new(-500, 0, 100, 100)), 1);
+    }
+
+    #[test]
+    fn no_installed_font_falls_back_to_builtin() {
+        let tried = RefCell::new(Vec::new());
+        let font = find_ui_font(&Config::default(), |typeface, style| {
+            tried.borrow_mut().push(format!("{}/{}", typeface, style));
+            Err::<orbfont::Font, _>("not found".to_string())
+        }, || orbfont::Font::from_data(BUILTIN_UI_FONT));
+        // the configured Sans isn't tried twice
+        assert_eq!(tried.into_inner(), ["Sans/Regular", "Mono/Regular"]);
+
+        let text = match font {
+            Ok(ref font) => font.render("Orbital", 16.0),
+            Err(err) => panic!("built-in font failed to load: {}", err),
+        };
+        assert!(text.width() > 0 && text.height() > 0);
+    }
+
+    #[test]
+    fn inhibitor_keeps_the_screen_from_blanking() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(60);
+        let mut idle = IdleBlank::new(start);
+        idle.inhibit(3, true);
+
+        // well past the timeout nothing blanks while the window inhibits it
+        assert_eq!(idle.deadline(timeout), None);
+        assert!(!idle.check(start + timeout * 2, timeout));
+        assert!(!idle.blanked);
+
+        // once released the idle time since the last input counts again
+        idle.inhibit(3, false);
+        assert_eq!(idle.deadline(timeout), Some(start + timeout));
+        assert!(idle.check(start + timeout * 2, timeout));
+        assert!(idle.blanked);
+
+        // input unblanks and restarts the idle time
+        let later = start + timeout * 3;
+        assert!(idle.input(later));
+        assert!(!idle.input(later));
+        assert!(!idle.check(later + timeout / 2, timeout));
+        assert_eq!(idle.deadline(timeout), Some(later + timeout));
+        // a zero timeout never blanks
+        assert_eq!(idle.deadline(Duration::ZERO), None);
+    }
+
+    #[test]
+    fn highlight_ring_surrounds_the_pointer() {
+        let ring = highlight_ring(100, 50, 1);
+        let area = highlight_rect(100, 50, 1);
+        assert_eq!(edges(area), (76, 26, 125, 75));
+        assert!(ring.iter().all(|span| edges(span.intersection(&area)) == edges(*span)));
+
+        // the top row is a single span over the middle
+        assert_eq!(edges(ring[0]), (100, 26, 101, 27));
+        // the middle row is the ring's thickness on each side, leaving the pointer uncovered
+        let middle: Vec<_> = ring.iter().filter(|span| span.top() == 50).map(|span| edges(*span)).collect();
+        assert_eq!(middle, [(76, 50, 79, 51), (122, 50, 125, 51)]);
+        assert!(!ring.iter().any(|span| span.contains(100, 50)));
+
+        assert_eq!(edges(highlight_rect(100, 50, 2)), (52, 2, 149, 99));
+    }
+
+    #[test]
+    fn panel_buttons_share_the_left_half() {
+        let panel = Rect::new(0, 0, 1024, 28);
+        assert!(panel_buttons(&panel, 1, 0).is_empty());
+
+        let buttons = panel_buttons(&panel, 1, 2);
+        assert_eq!(edges(buttons[0]), (4, 4, 164, 24));
+        assert_eq!(edges(buttons[1]), (168, 4, 328, 24));
+
+        // too many windows for full width buttons, they shrink to stay in the left half
+        let buttons = panel_buttons(&panel, 1, 10);
+        assert_eq!(edges(buttons[0]), (4, 4, 50, 24));
+        assert!(buttons[9].right() <= panel.width() / 2);
+
+        // on a second display the buttons follow the panel
+        let scaled = panel_buttons(&Rect::new(1024, 0, 2048, 56), 2, 1);
+        assert_eq!(edges(scaled[0]), (1032, 8, 1352, 48));
+    }
+
+    #[test]
+    fn panel_clock_shows_utc_time() {
+        assert_eq!(panel_clock(Duration::ZERO), "00:00");
+        assert_eq!(panel_clock(Duration::from_secs(((48 + 13) * 60 + 7) * 60 + 59)), "13:07");
+        assert_eq!(panel_clock(Duration::from_secs(24 * 60 * 60 - 1)), "23:59");
+    }
+
+    #[test]
+    fn double_click_needs_two_quick_presses_on_one_window() {
+        let start = Instant::now();
+        let mut last = None;
+        assert!(!double_click(&mut last, 1, start));
+        assert!(double_click(&mut last, 1, start + Duration::from_millis(200)));
+        // the press completing a double-click doesn't start the next one
+        assert!(!double_click(&mut last, 1, start + Duration::from_millis(300)));
+
+        // too slow, or on another window
+        assert!(!double_click(&mut last, 1, start + Duration::from_secs(1)));
+        assert!(!double_click(&mut last, 2, start + Duration::from_millis(1100)));
+        assert!(double_click(&mut last, 2, start + Duration::from_millis(1200)));
+    }
+
+    #[test]
+    fn spinner_dots_circle_the_center() {
+        let dots = spinner_dots(&Rect::new(100, 100, 200, 100), 1);
+        assert_eq!(dots.len(), SPINNER_DOTS);
+        // clockwise from the top, around (200, 150)
+        assert_eq!(edges(dots[0]), (198, 136, 202, 140));
+        assert_eq!(edges(dots[2]), (210, 148, 214, 152));
+        assert_eq!(edges(dots[4]), (198, 160, 202, 164));
+        assert_eq!(edges(dots[6]), (186, 148, 190, 152));
+
+        let scaled = spinner_dots(&Rect::new(100, 100, 200, 100), 2);
+        assert_eq!(edges(scaled[0]), (196, 122, 204, 130));
+    }
+
+    #[test]
+    fn swapped_windows_exchange_geometry() {
+        let config = Rc::new(Config::default());
+        let mut a = Window::new(0, 20, 200, 100, 1, config.clone());
+        let mut b = Window::new(150, 50, 300, 400, 1, config);
+        a.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        b.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+
+        // overlapping windows swap like any others
+        swap_geometry(&mut a, &mut b);
+        assert_eq!((a.x, a.y, b.x, b.y), (150, 50, 0, 20));
+
+        // positions are taken right away, while the sizes are left to the clients
+        let resizes = |window: &Window| window.events.iter()
+            .filter(|event| event.code == orbclient::EVENT_RESIZE)
+            .map(|event| (event.a, event.b))
+            .collect::<Vec<_>>();
+        assert_eq!(resizes(&a), [(300, 400)]);
+        assert_eq!(resizes(&b), [(200, 100)]);
+        for window in [&a, &b] {
+            assert!(window.events.iter().any(|event| event.code == orbclient::EVENT_MOVE));
+        }
+    }
+
+    #[test]
+    fn text_cursor_query_is_in_screen_coordinates() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        let mut editor = Window::new(200, 150, 400, 300, 1, config.clone());
+        editor.text_cursor = Some((12, 34));
+        editor.focused_at = 2;
+        windows.insert(1, editor);
+        windows.insert(2, Window::new(0, 0, 100, 100, 1, config.clone()));
+        let mut other = Window::new(-50, 10, 100, 100, 1, config);
+        other.text_cursor = Some((60, 0));
+        other.focused_at = 1;
+        windows.insert(3, other);
+        assert_eq!(text_cursor_query(&windows), b"1,212,184\n3,10,10\n");
+
+        // moving the window moves where its text cursor is reported
+        if let Some(editor) = windows.get_mut(&1) {
+            editor.x = 300;
+        }
+        assert_eq!(text_cursor_query(&windows), b"1,312,184\n3,10,10\n");
+    }
+
+    #[test]
+    fn subtitles_query_lists_windows_with_one() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        for (id, subtitle, focused_at) in [(1, "modified", 1), (2, "", 3), (3, "page 2/3", 2)] {
+            let mut window = Window::new(0, 0, 100, 100, 1, Rc::clone(&config));
+            window.title = "editor".to_string();
+            window.subtitle = subtitle.to_string();
+            window.focused_at = focused_at;
+            windows.insert(id, window);
+        }
+        assert_eq!(subtitles_query(&windows), b"3,page 2/3\n1,modified\n");
+    }
+
+    #[test]
+    fn mru_order_follows_focus_sequence() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        for id in 1..=4 {
+            let mut window = Window::new(0, 0, 100, 100, 1, config.clone());
+            window.title = id.to_string();
+            windows.insert(id, window);
+        }
+        // focused 1, then 3, then 2 and 4 never was
+        for (serial, id) in [1, 3, 2].into_iter().enumerate() {
+            windows.get_mut(&id).unwrap_or_else(|| panic!("no window {}", id)).focused_at = serial as u64 + 1;
+        }
+        assert_eq!(mru_order(&windows), [2, 3, 1, 4]);
+
+        // the first step goes to the previously focused window, the next ones further back
+        let mut order: VecDeque<usize> = [2, 1, 4, 3].into();
+        let mut cycle = None;
+        assert_eq!(mru_next(&mut cycle, &mut order, &windows), Some((2, 3)));
+        assert_eq!(order, [3, 2, 1, 4]);
+        assert_eq!(mru_next(&mut cycle, &mut order, &windows), Some((3, 1)));
+        assert_eq!(order, [1, 3, 2, 4]);
+        assert_eq!(mru_next(&mut cycle, &mut order, &windows), Some((1, 4)));
+        assert_eq!(mru_next(&mut cycle, &mut order, &windows), Some((4, 2)));
+        assert_eq!(order, [2, 4, 1, 3]);
+
+        // a window closed during the pass is skipped
+        windows.remove(&3);
+        assert_eq!(mru_next(&mut cycle, &mut order, &windows), Some((2, 1)));
+    }
+
+    #[test]
+    fn mru_cycle_keeps_its_place_when_windows_close() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        for id in 1..=5 {
+            let mut window = Window::new(0, 0, 100, 100, 1, config.clone());
+            window.title = id.to_string();
+            windows.insert(id, window);
+        }
+
+        // closing a window before the current one goes on with the one after it
+        let mut order: VecDeque<usize> = [3, 1, 2, 4, 5].into();
+        let mut cycle = Some(MruCycle { ids: vec![1, 3, 2, 4, 5], index: 1 });
+        windows.remove(&1);
+        assert_eq!(mru_next(&mut cycle, &mut order, &windows), Some((3, 2)));
+
+        // and so does closing the current one, which focus moved away from
+        windows.remove(&2);
+        order.retain(|&id| id != 2);
+        assert_eq!(mru_next(&mut cycle, &mut order, &windows), Some((3, 4)));
+        assert_eq!(mru_next(&mut cycle, &mut order, &windows), Some((4, 5)));
+        assert_eq!(mru_next(&mut cycle, &mut order, &windows), Some((5, 3)));
+    }
+
+    #[test]
+    fn switcher_next_brings_next_selectable_window_to_front() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        for (id, title) in [(1, "one"), (2, ""), (3, "three"), (4, "four")] {
+            let mut window = Window::new(0, 0, 100, 100, 1, config.clone());
+            window.title = title.to_string();
+            windows.insert(id, window);
+        }
+        let mut order: VecDeque<usize> = [1, 2, 3, 4].into();
+
+        // SWITCHER,next after SWITCHER,open: the untitled window is skipped
+        assert_eq!(switcher_next(&mut order, &windows), Some((1, 3)));
+        assert_eq!(order, [2, 3, 4, 1]);
+        assert_eq!(switcher_next(&mut order, &windows), Some((3, 4)));
+        assert_eq!(order, [2, 4, 1, 3]);
+
+        // nothing to switch between
+        let mut order: VecDeque<usize> = [2, 4].into();
+        assert_eq!(switcher_next(&mut order, &windows), None);
+        assert_eq!(order, [2, 4]);
+    }
+
+    #[test]
+    fn wallpaper_centered_on_its_display() {
+        let right = Rect::new(1920, 0, 1280, 1024);
+        let smaller = wallpaper_rect(&right, 1000, 800);
+        assert_eq!((smaller.left(), smaller.top(), smaller.right(), smaller.bottom()), (2060, 112, 3060, 912));
+
+        // a larger wallpaper spills past the display and is cropped to it when drawn, so it
+        // doesn't show up on its neighbour
+        let larger = wallpaper_rect(&right, 1920, 1080);
+        assert_eq!((larger.left(), larger.top()), (1600, -28));
+        let drawn = larger.intersection(&right);
+        assert_eq!((drawn.left(), drawn.top(), drawn.right(), drawn.bottom()), (1920, 0, 3200, 1024));
+    }
+
+    #[test]
+    fn minimize_animation_moves_outline_to_target() {
+        let from = Rect::new(100, 100, 400, 300);
+        let to = minimize_target(&Rect::new(0, 0, 1000, 800));
+        assert_eq!(edges(to), (500, 800, 500, 800));
+
+        assert_eq!(edges(lerp_rect(&from, &to, 0.0)), edges(from));
+        assert_eq!(edges(lerp_rect(&from, &to, 0.5)), (300, 450, 500, 600));
+
+        let start = Instant::now();
+        let animation = WindowAnimation { id: 1, from, to, start, restore: false, easing: Easing::Linear };
+        assert_eq!(animation.outline(start).map(edges), Some(edges(from)));
+        assert_eq!(animation.outline(start + ANIMATION_DURATION / 2).map(edges), Some((300, 450, 500, 600)));
+        assert!(animation.outline(start + ANIMATION_DURATION).is_none());
+
+        // eased out, the outline is already past the middle halfway through
+        let animation = WindowAnimation { easing: Easing::EaseOut, ..animation };
+        let halfway = animation.outline(start + ANIMATION_DURATION / 2).map(edges);
+        assert!(halfway.is_some_and(|(left, ..)| left > 300));
+    }
+
+    #[test]
+    fn easing_curves() {
+        for easing in [Easing::Linear, Easing::EaseInOut, Easing::EaseOut] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            // out of range progress is clamped
+            assert_eq!(easing.apply(-1.0), 0.0);
+            assert_eq!(easing.apply(2.0), 1.0);
+
+            let mut last = 0.0;
+            for step in 1..=100 {
+                let eased = easing.apply(step as f32 / 100.0);
+                assert!(eased >= last, "{:?} goes back at step {}", easing, step);
+                last = eased;
+            }
+        }
+
+        assert_eq!(Easing::Linear.apply(0.5), 0.5);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert_eq!(Easing::EaseOut.apply(0.5), 0.875);
+        assert!(Easing::EaseInOut.apply(0.25) < 0.25 && Easing::EaseOut.apply(0.25) > 0.25);
+    }
+
+    #[test]
+    fn outline_edges_stay_inside_rect() {
+        let [top, bottom, left, right] = outline_edges(&Rect::new(10, 10, 20, 10), 2);
+        assert_eq!(edges(top), (10, 10, 30, 12));
+        assert_eq!(edges(bottom), (10, 18, 30, 20));
+        assert_eq!(edges(left), (10, 10, 12, 20));
+        assert_eq!(edges(right), (28, 10, 30, 20));
+
+        // thinner than the outline
+        let [top, _, _, right] = outline_edges(&Rect::new(10, 10, 1, 0), 2);
+        assert_eq!(edges(top), (10, 10, 11, 10));
+        assert_eq!(edges(right), (10, 10, 11, 10));
+    }
+
+    #[test]
+    fn frame_clock_counts_synced_frames() {
+        let mut clock = FrameClock::default();
+        assert_eq!(clock.query(), b"0,0\n");
+
+        clock.tick(Duration::new(5, 16_000_000));
+        clock.tick(Duration::new(5, 33_000_000));
+        assert_eq!(clock.query(), b"2,5033000000\n");
+    }
+
+    #[test]
+    fn displays_are_paced_to_their_own_refresh_rate() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::default();
+        // never synced yet
+        assert!(pacer.is_due(0, 60, start) && pacer.is_due(1, 144, start));
+
+        pacer.synced(0, start);
+        pacer.synced(1, start);
+        let later = start + Duration::from_millis(8);
+        assert!(!pacer.is_due(0, 60, later));
+        assert!(pacer.is_due(1, 144, later));
+        assert_eq!(pacer.next_frame(0, 60), Some(start + Duration::from_secs(1) / 60));
+        assert!(pacer.is_due(0, 60, start + Duration::from_millis(17)));
+        // an unknown refresh rate doesn't hold frames back
+        assert!(pacer.is_due(0, 0, start));
+    }
+
+    #[test]
+    fn damage_on_displays_not_due_is_deferred() {
+        let screens = [Rect::new(0, 0, 1000, 800), Rect::new(1000, 0, 1000, 800)];
+        // a window across both displays
+        let mut redraws = vec![Rect::new(900, 100, 200, 100), Rect::new(1500, 0, 10, 10)];
+
+        // all due, left as it is
+        assert!(split_due(&mut redraws, &screens, &[true, true]).is_empty());
+        assert_eq!(redraws.len(), 2);
+
+        let deferred = split_due(&mut redraws, &screens, &[true, false]);
+        assert_eq!(redraws, [Rect::new(900, 100, 100, 100)]);
+        assert_eq!(deferred, [Rect::new(1000, 100, 100, 100), Rect::new(1500, 0, 10, 10)]);
+    }
+
+    #[test]
+    fn input_latency_percentiles() {
+        let start = Instant::now();
+        let mut latency = InputLatency::new(start);
+        assert_eq!(latency.percentile(50), None);
+
+        // frames without input before them record nothing
+        assert_eq!(latency.composited(start + LATENCY_LOG_INTERVAL), None);
+
+        // the latency is from the earliest input before the frame
+        latency.input(start);
+        latency.input(start + Duration::from_millis(5));
+        assert_eq!(latency.composited(start + Duration::from_micros(7500)), None);
+        assert_eq!(latency.percentile(50), Some(Duration::from_millis(8)));
+
+        // input that nothing was redrawn for is forgotten
+        latency.input(start + Duration::from_millis(10));
+        latency.unchanged();
+        assert_eq!(latency.composited(start + Duration::from_millis(500)), None);
+        assert_eq!(latency.count, 1);
+
+        for millis in 1..100 {
+            latency.record(Duration::from_millis(millis));
+        }
+        latency.record(Duration::from_secs(5));
+        assert_eq!(latency.percentile(50), Some(Duration::from_millis(51)));
+        assert_eq!(latency.percentile(99), Some(Duration::from_millis(100)));
+        assert_eq!(latency.percentile(100), Some(Duration::from_millis(LATENCY_BUCKETS as u64)));
+
+        // logged once the interval is over, then started over
+        let line = latency.composited(start + LATENCY_LOG_INTERVAL);
+        assert_eq!(line.as_deref(), Some("input latency over 101 frames: p50 51ms, p95 96ms, p99 100ms"));
+        assert_eq!(latency.percentile(50), None);
+    }
+
+    #[test]
+    fn window_menu_selection_wraps() {
+        let mut menu = WindowMenu::new(1);
+        assert_eq!(menu.action(), WindowMenuAction::Move);
+        menu.select(-1);
+        assert_eq!(menu.action(), WindowMenuAction::Close);
+        menu.select(1);
+        menu.select(3);
+        assert_eq!(menu.action(), WindowMenuAction::Tile(TilePosition::LeftHalf));
+        menu.select(WINDOW_MENU.len() as isize);
+        assert_eq!(menu.action(), WindowMenuAction::Tile(TilePosition::LeftHalf));
+    }
+
+    #[test]
+    fn window_menu_resizes_in_grid_steps() {
+        let mut window = Window::new(0, 0, 100, 100, 1, Rc::new(Config::default()));
+        assert_eq!(keyboard_resize(&window, (1, -1)), (100 + GRID_SIZE, 100 - GRID_SIZE));
+        // never resized to nothing
+        assert_eq!(keyboard_resize(&window, (-10, 0)), (GRID_SIZE, 100));
+
+        // larger increments are stepped by whole, instead of snapping back to the same size
+        window.size_hints = SizeHints { increment: (40, 1), ..SizeHints::default() };
+        assert_eq!(keyboard_resize(&window, (1, 1)).0, 120);
+    }
+
+    #[test]
+    fn window_menu_stays_on_screen() {
+        let screen = Rect::new(0, 0, 800, 600);
+        assert_eq!(edges(window_menu_rect(&screen, 100, 50, 200, 100)), (100, 50, 300, 150));
+        // past the bottom right, and above the top left of the screen
+        assert_eq!(edges(window_menu_rect(&screen, 700, 550, 200, 100)), (600, 500, 800, 600));
+        assert_eq!(edges(window_menu_rect(&screen, -50, -20, 200, 100)), (0, 0, 200, 100));
+    }
+
+    #[test]
+    fn close_confirmation_quits_on_enter_and_cancels_on_escape() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        windows.insert(1, Window::new(0, 0, 100, 100, 1, Rc::clone(&config)));
+        windows.insert(2, Window::new(0, 0, 100, 100, 1, Rc::clone(&config)));
+        let quits = |windows: &BTreeMap<usize, Window>, id: usize| windows.get(&id)
+            .map_or(0, |window| window.events.iter().filter(|event| event.code == orbclient::EVENT_QUIT).count());
+
+        // nothing to answer without a prompt
+        let mut pending = None;
+        assert!(!answer_close_confirm(&mut pending, &mut windows, orbclient::K_ENTER));
+
+        // other keys leave the prompt open, Enter quits the window it was shown for only
+        pending = Some(2);
+        assert!(!answer_close_confirm(&mut pending, &mut windows, orbclient::K_A));
+        assert_eq!(pending, Some(2));
+        assert!(answer_close_confirm(&mut pending, &mut windows, orbclient::K_ENTER));
+        assert_eq!(pending, None);
+        assert_eq!((quits(&windows, 1), quits(&windows, 2)), (0, 1));
+
+        // Escape closes the prompt without a quit
+        pending = Some(1);
+        assert!(answer_close_confirm(&mut pending, &mut windows, orbclient::K_ESC));
+        assert_eq!(pending, None);
+        assert_eq!((quits(&windows, 1), quits(&windows, 2)), (0, 1));
+
+        // a prompt for a window that closed meanwhile sends nothing
+        pending = Some(3);
+        assert!(answer_close_confirm(&mut pending, &mut windows, orbclient::K_ENTER));
+        assert_eq!(pending, None);
+    }
+
+    #[test]
+    fn ui_scale_override_rescales_windows() {
+        let font = orbfont::Font::from_data(BUILTIN_UI_FONT).unwrap_or_else(|err| panic!("{}", err));
+        let screens = [(Rect::new(0, 0, 1024, 768), 1)];
+        let mut window = Window::new(100, 100, 300, 200, 1, Rc::new(Config::default()));
+        assert_eq!(window.title_rect().height(), 28);
+
+        let mut redraws = Vec::new();
+        let scale = window_scale(Some(step_scale(1, 1)), &screens, window.rect());
+        rescale_window(&mut redraws, &mut window, scale, &font);
+        assert_eq!(window.title_rect().height(), 56);
+
+        // back to the scale of the display
+        let scale = window_scale(None, &screens, window.rect());
+        rescale_window(&mut redraws, &mut window, scale, &font);
+        assert_eq!(window.title_rect().height(), 28);
+
+        // the scale stays within bounds
+        assert_eq!(step_scale(1, -1), 1);
+        assert_eq!(step_scale(MAX_UI_SCALE, 1), MAX_UI_SCALE);
+    }
+
+    #[test]
+    fn dragged_windows_snap_to_the_grid() {
+        assert_eq!([snap_to_grid(7, 16), snap_to_grid(8, 16), snap_to_grid(-9, 16), snap_to_grid(7, 1)], [0, 16, -16, 7]);
+
+        // small moves add up until they reach the next grid line
+        let ((x, y), drag) = title_drag((32, 48), (100, 100), (105, 103), 16);
+        assert_eq!(((x, y), drag), ((32, 48), (100, 100)));
+        let ((x, y), drag) = title_drag((x, y), drag, (110, 92), 16);
+        assert_eq!(((x, y), drag), ((48, 48), (116, 100)));
+        let ((x, y), _) = title_drag((x, y), drag, (111, 84), 16);
+        assert_eq!((x, y), (48, 32));
+
+        // without a grid the window follows the cursor
+        assert_eq!(title_drag((32, 48), (100, 100), (105, 103), 1), ((37, 51), (105, 103)));
+    }
+
+    #[test]
+    fn resized_border_snaps_to_neighbor_edge() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        // 1 is resized, 2 sits to its right and 3 is hidden below it
+        windows.insert(1, Window::new(0, 100, 200, 200, 1, Rc::clone(&config)));
+        windows.insert(2, Window::new(300, 50, 200, 200, 1, Rc::clone(&config)));
+        let mut hidden = Window::new(0, 340, 200, 200, 1, config);
+        hidden.hidden = true;
+        windows.insert(3, hidden);
+        let order = VecDeque::from([1, 2, 3]);
+        let zbuffer = zbuffer(&order, &windows);
+
+        let (x, y) = snap_edges(&zbuffer, &windows, 1);
+        let top = windows[&2].frame_rect().top();
+        assert_eq!(x, [300, 500]);
+        assert_eq!(y, [top, 250]);
+
+        // the right border dragged to 293 lines up with the left edge of 2
+        assert_eq!(snap_to_edge(293, &x, 8), 300);
+        assert_eq!(snap_to_edge(306, &x, 8), 300);
+        assert_eq!(snap_to_edge(291, &x, 8), 291);
+        // and the bottom border with its bottom, not with the hidden window
+        assert_eq!(snap_to_edge(254, &y, 8), 250);
+        assert_eq!(snap_to_edge(336, &y, 8), 336);
+    }
+
+    #[test]
+    fn control_bypasses_snapping() {
+        let mut config = Config::default();
+        assert_eq!(drag_grid(&config, 0), 1);
+        config.snap_to_grid = true;
+        config.snap_grid_size = 32;
+        assert_eq!(drag_grid(&config, 0), 32);
+        assert_eq!(drag_grid(&config, SHIFT_ANY_MODIFIER), 32);
+        assert_eq!(drag_grid(&config, CONTROL_MODIFIER), 1);
+    }
 
-                    // Update cursor to center of this window
-                    relative_cursor_opt = Some((
-                        window.x + window.width() / 2,
-                        window.y + window.height() / 2,
-                        //TODO: allow cursors on relative windows?
-                        CursorKind::None
-                    ));
-                }
+    #[test]
+    fn fonts_query_lists_typefaces_that_can_be_loaded() {
+        let root = std::env::temp_dir().join(format!("orbital-test-{}-fonts", std::process::id()));
+        for path in ["Mono/Fira/Regular.ttf", "Sans/Fira/Bold.ttf", "Serif/Fira/Regular.ttf", "Other/Regular.ttf"] {
+            let path = root.join(path);
+            if let Err(err) = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|()| fs::write(&path, "")) {
+                panic!("could not write test font: {}", err);
             }
         }
 
-        // Handle relative window cursor
-        if let Some((x, y, kind)) = relative_cursor_opt {
-            self.scheme.update_cursor(x, y, kind);
-            return;
-        }
+        let typefaces = font_typefaces(&root, "Regular");
+        assert_eq!(typefaces, ["Mono", "Serif"]);
+        assert_eq!(font_typefaces(&root, "Bold"), ["Sans"]);
+        let _ = fs::remove_dir_all(&root);
+
+        // the font in use comes first, without listing it twice
+        assert_eq!(fonts_query(Some("Serif"), &typefaces), b"Serif\nMono\n");
+        assert_eq!(fonts_query(None, &typefaces), b"Mono\nSerif\n");
+        // where nothing can be listed there is still the font in use
+        assert!(font_typefaces(&root, "Regular").is_empty());
+        assert_eq!(fonts_query(Some("Mono"), &[]), b"Mono\n");
+    }
 
-        //TODO: more advanced logic for keeping mouse on screen.
-        // This logic assumes horizontal and touching, but not overlapping, screens
-        let mut max_x = 0;
-        let mut max_y = 0;
-        for display in self.orb.displays.iter() {
-            let rect = display.screen_rect();
-            max_x = cmp::max(max_x, rect.right() - 1);
-            max_y = cmp::max(max_y, rect.bottom() - 1);
+    #[test]
+    fn position_on_display_is_offset_by_the_display() {
+        let screens = [Rect::new(0, 0, 1920, 1080), Rect::new(1920, 56, 1280, 1024)];
+        assert_eq!(display_position(&screens, 1, 0, 0), Some((1920, 56)));
+        assert_eq!(display_position(&screens, 1, 100, 200), Some((2020, 256)));
+        assert_eq!(display_position(&screens, 0, 100, 200), Some((100, 200)));
+
+        // kept on the display
+        assert_eq!(display_position(&screens, 1, -50, 5000), Some((1920, 56 + 1023)));
+        assert_eq!(display_position(&screens, 1, 1280, 0), Some((1920 + 1279, 56)));
+
+        assert_eq!(display_position(&screens, 2, 0, 0), None);
+    }
+
+    #[test]
+    fn closing_a_tiled_window_reflows_the_other_half() {
+        let config = Rc::new(Config::default());
+        let area = Rect::new(0, 28, 1024, 740);
+        let mut windows = BTreeMap::new();
+        for (id, position) in [(1, TilePosition::LeftHalf), (2, TilePosition::RightHalf), (3, TilePosition::LeftHalf)] {
+            let mut window = Window::new(212, 184, 600, 400, 1, Rc::clone(&config));
+            window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+            assert!(tile_new_window(&mut window, position, &area).is_some());
+            windows.insert(id, window);
         }
+        // on another display
+        let mut other = Window::new(212, 184, 600, 400, 1, Rc::clone(&config));
+        other.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        assert!(tile_new_window(&mut other, TilePosition::RightHalf, &Rect::new(1024, 28, 1024, 740)).is_some());
+        windows.insert(4, other);
+        let order = VecDeque::from([4, 3, 2, 1]);
+
+        // closing a left window while another is still tiled there leaves the right one alone
+        windows.remove(&1);
+        assert_eq!(reflow_tile(&mut windows, &order, TilePosition::LeftHalf, &area), None);
+        assert!(windows.get(&2).is_some_and(|window| ! window.maximized()));
+
+        // closing the last left window, the right one expands to the full width
+        windows.remove(&3);
+        assert_eq!(reflow_tile(&mut windows, &order, TilePosition::LeftHalf, &area), Some((2, area)));
+        assert!(windows.get(&2).is_some_and(|window| window.maximized()));
+        // and keeps its floating geometry for when it is restored
+        assert_eq!(windows.get(&2).and_then(|window| window.restore).map(|(rect, _)| rect), Some(Rect::new(212, 184, 600, 400)));
+
+        // nothing is tiled to the other half of a window on the right now, and a maximized one
+        // has no other half
+        assert_eq!(reflow_tile(&mut windows, &order, TilePosition::RightHalf, &area), None);
+        assert_eq!(reflow_tile(&mut windows, &order, TilePosition::FullScreen, &area), None);
+    }
 
-        let x = cmp::max(0, cmp::min(max_x, self.scheme.cursor_x + event.dx));
-        let mut y = cmp::max(0, cmp::min(max_y, self.scheme.cursor_y + event.dy));
-        for display in self.orb.displays.iter() {
-            let rect = display.screen_rect();
-            if x >= rect.left() && x < rect.right() {
-                y = cmp::max(rect.top(), cmp::min(rect.bottom() - 1, y));
-            }
+    #[test]
+    fn hot_corner_fires_once_per_entry() {
+        let mut corners = HotCorners::new(&[
+            "top_left=show_desktop".to_string(),
+            "right = pointer_highlight".to_string(),
+            "middle=show_desktop".to_string(),
+        ]);
+        assert_eq!(corners.actions, [(HotCorner::TopLeft, HotCornerAction::ShowDesktop), (HotCorner::Right, HotCornerAction::PointerHighlight)]);
+
+        let start = Instant::now();
+        assert_eq!(corners.update(Some(HotCorner::TopLeft), start), None);
+        assert_eq!(corners.deadline(), Some(start + HOT_CORNER_DWELL));
+        // not before the dwell is over
+        assert_eq!(corners.update(Some(HotCorner::TopLeft), start + HOT_CORNER_DWELL / 2), None);
+        assert_eq!(corners.update(Some(HotCorner::TopLeft), start + HOT_CORNER_DWELL), Some(HotCornerAction::ShowDesktop));
+        // and only once while the cursor stays
+        assert_eq!(corners.update(Some(HotCorner::TopLeft), start + HOT_CORNER_DWELL * 10), None);
+        assert_eq!(corners.deadline(), None);
+
+        // leaving and entering again fires it again
+        let later = start + HOT_CORNER_DWELL * 20;
+        assert_eq!(corners.update(None, later), None);
+        assert_eq!(corners.update(Some(HotCorner::TopLeft), later), None);
+        assert_eq!(corners.update(Some(HotCorner::TopLeft), later + HOT_CORNER_DWELL), Some(HotCornerAction::ShowDesktop));
+
+        // corners without an action are like no corner
+        assert_eq!(corners.update(Some(HotCorner::BottomLeft), later), None);
+        assert_eq!(corners.update(Some(HotCorner::BottomLeft), later + HOT_CORNER_DWELL), None);
+    }
+
+    #[test]
+    fn hot_corners_are_on_the_outer_sides_of_the_displays() {
+        let screens = [Rect::new(0, 0, 1024, 768), Rect::new(1024, 0, 1280, 1024)];
+        assert_eq!(HotCorner::at(&screens, 0, 0, 2), Some(HotCorner::TopLeft));
+        assert_eq!(HotCorner::at(&screens, 1, 767, 2), Some(HotCorner::BottomLeft));
+        assert_eq!(HotCorner::at(&screens, 0, 300, 2), Some(HotCorner::Left));
+        assert_eq!(HotCorner::at(&screens, 500, 0, 2), Some(HotCorner::Top));
+        assert_eq!(HotCorner::at(&screens, 2303, 1023, 2), Some(HotCorner::BottomRight));
+        assert_eq!(HotCorner::at(&screens, 500, 300, 2), None);
+
+        // the side between the two displays is not an edge
+        assert_eq!(HotCorner::at(&screens, 1023, 300, 2), None);
+        assert_eq!(HotCorner::at(&screens, 1024, 300, 2), None);
+        assert_eq!(HotCorner::at(&screens, 1023, 0, 2), Some(HotCorner::Top));
+        // below the smaller display, the side of the larger one is
+        assert_eq!(HotCorner::at(&screens, 1024, 900, 2), Some(HotCorner::Left));
+        assert_eq!(HotCorner::at(&screens, 1023, 767, 2), Some(HotCorner::Bottom));
+    }
+
+    #[test]
+    fn mirroring_refuses_loops() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        for id in 1..=4 {
+            windows.insert(id, Window::new(0, 0, 10, 10, 1, Rc::clone(&config)));
+        }
+        // 1 mirrors 2, which mirrors 3
+        if let Some(window) = windows.get_mut(&1) {
+            window.mirror = Some(2);
+        }
+        if let Some(window) = windows.get_mut(&2) {
+            window.mirror = Some(3);
         }
 
-        self.mouse_event(MouseEvent { x, y });
+        // 3 can't mirror 1 or 2, nor itself
+        assert!(mirrors(&windows, 1, 3));
+        assert!(mirrors(&windows, 2, 3));
+        assert!(mirrors(&windows, 3, 3));
+        // but 4 can mirror any of them, and 3 can mirror 4
+        assert!(!mirrors(&windows, 1, 4));
+        assert!(!mirrors(&windows, 4, 3));
     }
 
-    // Find the display that a window (`rect`) most overlaps and return the index of it
-    fn get_display_index(displays: &[Display], rect: &Rect) -> usize {
-        // Find the index of the Display this window has the most overlap with
-        let mut display_index = 0;
-        let mut max_intersection_area = 0;
-        for (display_i, display) in displays.iter().enumerate() {
-            let intersect = display.screen_rect().intersection(rect);
-            let area = intersect.area();
-            if area > max_intersection_area {
-                display_index = display_i;
-                max_intersection_area = area;
-            }
+    #[test]
+    fn suspended_window_is_skipped_but_kept() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        windows.insert(1, Window::new(0, 30, 200, 200, 1, Rc::clone(&config)));
+        let mut suspended = Window::new(0, 30, 200, 200, 1, Rc::clone(&config));
+        suspended.painted = true;
+        suspended.suspended = true;
+        windows.insert(2, suspended);
+        let order = VecDeque::from([2, 1]);
+        let zbuffer = zbuffer(&order, &windows);
+        let screen = Rect::new(0, 0, 200, 230);
+
+        // it still has its place in the stack, but nothing of it is drawn or hit
+        assert_eq!(zbuffer[0].0, 2);
+        assert!(windows[&2].rect().is_empty() && windows[&2].title_rect().is_empty());
+        assert_eq!(input_target(&zbuffer, &windows, 100, 100), Some((1, 1, WindowRegion::Content)));
+        assert_eq!(scanout_window(&zbuffer, &windows, &screen, 0), None);
+        assert!(windows[&2].properties().flags.contains(ORBITAL_FLAG_SUSPENDED));
+
+        if let Some(window) = windows.get_mut(&2) {
+            window.suspended = false;
         }
+        assert_eq!(input_target(&zbuffer, &windows, 100, 100), Some((2, 0, WindowRegion::Content)));
+        assert!(!windows[&2].properties().flags.contains(ORBITAL_FLAG_SUSPENDED));
+    }
 
-        display_index
+    #[test]
+    fn modifiers_query_follows_shift_and_locks() {
+        let (mut held, mut locks, mut sticky, mut state) = (0, LockKeys::default(), StickyKeys::default(), 0);
+        let mut key = |scancode, pressed| {
+            let (changed, _) = track_modifiers(&mut held, &mut locks, &mut sticky, &mut state, scancode, pressed);
+            (changed, modifiers_query(state, locks.locked))
+        };
+        assert_eq!(key(orbclient::K_A, true), (false, b"0,0\n".to_vec()));
+
+        // pressing Shift changes the query, its repeats don't, and releasing it changes it back
+        let shift = SHIFT_LEFT_MODIFIER | SHIFT_ANY_MODIFIER;
+        assert_eq!(key(orbclient::K_LEFT_SHIFT, true), (true, format!("{},0\n", shift).into_bytes()));
+        assert_eq!(key(orbclient::K_LEFT_SHIFT, true), (false, format!("{},0\n", shift).into_bytes()));
+        assert_eq!(key(orbclient::K_LEFT_SHIFT, false), (true, b"0,0\n".to_vec()));
+
+        // so does toggling a lock, but not releasing it
+        assert_eq!(key(orbclient::K_CAPS, true), (true, b"0,1\n".to_vec()));
+        assert_eq!(key(orbclient::K_CAPS, false), (false, b"0,1\n".to_vec()));
+
+        // a lock toggles once per press, not for the repeats while it is held
+        let mut locks = LockKeys::default();
+        assert!(locks.key(orbclient::K_CAPS, true));
+        assert!(!locks.key(orbclient::K_CAPS, true));
+        assert!(!locks.key(orbclient::K_CAPS, false));
+        assert_eq!(locks.locked, CAPS_LOCK);
+        assert!(locks.key(K_NUM_LOCK, true));
+        assert_eq!(locks.locked, CAPS_LOCK | NUM_LOCK);
+        assert_eq!(modifiers_query(0, locks.locked), b"0,3\n");
+        assert!(!locks.key(K_NUM_LOCK, false));
+        assert!(locks.key(K_NUM_LOCK, true));
+        assert_eq!(locks.locked, CAPS_LOCK);
+        assert!(!locks.key(orbclient::K_A, true));
     }
 
-    fn button_event(&mut self, event: ButtonEvent) {
-        // Check for focus switch, dragging, and forward mouse events to applications
-        match self.scheme.dragging {
-            DragMode::None => {
-                let mut focus = 0;
-                for entry in self.scheme.zbuffer.iter() {
-                    let id = entry.0;
-                    let i = entry.2;
-                    if let Some(window) = self.scheme.windows.get(&id) {
-                        if window.rect().contains(self.scheme.cursor_x, self.scheme.cursor_y) {
-                            if self.scheme.modifier_state & SUPER_MODIFIER == SUPER_MODIFIER {
-                                if event.left && ! self.scheme.cursor_left {
-                                    focus = i;
-                                    self.scheme.dragging = DragMode::Title(id, self.scheme.cursor_x, self.scheme.cursor_y);
-                                }
-                            } else if let Some(window) = self.scheme.windows.get_mut(&id) {
-                                    window.event(event.to_event());
-                                    if event.left && !self.scheme.cursor_left
-                                        || event.middle && !self.scheme.cursor_middle
-                                        || event.right && !self.scheme.cursor_right {
-                                        focus = i;
-                                    }
-                                }
-                            break;
-                        } else if window.title_rect().contains(self.scheme.cursor_x, self.scheme.cursor_y) {
-                            //TODO: Trigger max and exit on release
-                            if event.left && ! self.scheme.cursor_left  {
-                                focus = i;
-                                if (window.max_contains(self.scheme.cursor_x, self.scheme.cursor_y)) && (window.resizable) {
-                                    self.tile_window(Some(&id), FullScreen);
-                                } else if (window.close_contains(self.scheme.cursor_x, self.scheme.cursor_y)) && (!window.unclosable) {
-                                    if let Some(window) = self.scheme.windows.get_mut(&id) {
-                                        window.event(QuitEvent.to_event());
-                                    }
-                                } else {
-                                    self.scheme.dragging = DragMode::Title(id, self.scheme.cursor_x, self.scheme.cursor_y);
-                                }
-                            }
-                            break;
-                        } else if window.left_border_rect().contains(self.scheme.cursor_x, self.scheme.cursor_y) {
-                            if event.left && ! self.scheme.cursor_left  {
-                                focus = i;
-                                self.scheme.dragging = DragMode::LeftBorder(id, self.scheme.cursor_x - window.x, window.x + window.width());
-                            }
-                            break;
-                        } else if window.right_border_rect().contains(self.scheme.cursor_x, self.scheme.cursor_y) {
-                            if event.left && ! self.scheme.cursor_left  {
-                                focus = i;
-                                self.scheme.dragging = DragMode::RightBorder(id, self.scheme.cursor_x - (window.x + window.width()));
-                            }
-                            break;
-                        } else if window.bottom_border_rect().contains(self.scheme.cursor_x, self.scheme.cursor_y) {
-                            if event.left && ! self.scheme.cursor_left  {
-                                focus = i;
-                                self.scheme.dragging = DragMode::BottomBorder(id, self.scheme.cursor_y - (window.y + window.height()));
-                            }
-                            break;
-                        } else if window.bottom_left_border_rect().contains(self.scheme.cursor_x, self.scheme.cursor_y) {
-                            if event.left && ! self.scheme.cursor_left  {
-                                focus = i;
-                                self.scheme.dragging = DragMode::BottomLeftBorder(id, self.scheme.cursor_x - window.x, self.scheme.cursor_y - (window.y + window.height()), window.x + window.width());
-                            }
-                            break;
-                        } else if window.bottom_right_border_rect().contains(self.scheme.cursor_x, self.scheme.cursor_y) {
-                            if event.left && ! self.scheme.cursor_left  {
-                                focus = i;
-                                self.scheme.dragging = DragMode::BottomRightBorder(id, self.scheme.cursor_x - (window.x + window.width()), self.scheme.cursor_y - (window.y + window.height()));
-                            }
-                            break;
-                        }
-                    }
-                }
+    #[test]
+    fn placement_margin_keeps_new_windows_off_the_top() {
+        // a panel that will take the top 48 pixels, but hasn't reserved them yet
+        let area = Rect::new(0, 0, 1024, 768);
+        let margin = PlacementMargin { top: 48, ..PlacementMargin::default() };
+        let inset = placement_area(&area, &margin);
+        assert_eq!(inset, Rect::new(0, 48, 1024, 720));
+
+        // a window as high as the area would be placed just below the title bar, over the panel
+        assert_eq!(placement(&area, 600, 740, 28), (212, 28));
+        let (x, y) = placement(&inset, 600, 690, 28);
+        assert_eq!((x, y), (212, 48 + 28));
+        assert!(y - 28 >= 48);
+
+        assert_eq!(placement_area(&area, &PlacementMargin::default()), area);
+        // a margin larger than the area leaves nothing of it
+        let all = PlacementMargin { left: 600, right: 600, ..PlacementMargin::default() };
+        assert!(placement_area(&area, &all).is_empty());
+    }
 
-                if focus > 0 {
-                    // Redraw old focused window
-                    if let Some(id) = self.scheme.order.front() {
-                        self.focus(*id, false);
-                    }
+    #[test]
+    fn state_dump_lists_windows_order_and_zbuffer() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        let mut front = Window::new(10, 20, 300, 200, 1, Rc::clone(&config));
+        front.title = "Terminal".to_string();
+        front.zorder = WindowZOrder::Front;
+        windows.insert(1, front);
+        let mut hidden = Window::new(0, 0, 100, 50, 1, Rc::clone(&config));
+        hidden.hidden = true;
+        windows.insert(2, hidden);
+        windows.insert(3, Window::new(-5, 5, 40, 30, 1, config));
+        let order = VecDeque::from([3, 1, 2]);
+        let zbuffer = zbuffer(&order, &windows);
+
+        assert_eq!(state_dump(&windows, &order, &zbuffer), vec![
+            "3 windows".to_string(),
+            "window 1 \"Terminal\": 10,20 300x200 Front flags 'f' focus 1".to_string(),
+            "window 2 \"\": 0,0 100x50 Normal flags 'h' focus 2".to_string(),
+            "window 3 \"\": -5,5 40x30 Normal flags '' focus 0".to_string(),
+            "order: 3 1 2".to_string(),
+            "zbuffer: 1 3 2".to_string(),
+        ]);
+    }
 
-                    // Reorder windows
-                    if let Some(id) = self.scheme.order.remove(focus) {
-                        if let Some(window) = self.scheme.windows.get(&id){
-                            match window.zorder {
-                                WindowZOrder::Front | WindowZOrder::Normal => {
-                                    // Transfer focus if a front or normal window
-                                    self.scheme.order.push_front(id);
-                                },
-                                WindowZOrder::Back => {
-                                    // Return to original position if a background window
-                                    self.scheme.order.insert(focus, id);
-                                }
-                            }
-                        }
-                    }
+    #[test]
+    fn background_window_syncs_coalesced_to_capped_rate() {
+        let interval = Duration::from_secs(1) / 10;
+        let start = Instant::now();
+        let mut limiter = SyncLimiter::default();
 
-                    // Redraw new focused window
-                    if let Some(id) = self.scheme.order.front() {
-                        self.focus(*id, true);
-                    }
-                }
-            },
-            _ => if ! event.left {
-                self.scheme.dragging = DragMode::None;
-            }
+        // the first sync is done right away, the rapid ones after it wait for the interval
+        assert!(limiter.sync(1, start, Some(interval)));
+        for ms in 1..100 {
+            assert!(!limiter.sync(1, start + Duration::from_millis(ms), Some(interval)));
         }
+        assert_eq!(limiter.next_sync(interval), Some(start + interval));
+        assert!(limiter.flush(start + Duration::from_millis(99), interval).is_empty());
 
-        self.scheme.cursor_left = event.left;
-        self.scheme.cursor_middle = event.middle;
-        self.scheme.cursor_right = event.right;
+        // and are coalesced into one once it is up
+        assert_eq!(limiter.flush(start + interval, interval), vec![1]);
+        assert!(limiter.flush(start + interval, interval).is_empty());
+        assert_eq!(limiter.next_sync(interval), None);
+
+        // the focused window isn't limited
+        for ms in 0..100 {
+            assert!(limiter.sync(2, start + Duration::from_millis(ms), None));
+        }
+
+        // a closed window has nothing left to sync
+        assert!(!limiter.sync(1, start + interval + Duration::from_millis(1), Some(interval)));
+        limiter.remove(1);
+        assert_eq!(limiter.next_sync(interval), None);
     }
 
-    fn resize_event(&mut self, event: ResizeEvent) {
-        self.orb.resize(event.width as i32, event.height as i32);
+    #[test]
+    fn closing_focused_window_returns_focus_to_window_it_was_opened_from() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        windows.insert(1, Window::new(0, 0, 100, 100, 1, Rc::clone(&config)));
+        windows.insert(3, Window::new(0, 0, 100, 100, 1, Rc::clone(&config)));
+        // modal 2 opened from 1, then 3 was clicked and 2 again
+        let mut modal = Window::new(0, 0, 100, 100, 1, Rc::clone(&config));
+        modal.opened_from = Some(1);
+        let mut dialog = Window::new(0, 0, 100, 100, 1, config);
+        dialog.opened_from = Some(2);
+        windows.insert(4, dialog);
+
+        // closing 2 while it has focus: 1 gets it back rather than 3, and 4 is now opened from 1
+        let mut order = VecDeque::from([3, 1, 4]);
+        return_focus(&mut order, &mut windows, 2, modal.opened_from, true);
+        assert_eq!(order, VecDeque::from([1, 3, 4]));
+        assert_eq!(windows[&4].opened_from, Some(1));
+
+        // closing it in the background leaves focus alone
+        let mut order = VecDeque::from([3, 1, 4]);
+        return_focus(&mut order, &mut windows, 2, modal.opened_from, false);
+        assert_eq!(order, VecDeque::from([3, 1, 4]));
+
+        // a window that can't take focus doesn't get it back
+        windows.get_mut(&1).unwrap_or_else(|| panic!("window 1")).minimized = true;
+        return_focus(&mut order, &mut windows, 2, modal.opened_from, true);
+        assert_eq!(order, VecDeque::from([3, 1, 4]));
+
+        // nor does one that closed
+        return_focus(&mut order, &mut windows, 2, Some(5), true);
+        assert_eq!(order, VecDeque::from([3, 1, 4]));
+    }
 
-        let screen_rect = self.orb.screen_rect();
-        schedule(&mut self.scheme.redraws, screen_rect);
+    #[test]
+    fn osd_opacity_scales_bar_color_alpha() {
+        let bar_color: Color = Config::default().bar_color.into();
+        assert_eq!(osd_color(bar_color, 1.0), bar_color);
+        let color = osd_color(bar_color, 0.5);
+        assert_eq!(color.a(), (bar_color.a() as f32 * 0.5).round() as u8);
+        assert_eq!((color.r(), color.g(), color.b()), (bar_color.r(), bar_color.g(), bar_color.b()));
+        assert_eq!(osd_color(bar_color, 0.0).a(), 0);
+        // out of range opacities are clamped
+        assert_eq!(osd_color(bar_color, 2.0), bar_color);
+        assert_eq!(osd_color(bar_color, -1.0).a(), 0);
+    }
 
-        let screen_event = ScreenEvent {
-            width: self.orb.image().width() as u32,
-            height: self.orb.image().height() as u32,
-        }.to_event();
-        for (_window_id, window) in self.scheme.windows.iter_mut() {
-            window.event(screen_event);
-        }
+    #[test]
+    fn resize_osd_shown_while_resizing_and_briefly_after() {
+        let start = Instant::now();
+        let mut osd = ResizeOsd::default();
+        assert!(!osd.update(None, start));
+        assert_eq!(osd.id, None);
+
+        assert!(!osd.update(resizing(&DragMode::BottomRightBorder(3, 0, 0)), start));
+        assert_eq!((osd.id, osd.hide_at), (Some(3), None));
+
+        // moving a window isn't resizing it, the size stays shown for a moment
+        let end = start + Duration::from_millis(500);
+        assert!(!osd.update(resizing(&DragMode::Title(3, 0, 0)), end));
+        assert_eq!(osd.hide_at, Some(end + RESIZE_OSD_LINGER));
+        assert!(!osd.update(None, end + Duration::from_millis(10)));
+        assert_eq!(osd.hide_at, Some(end + RESIZE_OSD_LINGER));
+        assert!(osd.update(None, end + RESIZE_OSD_LINGER));
+        assert_eq!((osd.id, osd.hide_at), (None, None));
+
+        // a resize starting again while it lingers keeps it shown
+        osd.update(resizing(&DragMode::LeftBorder(3, 0, 0)), start);
+        osd.update(None, start);
+        osd.update(resizing(&DragMode::RightBorder(4, 0)), start + Duration::from_millis(100));
+        assert_eq!((osd.id, osd.hide_at), (Some(4), None));
     }
 
-    pub fn event(&mut self, event_union: Event){
-        self.scheme.rezbuffer();
+    #[test]
+    fn resize_osd_centered_on_window() {
+        assert_eq!(edges(resize_osd_rect(&Rect::new(100, 50, 400, 300), 80, 24)), (260, 188, 340, 212));
+        // on a window smaller than the size, it overhangs evenly
+        assert_eq!(edges(resize_osd_rect(&Rect::new(0, 0, 40, 10), 80, 24)), (-20, -7, 60, 17));
+    }
 
-        match event_union.to_option() {
-            EventOption::Key(event) => self.key_event(event),
-            EventOption::Mouse(MouseEvent { x, y }) => {
-                // ps2d gives us absolute mouse events with x and y in the range 0..65535.
-                // We need to translate this back to screen coordinates. We are using the
-                // size of the first display here as the only multi-display system supported
-                // by qemu doesn't produce absolute mouse events using vmmouse at all.
-                // FIXME once we have usb tablet support add a new event like MouseEvent
-                // which indicates the input device from which the event originated to use
-                // the correct display for getting the size.
-                self.mouse_event(MouseEvent {
-                    x: x * self.orb.displays[0].image.width() / 65536,
-                    y: y * self.orb.displays[0].image.height() / 65536,
-                });
-            }
-            EventOption::MouseRelative(event) => self.mouse_relative_event(event),
-            EventOption::Button(event) => self.button_event(event),
-            EventOption::Scroll(_) => {
-                if let Some(entry) = self.scheme.zbuffer.first() {
-                    let id = entry.0;
-                    if let Some(window) = self.scheme.windows.get_mut(&id) {
-                        window.event(event_union);
-                    }
-                }
-            },
-            EventOption::Resize(event) => self.resize_event(event),
-            event => error!("unexpected event: {:?}", event)
-        }
+    #[test]
+    fn hot_corner_bound_twice_keeps_first_entry() {
+        let config = [
+            "top_left=show_switcher".to_string(),
+            "bottom_right=show_desktop".to_string(),
+            " top_left = pointer_highlight".to_string(),
+            "top_left=show_desktop".to_string(),
+            "middle=show_desktop".to_string(),
+        ];
+        let (actions, warnings) = HotCorners::bindings(&config);
+        assert_eq!(actions, vec![
+            (HotCorner::TopLeft, HotCornerAction::ShowSwitcher),
+            (HotCorner::BottomRight, HotCornerAction::ShowDesktop),
+        ]);
+        assert_eq!(warnings, vec![
+            "hot_corners entry ' top_left = pointer_highlight' ignored, the corner is already bound by 'top_left=show_switcher'",
+            "hot_corners entry 'top_left=show_desktop' ignored, the corner is already bound by 'top_left=show_switcher'",
+            "invalid hot_corners entry 'middle=show_desktop'",
+        ]);
+        assert_eq!(HotCorners::new(&config).action(HotCorner::TopLeft), Some(HotCornerAction::ShowSwitcher));
     }
 
-    pub fn display_event(&mut self, events: &[Event]) -> io::Result<()> {
-        for &event in events {
-            self.event(event);
-        }
+    #[test]
+    fn cursor_scale_multiplies_cursor_size() {
+        let size = |cursors: &BTreeMap<CursorKind, Image>| {
+            let cursor = &cursors[&CursorKind::LeftPtr];
+            (cursor.width(), cursor.height())
+        };
+        let (width, height) = size(&load_cursors(&Config::default(), 1));
+        assert!(width > 0 && height > 0);
 
-        self.scheme_event(&mut [])?;
+        let config = Config {
+            cursor_scale: 2,
+            ..Config::default()
+        };
+        assert_eq!(size(&load_cursors(&config, 1)), (width * 2, height * 2));
+        // on top of the UI scale
+        assert_eq!(size(&load_cursors(&config, 2)), (width * 4, height * 4));
 
-        // redrawn by handle_after
+        let config = Config {
+            cursor_scale: 0,
+            ..Config::default()
+        };
+        assert_eq!(size(&load_cursors(&config, 1)), (width, height));
+    }
 
-        Ok(())
+    #[test]
+    fn hovering_past_autoraise_delay_raises_window() {
+        let delay = Duration::from_millis(500);
+        let start = Instant::now();
+        let mut autoraise = AutoRaise::default();
+
+        assert_eq!(autoraise.update(Some(3), start, delay), None);
+        assert_eq!(autoraise.deadline(delay), Some(start + delay));
+        assert_eq!(autoraise.update(Some(3), start + Duration::from_millis(499), delay), None);
+        assert_eq!(autoraise.update(Some(3), start + delay, delay), Some(3));
+        // only once while it stays hovered
+        assert_eq!(autoraise.update(Some(3), start + delay * 2, delay), None);
+        assert_eq!(autoraise.deadline(delay), None);
+
+        // leaving the window starts the delay over
+        let later = start + delay * 3;
+        assert_eq!(autoraise.update(Some(2), later, delay), None);
+        assert_eq!(autoraise.update(None, later + Duration::from_millis(100), delay), None);
+        assert_eq!(autoraise.update(Some(2), later + Duration::from_millis(200), delay), None);
+        assert_eq!(autoraise.update(Some(2), later + delay, delay), None);
+        assert_eq!(autoraise.update(Some(2), later + Duration::from_millis(200) + delay, delay), Some(2));
+
+        // it goes above the other normal windows but stays below those kept on top, and keeps
+        // its order index so that it isn't focused
+        let mut zbuffer = vec![
+            (1, WindowZOrder::Front, 1),
+            (2, WindowZOrder::Normal, 0),
+            (3, WindowZOrder::Normal, 2),
+            (4, WindowZOrder::Back, 3),
+        ];
+        raise_in_class(&mut zbuffer, 3);
+        assert_eq!(zbuffer_ids(&zbuffer), vec![1, 3, 2, 4]);
+        assert_eq!(zbuffer[1], (3, WindowZOrder::Normal, 2));
+        raise_in_class(&mut zbuffer, 1);
+        assert_eq!(zbuffer_ids(&zbuffer), vec![1, 3, 2, 4]);
+        raise_in_class(&mut zbuffer, 5);
+        assert_eq!(zbuffer.len(), 4);
     }
 
-    pub fn scheme_event(&mut self, _packets: &mut [Packet]) -> io::Result<()> {
-        for (id, window) in self.scheme.windows.iter_mut() {
-            if ! window.events.is_empty() {
-                if !window.notified_read || window.asynchronous {
-                    window.notified_read = true;
-                    self.orb.scheme_write(&Packet {
-                        id: 0,
-                        pid: 0,
-                        uid: 0,
-                        gid: 0,
-                        a: syscall::number::SYS_FEVENT,
-                        b: *id,
-                        c: syscall::flag::EVENT_READ.bits(),
-                        d: window.events.len() * mem::size_of::<Event>()
-                    })?;
-                }
-            } else {
-                window.notified_read = false;
+    #[test]
+    fn shortcut_with_most_held_modifiers_wins() {
+        let shortcuts = ShortcutAction::defaults();
+        let super_shift = SUPER_MODIFIER | SHIFT_LEFT_MODIFIER | SHIFT_ANY_MODIFIER;
+        assert_eq!(shortcut_action(&shortcuts, orbclient::K_UP, SUPER_MODIFIER), Some(ShortcutAction::MoveUp));
+        assert_eq!(shortcut_action(&shortcuts, orbclient::K_UP, super_shift), Some(ShortcutAction::TileTop));
+        // extra modifiers no binding uses are allowed
+        assert_eq!(shortcut_action(&shortcuts, orbclient::K_Q, super_shift), Some(ShortcutAction::QuitWindow));
+        assert_eq!(shortcut_action(&shortcuts, orbclient::K_BRACE_OPEN, super_shift), Some(ShortcutAction::VolumeDown));
+        // but the modifiers of a binding have to be held
+        assert_eq!(shortcut_action(&shortcuts, orbclient::K_Q, 0), None);
+        assert_eq!(shortcut_action(&shortcuts, orbclient::K_ENTER, SUPER_MODIFIER), Some(ShortcutAction::Maximize));
+        assert_eq!(shortcut_action(&shortcuts, orbclient::K_A, SUPER_MODIFIER), None);
+
+        let mut shortcuts = BTreeMap::new();
+        shortcuts.insert(ShortcutAction::QuitWindow, vec![KeyCombo { scancode: orbclient::K_W, super_key: true, ..KeyCombo::default() }]);
+        assert_eq!(shortcut_action(&shortcuts, orbclient::K_W, SUPER_MODIFIER), Some(ShortcutAction::QuitWindow));
+        assert_eq!(shortcut_action(&shortcuts, orbclient::K_Q, SUPER_MODIFIER), None);
+    }
+
+    #[test]
+    fn recorder_receives_composited_region() {
+        // a window redrawn at (2, 1) of a 3x2 rect, on a display that starts at x 100
+        let mut screen = Image::new(8, 6);
+        let color = Color::rgb(0x12, 0x34, 0x56);
+        screen.rect(2, 1, 3, 2, color);
+        let region = Rect::new(102, 1, 3, 2);
+
+        let mut recorder = Recorder::new();
+        assert!(recorder.push(record_update(&screen, 100, 0, &region), RECORD_QUEUE_BYTES));
+        assert_eq!(recorder.queued(), 16 + 3 * 2 * 4);
+
+        // read in pieces that don't line up with the header or the pixels
+        let mut update = Vec::new();
+        let mut buf = [0; 5];
+        loop {
+            let count = recorder.read(&mut buf);
+            if count == 0 {
+                break;
             }
+            update.extend_from_slice(&buf[..count]);
         }
+        let header: Vec<i32> = update[..16].chunks(4)
+            .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(header, [102, 1, 3, 2]);
+        let pixels: Vec<u32> = update[16..].chunks(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(pixels, [color.data; 6]);
+        assert_eq!(recorder.queued(), 0);
+    }
 
-        // redrawn by handle_after
+    #[test]
+    fn recorder_receives_synced_window() {
+        // a borderless window at (102, 1) on a display that starts at x 100
+        let mut window = Window::new(102, 1, 3, 2, 1, Rc::new(Config::default()));
+        window.set_flag(ORBITAL_FLAG_BORDERLESS, true);
+        let mut redraws = Vec::new();
+        window_synced(&mut redraws, &mut window);
+        assert!(window.painted);
 
-        Ok(())
+        let mut damage = Vec::new();
+        for rect in redraws {
+            schedule(&mut damage, rect);
+        }
+        compact(&mut damage);
+        let screen_rect = Rect::new(100, 0, 8, 6);
+        let mut screen = Image::new(8, 6);
+        let color = Color::rgb(0x12, 0x34, 0x56);
+        screen.rect(2, 1, 3, 2, color);
+
+        let mut recorders = BTreeMap::from([(1, Recorder::new())]);
+        for rect in sync_rects(&damage, &screen_rect) {
+            assert!(!record_display(&mut recorders, &screen, 100, 0, &rect, RECORD_QUEUE_BYTES));
+        }
+
+        // the recording gets just the window's region
+        let recorder = recorders.get_mut(&1).unwrap_or_else(|| panic!("recorder 1"));
+        assert_eq!(recorder.queued(), 16 + 3 * 2 * 4);
+        let mut update = vec![0; recorder.queued()];
+        assert_eq!(recorder.read(&mut update), update.len());
+        let header: Vec<i32> = update[..16].chunks(4)
+            .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(header, [102, 1, 3, 2]);
+        assert!(update[16..].chunks(4).all(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) == color.data));
     }
 
-    fn window_new(&mut self, mut x: i32, mut y: i32,
-                  width: i32, height: i32,
-                  flags: &str,
-                  title: String) -> Result<usize> {
-        let id = self.scheme.next_id as usize;
-        self.scheme.next_id += 1;
-        if self.scheme.next_id < 0 {
-            //TODO: should this be an error?
-            self.scheme.next_id = 1;
-        }
+    #[test]
+    fn slow_recorder_drops_unread_updates() {
+        let mut recorder = Recorder::new();
+        assert!(recorder.push(vec![1; 16], 40));
+        assert!(recorder.push(vec![2; 16], 40));
+        let mut buf = [0; 4];
+        assert_eq!(recorder.read(&mut buf), 4);
+
+        // the update being read is finished, the one not started yet is dropped
+        assert!(! recorder.push(vec![3; 16], 40));
+        assert_eq!(recorder.queued(), 12);
+        // and so are the updates until the keyframe, which are in it
+        assert!(recorder.push(vec![4; 16], 40));
+        assert_eq!(recorder.queued(), 12);
+        let mut buf = [0; 32];
+        assert_eq!(recorder.read(&mut buf), 12);
+        assert_eq!(&buf[..12], &[1; 12]);
+
+        // once caught up the keyframe fits, until the client falls behind again
+        recorder.composited();
+        assert!(recorder.push(vec![5; 32], 40));
+        assert!(! recorder.push(vec![6; 16], 40));
+        assert_eq!(recorder.queued(), 0);
+    }
 
-        // Unfocus previous top window
-        if let Some(id) = self.scheme.order.front() {
-            self.focus(*id, false);
-        }
+    #[test]
+    fn record_limit_fits_a_keyframe_of_large_displays() {
+        let screens = [Rect::new(0, 0, 7680, 4320), Rect::new(7680, 0, 3840, 2160)];
+        let keyframe = 16 + 7680 * 4320 * 4 + 16 + 3840 * 2160 * 4;
+        assert!(keyframe > RECORD_QUEUE_BYTES);
+        assert_eq!(record_limit(&screens), keyframe + RECORD_QUEUE_BYTES);
+    }
 
-        let mut window = Window::new(x, y, width, height, self.scheme.scale, Rc::clone(&self.scheme.config));
+    #[test]
+    fn panel_button_of_hidden_tab_switches_from_active_tab() {
+        let config = Rc::new(Config::default());
+        let mut group = TabGroup::new(1);
+        group.tabs.push(2);
+        let tab_groups = BTreeMap::from([(7, group)]);
+
+        let mut window = Window::new(0, 0, 100, 100, 1, config);
+        window.title = "hidden".to_string();
+        window.tab_group = Some(7);
+        window.tab_hidden = true;
+        assert!(panel_lists(&window));
+        assert_eq!(shown_tab(&window, &tab_groups), Some(1));
+
+        // the active tab, or a window outside any group, is focused as it is
+        window.tab_hidden = false;
+        assert_eq!(shown_tab(&window, &tab_groups), None);
+        window.tab_group = None;
+        window.tab_hidden = true;
+        assert_eq!(shown_tab(&window, &tab_groups), None);
+    }
 
-        for flag in flags.chars() {
-            window.set_flag(flag, true);
+    #[test]
+    fn window_list_shows_minimized_windows_last() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        for id in 1..=4 {
+            let mut window = Window::new(0, 0, 100, 100, 1, Rc::clone(&config));
+            window.title = format!("window {}", id);
+            windows.insert(id, window);
         }
+        windows.get_mut(&2).unwrap_or_else(|| panic!("window 2")).minimized = true;
+        // windows without a title aren't listed, minimized or not
+        windows.insert(5, Window::new(0, 0, 100, 100, 1, config));
+        windows.get_mut(&5).unwrap_or_else(|| panic!("window 5")).minimized = true;
 
-        window.title = title;
-        window.render_title(&self.scheme.font);
+        let order = VecDeque::from([2, 4, 5, 1, 3]);
+        assert_eq!(window_list(&order, &windows), (vec![4, 1, 3], vec![2]));
 
-        if x < 0 && y < 0 {
-            // Automatic placement
-            window.x = cmp::max(0, (self.orb.image().width() - width)/2);
-            window.y = cmp::max(window.title_rect().height(), (self.orb.image().height() - height)/2);
-        }
+        // once restored it is switched to from its place in the order again
+        windows.get_mut(&2).unwrap_or_else(|| panic!("window 2")).minimized = false;
+        assert_eq!(window_list(&order, &windows), (vec![2, 4, 1, 3], vec![]));
+    }
 
-        // Redraw new window
-        schedule(&mut self.scheme.redraws, window.title_rect());
-        schedule(&mut self.scheme.redraws, window.rect());
+    #[test]
+    fn screenshot_is_bgra_rows() {
+        let mut screen = Image::new(3, 2);
+        screen.pixel(2, 1, Color::rgb(0x11, 0x22, 0x33));
+        let data = screenshot(&screen);
+        assert_eq!(data.len(), 3 * 2 * 4);
+        // the last pixel of the second row
+        assert_eq!(&data[20..], [0x33, 0x22, 0x11, 0xFF]);
+        assert_eq!(&data[..4], [0, 0, 0, 0xFF]);
+    }
 
-        // Add to zorder as appropriate
-        match window.zorder {
-            WindowZOrder::Front | WindowZOrder::Normal => {
-                self.scheme.order.push_front(id);
-            },
-            WindowZOrder::Back => {
-                self.scheme.order.push_back(id);
-            }
-        }
+    #[test]
+    fn pixel_query_reads_the_display_under_the_point() {
+        let red = Color::rgb(0xFF, 0, 0);
+        let blue = Color::rgb(0, 0, 0xFF);
+        let mut left = Image::new(4, 4);
+        left.rect(0, 0, 2, 2, red);
+        let mut right = Image::from_color(4, 2, blue);
+        right.pixel(3, 1, red);
+        let screens = [(Rect::new(0, 0, 4, 4), &left), (Rect::new(4, 0, 4, 2), &right)];
+
+        assert_eq!(pixel_query(&screens, 1, 1), Some(b"#FFFF0000\n".to_vec()));
+        assert_eq!(pixel_query(&screens, 3, 3), Some(b"#FF000000\n".to_vec()));
+        assert_eq!(pixel_query(&screens, 5, 0), Some(b"#FF0000FF\n".to_vec()));
+        assert_eq!(pixel_query(&screens, 7, 1), Some(b"#FFFF0000\n".to_vec()));
+        // off the screens, the nearest pixel is read
+        assert_eq!(pixel_query(&screens, -10, -10), Some(b"#FFFF0000\n".to_vec()));
+        assert_eq!(pixel_query(&screens, 20, 1), Some(b"#FFFF0000\n".to_vec()));
+        assert_eq!(pixel_query(&screens, 7, 3), Some(b"#FFFF0000\n".to_vec()));
+        assert_eq!(pixel_query(&screens, i32::MIN, i32::MIN), Some(b"#FFFF0000\n".to_vec()));
+        assert_eq!(pixel_query(&screens, i32::MAX, 0), Some(b"#FF0000FF\n".to_vec()));
+        let none: &[(Rect, &Image)] = &[];
+        assert_eq!(pixel_query(none, 0, 0), None);
+    }
 
-        self.scheme.windows.insert(id, window);
+    #[test]
+    fn far_apart_damage_is_synced_separately() {
+        let screens = [Rect::new(0, 0, 3840, 2160), Rect::new(3840, 0, 1920, 1080)];
+        let mut damage = Vec::new();
+        schedule(&mut damage, Rect::new(0, 0, 16, 16));
+        schedule(&mut damage, Rect::new(3824, 2144, 16, 16));
+        compact(&mut damage);
+
+        // two small syncs rather than one of the whole screen
+        let syncs = sync_rects(&damage, &screens[0]);
+        assert_eq!(syncs, [Rect::new(0, 0, 16, 16), Rect::new(3824, 2144, 16, 16)]);
+        assert!(sync_rects(&damage, &screens[1]).is_empty());
+
+        // damage across both displays syncs its part of each
+        let across = [Rect::new(3830, 10, 20, 20)];
+        assert_eq!(sync_rects(&across, &screens[0]), [Rect::new(3830, 10, 10, 20)]);
+        assert_eq!(sync_rects(&across, &screens[1]), [Rect::new(3840, 10, 10, 20)]);
+    }
 
-        // Focus new top window
-        if let Some(id) = self.scheme.order.front() {
-            self.focus(*id, true);
+    #[test]
+    fn windows_of_other_clients_are_not_their_own() {
+        let config = Rc::new(Config::default());
+        let mut windows = BTreeMap::new();
+        for (id, owner) in [(1, 10), (2, 10), (3, 20)] {
+            let mut window = Window::new(0, 0, 100, 100, 1, Rc::clone(&config));
+            window.owner = owner;
+            windows.insert(id, window);
         }
+        assert!(same_owner(&windows, 1, 2));
+        assert!(! same_owner(&windows, 1, 3));
+        assert!(! same_owner(&windows, 3, 2));
+        // nor is a window that doesn't exist
+        assert!(! same_owner(&windows, 1, 4));
+    }
 
-        // Ensure mouse cursor is correct
-        let event = MouseEvent {
-            x: self.scheme.cursor_x,
-            y: self.scheme.cursor_y,
-        };
-        self.mouse_event(event);
-
-        Ok(id)
+    #[test]
+    fn shortcuts_list_shows_configured_keys() {
+        let mut shortcuts = ShortcutAction::defaults();
+        let list = shortcuts_list(&shortcuts);
+        assert_eq!(list[0], "Super-Q: Quit current window");
+        assert!(list.contains(&"Super-M / Super-ENTER: Toggle window max (maximize or restore)".to_string()));
+        assert!(list.contains(&"Super-Shift-left_arrow: Tile window to left".to_string()));
+
+        // rebinding an action shows its new keys, and an unbound one isn't listed
+        shortcuts.insert(ShortcutAction::QuitWindow, vec![KeyCombo { scancode: orbclient::K_W, ctrl: true, super_key: true, ..KeyCombo::default() }]);
+        shortcuts.insert(ShortcutAction::Peek, Vec::new());
+        let list = shortcuts_list(&shortcuts);
+        assert_eq!(list[0], "Super-Ctrl-W: Quit current window");
+        assert!(! list.iter().any(|line| line.ends_with("while held")));
+        assert_eq!(list.len(), ShortcutAction::NAMES.len() + 1);
+        assert_eq!(list.last().map(String::as_str), Some("Shift five times: Toggle sticky keys"));
     }
 }
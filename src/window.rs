@@ -1,32 +1,164 @@
-use orbclient::{Color, Event, Renderer};
-use orbfont::Font;
+use orbclient::{Color, Event, Mode, MoveEvent, QuitEvent, Renderer, ResizeEvent, ScrollEvent};
+use orbfont::{Font, Text};
 use crate::core::{
+    Anchor,
     Properties,
+    Rotation,
+    SizeHints,
+    StackRelation,
     display::Display,
     image::{Image, ImageAligned},
     rect::Rect,
     self
 };
 
+use std::cell::Cell;
 use std::cmp::{min, max};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::mem;
 
 use std::rc::Rc;
 
 // use theme::{BAR_COLOR, BAR_HIGHLIGHT_COLOR, TEXT_COLOR, TEXT_HIGHLIGHT_COLOR};
-use crate::config::Config;
+use crate::config::{Config, MAX_BLUR_RADIUS};
 
 //TODO: move to orbclient?
 pub const ORBITAL_FLAG_ASYNC: char = 'a';
 pub const ORBITAL_FLAG_BACK: char = 'b';
+/// Have orbital ask the user to confirm before the window is asked to quit, for apps that want
+/// an "Are you sure?" without a dialog of their own
+pub const ORBITAL_FLAG_CONFIRM_CLOSE: char = 'c';
+/// Hold back mouse motion and scroll events until the input being handled is done with and its
+/// frame composited, delivering only the latest state then. This saves apps that only care about
+/// the current state, such as games, from working through a backlog of events, for up to a frame
+/// of added latency. Any other event delivers the held back ones right away, before itself
+pub const ORBITAL_FLAG_FRAME_EVENTS: char = 'e';
 pub const ORBITAL_FLAG_FRONT: char = 'f';
+/// Blur what is behind a transparent window, like frosted glass
+pub const ORBITAL_FLAG_BLUR: char = 'g';
 pub const ORBITAL_FLAG_HIDDEN: char = 'h';
+/// Let all mouse input pass through the window to the windows below it
+pub const ORBITAL_FLAG_INPUT_TRANSPARENT: char = 'i';
+/// Reported while the window is minimized, see [Window::minimized]. Setting it minimizes the
+/// window, and clearing it restores it
+pub const ORBITAL_FLAG_MINIMIZED: char = 'k';
 pub const ORBITAL_FLAG_BORDERLESS: char = 'l';
 pub const ORBITAL_FLAG_MAXIMIZED: char = 'm';
+pub const ORBITAL_FLAG_NO_FOCUS: char = 'n';
+/// Reported while the window is suspended with `SUSPEND,1`, see [Window::suspended]
+pub const ORBITAL_FLAG_SUSPENDED: char = 'p';
 pub const ORBITAL_FLAG_RESIZABLE: char = 'r';
+/// Acknowledge the resizes orbital asks for with `ACKSIZE,<serial>`, using the serial of the
+/// [ORBITAL_EVENT_ACKSIZE] that follows the resize event. Until then the window is shown where
+/// and as it was, so that it doesn't show up at its new place before it has redrawn at its new size
+pub const ORBITAL_FLAG_SYNC_RESIZE: char = 's';
 pub const ORBITAL_FLAG_TRANSPARENT: char = 't';
 pub const ORBITAL_FLAG_UNCLOSABLE: char = 'u';
 
+/// The window flags as a bitmask, as read from `orbital:flags/<id>`, for clients that would
+/// rather not parse the flags of fpath. Each flag keeps its bit, so the values are stable
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct WindowFlags(pub u32);
+
+impl WindowFlags {
+    pub const ASYNC: WindowFlags = WindowFlags(1 << 0);
+    pub const BACK: WindowFlags = WindowFlags(1 << 1);
+    pub const CONFIRM_CLOSE: WindowFlags = WindowFlags(1 << 2);
+    pub const FRAME_EVENTS: WindowFlags = WindowFlags(1 << 3);
+    pub const FRONT: WindowFlags = WindowFlags(1 << 4);
+    pub const BLUR: WindowFlags = WindowFlags(1 << 5);
+    pub const HIDDEN: WindowFlags = WindowFlags(1 << 6);
+    pub const INPUT_TRANSPARENT: WindowFlags = WindowFlags(1 << 7);
+    pub const BORDERLESS: WindowFlags = WindowFlags(1 << 8);
+    pub const MAXIMIZED: WindowFlags = WindowFlags(1 << 9);
+    pub const NO_FOCUS: WindowFlags = WindowFlags(1 << 10);
+    pub const SUSPENDED: WindowFlags = WindowFlags(1 << 11);
+    pub const RESIZABLE: WindowFlags = WindowFlags(1 << 12);
+    pub const SYNC_RESIZE: WindowFlags = WindowFlags(1 << 13);
+    pub const TRANSPARENT: WindowFlags = WindowFlags(1 << 14);
+    pub const UNCLOSABLE: WindowFlags = WindowFlags(1 << 15);
+    pub const MINIMIZED: WindowFlags = WindowFlags(1 << 16);
+
+    /// Each flag with its character, in the order the flags string of fpath lists them
+    const CHARS: &'static [(char, WindowFlags)] = &[
+        (ORBITAL_FLAG_ASYNC, WindowFlags::ASYNC),
+        (ORBITAL_FLAG_BLUR, WindowFlags::BLUR),
+        (ORBITAL_FLAG_BORDERLESS, WindowFlags::BORDERLESS),
+        (ORBITAL_FLAG_CONFIRM_CLOSE, WindowFlags::CONFIRM_CLOSE),
+        (ORBITAL_FLAG_FRAME_EVENTS, WindowFlags::FRAME_EVENTS),
+        (ORBITAL_FLAG_HIDDEN, WindowFlags::HIDDEN),
+        (ORBITAL_FLAG_INPUT_TRANSPARENT, WindowFlags::INPUT_TRANSPARENT),
+        (ORBITAL_FLAG_MAXIMIZED, WindowFlags::MAXIMIZED),
+        (ORBITAL_FLAG_MINIMIZED, WindowFlags::MINIMIZED),
+        (ORBITAL_FLAG_NO_FOCUS, WindowFlags::NO_FOCUS),
+        (ORBITAL_FLAG_RESIZABLE, WindowFlags::RESIZABLE),
+        (ORBITAL_FLAG_SUSPENDED, WindowFlags::SUSPENDED),
+        (ORBITAL_FLAG_SYNC_RESIZE, WindowFlags::SYNC_RESIZE),
+        (ORBITAL_FLAG_TRANSPARENT, WindowFlags::TRANSPARENT),
+        (ORBITAL_FLAG_UNCLOSABLE, WindowFlags::UNCLOSABLE),
+        (ORBITAL_FLAG_BACK, WindowFlags::BACK),
+        (ORBITAL_FLAG_FRONT, WindowFlags::FRONT),
+    ];
+
+    pub fn contains(self, other: WindowFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: WindowFlags) {
+        self.0 |= other.0;
+    }
+}
+
+/// The flags of a flags string, ignoring the characters that aren't flags
+impl From<&str> for WindowFlags {
+    fn from(flags: &str) -> Self {
+        let mut bits = WindowFlags::default();
+        for (_, flag) in WindowFlags::CHARS.iter().filter(|(c, _)| flags.contains(*c)) {
+            bits.insert(*flag);
+        }
+        bits
+    }
+}
+
+/// The flags string of fpath
+impl fmt::Display for WindowFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (c, _) in WindowFlags::CHARS.iter().filter(|(_, flag)| self.contains(*flag)) {
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Event sent once after a window that requested a frame callback has been composited
+//TODO: move to orbclient?
+pub const ORBITAL_EVENT_FRAME: i64 = 0x2000_0000;
+
+/// Event sent when a window moves to a display of another scale, with the new scale in `a`, so
+/// that the client can render its contents at that scale
+//TODO: move to orbclient?
+pub const ORBITAL_EVENT_SCALE: i64 = 0x2000_0001;
+
+/// Event sent after a resize event to a window with [ORBITAL_FLAG_SYNC_RESIZE], with the serial to
+/// acknowledge the resize with in `a`
+//TODO: move to orbclient?
+pub const ORBITAL_EVENT_ACKSIZE: i64 = 0x2000_0002;
+
+/// Identifies a group of windows shown as tabs in one title bar
+pub type GroupId = usize;
+
+// A resize orbital asked a window with ORBITAL_FLAG_SYNC_RESIZE for, which the client hasn't
+// acknowledged yet
+struct PendingResize {
+    serial: u32,
+    // Where the window moves once the resize is acknowledged
+    x: i32,
+    y: i32,
+    // The buffer from before the client resized it, which is shown until then
+    shown: Option<ImageAligned>,
+}
+
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum WindowZOrder {
     Back,
@@ -34,30 +166,156 @@ pub enum WindowZOrder {
     Front,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TilePosition {
+    LeftHalf,
+    TopHalf,
+    RightHalf,
+    BottomHalf,
+    FullScreen,
+}
+
+impl TilePosition {
+    /// Return the part of `area` that is covered by a window tiled to this position
+    pub fn rect(&self, area: &Rect) -> Rect {
+        let half_width = area.width() / 2;
+        let half_height = area.height() / 2;
+        match self {
+            TilePosition::LeftHalf => Rect::new(area.left(), area.top(), half_width, area.height()),
+            TilePosition::RightHalf => Rect::new(area.left() + half_width, area.top(), half_width, area.height()),
+            TilePosition::TopHalf => Rect::new(area.left(), area.top(), area.width(), half_height),
+            TilePosition::BottomHalf => Rect::new(area.left(), area.top() + half_height, area.width(), half_height),
+            TilePosition::FullScreen => *area,
+        }
+    }
+
+    /// The other half of the area, None for [TilePosition::FullScreen] which has none
+    pub fn opposite(&self) -> Option<TilePosition> {
+        match self {
+            TilePosition::LeftHalf => Some(TilePosition::RightHalf),
+            TilePosition::RightHalf => Some(TilePosition::LeftHalf),
+            TilePosition::TopHalf => Some(TilePosition::BottomHalf),
+            TilePosition::BottomHalf => Some(TilePosition::TopHalf),
+            TilePosition::FullScreen => None,
+        }
+    }
+}
+
+/// The part of a window, or of its decorations, under a point
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowRegion {
+    Content,
+    Title,
+    BottomLeftCorner,
+    BottomRightCorner,
+    LeftBorder,
+    RightBorder,
+    BottomBorder,
+}
+
 pub struct Window {
     pub x: i32,
     pub y: i32,
     pub scale: i32,
     pub title: String,
+    /// Secondary text drawn right-aligned in the title bar, before the buttons
+    pub subtitle: String,
+    /// Where the client's text cursor is, relative to the window contents, for placing the
+    /// candidate popup of an input method next to it
+    pub text_cursor: Option<(i32, i32)>,
+    /// The parts of the contents that take mouse input, relative to the contents. Clicks on the
+    /// rest pass through to the windows below it. Empty takes input everywhere
+    pub input_shape: Vec<Rect>,
+    /// The window whose contents are shown in place of this window's own, scaled to fit
+    pub mirror: Option<usize>,
+    /// What is drawn in place of the contents while mirroring: the scaled copy of the mirrored
+    /// window, or a placeholder once that window closed
+    pub mirrored: Option<Image>,
     pub asynchronous: bool,
     pub borderless: bool,
     pub hidden: bool,
+    /// Hidden at the user's request until restored, such as from a taskbar
+    pub minimized: bool,
+    /// Not drawn nor hit by the mouse until resumed, like a hidden window, but without the
+    /// client being told anything changed, for debugging or to save power
+    pub suspended: bool,
+    /// Rolled up to just its title bar, the contents are neither drawn nor hit by the mouse
+    pub shaded: bool,
+    /// How opaque the contents are drawn, from 0 for invisible to 255 for as they are. Below 255
+    /// the window is blended over what is behind it, transparent or not
+    pub opacity: u8,
+    /// Covering its whole display without decorations, until it is restored
+    pub fullscreen: bool,
+    /// The tab group the window is in, which it shares its title bar and place on screen with
+    pub tab_group: Option<GroupId>,
+    /// In a tab group but not the active tab, so hidden behind the one that is
+    pub tab_hidden: bool,
     pub resizable: bool,
     pub transparent: bool,
+    /// Blur the windows and background behind the transparent parts of the window
+    pub blur: bool,
+    /// Has no close button and is never asked to quit by orbital
     pub unclosable: bool,
+    /// Ask the user before closing, see [ORBITAL_FLAG_CONFIRM_CLOSE]
+    pub confirm_close: bool,
+    /// Never take keyboard focus, not even when clicked, such as docks and tooltips
+    pub no_focus: bool,
+    /// Drawn like any other window, but never hit by the mouse and never focused
+    pub input_transparent: bool,
     pub zorder: WindowZOrder,
-    pub restore: Option<Rect>,
+    /// Keep the window directly above or below another window, overriding the z-order buckets
+    pub stack: Option<StackRelation>,
+    /// Sizes the window can be resized to
+    pub size_hints: SizeHints,
+    /// How the buffer is turned when drawn. On its side, the window can't be resized by dragging
+    /// its borders or by tiling, as those sizes are on screen rather than of the buffer
+    pub rotation: Rotation,
+    /// Floating geometry to return to when un-tiled, and the position the window is tiled to
+    pub restore: Option<(Rect, TilePosition)>,
+    /// When the window was last focused, counting up with every focus change. 0 if it never was
+    pub focused_at: u64,
+    /// The window that had focus when this one opened and took it, which gets focus back when
+    /// this one closes while focused
+    pub opened_from: Option<usize>,
+    /// The pid of the client that opened the window, which other clients can't use it as their own
+    pub owner: usize,
     image: ImageAligned,
     title_image: Image,
     title_image_unfocused: Image,
+    subtitle_image: Image,
     pub events: VecDeque<Event>,
     pub notified_read: bool,
+    /// Send an [ORBITAL_EVENT_FRAME] the next time the window is composited
+    pub frame_requested: bool,
     //TODO: implement better clipboard mechanism
     pub clipboard_seek: usize,
     pub mouse_cursor: bool,
     pub mouse_grab: bool,
     pub mouse_relative: bool,
+    /// Keep the cursor shown at the center of the window while in relative mode, instead of
+    /// hiding it
+    pub mouse_relative_cursor: bool,
     pub maps: usize,
+    /// The client has synced the window at least once, so it shows what it drew
+    pub painted: bool,
+    /// Part of the image changed since each reader last read the contents back, in window
+    /// coordinates, by the reader's query handle. A reader without an entry hasn't asked for them
+    /// yet
+    pub contents_dirty: BTreeMap<usize, Rect>,
+    /// Hold back motion events until the end of the pass, see [ORBITAL_FLAG_FRAME_EVENTS]
+    pub frame_events: bool,
+    // The motion events held back until the next frame
+    held_events: VecDeque<Event>,
+    /// Wait for resizes to be acknowledged, see [ORBITAL_FLAG_SYNC_RESIZE]
+    pub sync_resize: bool,
+    // The serial of the last resize that had to be acknowledged
+    resize_serial: u32,
+    pending_resize: Option<PendingResize>,
+    /// Factor the window's scroll events are multiplied by, on top of
+    /// [Config::scroll_sensitivity]
+    pub scroll_factor: f32,
+    // The fractions of scroll deltas left over by scaling, added to the next scroll event
+    scroll_remainder: (f32, f32),
 
     config: Rc<Config>
 }
@@ -74,80 +332,170 @@ impl Window {
             y,
             scale,
             title: String::new(),
+            subtitle: String::new(),
+            text_cursor: None,
+            input_shape: Vec::new(),
+            mirror: None,
+            mirrored: None,
             asynchronous: false,
             borderless: false,
             hidden: false,
+            minimized: false,
+            suspended: false,
+            opacity: 255,
+            shaded: false,
+            fullscreen: false,
+            tab_group: None,
+            tab_hidden: false,
             resizable: false,
             transparent: false,
+            blur: false,
             unclosable: false,
+            confirm_close: false,
+            no_focus: false,
+            input_transparent: false,
             zorder: WindowZOrder::Normal,
+            stack: None,
+            size_hints: SizeHints::default(),
+            rotation: Rotation::None,
             restore: None,
+            focused_at: 0,
+            opened_from: None,
+            owner: 0,
             // TODO: get a system constant for the page size
             image: ImageAligned::new(w, h, 4096), // Ensure that image data is page aligned at beginning and end
             title_image: Image::new(0, 0),
             title_image_unfocused: Image::new(0, 0),
+            subtitle_image: Image::new(0, 0),
             events: VecDeque::new(),
             notified_read: false,
+            frame_requested: false,
             //TODO: implement better clipboard mechanism
             clipboard_seek: 0,
             mouse_cursor: true,
             mouse_grab: false,
             mouse_relative: false,
+            mouse_relative_cursor: false,
             maps: 0,
+            painted: false,
+            contents_dirty: BTreeMap::new(),
+            frame_events: false,
+            held_events: VecDeque::new(),
+            sync_resize: false,
+            resize_serial: 0,
+            pending_resize: None,
+            scroll_factor: 1.0,
+            scroll_remainder: (0.0, 0.0),
             config
         }
     }
 
+    // The buffer shown on screen, which is the one from before a resize until it is acknowledged
+    fn shown(&self) -> &ImageAligned {
+        self.pending_resize.as_ref()
+            .and_then(|pending| pending.shown.as_ref())
+            .unwrap_or(&self.image)
+    }
+
+    /// The width on screen, which is the height of the buffer when it is rotated sideways
     pub fn width(&self) -> i32 {
-        self.image.width()
+        if self.rotation.sideways() { self.shown().height() } else { self.shown().width() }
     }
 
+    /// The height on screen, which is the width of the buffer when it is rotated sideways
     pub fn height(&self) -> i32 {
-        self.image.height()
+        if self.rotation.sideways() { self.shown().width() } else { self.shown().height() }
+    }
+
+    /// The size of the buffer, as the window sees itself
+    pub fn buffer_size(&self) -> (i32, i32) {
+        (self.image.width(), self.image.height())
+    }
+
+    /// The buffer pixel shown at (x, y) on screen, relative to the top left of the window
+    pub fn buffer_point(&self, x: i32, y: i32) -> (i32, i32) {
+        match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Cw90 => (y, self.width() - 1 - x),
+            Rotation::Cw180 => (self.width() - 1 - x, self.height() - 1 - y),
+            Rotation::Cw270 => (self.height() - 1 - y, x),
+        }
+    }
+
+    /// Whether the window is shown, it is neither hidden, minimized, suspended nor behind another
+    /// tab
+    pub fn visible(&self) -> bool {
+        ! self.hidden && ! self.minimized && ! self.suspended && ! self.tab_hidden
     }
 
     pub fn rect(&self) -> Rect {
-        if self.hidden {
+        if ! self.visible() {
             Rect::new(self.x, self.y, 0, 0)
+        } else if self.shaded {
+            Rect::new(self.x, self.y, self.width(), 0)
         } else {
             Rect::new(self.x, self.y, self.width(), self.height())
         }
     }
 
+    /// Whether the window has a title bar and borders
+    pub fn decorated(&self) -> bool {
+        ! self.borderless && ! self.fullscreen
+    }
+
     pub fn title_rect(&self) -> Rect {
-        if self.borderless || self.hidden {
+        if ! self.decorated() || ! self.visible() {
             Rect::new(self.x, self.y, 0, 0)
         } else {
             Rect::new(self.x, self.y - TITLE_HEIGHT * self.scale, self.width(), TITLE_HEIGHT * self.scale)
         }
     }
 
+    /// The window and its title bar, whether they are shown or not
+    pub fn frame_rect(&self) -> Rect {
+        let title_height = if self.decorated() { TITLE_HEIGHT * self.scale } else { 0 };
+        Rect::new(self.x, self.y - title_height, self.width(), self.height() + title_height)
+    }
+
     pub fn bottom_border_rect(&self) -> Rect {
-        if self.resizable {
+        if self.border_resizable() {
             Rect::new(self.x, self.y + self.height(), self.width(), 8 * self.scale)
         } else {
             Rect::new(-1, -1, 0, 0)
         }
     }
 
+    /// Whether orbital can resize the window on screen, such as by dragging its borders
+    pub fn border_resizable(&self) -> bool {
+        self.resizable && ! self.rotation.sideways() && ! self.shaded && ! self.fullscreen
+    }
+
+    // The size of the square corner resize handles, which extend from the outer corner of the
+    // borders over the sides
+    fn corner_grab_size(&self) -> i32 {
+        max(8, self.config.corner_grab_size as i32) * self.scale
+    }
+
     pub fn bottom_left_border_rect(&self) -> Rect {
-        if self.resizable {
-            Rect::new(self.x - 8 * self.scale, self.y + self.height(), 8 * self.scale, 8 * self.scale)
+        if self.border_resizable() {
+            let size = self.corner_grab_size();
+            Rect::new(self.x - 8 * self.scale, self.y + self.height() + 8 * self.scale - size, size, size)
         } else {
             Rect::new(-1, -1, 0, 0)
         }
     }
 
     pub fn bottom_right_border_rect(&self) -> Rect {
-        if self.resizable {
-            Rect::new(self.x + self.width(), self.y + self.height(), 8 * self.scale, 8 * self.scale)
+        if self.border_resizable() {
+            let size = self.corner_grab_size();
+            Rect::new(self.x + self.width() + 8 * self.scale - size, self.y + self.height() + 8 * self.scale - size, size, size)
         } else {
             Rect::new(-1, -1, 0, 0)
         }
     }
 
     pub fn left_border_rect(&self) -> Rect {
-        if self.resizable {
+        if self.border_resizable() {
             Rect::new(self.x - 8 * self.scale, self.y, 8 * self.scale, self.height())
         } else {
             Rect::new(-1, -1, 0, 0)
@@ -155,19 +503,146 @@ impl Window {
     }
 
     pub fn right_border_rect(&self) -> Rect {
-        if self.resizable {
+        if self.border_resizable() {
             Rect::new(self.x + self.width(), self.y, 8 * self.scale, self.height())
         } else {
             Rect::new(-1, -1, 0, 0)
         }
     }
 
+    /// Whether the title bar is drawn, which with `defer_decorations` waits for the window to be
+    /// painted
+    pub fn title_shown(&self) -> bool {
+        self.painted || ! self.config.defer_decorations
+    }
+
+    /// Whether what is behind the window is blurred when it is drawn
+    pub fn blurred(&self) -> bool {
+        self.transparent && self.blur && self.config.blur_radius > 0 && self.mirrored.is_none()
+    }
+
+    /// The region of the window at (x, y), if any. The contents of the window come first, then the
+    /// resize corners, which can be larger than the borders and overlap them. Contents outside
+    /// of the input shape aren't part of any region, and neither is a title bar that isn't shown
+    pub fn region_at(&self, x: i32, y: i32) -> Option<WindowRegion> {
+        if ! self.visible() {
+            return None;
+        }
+
+        let in_shape = self.input_shape.is_empty()
+            || self.input_shape.iter().any(|rect| rect.contains(x - self.x, y - self.y));
+        let content = if in_shape { self.rect() } else { Rect::default() };
+        let title = if self.title_shown() { self.title_rect() } else { Rect::default() };
+        let regions = [
+            (content, WindowRegion::Content),
+            (title, WindowRegion::Title),
+            (self.bottom_left_border_rect(), WindowRegion::BottomLeftCorner),
+            (self.bottom_right_border_rect(), WindowRegion::BottomRightCorner),
+            (self.left_border_rect(), WindowRegion::LeftBorder),
+            (self.right_border_rect(), WindowRegion::RightBorder),
+            (self.bottom_border_rect(), WindowRegion::BottomBorder),
+        ];
+        regions.iter().find(|(rect, _)| rect.contains(x, y)).map(|&(_, region)| region)
+    }
+
+    pub fn maximized(&self) -> bool {
+        matches!(self.restore, Some((_, TilePosition::FullScreen)))
+    }
+
+    /// Whether the window can be tiled or maximized, which changes its size. Windows that aren't
+    /// resizable keep their size, unless they were tiled before they stopped being resizable and
+    /// can still be restored.
+    pub fn tileable(&self) -> bool {
+        ! self.rotation.sideways() && (self.resizable || self.restore.is_some())
+    }
+
+    /// Keep the window above normal windows, or stop keeping it there, returning whether it is
+    /// kept on top now. Windows kept behind all others, like the desktop, stay where they are
+    pub fn toggle_front(&mut self) -> Option<bool> {
+        self.zorder = match self.zorder {
+            WindowZOrder::Back => return None,
+            WindowZOrder::Normal => WindowZOrder::Front,
+            WindowZOrder::Front => WindowZOrder::Normal,
+        };
+        Some(self.zorder == WindowZOrder::Front)
+    }
+
+    /// Where the client's text cursor is on the screen, if the client reported one
+    pub fn screen_text_cursor(&self) -> Option<(i32, i32)> {
+        self.text_cursor.map(|(x, y)| (self.x + x, self.y + y))
+    }
+
+    /// Whether the window can take keyboard focus
+    pub fn focusable(&self) -> bool {
+        ! self.no_focus && ! self.input_transparent && ! self.minimized && ! self.tab_hidden
+    }
+
+    /// `rect` with its size kept to the size hints, at the same top left corner
+    pub fn constrain_rect(&self, rect: Rect) -> Rect {
+        let (width, height) = self.size_hints.constrain(rect.width(), rect.height());
+        Rect::new(rect.left(), rect.top(), width, height)
+    }
+
+    /// The geometry for tiling the window to `position` in `area`. Maximized, a window with an
+    /// aspect ratio in its size hints is letterboxed rather than stretched to the whole area
+    pub fn tile_rect(&self, position: TilePosition, area: &Rect) -> Rect {
+        match position {
+            TilePosition::FullScreen => self.size_hints.fit_aspect(area),
+            _ => position.rect(area),
+        }
+    }
+
+    /// Tile the window to `position`, where `tile_rect` is the geometry for that position, and
+    /// return the geometry the window should take.
+    ///
+    /// Tiling to the position the window is already tiled to restores the floating geometry the
+    /// window had before it was first tiled. Tiling to a different position re-tiles the window,
+    /// but keeps that original floating geometry for when it is eventually restored. The tiled
+    /// size is kept to the size hints, so a window can end up smaller or larger than its tile.
+    /// A full-screen window is tiled to [TilePosition::FullScreen], so tiling it there restores it.
+    pub fn tile(&mut self, position: TilePosition, tile_rect: Rect) -> Rect {
+        let tile_rect = self.constrain_rect(tile_rect);
+        self.fullscreen = false;
+        match self.restore {
+            Some((restore, tiled)) if tiled == position => {
+                self.restore = None;
+                restore
+            },
+            Some((restore, _)) => {
+                self.restore = Some((restore, position));
+                tile_rect
+            },
+            None => {
+                self.restore = Some((self.rect(), position));
+                tile_rect
+            }
+        }
+    }
+
+    /// Make the window cover all of `screen` without decorations, and return the geometry it
+    /// should take. The floating geometry is kept for when it is restored, like when tiling
+    pub fn fullscreen(&mut self, screen: Rect) -> Rect {
+        let restore = self.restore.map_or(self.rect(), |(restore, _)| restore);
+        self.restore = Some((restore, TilePosition::FullScreen));
+        self.fullscreen = true;
+        self.constrain_rect(screen)
+    }
+
     pub fn max_contains(&self, x: i32, y: i32) -> bool {
-        ! self.borderless && x >= max(self.x + 6 * self.scale, self.x + self.width() - 36 * self.scale)  && y >= self.y - TITLE_HEIGHT * self.scale && x < self.x + self.width() - 18 * self.scale && y < self.y
+        self.decorated() && x >= max(self.x + 6 * self.scale, self.x + self.width() - 36 * self.scale)  && y >= self.y - TITLE_HEIGHT * self.scale && x < self.x + self.width() - 18 * self.scale && y < self.y
     }
 
     pub fn close_contains(&self, x: i32, y: i32) -> bool {
-        ! self.borderless && x >= max(self.x + 6 * self.scale, self.x + self.width() - 18 * self.scale)  && y >= self.y - TITLE_HEIGHT * self.scale && x < self.x + self.width() && y < self.y
+        self.decorated() && x >= max(self.x + 6 * self.scale, self.x + self.width() - 18 * self.scale)  && y >= self.y - TITLE_HEIGHT * self.scale && x < self.x + self.width() && y < self.y
+    }
+
+    /// The area of the title bar the subtitle is drawn in: right-aligned before the buttons and
+    /// clipped so that it doesn't extend left of where the title starts
+    pub fn subtitle_rect(&self) -> Rect {
+        let buttons = if self.resizable { 36 } else if ! self.unclosable { 18 } else { 0 };
+        let right = self.x + self.width() - (buttons + 6) * self.scale;
+        let left = max(self.x + 6 * self.scale, right - self.subtitle_image.width());
+        Rect::new(left, self.y - TITLE_HEIGHT * self.scale + 6 * self.scale, max(0, right - left), self.subtitle_image.height())
     }
 
     pub fn draw_title(&mut self, display: &mut Display, rect: &Rect, focused: bool, window_max: &mut Image, window_close: &mut Image) {
@@ -180,7 +655,19 @@ impl Window {
             display.rect(&title_intersect, if focused { bar_highlight_color } else { bar_color });
 
             let mut x = self.x + 6 * self.scale;
-            let w = max(self.x + 6 * self.scale, self.x + self.width() - 18 * self.scale) - x;
+            let mut w = max(self.x + 6 * self.scale, self.x + self.width() - 18 * self.scale) - x;
+
+            if ! self.subtitle.is_empty() {
+                let subtitle_rect = self.subtitle_rect();
+                // the title gets the space left of the subtitle
+                w = min(w, subtitle_rect.left() - 6 * self.scale - x);
+
+                let image_intersect = rect.intersection(&subtitle_rect);
+                if ! image_intersect.is_empty() {
+                    display.roi(&image_intersect).blend(&self.subtitle_image.roi(&image_intersect.offset(-subtitle_rect.left(), -subtitle_rect.top())));
+                }
+            }
+
             if w > 0 {
                 let title_image = if focused { &mut self.title_image } else { &mut self.title_image_unfocused };
                 let image_rect = Rect::new(x, title_rect.top() + 6 * self.scale, min(w, title_image.width()), title_image.height());
@@ -214,51 +701,233 @@ impl Window {
         }
     }
 
-    pub fn draw(&mut self, display: &mut Display, rect: &Rect) {
+    /// Draw the part of the window in `rect`, darkened by `dim` as described for
+    /// [Config::unfocused_dim]
+    pub fn draw(&mut self, display: &mut Display, rect: &Rect, dim: u8) {
         let self_rect = self.rect();
         let intersect = self_rect.intersection(rect);
+        if let Some(mirrored) = &mut self.mirrored {
+            // it is rendered again when the window is resized, until then only what it covers
+            let bounds = Rect::new(self_rect.left(), self_rect.top(), mirrored.width(), mirrored.height());
+            let intersect = intersect.intersection(&bounds);
+            if ! intersect.is_empty() {
+                display.roi(&intersect).blit(&mirrored.roi(&intersect.offset(-self_rect.left(), -self_rect.top())));
+                if dim > 0 {
+                    display.rect(&intersect, Color::rgba(0, 0, 0, dim));
+                }
+            }
+            return;
+        }
         if ! intersect.is_empty() {
-            if self.transparent {
-                display.roi(&intersect).blend(&self.image.roi(&intersect.offset(-self_rect.left(), -self_rect.top())));
+            let local = intersect.offset(-self_rect.left(), -self_rect.top());
+            let mut rotated = self.rotated(&local);
+            let translucent = self.transparent || self.opacity < 255;
+            let blurred = self.blurred();
+            if translucent && (dim > 0 || self.opacity < 255) {
+                // A dim overlay would cover up what is behind the window, so the window's own
+                // pixels are darkened instead, keeping their alpha, and faded by the opacity
+                let image = match &mut rotated {
+                    Some(image) => image,
+                    None => {
+                        let mut image = Image::new(local.width(), local.height());
+                        image.roi(&Rect::new(0, 0, local.width(), local.height())).blit(&shown_mut(&mut self.image, &mut self.pending_resize).roi(&local));
+                        rotated.insert(image)
+                    },
+                };
+                for color in image.data_mut() {
+                    *color = fade_color(dim_color(*color, dim), self.opacity, self.transparent);
+                }
+            }
+            let source = match &mut rotated {
+                Some(image) => image.roi(&Rect::new(0, 0, local.width(), local.height())),
+                None => shown_mut(&mut self.image, &mut self.pending_resize).roi(&local),
+            };
+
+            if translucent {
+                if blurred {
+                    // The display already holds everything below the window in this rect, which
+                    // covers all of the window on this display, see cover_blurred
+                    let radius = min(self.config.blur_radius, MAX_BLUR_RADIUS) as i32 * self.scale;
+                    display.roi(&intersect).box_blur(radius);
+                }
+                display.roi(&intersect).blend(&source);
             } else {
-                display.roi(&intersect).blit(&self.image.roi(&intersect.offset(-self_rect.left(), -self_rect.top())));
+                display.roi(&intersect).blit(&source);
+                if dim > 0 {
+                    display.rect(&intersect, Color::rgba(0, 0, 0, dim));
+                }
+            }
+        }
+    }
+
+    /// A copy of the window contents as shown on screen, scaled to fit `width` by `height`
+    /// keeping their aspect ratio and centered, with black bars on the sides that aren't covered
+    pub fn mirror_image(&self, width: i32, height: i32) -> Image {
+        let mut image = Image::from_color(width, height, Color::rgb(0, 0, 0));
+        let (source_width, source_height) = (self.width(), self.height());
+        if source_width <= 0 || source_height <= 0 {
+            return image;
+        }
+
+        let (fit_width, fit_height) = if width as i64 * source_height as i64 <= height as i64 * source_width as i64 {
+            (width, (source_height as i64 * width as i64 / source_width as i64) as i32)
+        } else {
+            ((source_width as i64 * height as i64 / source_height as i64) as i32, height)
+        };
+        let (left, top) = ((width - fit_width) / 2, (height - fit_height) / 2);
+
+        let buffer_width = self.shown().width();
+        let source = self.shown().data();
+        let data = image.data_mut();
+        for y in 0..fit_height {
+            let source_y = (y as i64 * source_height as i64 / fit_height as i64) as i32;
+            for x in 0..fit_width {
+                let source_x = (x as i64 * source_width as i64 / fit_width as i64) as i32;
+                let (buffer_x, buffer_y) = self.buffer_point(source_x, source_y);
+                data[((top + y) * width + left + x) as usize] = source[(buffer_y * buffer_width + buffer_x) as usize];
+            }
+        }
+        image
+    }
+
+    // The pixels shown in `local`, a part of the window on screen relative to its top left, when
+    // the buffer is rotated and can't be drawn as it is
+    fn rotated(&self, local: &Rect) -> Option<Image> {
+        if self.rotation == Rotation::None {
+            return None;
+        }
+
+        let width = self.shown().width();
+        let data = self.shown().data();
+        let mut pixels = Vec::with_capacity(local.area() as usize);
+        for y in local.top()..local.bottom() {
+            for x in local.left()..local.right() {
+                let (buffer_x, buffer_y) = self.buffer_point(x, y);
+                pixels.push(data[(buffer_y * width + buffer_x) as usize]);
             }
         }
+        Some(Image::from_data(local.width(), local.height(), pixels.into_boxed_slice()))
     }
 
     pub fn event(&mut self, event: Event) {
-        // Combine or replace the last event for some event types where it improves latency without disrupting logic
-        if let Some(last_event) = self.events.back_mut() {
-            if last_event.code == event.code {
-                match event.code {
-                    // Absolute mouse events, window move, window resize, and screen report events can be replaced
-                    orbclient::EVENT_MOUSE |
-                    orbclient::EVENT_MOVE |
-                    orbclient::EVENT_RESIZE |
-                    orbclient::EVENT_SCREEN => {
-                        *last_event = event;
-                        return;
-                    }
-                    // Relative mouse events and scroll events can be combined with addition
-                    orbclient::EVENT_MOUSE_RELATIVE | orbclient::EVENT_SCROLL => {
-                        last_event.a += event.a;
-                        last_event.b += event.b;
-                        return;
-                    }
-                    // Other events cannot be combined or replaced
-                    _ => {}
-                }
+        if self.frame_events {
+            if matches!(event.code, orbclient::EVENT_MOUSE | orbclient::EVENT_MOUSE_RELATIVE | orbclient::EVENT_SCROLL) {
+                push_event(&mut self.held_events, event);
+                return;
             }
+            self.release_events();
+        }
+        push_event(&mut self.events, event);
+    }
+
+    /// Ask the client to take the geometry `rect` on screen, moving the window there. With
+    /// [ORBITAL_FLAG_SYNC_RESIZE] a change of size also sends an [ORBITAL_EVENT_ACKSIZE], and the
+    /// window stays where and as it was until [Window::ack_resize]
+    pub fn configure(&mut self, rect: Rect) {
+        let (x, y) = (rect.left(), rect.top());
+        let resized = rect.width() != self.width() || rect.height() != self.height();
+        if self.sync_resize && resized {
+            self.resize_serial = self.resize_serial.wrapping_add(1);
+            let shown = self.pending_resize.take().and_then(|pending| pending.shown);
+            self.pending_resize = Some(PendingResize { serial: self.resize_serial, x, y, shown });
+        } else {
+            self.x = x;
+            self.y = y;
         }
 
-        // Push event if not combined or replaced
-        self.events.push_back(event);
+        self.event(MoveEvent { x, y }.to_event());
+        self.event(ResizeEvent { width: rect.width() as u32, height: rect.height() as u32 }.to_event());
+        if let Some(pending) = &self.pending_resize {
+            let mut ack_event = Event::new();
+            ack_event.code = ORBITAL_EVENT_ACKSIZE;
+            ack_event.a = pending.serial as i64;
+            self.event(ack_event);
+        }
+    }
+
+    /// The client has redrawn at the size of the resize with `serial`, so show the window at its
+    /// new place and size. Returns false for any other serial, such as of an earlier resize
+    /// that was overtaken by another one, which is ignored
+    pub fn ack_resize(&mut self, serial: u32) -> bool {
+        if self.pending_resize.as_ref().is_none_or(|pending| pending.serial != serial) {
+            return false;
+        }
+        if let Some(pending) = self.pending_resize.take() {
+            self.x = pending.x;
+            self.y = pending.y;
+        }
+        true
+    }
+
+    /// Deliver the motion events held back, once the events being handled are and the frame they
+    /// caused, if any, has been composited
+    pub fn release_events(&mut self) {
+        for event in mem::take(&mut self.held_events) {
+            push_event(&mut self.events, event);
+        }
+    }
+
+    /// Ask the client to close the window, unless it is unclosable. This only sends a
+    /// [QuitEvent], which the client can ignore, for example to ask about unsaved changes first.
+    /// The window is removed once the client closes it. Returns whether the request was sent.
+    pub fn request_quit(&mut self) -> bool {
+        if self.unclosable {
+            return false;
+        }
+        self.event(QuitEvent.to_event());
+        true
+    }
+
+    /// Scale a scroll event for this window by its scroll factor and `sensitivity`. Fractions of
+    /// a step are kept for the next event, so slow scrolling still adds up to whole steps.
+    pub fn scale_scroll(&mut self, event: ScrollEvent, sensitivity: f32) -> ScrollEvent {
+        let factor = self.scroll_factor * sensitivity;
+        let x = self.scroll_remainder.0 + event.x as f32 * factor;
+        let y = self.scroll_remainder.1 + event.y as f32 * factor;
+        self.scroll_remainder = (x.fract(), y.fract());
+        ScrollEvent { x: x.trunc() as i32, y: y.trunc() as i32 }
     }
 
     pub fn map(&mut self) -> &mut [Color] {
         self.image.data_mut()
     }
 
+    /// Copy out the window contents for the `reader` to read back, the whole image or only the
+    /// part changed since that reader's last read, see [Window::contents_dirty]. Starts with an
+    /// `x,y,width,height` line giving the region in window coordinates, followed by its pixels as
+    /// little endian ARGB `u32`s, row by row with no padding, so the stride is `width * 4` bytes.
+    /// The dirty region is kept until the reader has read all of it, see [Window::contents_read],
+    /// so copying the contents out again before that still has everything changed since.
+    pub fn read_contents(&mut self, reader: usize, dirty_only: bool) -> Vec<u8> {
+        let full = Rect::new(0, 0, self.image.width(), self.image.height());
+        let dirty = *self.contents_dirty.entry(reader).or_insert(full);
+        let rect = if dirty_only { dirty } else { full };
+
+        let mut data = format!("{},{},{},{}\n", rect.left(), rect.top(), rect.width(), rect.height()).into_bytes();
+        data.reserve(rect.area() as usize * 4);
+        for row in self.image.roi(&rect).rows() {
+            for color in row {
+                data.extend_from_slice(&color.data.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    /// The `reader` has read all of the contents last copied out for it, which it is now up to
+    /// date with. A window unchanged since reads as an empty region
+    pub fn contents_read(&mut self, reader: usize) {
+        if let Some(dirty) = self.contents_dirty.get_mut(&reader) {
+            *dirty = Rect::default();
+        }
+    }
+
+    /// Mark `rect` of the image, in window coordinates, as changed for all of its readers
+    pub fn damage_contents(&mut self, rect: Rect) {
+        for dirty in self.contents_dirty.values_mut() {
+            *dirty = dirty.container(&rect);
+        }
+    }
+
     pub fn read(&mut self, buf: &mut [Event]) -> usize {
         for (i, event) in buf.iter_mut().enumerate() {
             *event = match self.events.pop_front() {
@@ -269,31 +938,42 @@ impl Window {
         buf.len()
     }
 
-    pub fn properties(&self) -> Properties {
-        //TODO: avoid allocation
-        let mut flags = String::with_capacity(8);
-        if self.asynchronous { flags.push(ORBITAL_FLAG_ASYNC) }
-        if self.borderless { flags.push(ORBITAL_FLAG_BORDERLESS) }
-        if self.hidden { flags.push(ORBITAL_FLAG_HIDDEN) }
-        if self.restore.is_some() { flags.push(ORBITAL_FLAG_MAXIMIZED) }
-        if self.resizable { flags.push(ORBITAL_FLAG_RESIZABLE) }
-        if self.transparent { flags.push(ORBITAL_FLAG_TRANSPARENT) }
-        if self.unclosable { flags.push(ORBITAL_FLAG_UNCLOSABLE) }
+    pub fn flags(&self) -> WindowFlags {
+        let mut flags = WindowFlags::default();
+        if self.asynchronous { flags.insert(WindowFlags::ASYNC) }
+        if self.blur { flags.insert(WindowFlags::BLUR) }
+        if self.borderless { flags.insert(WindowFlags::BORDERLESS) }
+        if self.confirm_close { flags.insert(WindowFlags::CONFIRM_CLOSE) }
+        if self.frame_events { flags.insert(WindowFlags::FRAME_EVENTS) }
+        if self.hidden { flags.insert(WindowFlags::HIDDEN) }
+        if self.input_transparent { flags.insert(WindowFlags::INPUT_TRANSPARENT) }
+        if self.maximized() { flags.insert(WindowFlags::MAXIMIZED) }
+        if self.minimized { flags.insert(WindowFlags::MINIMIZED) }
+        if self.no_focus { flags.insert(WindowFlags::NO_FOCUS) }
+        if self.resizable { flags.insert(WindowFlags::RESIZABLE) }
+        if self.suspended { flags.insert(WindowFlags::SUSPENDED) }
+        if self.sync_resize { flags.insert(WindowFlags::SYNC_RESIZE) }
+        if self.transparent { flags.insert(WindowFlags::TRANSPARENT) }
+        if self.unclosable { flags.insert(WindowFlags::UNCLOSABLE) }
         match self.zorder {
             WindowZOrder::Back => {
-                 flags.push(ORBITAL_FLAG_BACK)
+                 flags.insert(WindowFlags::BACK)
             },
             WindowZOrder::Normal => {},
             WindowZOrder::Front => {
-                 flags.push(ORBITAL_FLAG_FRONT)
+                 flags.insert(WindowFlags::FRONT)
             },
         }
+        flags
+    }
+
+    pub fn properties(&self) -> Properties {
         Properties {
-            flags,
+            flags: self.flags().to_string(),
             x: self.x,
             y: self.y,
-            width: self.width(),
-            height: self.height(),
+            width: self.image.width(),
+            height: self.image.height(),
             title: &self.title
         }
     }
@@ -301,6 +981,7 @@ impl Window {
     pub fn render_title(&mut self, font: &Font) {
         let text_color = self.config.text_color;
         let text_highlight_color = self.config.text_highlight_color;
+        let antialias = self.config.font_antialias;
 
         let title_render = font.render(&self.title, (TITLE_TEXT_HEIGHT * self.scale) as f32);
 
@@ -308,21 +989,46 @@ impl Window {
 
         self.title_image = Image::from_color(title_render.width() as i32, title_render.height() as i32, color_blank);
         self.title_image.mode().set(orbclient::Mode::Overwrite);
-        title_render.draw(&mut self.title_image, 0, 0, text_highlight_color.into());
+        draw_text(&title_render, &mut self.title_image, 0, 0, text_highlight_color.into(), antialias);
 
         self.title_image_unfocused = Image::from_color(title_render.width() as i32, title_render.height() as i32, color_blank);
         self.title_image_unfocused.mode().set(orbclient::Mode::Overwrite);
-        title_render.draw(&mut self.title_image_unfocused, 0, 0, text_color.into());
+        draw_text(&title_render, &mut self.title_image_unfocused, 0, 0, text_color.into(), antialias);
+
+        let subtitle_render = font.render(&self.subtitle, (TITLE_TEXT_HEIGHT * self.scale) as f32);
+        self.subtitle_image = Image::from_color(subtitle_render.width() as i32, subtitle_render.height() as i32, color_blank);
+        self.subtitle_image.mode().set(orbclient::Mode::Overwrite);
+        draw_text(&subtitle_render, &mut self.subtitle_image, 0, 0, self.config.subtitle_color.into(), antialias);
     }
 
     pub fn set_flag(&mut self, flag: char, value: bool) {
         match flag {
             ORBITAL_FLAG_ASYNC => self.asynchronous = value,
             ORBITAL_FLAG_BACK => self.zorder = if value { WindowZOrder::Back } else { WindowZOrder::Normal },
+            ORBITAL_FLAG_FRAME_EVENTS => {
+                self.frame_events = value;
+                if ! value {
+                    self.release_events();
+                }
+            },
             ORBITAL_FLAG_FRONT => self.zorder = if value { WindowZOrder::Front } else { WindowZOrder::Normal },
             ORBITAL_FLAG_HIDDEN => self.hidden = value,
+            ORBITAL_FLAG_INPUT_TRANSPARENT => self.input_transparent = value,
+            // a window opened with the flag starts out minimized, later changes go through
+            // OrbitalScheme so that they are animated and the focus moves
+            ORBITAL_FLAG_MINIMIZED => self.minimized = value,
+            ORBITAL_FLAG_BLUR => self.blur = value,
             ORBITAL_FLAG_BORDERLESS => self.borderless = value,
+            ORBITAL_FLAG_CONFIRM_CLOSE => self.confirm_close = value,
+            ORBITAL_FLAG_NO_FOCUS => self.no_focus = value,
             ORBITAL_FLAG_RESIZABLE => self.resizable = value,
+            ORBITAL_FLAG_SYNC_RESIZE => {
+                self.sync_resize = value;
+                // a client no longer acknowledging resizes gets the one that is pending applied
+                if let Some(serial) = self.pending_resize.as_ref().map(|pending| pending.serial).filter(|_| ! value) {
+                    self.ack_resize(serial);
+                }
+            },
             ORBITAL_FLAG_TRANSPARENT => self.transparent = value,
             ORBITAL_FLAG_UNCLOSABLE => self.unclosable = value,
             _ => {
@@ -331,52 +1037,166 @@ impl Window {
         }
     }
 
-    pub fn set_size(&mut self, w: i32, h: i32) {
+    /// Resize the window buffer, keeping the content and on screen position of the `anchor`
+    /// corner in place
+    pub fn set_size(&mut self, w: i32, h: i32, anchor: Anchor) {
         if self.maps > 0 {
             log::warn!("resized while {} mapping(s) still held", self.maps);
         }
 
+        // How far the old content moves inside the new buffer
+        let dx = match anchor {
+            Anchor::TopLeft | Anchor::BottomLeft => 0,
+            Anchor::TopRight | Anchor::BottomRight => w - self.image.width(),
+        };
+        let dy = match anchor {
+            Anchor::TopLeft | Anchor::TopRight => 0,
+            Anchor::BottomLeft | Anchor::BottomRight => h - self.image.height(),
+        };
+
         //TODO: Invalidate old mappings
         let mut new_image = ImageAligned::new(w, h, 4096);
         let new_rect = Rect::new(0, 0, w, h);
 
-        let rect = Rect::new(0, 0, self.image.width(), self.image.height());
+        let rect = Rect::new(dx, dy, self.image.width(), self.image.height());
         let intersect = new_rect.intersection(&rect);
         if ! intersect.is_empty() {
-            new_image.roi(&intersect).blit(&self.image.roi(&intersect));
+            new_image.roi(&intersect).blit(&self.image.roi(&intersect.offset(-dx, -dy)));
         }
 
-        self.image = new_image;
+        let old_image = mem::replace(&mut self.image, new_image);
+        self.damage_contents(new_rect);
+        match &mut self.pending_resize {
+            // the old buffer is shown until the resize is acknowledged, and where the window
+            // moves to then already accounts for the anchor
+            Some(pending) => {
+                pending.shown.get_or_insert(old_image);
+            },
+            None => {
+                self.x -= dx;
+                self.y -= dy;
+            }
+        }
+    }
+}
+
+// Window::shown for drawing, borrowing only the fields it needs
+fn shown_mut<'a>(image: &'a mut ImageAligned, pending_resize: &'a mut Option<PendingResize>) -> &'a mut ImageAligned {
+    match pending_resize {
+        Some(PendingResize { shown: Some(shown), .. }) => shown,
+        _ => image,
+    }
+}
+
+/// `color` darkened by `dim`, from 0 for unchanged to 255 for black, with the same alpha
+fn dim_color(color: Color, dim: u8) -> Color {
+    let scale = |channel: u8| (channel as u32 * (255 - dim as u32) / 255) as u8;
+    Color::rgba(scale(color.r()), scale(color.g()), scale(color.b()), color.a())
+}
+
+/// `color` with its alpha scaled by `opacity`, from 0 for invisible to 255 for unchanged. The
+/// alpha of windows that aren't `transparent` isn't used, they are taken to be opaque
+fn fade_color(color: Color, opacity: u8, transparent: bool) -> Color {
+    let alpha = if transparent { color.a() as u32 } else { 255 };
+    Color::rgba(color.r(), color.g(), color.b(), (alpha * opacity as u32 / 255) as u8)
+}
+
+/// Draw rendered text onto an image, with smoothed glyph edges unless `antialias` is false
+pub fn draw_text(text: &Text, image: &mut Image, x: i32, y: i32, color: Color, antialias: bool) {
+    if antialias {
+        text.draw(image, x, y, color);
+    } else {
+        text.draw(&mut Aliased(image), x, y, color);
+    }
+}
+
+// Queue `event`, combining it with or replacing the last queued event for some event types where
+// that improves latency without disrupting logic
+fn push_event(events: &mut VecDeque<Event>, event: Event) {
+    if let Some(last_event) = events.back_mut() {
+        if last_event.code == event.code {
+            match event.code {
+                // Absolute mouse events, window move, window resize, and screen report events can be replaced
+                orbclient::EVENT_MOUSE |
+                orbclient::EVENT_MOVE |
+                orbclient::EVENT_RESIZE |
+                orbclient::EVENT_SCREEN => {
+                    *last_event = event;
+                    return;
+                }
+                // Relative mouse events and scroll events can be combined with addition
+                orbclient::EVENT_MOUSE_RELATIVE | orbclient::EVENT_SCROLL => {
+                    last_event.a += event.a;
+                    last_event.b += event.b;
+                    return;
+                }
+                // Other events cannot be combined or replaced
+                _ => {}
+            }
+        }
+    }
+
+    // Push event if not combined or replaced
+    events.push_back(event);
+}
+
+/// Renderer that snaps the glyph coverage orbfont draws with to fully opaque or transparent
+struct Aliased<'a>(&'a mut Image);
+
+impl Renderer for Aliased<'_> {
+    fn width(&self) -> u32 {
+        Renderer::width(self.0)
+    }
+
+    fn height(&self) -> u32 {
+        Renderer::height(self.0)
+    }
+
+    fn data(&self) -> &[Color] {
+        self.0.data()
+    }
+
+    fn data_mut(&mut self) -> &mut [Color] {
+        self.0.data_mut()
+    }
+
+    fn sync(&mut self) -> bool {
+        self.0.sync()
+    }
+
+    fn mode(&self) -> &Cell<Mode> {
+        self.0.mode()
+    }
+
+    fn pixel(&mut self, x: i32, y: i32, color: Color) {
+        if color.a() >= 128 {
+            self.0.pixel(x, y, Color { data: color.data | 0xFF00_0000 });
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use orbclient::{Color, Event};
-    use crate::window::Window;
+    use orbclient::{Color, Event, KeyEvent, MouseEvent, Renderer, ScrollEvent};
+    use crate::core::{Anchor, Rotation, SizeHints, image::Image, rect::Rect};
+    use crate::window::{
+        ORBITAL_EVENT_ACKSIZE, ORBITAL_FLAG_BORDERLESS, ORBITAL_FLAG_FRAME_EVENTS, ORBITAL_FLAG_RESIZABLE,
+        ORBITAL_FLAG_SYNC_RESIZE, ORBITAL_FLAG_UNCLOSABLE, Aliased, dim_color, fade_color, TilePosition, Window, WindowFlags,
+        WindowRegion,
+        WindowZOrder,
+    };
     use std::rc::Rc;
     use crate::config::Config;
 
     // create a default config that can be used to create Windows for testing
-    // TODO implement or derive Default for orbclient::Color and then just use Config::default()
     fn test_config() -> Config {
         Config {
-            cursor: String::default(),
-            bottom_left_corner: String::default(),
-            bottom_right_corner: String::default(),
-            bottom_side: String::default(),
-            left_side: String::default(),
-            right_side: String::default(),
-            window_max: String::default(),
-            window_max_unfocused: String::default(),
-            window_close: String::default(),
-            window_close_unfocused: String::default(),
-
             background_color: Color::rgba(1, 2, 3, 200).into(),
             bar_color: Color::rgba(1, 2, 3, 200).into(),
             bar_highlight_color: Color::rgba(1, 2, 3, 200).into(),
             text_color: Color::rgba(1, 2, 3, 200).into(),
             text_highlight_color: Color::rgba(1, 2, 3, 200).into(),
+            ..Config::default()
         }
     }
 
@@ -454,4 +1274,510 @@ mod test {
         // let's try and read events from the empty queue into the buffer
         assert_eq!(window.read(buf.as_mut_slice()), 0, "Did not expect to read any events");
     }
+
+    #[test]
+    fn tile_to_other_position_keeps_floating_geometry() {
+        let mut window = Window::new(10, 20, 100, 50, 1, Rc::new(test_config()));
+        let area = Rect::new(0, 28, 800, 572);
+
+        let left = window.tile(TilePosition::LeftHalf, TilePosition::LeftHalf.rect(&area));
+        assert_eq!((left.left(), left.top(), left.width(), left.height()), (0, 28, 400, 572));
+
+        // Tiling to a different position re-tiles instead of restoring
+        let right = window.tile(TilePosition::RightHalf, TilePosition::RightHalf.rect(&area));
+        assert_eq!((right.left(), right.top(), right.width(), right.height()), (400, 28, 400, 572));
+
+        // Tiling to the same position again restores the original floating geometry
+        let restore = window.tile(TilePosition::RightHalf, TilePosition::RightHalf.rect(&area));
+        assert_eq!((restore.left(), restore.top(), restore.width(), restore.height()), (10, 20, 100, 50));
+        assert!(window.restore.is_none());
+    }
+
+    #[test]
+    fn maximize_then_tile_left() {
+        let mut window = Window::new(10, 20, 100, 50, 1, Rc::new(test_config()));
+        let area = Rect::new(0, 28, 800, 572);
+
+        window.tile(TilePosition::FullScreen, TilePosition::FullScreen.rect(&area));
+        assert!(window.maximized());
+
+        let left = window.tile(TilePosition::LeftHalf, TilePosition::LeftHalf.rect(&area));
+        assert_eq!((left.left(), left.top(), left.width(), left.height()), (0, 28, 400, 572));
+        assert!(!window.maximized());
+
+        let restore = window.tile(TilePosition::LeftHalf, TilePosition::LeftHalf.rect(&area));
+        assert_eq!((restore.left(), restore.top(), restore.width(), restore.height()), (10, 20, 100, 50));
+    }
+
+    #[test]
+    fn fullscreen_covers_screen_and_restores() {
+        let screen = Rect::new(0, 0, 800, 600);
+        let area = Rect::new(0, 28, 800, 572);
+        let mut window = Window::new(10, 40, 100, 50, 1, Rc::new(test_config()));
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+
+        assert_eq!(window.fullscreen(screen), screen);
+        assert!(window.fullscreen && window.maximized());
+        (window.x, window.y) = (0, 0);
+        assert!(window.title_rect().is_empty() && window.frame_rect().top() == 0);
+        assert!(!window.border_resizable());
+        assert!(!window.max_contains(795, -10));
+
+        // maximizing again restores it, with its decorations
+        let restore = window.tile(TilePosition::FullScreen, TilePosition::FullScreen.rect(&area));
+        assert_eq!(restore, Rect::new(10, 40, 100, 50));
+        assert!(!window.fullscreen && !window.maximized());
+        (window.x, window.y) = (restore.left(), restore.top());
+
+        // from maximized it keeps the floating geometry
+        window.tile(TilePosition::FullScreen, TilePosition::FullScreen.rect(&area));
+        window.fullscreen(screen);
+        assert_eq!(window.restore, Some((Rect::new(10, 40, 100, 50), TilePosition::FullScreen)));
+    }
+
+    #[test]
+    fn synced_resize_is_shown_once_acknowledged() {
+        let mut window = Window::new(10, 40, 100, 50, 1, Rc::new(test_config()));
+        window.set_flag(ORBITAL_FLAG_SYNC_RESIZE, true);
+        window.configure(Rect::new(0, 28, 800, 572));
+        let codes: Vec<(i64, i64)> = window.events.drain(..).map(|event| (event.code, event.a)).collect();
+        assert_eq!(codes, [(orbclient::EVENT_MOVE, 0), (orbclient::EVENT_RESIZE, 800), (ORBITAL_EVENT_ACKSIZE, 1)]);
+
+        // the client resizes its buffer, the old one is still shown where it was
+        window.set_size(800, 572, Anchor::TopLeft);
+        assert_eq!(window.buffer_size(), (800, 572));
+        assert_eq!(window.rect(), Rect::new(10, 40, 100, 50));
+
+        // a stale serial is ignored
+        assert!(!window.ack_resize(0));
+        assert_eq!(window.rect(), Rect::new(10, 40, 100, 50));
+        assert!(window.ack_resize(1));
+        assert_eq!(window.rect(), Rect::new(0, 28, 800, 572));
+        assert!(!window.ack_resize(1));
+
+        // windows that don't acknowledge resizes just move
+        let mut window = Window::new(10, 40, 100, 50, 1, Rc::new(test_config()));
+        window.configure(Rect::new(0, 28, 800, 572));
+        assert_eq!((window.x, window.y), (0, 28));
+        assert!(window.events.iter().all(|event| event.code != ORBITAL_EVENT_ACKSIZE));
+    }
+
+    #[test]
+    fn toggling_borderless_keeps_content_in_place() {
+        let mut window = Window::new(10, 40, 100, 50, 1, Rc::new(test_config()));
+        let title = window.title_rect();
+        assert_eq!((title.left(), title.top(), title.width(), title.height()), (10, 12, 100, 28));
+
+        window.set_flag(ORBITAL_FLAG_BORDERLESS, true);
+        let rect = window.rect();
+        assert_eq!((rect.left(), rect.top(), rect.width(), rect.height()), (10, 40, 100, 50));
+        assert!(window.title_rect().is_empty());
+        assert!(!window.max_contains(105, 20) && !window.close_contains(105, 20));
+
+        window.set_flag(ORBITAL_FLAG_BORDERLESS, false);
+        let rect = window.rect();
+        assert_eq!((rect.left(), rect.top(), rect.width(), rect.height()), (10, 40, 100, 50));
+        let title = window.title_rect();
+        assert_eq!((title.left(), title.top(), title.width(), title.height()), (10, 12, 100, 28));
+    }
+
+    // fill the window with a distinct color for every pixel
+    fn fill_window(window: &mut Window) {
+        let len = (window.width() * window.height()) as usize;
+        for (i, pixel) in window.map().iter_mut().enumerate().take(len) {
+            pixel.data = i as u32;
+        }
+    }
+
+    fn pixel(window: &Window, x: i32, y: i32) -> u32 {
+        window.image.data()[(y * window.width() + x) as usize].data
+    }
+
+    #[test]
+    fn resize_bottom_right_anchor_grow() {
+        let mut window = Window::new(100, 100, 4, 3, 1, Rc::new(test_config()));
+        fill_window(&mut window);
+
+        window.set_size(6, 5, Anchor::BottomRight);
+        assert_eq!((window.x, window.y, window.width(), window.height()), (98, 98, 6, 5));
+        // old bottom right pixel (3, 2) is still the bottom right pixel
+        assert_eq!(pixel(&window, 5, 4), 2 * 4 + 3);
+        // old top left pixel moved down and right by the growth
+        assert_eq!(pixel(&window, 2, 2), 0);
+        // new area is blank
+        assert_eq!(pixel(&window, 0, 0), 0);
+        assert_eq!(pixel(&window, 1, 4), 0);
+    }
+
+    #[test]
+    fn resize_bottom_right_anchor_shrink() {
+        let mut window = Window::new(100, 100, 4, 3, 1, Rc::new(test_config()));
+        fill_window(&mut window);
+
+        window.set_size(2, 2, Anchor::BottomRight);
+        assert_eq!((window.x, window.y, window.width(), window.height()), (102, 101, 2, 2));
+        // the bottom right 2x2 part of the old content is kept
+        assert_eq!(pixel(&window, 0, 0), 4 + 2);
+        assert_eq!(pixel(&window, 1, 0), 4 + 3);
+        assert_eq!(pixel(&window, 0, 1), 2 * 4 + 2);
+        assert_eq!(pixel(&window, 1, 1), 2 * 4 + 3);
+    }
+
+    #[test]
+    fn resize_top_left_anchor_keeps_origin() {
+        let mut window = Window::new(100, 100, 4, 3, 1, Rc::new(test_config()));
+        fill_window(&mut window);
+
+        window.set_size(2, 2, Anchor::default());
+        assert_eq!((window.x, window.y, window.width(), window.height()), (100, 100, 2, 2));
+        assert_eq!(pixel(&window, 1, 1), 4 + 1);
+    }
+
+    #[test]
+    fn non_resizable_window_is_not_tileable() {
+        let mut window = Window::new(10, 20, 100, 50, 1, Rc::new(test_config()));
+        assert!(!window.tileable());
+
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        assert!(window.tileable());
+
+        // a window tiled while it was resizable can still be restored
+        let area = Rect::new(0, 28, 800, 572);
+        window.tile(TilePosition::FullScreen, TilePosition::FullScreen.rect(&area));
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, false);
+        assert!(window.tileable());
+        let restore = window.tile(TilePosition::FullScreen, TilePosition::FullScreen.rect(&area));
+        assert_eq!((restore.left(), restore.top(), restore.width(), restore.height()), (10, 20, 100, 50));
+        assert!(!window.tileable());
+    }
+
+    #[test]
+    fn subtitle_right_aligned_before_buttons() {
+        let mut window = Window::new(10, 40, 200, 100, 1, Rc::new(test_config()));
+        window.subtitle = "modified".to_string();
+        window.subtitle_image = Image::new(50, 16);
+
+        // before the close button
+        let subtitle = window.subtitle_rect();
+        assert_eq!((subtitle.left(), subtitle.top(), subtitle.width(), subtitle.height()), (136, 18, 50, 16));
+
+        // before the maximize button too
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        let subtitle = window.subtitle_rect();
+        assert_eq!((subtitle.left(), subtitle.width()), (118, 50));
+
+        // clipped to start where the title does
+        window.subtitle_image = Image::new(500, 16);
+        let subtitle = window.subtitle_rect();
+        assert_eq!((subtitle.left(), subtitle.width()), (16, 152));
+    }
+
+    #[test]
+    fn aliased_text_pixels_are_opaque_or_skipped() {
+        let background = Color::rgb(0, 0, 0);
+        let mut image = Image::from_color(3, 1, background);
+        let mut aliased = Aliased(&mut image);
+        aliased.pixel(0, 0, Color::rgba(255, 255, 255, 127));
+        aliased.pixel(1, 0, Color::rgba(255, 255, 255, 128));
+        aliased.pixel(2, 0, Color::rgba(255, 255, 255, 255));
+
+        assert_eq!(image.data()[0], background);
+        assert_eq!(image.data()[1], Color::rgb(255, 255, 255));
+        assert_eq!(image.data()[2], Color::rgb(255, 255, 255));
+    }
+
+    #[test]
+    fn read_contents_keeps_dirty_region_until_read() {
+        let mut window = Window::new(0, 0, 3, 2, 1, Rc::new(test_config()));
+        window.map().iter_mut().enumerate().for_each(|(i, color)| color.data = i as u32);
+
+        // the whole window is new to a reader
+        let data = window.read_contents(1, true);
+        assert!(data.starts_with(b"0,0,3,2\n"));
+        assert_eq!(data.len(), 8 + 3 * 2 * 4);
+        assert_eq!(data[8 + 4 * 4..8 + 5 * 4], 4u32.to_le_bytes());
+        // until the reader has read it, it stays dirty
+        assert!(window.read_contents(1, true).starts_with(b"0,0,3,2\n"));
+
+        // nothing changed since
+        window.contents_read(1);
+        assert_eq!(window.read_contents(1, true), b"0,0,0,0\n");
+
+        // a full read is always complete
+        window.damage_contents(Rect::new(1, 1, 2, 1));
+        assert_eq!(window.read_contents(1, false).len(), 8 + 3 * 2 * 4);
+        assert_eq!(window.contents_dirty[&1], Rect::new(1, 1, 2, 1));
+
+        window.contents_read(1);
+        window.damage_contents(Rect::new(1, 1, 2, 1));
+        let data = window.read_contents(1, true);
+        assert_eq!(data[..8], *b"1,1,2,1\n");
+        assert_eq!(data[8..], [4u32.to_le_bytes(), 5u32.to_le_bytes()].concat());
+
+        // changes made before the reader read the last copy are added to it
+        window.damage_contents(Rect::new(0, 0, 1, 1));
+        assert!(window.read_contents(1, true).starts_with(b"0,0,3,2\n"));
+
+        // each reader has its own dirty region, reading doesn't reset another's
+        window.contents_read(1);
+        assert!(window.read_contents(2, true).starts_with(b"0,0,3,2\n"));
+        window.contents_read(2);
+        window.damage_contents(Rect::new(0, 0, 1, 1));
+        assert!(window.read_contents(1, true).starts_with(b"0,0,1,1\n"));
+        window.contents_read(1);
+        assert!(window.read_contents(2, true).starts_with(b"0,0,1,1\n"));
+        window.contents_read(1);
+
+        // resizing replaces the image, so all of it is dirty again
+        window.set_size(4, 2, Anchor::TopLeft);
+        assert!(window.read_contents(1, true).starts_with(b"0,0,4,2\n"));
+    }
+
+    #[test]
+    fn minimized_window_is_not_shown_or_focusable() {
+        let mut window = Window::new(10, 40, 100, 50, 1, Rc::new(test_config()));
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        assert!(window.visible() && window.focusable());
+        assert!(window.region_at(50, 60).is_some());
+
+        window.minimized = true;
+        assert!(!window.visible() && !window.focusable());
+        assert!(window.rect().is_empty() && window.title_rect().is_empty());
+        // not even the borders, which are outside of the window rect
+        assert!(window.region_at(50, 60).is_none());
+        assert!(window.region_at(50, 92).is_none());
+
+        // the frame is kept for restoring it
+        let frame = window.frame_rect();
+        assert_eq!((frame.left(), frame.top(), frame.width(), frame.height()), (10, 12, 100, 78));
+    }
+
+    #[test]
+    fn tiling_keeps_to_size_hints() {
+        let mut window = Window::new(10, 40, 100, 50, 1, Rc::new(test_config()));
+        window.size_hints = SizeHints { min: (600, 0), increment: (8, 16), ..SizeHints::default() };
+
+        let area = Rect::new(0, 0, 1000, 700);
+        let rect = window.tile(TilePosition::LeftHalf, TilePosition::LeftHalf.rect(&area));
+        assert_eq!((rect.left(), rect.top(), rect.width(), rect.height()), (0, 0, 600, 688));
+    }
+
+    #[test]
+    fn maximizing_keeps_aspect_ratio() {
+        let mut window = Window::new(10, 40, 100, 50, 1, Rc::new(test_config()));
+        let area = Rect::new(0, 20, 1024, 768);
+        assert_eq!(window.tile_rect(TilePosition::FullScreen, &area), area);
+
+        // a 16:9 window on a 4:3 area is letterboxed, and pillarboxed on a wider one
+        window.size_hints = SizeHints { aspect: (16, 9), ..SizeHints::default() };
+        let rect = window.tile(TilePosition::FullScreen, window.tile_rect(TilePosition::FullScreen, &area));
+        assert_eq!((rect.left(), rect.top(), rect.width(), rect.height()), (0, 116, 1024, 576));
+        let wide = window.tile_rect(TilePosition::FullScreen, &Rect::new(0, 0, 2560, 1080));
+        assert_eq!((wide.left(), wide.top(), wide.width(), wide.height()), (320, 0, 1920, 1080));
+
+        // halves are still filled
+        assert_eq!(window.tile_rect(TilePosition::LeftHalf, &area), TilePosition::LeftHalf.rect(&area));
+    }
+
+    #[test]
+    fn dimming_darkens_color_but_keeps_alpha() {
+        let color = Color::rgba(200, 100, 0, 128);
+        assert_eq!(dim_color(color, 0), color);
+        assert_eq!(dim_color(color, 255), Color::rgba(0, 0, 0, 128));
+        // about half as bright, and a fully transparent pixel stays invisible
+        assert_eq!(dim_color(color, 128), Color::rgba(99, 49, 0, 128));
+        assert_eq!(dim_color(Color::rgba(255, 255, 255, 0), 128).a(), 0);
+    }
+
+    #[test]
+    fn opacity_fades_alpha_whether_transparent_or_not() {
+        let color = Color::rgba(200, 100, 0, 128);
+        assert_eq!(fade_color(color, 255, true), color);
+        assert_eq!(fade_color(color, 128, true), Color::rgba(200, 100, 0, 64));
+        // the alpha of an opaque window is ignored, it fades from fully opaque
+        assert_eq!(fade_color(color, 128, false), Color::rgba(200, 100, 0, 128));
+        assert_eq!(fade_color(Color::rgba(1, 2, 3, 0), 255, false).a(), 255);
+        assert_eq!(fade_color(color, 0, false).a(), 0);
+    }
+
+    #[test]
+    fn scroll_factor_halves_accumulated_deltas() {
+        let mut window = Window::new(0, 0, 10, 10, 1, Rc::new(test_config()));
+        window.scroll_factor = 0.5;
+
+        let deltas: Vec<(i32, i32)> = [(0, 1), (0, 1), (0, 3), (-1, -1), (-1, 0)].iter()
+            .map(|&(x, y)| window.scale_scroll(ScrollEvent { x, y }, 1.0))
+            .map(|event| (event.x, event.y))
+            .collect();
+        assert_eq!(deltas, [(0, 0), (0, 1), (0, 1), (0, 0), (-1, 0)]);
+        // in total the window got half of the scrolling
+        let total = deltas.iter().fold((0, 0), |total, delta| (total.0 + delta.0, total.1 + delta.1));
+        assert_eq!(total, (-1, 2));
+
+        // combined with the global sensitivity
+        window.scroll_factor = 2.0;
+        let event = window.scale_scroll(ScrollEvent { x: 1, y: 2 }, 1.5);
+        assert_eq!((event.x, event.y), (3, 6));
+    }
+
+    #[test]
+    fn quit_request_leaves_window_to_the_client() {
+        let mut window = Window::new(0, 0, 10, 10, 1, Rc::new(test_config()));
+        // a client that ignores the first request gets the next one as well
+        assert!(window.request_quit());
+        assert!(window.request_quit());
+        let quits = window.events.iter().filter(|event| event.code == orbclient::EVENT_QUIT).count();
+        assert_eq!(quits, 2);
+
+        let mut unclosable = Window::new(0, 0, 10, 10, 1, Rc::new(test_config()));
+        unclosable.set_flag(ORBITAL_FLAG_UNCLOSABLE, true);
+        assert!(!unclosable.request_quit());
+        assert!(unclosable.events.is_empty());
+    }
+
+    #[test]
+    fn frame_events_deliver_motion_once_per_frame() {
+        let mut window = Window::new(0, 0, 10, 10, 1, Rc::new(test_config()));
+        window.set_flag(ORBITAL_FLAG_FRAME_EVENTS, true);
+        for x in 1..=3 {
+            window.event(MouseEvent { x, y: 5 }.to_event());
+        }
+        // nothing is delivered until the frame, then only the latest position
+        assert!(window.events.is_empty());
+        window.release_events();
+        let moves: Vec<_> = window.events.drain(..).map(|event| (event.code, event.a, event.b)).collect();
+        assert_eq!(moves, [(orbclient::EVENT_MOUSE, 3, 5)]);
+
+        // a key press brings the held back motion along, before itself
+        window.event(MouseEvent { x: 7, y: 8 }.to_event());
+        window.event(KeyEvent { character: 'a', scancode: 0x1E, pressed: true }.to_event());
+        let codes: Vec<_> = window.events.iter().map(|event| event.code).collect();
+        assert_eq!(codes, [orbclient::EVENT_MOUSE, orbclient::EVENT_KEY]);
+        assert!(window.properties().flags.contains(ORBITAL_FLAG_FRAME_EVENTS));
+    }
+
+    #[test]
+    fn shaded_window_collapses_to_its_title_bar() {
+        let mut window = Window::new(10, 40, 200, 100, 1, Rc::new(test_config()));
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        let title = window.title_rect();
+
+        window.shaded = true;
+        assert!(window.rect().is_empty());
+        assert_eq!((window.rect().left(), window.rect().top()), (10, 40));
+        assert_eq!(window.title_rect(), title);
+        assert!(window.bottom_border_rect().is_empty() && window.right_border_rect().is_empty());
+        // the buffer keeps its size while rolled up
+        assert_eq!((window.width(), window.height()), (200, 100));
+
+        window.shaded = false;
+        assert_eq!(window.rect(), Rect::new(10, 40, 200, 100));
+        assert!(!window.bottom_border_rect().is_empty());
+    }
+
+    #[test]
+    fn rotated_window_swaps_size_and_maps_corners() {
+        let mut window = Window::new(10, 20, 4, 2, 1, Rc::new(test_config()));
+        window.set_flag(ORBITAL_FLAG_RESIZABLE, true);
+        let top_right = Color::rgb(1, 2, 3);
+        window.map()[3] = top_right;
+
+        window.rotation = Rotation::Cw90;
+        let rect = window.rect();
+        assert_eq!((rect.left(), rect.top(), rect.width(), rect.height()), (10, 20, 2, 4));
+        assert_eq!(window.buffer_size(), (4, 2));
+        assert!(! window.tileable() && window.right_border_rect().is_empty());
+
+        // turned clockwise, the top right of the buffer is at the bottom right on screen
+        assert_eq!(window.buffer_point(1, 3), (3, 0));
+        let rotated = window.rotated(&Rect::new(0, 0, 2, 4)).unwrap_or_else(|| panic!("not rotated"));
+        assert_eq!(rotated.data()[7], top_right);
+
+        window.rotation = Rotation::Cw180;
+        assert_eq!((window.width(), window.height()), (4, 2));
+        assert_eq!(window.buffer_point(0, 1), (3, 0));
+
+        window.rotation = Rotation::Cw270;
+        assert_eq!(window.buffer_point(0, 0), (3, 0));
+        assert_eq!(window.buffer_point(1, 0), (3, 1));
+
+        window.rotation = Rotation::None;
+        assert!(window.rotated(&Rect::new(0, 0, 4, 2)).is_none());
+    }
+
+    #[test]
+    fn mirror_is_a_scaled_copy_of_the_source() {
+        let config = Rc::new(test_config());
+        let mut source = Window::new(0, 0, 4, 2, 1, Rc::clone(&config));
+        // the buffer is padded past its pixels for alignment
+        for (i, pixel) in source.map().iter_mut().take(8).enumerate() {
+            *pixel = Color::rgb(i as u8 * 10, 0, 255 - i as u8);
+        }
+        let expected = Image::from_data(4, 2, source.map()[..8].to_vec().into_boxed_slice()).scale(2)
+            .unwrap_or_else(|| panic!("no scaled copy"));
+
+        let mut mirror = Window::new(100, 100, 8, 4, 1, Rc::clone(&config));
+        mirror.mirrored = Some(source.mirror_image(mirror.width(), mirror.height()));
+        assert_eq!(mirror.mirrored.as_ref().map(|image| image.data()), Some(expected.data()));
+
+        // a taller area is letterboxed
+        let letterboxed = source.mirror_image(8, 8);
+        let black = Color::rgb(0, 0, 0);
+        assert!(letterboxed.data()[..16].iter().all(|&pixel| pixel == black));
+        assert_eq!(&letterboxed.data()[16..48], expected.data());
+        assert!(letterboxed.data()[48..].iter().all(|&pixel| pixel == black));
+
+        // a rotated source is mirrored as it is shown
+        source.rotation = Rotation::Cw90;
+        let rotated = source.mirror_image(2, 4);
+        assert_eq!(rotated.data()[0], source.map()[4]);
+        assert_eq!(rotated.data()[1], source.map()[0]);
+    }
+
+    #[test]
+    fn flags_round_trip_through_bitmask() {
+        let mut window = Window::new(0, 0, 100, 100, 1, Rc::new(test_config()));
+        for flag in [ORBITAL_FLAG_BORDERLESS, ORBITAL_FLAG_RESIZABLE, ORBITAL_FLAG_UNCLOSABLE] {
+            window.set_flag(flag, true);
+        }
+        window.zorder = WindowZOrder::Front;
+
+        let flags = window.flags();
+        assert_eq!(flags, WindowFlags(WindowFlags::BORDERLESS.0 | WindowFlags::RESIZABLE.0 | WindowFlags::UNCLOSABLE.0 | WindowFlags::FRONT.0));
+        // the string is the flags string of fpath as it always was
+        assert_eq!(flags.to_string(), window.properties().flags);
+        assert_eq!(flags.to_string(), "lruf");
+        assert_eq!(WindowFlags::from("lruf"), flags);
+
+        // every flag has a bit of its own
+        let all = WindowFlags::from("abceghiklmnprstuf");
+        assert_eq!(all.0.count_ones(), 17);
+        assert_eq!(WindowFlags::from(all.to_string().as_str()), all);
+        // characters that aren't flags are ignored
+        assert_eq!(WindowFlags::from("xyz"), WindowFlags::default());
+        assert_eq!(WindowFlags::default().to_string(), "");
+
+        // a minimized window reports the k flag with the others
+        window.minimized = true;
+        assert!(window.flags().contains(WindowFlags::MINIMIZED));
+        assert_eq!(window.properties().flags, "lkruf");
+    }
+
+    #[test]
+    fn deferred_title_bar_waits_for_first_paint() {
+        // by default the title bar is drawn right away
+        let mut window = Window::new(0, 40, 100, 100, 1, Rc::new(test_config()));
+        assert!(window.title_shown());
+        assert_eq!(window.region_at(50, 20), Some(WindowRegion::Title));
+
+        // deferred, it can't be clicked or dragged until it is drawn
+        let config = Config { defer_decorations: true, ..test_config() };
+        window = Window::new(0, 40, 100, 100, 1, Rc::new(config));
+        assert!(! window.title_shown());
+        assert_eq!(window.region_at(50, 20), None);
+        assert_eq!(window.region_at(50, 60), Some(WindowRegion::Content));
+        window.painted = true;
+        assert!(window.title_shown());
+        assert_eq!(window.region_at(50, 20), Some(WindowRegion::Title));
+    }
 }